@@ -0,0 +1,5 @@
+//! Shared by the gadgets and the `ubuntu-gadgets` binary: where config and state files live,
+//! and how logging is set up.
+
+pub mod logging;
+pub mod xdg;
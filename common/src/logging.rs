@@ -0,0 +1,95 @@
+//! The tracing subscriber of the gadgets.
+
+use std::env;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    EnvFilter,
+    fmt::{self, MakeWriter, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+};
+
+/// From the quietest to the most verbose.
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::OFF,
+    LevelFilter::ERROR,
+    LevelFilter::WARN,
+    LevelFilter::INFO,
+    LevelFilter::DEBUG,
+    LevelFilter::TRACE,
+];
+
+/// Log level for `verbose` times `-v`: `default`, one level more verbose for each `-v`, up to `trace`.
+pub fn verbosity_level(default: LevelFilter, verbose: u8) -> LevelFilter {
+    let start = LEVELS
+        .iter()
+        .position(|&level| level == default)
+        .unwrap_or(0);
+    LEVELS[(start + verbose as usize).min(LEVELS.len() - 1)]
+}
+
+/// `rust_log` if it is a valid filter, otherwise [`verbosity_level`].
+pub fn filter_for(default: LevelFilter, verbose: u8, rust_log: Option<&str>) -> EnvFilter {
+    rust_log
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| EnvFilter::new(verbosity_level(default, verbose).to_string()))
+}
+
+/// [`filter_for`] with `RUST_LOG` from the environment.
+pub fn env_filter(default: LevelFilter, verbose: u8) -> EnvFilter {
+    filter_for(
+        default,
+        verbose,
+        env::var(EnvFilter::DEFAULT_ENV).ok().as_deref(),
+    )
+}
+
+/// Logs passing `filter` to `writer`, colored if `ansi`, and without colors to `log_file` if given.
+pub fn subscriber<W>(
+    filter: EnvFilter,
+    ansi: bool,
+    writer: W,
+    log_file: Option<BoxMakeWriter>,
+) -> impl tracing::Subscriber + Send + Sync
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_ansi(ansi).with_writer(writer))
+        .with(log_file.map(|writer| fmt::layer().with_ansi(false).with_writer(writer)))
+}
+
+/// Install [`subscriber`] as the global default.
+pub fn init<W>(filter: EnvFilter, ansi: bool, writer: W, log_file: Option<BoxMakeWriter>)
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    tracing::subscriber::set_global_default(subscriber(filter, ansi, writer, log_file)).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbose_raises_the_level_unless_rust_log() {
+        let levels = |default| -> Vec<String> {
+            (0..5)
+                .map(|verbose| filter_for(default, verbose, None).to_string())
+                .collect()
+        };
+        assert_eq!(
+            levels(LevelFilter::WARN),
+            ["warn", "info", "debug", "trace", "trace"]
+        );
+        assert_eq!(
+            levels(LevelFilter::INFO),
+            ["info", "debug", "trace", "trace", "trace"]
+        );
+        let filter = filter_for(LevelFilter::WARN, 2, Some("error"));
+        assert_eq!(filter.to_string(), "error");
+        // 不合法的 RUST_LOG 被忽略
+        let filter = filter_for(LevelFilter::WARN, 2, Some("=#"));
+        assert_eq!(filter.to_string(), "debug");
+    }
+}
@@ -0,0 +1,96 @@
+//! 供异步程序使用的窗口监听: 阻塞的事件循环运行在单独的线程中, 变化通过 channel 传出.
+
+use crate::{
+    IbusSwitchError, ListenOptions, WindowInfo, watcher::PropertyWatcher, x11::watch_focus_changes,
+};
+use std::thread;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// An active window change, see [`listen_active_window_changes_async`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowChange {
+    pub previous: Option<WindowInfo>,
+    pub current: WindowInfo,
+}
+
+/// Listen active window changes on a background thread, changes are received from the returned channel.
+/// Like [`crate::listen_active_window_changes_with`], the initial active window is reported first
+/// and repeated changes to the same window are dropped.
+///
+/// The channel yields an error and closes if watching fails, e.g. the X server goes away.
+/// Dropping the receiver stops the thread after the next change.
+/// Doesn't depend on a particular runtime, the channel is from tokio.
+pub fn listen_active_window_changes_async(
+    options: ListenOptions,
+) -> UnboundedReceiver<Result<WindowChange, IbusSwitchError>> {
+    spawn_changes(move |tx| {
+        let mut watcher = PropertyWatcher::with_screens(options.debounce, options.all_screens)?;
+        let change_tx = tx.clone();
+        watch_focus_changes(&mut watcher, options, move |_, previous, current, _| {
+            let _ = change_tx.send(Ok(WindowChange {
+                previous: previous.cloned(),
+                current: current.clone(),
+            }));
+        })?;
+        watcher.stop_when(move || tx.is_closed());
+        watcher.run()
+    })
+}
+
+type ChangeSender = UnboundedSender<Result<WindowChange, IbusSwitchError>>;
+
+/// 在新线程中运行 `listen`, 它把变化发送到 channel, 返回的错误也发送出去, 然后 channel 关闭.
+fn spawn_changes(
+    listen: impl FnOnce(ChangeSender) -> Result<(), IbusSwitchError> + Send + 'static,
+) -> UnboundedReceiver<Result<WindowChange, IbusSwitchError>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        if let Err(e) = listen(tx.clone()) {
+            let _ = tx.send(Err(e));
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32) -> WindowInfo {
+        WindowInfo {
+            id,
+            ..WindowInfo::none()
+        }
+    }
+
+    #[test]
+    fn changes_then_error_arrive_in_order() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut changes = spawn_changes(|tx| {
+                for (previous, current) in [(None, 1), (Some(1), 2)] {
+                    let _ = tx.send(Ok(WindowChange {
+                        previous: previous.map(window),
+                        current: window(current),
+                    }));
+                }
+                Err(IbusSwitchError::AtomNotFound(
+                    "_NET_ACTIVE_WINDOW".to_owned(),
+                ))
+            });
+            let first = changes.recv().await.unwrap().unwrap();
+            assert_eq!((first.previous, first.current.id), (None, 1));
+            let second = changes.recv().await.unwrap().unwrap();
+            assert_eq!(second.previous.map(|w| w.id), Some(1));
+            assert_eq!(second.current.id, 2);
+            assert!(matches!(
+                changes.recv().await,
+                Some(Err(IbusSwitchError::AtomNotFound(_)))
+            ));
+            // 出错后 channel 关闭
+            assert!(changes.recv().await.is_none());
+        });
+    }
+}
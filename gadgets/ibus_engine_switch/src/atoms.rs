@@ -0,0 +1,92 @@
+use crate::{IbusSwitchError, x11::intern_atom};
+use std::{cell::RefCell, collections::HashMap};
+use x11rb::{connection::Connection, protocol::xproto::ConnectionExt as _};
+
+/// Interned atoms and atom names of one connection, each is asked from the X server only once.
+#[derive(Debug, Default)]
+pub struct AtomCache {
+    atoms: RefCell<HashMap<Vec<u8>, u32>>,
+    names: RefCell<HashMap<u32, String>>,
+}
+
+impl AtomCache {
+    pub fn new() -> AtomCache {
+        AtomCache::default()
+    }
+
+    /// The atom of `name`, interned on first use. Must always be used with the same connection.
+    pub fn intern(&self, conn: &impl Connection, name: &[u8]) -> Result<u32, IbusSwitchError> {
+        if let Some(&atom) = self.atoms.borrow().get(name) {
+            return Ok(atom);
+        }
+        let atom = intern_atom(conn, name)?;
+        self.atoms.borrow_mut().insert(name.to_owned(), atom);
+        Ok(atom)
+    }
+
+    /// The name of `atom`, asked on first use.
+    pub fn name(&self, conn: &impl Connection, atom: u32) -> Result<String, IbusSwitchError> {
+        if let Some(name) = self.names.borrow().get(&atom) {
+            return Ok(name.clone());
+        }
+        let name = conn.get_atom_name(atom)?.reply()?.name;
+        let name = String::from_utf8_lossy(&name).into_owned();
+        self.names.borrow_mut().insert(atom, name.clone());
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fake::x11::{FakeConnection, ROOT},
+        get_active_window,
+    };
+    use x11rb::protocol::xproto;
+
+    #[test]
+    fn each_atom_is_requested_once() {
+        let conn = FakeConnection::new();
+        let atoms = AtomCache::new();
+        let active = atoms.intern(&conn, b"_NET_ACTIVE_WINDOW").unwrap();
+        assert_eq!(atoms.intern(&conn, b"_NET_ACTIVE_WINDOW").unwrap(), active);
+        assert_eq!(conn.count(xproto::INTERN_ATOM_REQUEST), 1);
+        let utf8 = atoms.intern(&conn, b"UTF8_STRING").unwrap();
+        assert_ne!(utf8, active);
+        assert_eq!(conn.count(xproto::INTERN_ATOM_REQUEST), 2);
+
+        assert_eq!(atoms.name(&conn, utf8).unwrap(), "UTF8_STRING");
+        assert_eq!(atoms.name(&conn, utf8).unwrap(), "UTF8_STRING");
+        assert_eq!(conn.count(xproto::GET_ATOM_NAME_REQUEST), 1);
+        // 名称的缓存和 atom 的缓存是分开的
+        assert_eq!(conn.count(xproto::INTERN_ATOM_REQUEST), 2);
+    }
+
+    #[test]
+    fn window_queries_reuse_the_cache() {
+        let conn = FakeConnection::new();
+        let window = 0x3a00003;
+        conn.set_property32(
+            ROOT,
+            b"_NET_ACTIVE_WINDOW",
+            xproto::AtomEnum::WINDOW,
+            &[window],
+        );
+        conn.set_property(
+            window,
+            b"_NET_WM_NAME",
+            conn.atom(b"UTF8_STRING"),
+            8,
+            "终端".into(),
+        );
+        let atoms = AtomCache::new();
+        for _ in 0..3 {
+            let info = get_active_window(&conn, &atoms, ROOT).unwrap().unwrap();
+            assert_eq!(info.id, window);
+            assert_eq!(info.title.as_deref(), Some("终端"));
+        }
+        // _NET_ACTIVE_WINDOW, _NET_WM_NAME, UTF8_STRING 和 _NET_WM_WINDOW_TYPE 各一次
+        assert_eq!(conn.count(xproto::INTERN_ATOM_REQUEST), 4);
+    }
+}
@@ -0,0 +1,93 @@
+//! 切换窗口后自动切换到英文的计时.
+
+use std::time::{Duration, Instant};
+
+/// Timer of switching to English automatically after focusing another window.
+/// Every focus change restarts it, and switching manually cancels it.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoswitchTimer {
+    delay: Duration,
+    /// 待执行的自动切换的时间, None 表示没有.
+    deadline: Option<Instant>,
+}
+
+impl AutoswitchTimer {
+    pub fn new(delay: Duration) -> AutoswitchTimer {
+        AutoswitchTimer {
+            delay,
+            deadline: None,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// A window got focus at `now`, (re)start the timer.
+    pub fn focus(&mut self, now: Instant) {
+        self.deadline = Some(now + self.delay);
+    }
+
+    /// Cancel the pending switch, returns whether there was one.
+    pub fn cancel(&mut self) -> bool {
+        self.deadline.take().is_some()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// How long to wait from `now` before [`AutoswitchTimer::tick`], None if nothing is pending.
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// Returns true, only once, if the pending switch is due at `now`.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                self.deadline = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_and_cancel() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let at = |t| start + ms(t);
+        let mut timer = AutoswitchTimer::new(ms(100));
+        assert!(!timer.tick(at(1000)));
+        assert_eq!(timer.timeout(at(0)), None);
+        // 到期前不切换, 到期时切换一次
+        timer.focus(at(0));
+        assert_eq!(timer.timeout(at(40)), Some(ms(60)));
+        assert!(!timer.tick(at(99)));
+        assert!(timer.tick(at(100)));
+        assert!(!timer.tick(at(200)));
+        assert!(!timer.is_pending());
+        // 期间的焦点变化重新计时
+        timer.focus(at(300));
+        timer.focus(at(350));
+        assert!(!timer.tick(at(400)));
+        assert!(timer.tick(at(450)));
+        // 期间手动切换取消
+        timer.focus(at(500));
+        assert!(timer.cancel());
+        assert!(!timer.cancel());
+        assert!(!timer.tick(at(1000)));
+        // 超过期限才检查时也只切换一次
+        timer.focus(at(1000));
+        assert_eq!(timer.timeout(at(2000)), Some(Duration::ZERO));
+        assert!(timer.tick(at(2000)));
+        assert!(!timer.tick(at(2001)));
+    }
+}
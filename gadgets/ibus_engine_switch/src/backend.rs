@@ -0,0 +1,249 @@
+use crate::{
+    config::{Backend, Config},
+    fcitx5::Fcitx5,
+    ibus::IBus,
+};
+use std::sync::Mutex;
+use tracing::{Span, field, info, instrument, warn};
+
+/// An input method framework whose engines can be switched.
+pub trait InputMethodBackend: Send {
+    /// Name of the engine currently in use.
+    fn current_engine(&self) -> Result<String, anyhow::Error>;
+
+    fn set_engine(&self, engine: &str) -> Result<(), anyhow::Error>;
+
+    /// Names of the installed engines.
+    fn list_engines(&self) -> Result<Vec<String>, anyhow::Error>;
+
+    /// Fails if the backend can't switch at all, e.g. the D-Bus connection failed
+    /// and the program to fall back to is missing.
+    fn check_available(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+/// Create the backend selected by config.
+pub fn create_backend(config: &Config) -> Box<dyn InputMethodBackend> {
+    match config.backend {
+        Backend::Ibus => Box::new(IBus::new(config.transport)),
+        Backend::Fcitx5 => Box::new(Fcitx5::new(config.transport)),
+    }
+}
+
+/// Wraps a backend for `--dry-run`: switching is only logged and the engine switched to
+/// is reported as current, so the simulation stays coherent.
+pub struct DryRun {
+    inner: Box<dyn InputMethodBackend>,
+    /// 模拟的当前输入法, 第一次切换前使用实际的输入法.
+    engine: Mutex<Option<String>>,
+}
+
+impl DryRun {
+    pub fn new(inner: Box<dyn InputMethodBackend>) -> DryRun {
+        DryRun {
+            inner,
+            engine: Mutex::new(None),
+        }
+    }
+}
+
+impl InputMethodBackend for DryRun {
+    fn current_engine(&self) -> Result<String, anyhow::Error> {
+        match &*self.engine.lock().unwrap() {
+            Some(engine) => Ok(engine.clone()),
+            None => self.inner.current_engine(),
+        }
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<(), anyhow::Error> {
+        info!("Dry run: would switch to {engine}.");
+        *self.engine.lock().unwrap() = Some(engine.to_owned());
+        Ok(())
+    }
+
+    fn list_engines(&self) -> Result<Vec<String>, anyhow::Error> {
+        self.inner.list_engines()
+    }
+
+    fn check_available(&self) -> Result<(), anyhow::Error> {
+        self.inner.check_available()
+    }
+}
+
+/// Name of the engine currently in use, only queried without switching or recording anything.
+/// Fails if the framework doesn't respond or no engine is set.
+pub fn current_engine(backend: &dyn InputMethodBackend) -> Result<String, anyhow::Error> {
+    backend.current_engine()
+}
+
+#[deprecated = "renamed to `current_engine`"]
+pub fn get_current_engine(backend: &dyn InputMethodBackend) -> Result<String, anyhow::Error> {
+    current_engine(backend)
+}
+
+/// Switch to `engine` and verify it, see [`set_engine_verified`]. Returns the actual engine.
+#[instrument(skip(backend), fields(actual = field::Empty))]
+pub fn set_engine(
+    backend: &dyn InputMethodBackend,
+    engine: &str,
+    retries: usize,
+) -> Result<String, anyhow::Error> {
+    let actual = set_engine_verified(
+        engine,
+        retries,
+        |engine| backend.set_engine(engine),
+        || backend.current_engine(),
+    )?;
+    Span::current().record("actual", actual.as_str());
+    Ok(actual)
+}
+
+/// Whether switching to `engine` needs to call the backend: not if the cached `current` engine
+/// already is it, unless `force`d because the cache may be stale.
+pub fn needs_switch(current: &str, engine: &str, force: bool) -> bool {
+    force || current != engine
+}
+
+/// 设置输入法并确认其生效, 未生效时最多重试 `retries` 次, 返回最后实际的输入法.
+pub fn set_engine_verified(
+    engine: &str,
+    retries: usize,
+    mut set: impl FnMut(&str) -> Result<(), anyhow::Error>,
+    mut get: impl FnMut() -> Result<String, anyhow::Error>,
+) -> Result<String, anyhow::Error> {
+    let mut actual = String::new();
+    for attempt in 0..=retries {
+        if let Err(e) = set(engine) {
+            warn!(
+                "Failed to switch to {engine} (attempt {}): {e}",
+                attempt + 1
+            );
+        }
+        actual = get()?;
+        if actual == engine {
+            break;
+        }
+        warn!(
+            "Engine is {actual} instead of {engine} (attempt {}).",
+            attempt + 1
+        );
+    }
+    Ok(actual)
+}
+
+/// 检查配置的输入法是否都已安装, strict 模式下有未安装的输入法则报错.
+pub fn check_engines(
+    config: &Config,
+    backend: &dyn InputMethodBackend,
+) -> Result<(), anyhow::Error> {
+    let available = match backend.list_engines() {
+        Ok(available) => available,
+        Err(e) => {
+            warn!("Failed to list engines, skip checking: {e}");
+            return Ok(());
+        }
+    };
+    let missing: Vec<&str> = config
+        .engines
+        .iter()
+        .filter(|engine| !available.contains(engine))
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    for engine in &missing {
+        warn!("Engine {engine} is not installed.");
+    }
+    if config.strict {
+        return Err(anyhow::anyhow!(
+            "Unknown engine(s): {}.",
+            missing.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    /// 第 `effective` 次设置才生效的输入法框架.
+    struct Flaky {
+        effective: usize,
+        sets: Cell<usize>,
+        current: RefCell<String>,
+    }
+
+    impl Flaky {
+        fn new(effective: usize) -> Flaky {
+            Flaky {
+                effective,
+                sets: Cell::new(0),
+                current: RefCell::new("xkb:us::eng".to_owned()),
+            }
+        }
+
+        fn switch(&self, engine: &str, retries: usize) -> String {
+            let set = |e: &str| {
+                self.sets.set(self.sets.get() + 1);
+                if self.sets.get() >= self.effective {
+                    *self.current.borrow_mut() = e.to_owned();
+                }
+                Ok(())
+            };
+            set_engine_verified(engine, retries, set, || Ok(self.current.borrow().clone())).unwrap()
+        }
+    }
+
+    #[test]
+    fn verified_first_time() {
+        let flaky = Flaky::new(1);
+        assert_eq!(flaky.switch("rime", 2), "rime");
+        assert_eq!(flaky.sets.get(), 1);
+    }
+
+    #[test]
+    fn retried_until_effective() {
+        let flaky = Flaky::new(3);
+        assert_eq!(flaky.switch("rime", 2), "rime");
+        assert_eq!(flaky.sets.get(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let flaky = Flaky::new(5);
+        assert_eq!(flaky.switch("rime", 2), "xkb:us::eng");
+        assert_eq!(flaky.sets.get(), 3);
+    }
+
+    #[test]
+    fn failing_set_is_retried_and_query_errors_are_returned() {
+        let mut attempts = 0;
+        let result = set_engine_verified(
+            "rime",
+            1,
+            |_| {
+                attempts += 1;
+                Err(anyhow::anyhow!("ibus is busy"))
+            },
+            || Ok("xkb:us::eng".to_owned()),
+        );
+        assert_eq!(result.unwrap(), "xkb:us::eng");
+        assert_eq!(attempts, 2);
+        let result = set_engine_verified("rime", 1, |_| Ok(()), || Err(anyhow::anyhow!("gone")));
+        assert_eq!(result.unwrap_err().to_string(), "gone");
+    }
+
+    #[test]
+    fn current_engine_only_queries() {
+        use crate::fake::{BrokenBackend, FakeBackend};
+        let backend = FakeBackend::new(&["rime", "xkb:us::eng"]);
+        let switched = backend.switched();
+        assert_eq!(current_engine(&backend).unwrap(), "rime");
+        assert!(switched.lock().unwrap().is_empty());
+        assert!(current_engine(&BrokenBackend).is_err());
+    }
+}
@@ -0,0 +1,546 @@
+use crate::engine::engine_index;
+use rdev::Key;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::value::StrDeserializer};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Super,
+}
+
+impl Modifier {
+    /// The modifier of `key`, left and right keys are treated the same.
+    pub fn of(key: Key) -> Option<Modifier> {
+        match key {
+            Key::ControlLeft | Key::ControlRight => Some(Modifier::Ctrl),
+            Key::ShiftLeft | Key::ShiftRight => Some(Modifier::Shift),
+            Key::Alt | Key::AltGr => Some(Modifier::Alt),
+            Key::MetaLeft | Key::MetaRight => Some(Modifier::Super),
+            _ => None,
+        }
+    }
+}
+
+/// The key of a [`Binding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeySpec {
+    /// A physical key position, named by its [`rdev::Key`] variant such as `LeftBracket`.
+    /// On non-US layouts the key at that position may produce another character.
+    Physical(Key),
+    /// Whichever key produces the character in the current layout, given as a single character such as `[`.
+    Char(char),
+}
+
+impl<'de> Deserialize<'de> for KeySpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            // rdev::Key 的变体名都不止一个字符, 不会混淆.
+            (Some(c), None) => Ok(KeySpec::Char(c.to_ascii_lowercase())),
+            _ => Key::deserialize(StrDeserializer::<D::Error>::new(&s)).map(KeySpec::Physical),
+        }
+    }
+}
+
+/// 与配置中的写法相同: 字符或 [`rdev::Key`] 的变体名.
+impl Serialize for KeySpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            KeySpec::Physical(key) => key.serialize(serializer),
+            KeySpec::Char(c) => serializer.collect_str(c),
+        }
+    }
+}
+
+impl KeySpec {
+    /// The physical key, characters are looked up in [`key_for_char`].
+    pub fn physical(&self) -> Option<Key> {
+        match *self {
+            KeySpec::Physical(key) => Some(key),
+            KeySpec::Char(c) => key_for_char(c),
+        }
+    }
+}
+
+/// The key producing `c` on a US QWERTY layout, without Shift. Letters are case-insensitive.
+/// Used where the produced character is not known, e.g. global hotkeys.
+pub fn key_for_char(c: char) -> Option<Key> {
+    let key = match c.to_ascii_lowercase() {
+        'a' => Key::KeyA,
+        'b' => Key::KeyB,
+        'c' => Key::KeyC,
+        'd' => Key::KeyD,
+        'e' => Key::KeyE,
+        'f' => Key::KeyF,
+        'g' => Key::KeyG,
+        'h' => Key::KeyH,
+        'i' => Key::KeyI,
+        'j' => Key::KeyJ,
+        'k' => Key::KeyK,
+        'l' => Key::KeyL,
+        'm' => Key::KeyM,
+        'n' => Key::KeyN,
+        'o' => Key::KeyO,
+        'p' => Key::KeyP,
+        'q' => Key::KeyQ,
+        'r' => Key::KeyR,
+        's' => Key::KeyS,
+        't' => Key::KeyT,
+        'u' => Key::KeyU,
+        'v' => Key::KeyV,
+        'w' => Key::KeyW,
+        'x' => Key::KeyX,
+        'y' => Key::KeyY,
+        'z' => Key::KeyZ,
+        '0' => Key::Num0,
+        '1' => Key::Num1,
+        '2' => Key::Num2,
+        '3' => Key::Num3,
+        '4' => Key::Num4,
+        '5' => Key::Num5,
+        '6' => Key::Num6,
+        '7' => Key::Num7,
+        '8' => Key::Num8,
+        '9' => Key::Num9,
+        '`' => Key::BackQuote,
+        '-' => Key::Minus,
+        '=' => Key::Equal,
+        '[' => Key::LeftBracket,
+        ']' => Key::RightBracket,
+        ';' => Key::SemiColon,
+        '\'' => Key::Quote,
+        '\\' => Key::BackSlash,
+        ',' => Key::Comma,
+        '.' => Key::Dot,
+        '/' => Key::Slash,
+        ' ' => Key::Space,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The character of a key press as reported by rdev (`Event::name`), in the current layout.
+/// Ctrl turns the character into a control character, which is mapped back, e.g. `\x1b` to `[`.
+/// Letters are lowercased.
+pub fn event_char(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    let c = match c as u32 {
+        0x01..=0x1a => char::from(b'a' + (c as u8 - 1)),
+        0x1b => '[',
+        0x1c => '\\',
+        0x1d => ']',
+        0x1f => '_',
+        _ => c,
+    };
+    Some(c.to_ascii_lowercase())
+}
+
+/// What a key binding does.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// 切换到英文 (第一个输入法).
+    ToEnglish,
+    /// 切换到第二个输入法.
+    ToOther,
+    /// 切换到下一个输入法.
+    Toggle,
+    /// 切换到指定的输入法.
+    Set(String),
+}
+
+impl Action {
+    /// Index of the engine a `set` action switches to, by name or index in `engines`,
+    /// see [`engine_index`]. None for other actions or unknown engines.
+    pub fn set_target(&self, engines: &[String]) -> Option<usize> {
+        match self {
+            Action::Set(engine) => engine_index(engines, engine),
+            _ => None,
+        }
+    }
+}
+
+/// Check that every `set` action in `bindings` names one of `engines`.
+pub fn check_bindings(bindings: &[Binding], engines: &[String]) -> Result<(), anyhow::Error> {
+    for binding in bindings {
+        if let Action::Set(engine) = &binding.action
+            && binding.action.set_target(engines).is_none()
+        {
+            let key = match binding.key {
+                KeySpec::Physical(key) => format!("{key:?}"),
+                KeySpec::Char(c) => c.to_string(),
+            };
+            return Err(anyhow::anyhow!(
+                "Key binding {key} sets unknown engine {engine:?}, expected one of {engines:?} or its index."
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A key binding, e.g. in config:
+///
+/// ```toml
+/// [[bindings]]
+/// modifiers = ["ctrl"]
+/// key = "LeftBracket"
+/// action = "to-english"
+/// ```
+///
+/// `key` is the name of a [`rdev::Key`] variant or a single character, see [`KeySpec`].
+/// `action` is one of `to-english`, `to-other`, `toggle` or `{ set = "<engine>" }`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Binding {
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    pub key: KeySpec,
+    pub action: Action,
+}
+
+impl Binding {
+    /// The default binding, `Ctrl + [` switches to English.
+    pub fn defaults() -> Vec<Binding> {
+        vec![Binding {
+            modifiers: vec![Modifier::Ctrl],
+            key: KeySpec::Physical(Key::LeftBracket),
+            action: Action::ToEnglish,
+        }]
+    }
+
+    /// The key combination as written in logs, e.g. `Ctrl+LeftBracket` or `Alt+a`.
+    pub fn combo(&self) -> String {
+        let key = match self.key {
+            KeySpec::Physical(key) => format!("{key:?}"),
+            KeySpec::Char(c) => c.to_string(),
+        };
+        self.modifiers
+            .iter()
+            .map(|m| format!("{m:?}"))
+            .chain([key])
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+
+    /// `name` is the character the press produced, if known, see [`event_char`].
+    fn matches(&self, key: Key, name: Option<&str>, modifiers: &[Modifier]) -> bool {
+        let key_matches = match self.key {
+            KeySpec::Physical(k) => k == key,
+            KeySpec::Char(c) => match name.and_then(event_char) {
+                Some(produced) => produced == c,
+                None => key_for_char(c) == Some(key),
+            },
+        };
+        key_matches
+            && self.modifiers.iter().all(|m| modifiers.contains(m))
+            && modifiers.iter().all(|m| self.modifiers.contains(m))
+    }
+}
+
+/// Tracks modifier state over key events and resolves bindings.
+pub struct KeyBindings {
+    bindings: Vec<Binding>,
+    /// 当前按下的修饰键.
+    pressed_modifiers: Vec<Key>,
+    /// 当前按下的非修饰键, 按住时 rdev 会重复发送 KeyPress, 松开前忽略.
+    pressed_keys: Vec<Key>,
+    /// 触发了绑定而被拦截的按键, 其重复和松开也要拦截.
+    consumed_keys: Vec<Key>,
+}
+
+impl KeyBindings {
+    pub fn new(bindings: Vec<Binding>) -> KeyBindings {
+        KeyBindings {
+            bindings,
+            pressed_modifiers: Vec::new(),
+            pressed_keys: Vec::new(),
+            consumed_keys: Vec::new(),
+        }
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Feed a key event, returns the action of the binding it triggers.
+    /// A binding triggers when its key is pressed while exactly its modifiers are held,
+    /// auto-repeated presses before the key is released are ignored.
+    /// `name` is the character produced by the press (`rdev::Event::name`), for [`KeySpec::Char`] bindings.
+    pub fn on_key(&mut self, key: Key, name: Option<&str>, pressed: bool) -> Option<&Action> {
+        if Modifier::of(key).is_some() {
+            self.pressed_modifiers.retain(|&k| k != key);
+            if pressed {
+                self.pressed_modifiers.push(key);
+            }
+            return None;
+        }
+        if !pressed {
+            self.pressed_keys.retain(|&k| k != key);
+            return None;
+        }
+        if self.pressed_keys.contains(&key) {
+            return None;
+        }
+        self.pressed_keys.push(key);
+        let modifiers: Vec<Modifier> = self
+            .pressed_modifiers
+            .iter()
+            .filter_map(|&k| Modifier::of(k))
+            .collect();
+        self.bindings
+            .iter()
+            .find(|binding| binding.matches(key, name, &modifiers))
+            .map(|binding| &binding.action)
+    }
+
+    /// Like [`KeyBindings::on_key`] when grabbing keys, also returns whether to consume the event
+    /// instead of passing it to the application.
+    /// The press triggering a binding is consumed, so are its auto-repeats and release.
+    pub fn on_grabbed_key(
+        &mut self,
+        key: Key,
+        name: Option<&str>,
+        pressed: bool,
+    ) -> (bool, Option<Action>) {
+        let consumed = self.consumed_keys.contains(&key);
+        if let Some(action) = self.on_key(key, name, pressed).cloned() {
+            if !consumed {
+                self.consumed_keys.push(key);
+            }
+            return (true, Some(action));
+        }
+        if !pressed {
+            self.consumed_keys.retain(|&k| k != key);
+        }
+        (consumed, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings() -> KeyBindings {
+        KeyBindings::new(vec![
+            Binding {
+                modifiers: vec![Modifier::Ctrl],
+                key: KeySpec::Physical(Key::LeftBracket),
+                action: Action::ToEnglish,
+            },
+            Binding {
+                modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+                key: KeySpec::Physical(Key::Space),
+                action: Action::Toggle,
+            },
+        ])
+    }
+
+    /// 依次输入 (键, 是否按下), 返回触发的动作.
+    fn feed(bindings: &mut KeyBindings, events: &[(Key, bool)]) -> Vec<Action> {
+        events
+            .iter()
+            .filter_map(|&(key, pressed)| bindings.on_key(key, None, pressed).cloned())
+            .collect()
+    }
+
+    #[test]
+    fn press_release_sequence() {
+        let mut bindings = bindings();
+        let actions = feed(
+            &mut bindings,
+            &[
+                (Key::ControlLeft, true),
+                (Key::LeftBracket, true),
+                (Key::LeftBracket, false),
+                (Key::ControlLeft, false),
+                (Key::ShiftLeft, true),
+                (Key::ControlRight, true),
+                (Key::Space, true),
+                (Key::Space, false),
+                (Key::ControlRight, false),
+                (Key::ShiftLeft, false),
+            ],
+        );
+        assert_eq!(actions, [Action::ToEnglish, Action::Toggle]);
+    }
+
+    #[test]
+    fn modifiers_must_match_exactly() {
+        let mut bindings = bindings();
+        // 没有修饰键, 多了 Shift, 以及松开 Ctrl 之后.
+        let actions = feed(
+            &mut bindings,
+            &[
+                (Key::LeftBracket, true),
+                (Key::LeftBracket, false),
+                (Key::ControlLeft, true),
+                (Key::ShiftLeft, true),
+                (Key::LeftBracket, true),
+                (Key::LeftBracket, false),
+                (Key::ShiftLeft, false),
+                (Key::ControlLeft, false),
+                (Key::Space, true),
+                (Key::Space, false),
+            ],
+        );
+        assert!(actions.is_empty(), "{actions:?}");
+    }
+
+    #[test]
+    fn auto_repeat_fires_once() {
+        let mut bindings = bindings();
+        let mut events = vec![(Key::ControlLeft, true)];
+        // 按住时 rdev 重复发送 KeyPress
+        events.extend([(Key::LeftBracket, true); 5]);
+        events.push((Key::LeftBracket, false));
+        events.push((Key::LeftBracket, true));
+        events.push((Key::LeftBracket, true));
+        assert_eq!(
+            feed(&mut bindings, &events),
+            [Action::ToEnglish, Action::ToEnglish]
+        );
+        // 修饰键的重复不影响
+        let actions = feed(
+            &mut bindings,
+            &[
+                (Key::LeftBracket, false),
+                (Key::ControlLeft, true),
+                (Key::ControlLeft, true),
+                (Key::LeftBracket, true),
+            ],
+        );
+        assert_eq!(actions, [Action::ToEnglish]);
+    }
+
+    #[test]
+    fn grab_consumes_only_the_triggering_key() {
+        let mut bindings = bindings();
+        let decisions: Vec<(bool, Option<Action>)> = [
+            (Key::ControlLeft, true),
+            // Ctrl+[ 被拦截, 包括重复和松开
+            (Key::LeftBracket, true),
+            (Key::LeftBracket, true),
+            (Key::LeftBracket, false),
+            // 没有绑定的按键照常传递
+            (Key::KeyA, true),
+            (Key::KeyA, false),
+            (Key::ControlLeft, false),
+            // 没有修饰键时 [ 也照常传递
+            (Key::LeftBracket, true),
+            (Key::LeftBracket, false),
+        ]
+        .into_iter()
+        .map(|(key, pressed)| bindings.on_grabbed_key(key, None, pressed))
+        .collect();
+        assert_eq!(
+            decisions,
+            [
+                (false, None),
+                (true, Some(Action::ToEnglish)),
+                (true, None),
+                (true, None),
+                (false, None),
+                (false, None),
+                (false, None),
+                (false, None),
+                (false, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn binding_strings_resolve_to_keys() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            key: KeySpec,
+        }
+        let spec = |s: &str| toml::from_str::<Wrapper>(&format!("key = {s:?}")).map(|w| w.key);
+        assert_eq!(
+            spec("LeftBracket").unwrap(),
+            KeySpec::Physical(Key::LeftBracket)
+        );
+        assert_eq!(spec("[").unwrap(), KeySpec::Char('['));
+        assert_eq!(spec("A").unwrap(), KeySpec::Char('a'));
+        assert!(spec("Bracket").is_err());
+        assert!(spec("").is_err());
+        for (s, key) in [
+            ("LeftBracket", Key::LeftBracket),
+            ("[", Key::LeftBracket),
+            ("a", Key::KeyA),
+            ("7", Key::Num7),
+            (";", Key::SemiColon),
+            (" ", Key::Space),
+            ("F5", Key::F5),
+        ] {
+            assert_eq!(spec(s).unwrap().physical(), Some(key), "{s:?}");
+        }
+        assert_eq!(spec("ü").unwrap().physical(), None);
+        // 写回配置时不变
+        let binding = Binding {
+            modifiers: vec![Modifier::Alt],
+            key: spec("[").unwrap(),
+            action: Action::Toggle,
+        };
+        assert_eq!(binding.combo(), "Alt+[");
+        assert_eq!(
+            toml::to_string(&binding).unwrap().lines().nth(1),
+            Some(r#"key = "[""#)
+        );
+    }
+
+    #[test]
+    fn char_bindings_follow_the_layout() {
+        let mut bindings = KeyBindings::new(vec![Binding {
+            modifiers: vec![Modifier::Ctrl],
+            key: KeySpec::Char('['),
+            action: Action::ToEnglish,
+        }]);
+        bindings.on_key(Key::ControlLeft, None, true);
+        // 德语布局中 `[` 不在 LeftBracket 的位置, Ctrl 把它变成 \x1b
+        assert_eq!(bindings.on_key(Key::LeftBracket, Some("ü"), true), None);
+        assert_eq!(
+            bindings.on_key(Key::Num8, Some("\x1b"), true),
+            Some(&Action::ToEnglish)
+        );
+        assert_eq!(event_char("\x01"), Some('a'));
+        assert_eq!(event_char("ab"), None);
+    }
+
+    #[test]
+    fn set_bindings_resolve_engines() {
+        let binding: Binding = toml::from_str(
+            r#"
+            modifiers = ["ctrl"]
+            key = "F2"
+            action = { set = "rime" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(binding.action, Action::Set("rime".to_owned()));
+        let engines = ["xkb:us::eng", "rime", "anthy"].map(str::to_owned);
+        assert_eq!(binding.action.set_target(&engines), Some(1));
+        assert_eq!(Action::Set("2".to_owned()).set_target(&engines), Some(2));
+        assert_eq!(Action::Set("mozc".to_owned()).set_target(&engines), None);
+        assert_eq!(Action::Toggle.set_target(&engines), None);
+        assert!(check_bindings(std::slice::from_ref(&binding), &engines).is_ok());
+
+        let unknown = Binding {
+            action: Action::Set("mozc".to_owned()),
+            ..binding
+        };
+        assert_eq!(
+            check_bindings(&[unknown], &engines)
+                .unwrap_err()
+                .to_string(),
+            r#"Key binding F2 sets unknown engine "mozc", expected one of ["xkb:us::eng", "rime", "anthy"] or its index."#
+        );
+    }
+}
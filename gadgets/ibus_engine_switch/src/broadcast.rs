@@ -0,0 +1,79 @@
+//! `subscribe` 命令的订阅者: 输入法改变时向每个订阅的连接写入一行输入法名.
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::debug;
+
+/// Write timeout of subscribed connections, a subscriber not reading in time is dropped
+/// instead of holding up switching.
+pub const SUBSCRIBER_TIMEOUT: Duration = Duration::from_millis(200);
+
+type Subscriber = Box<dyn Write + Send>;
+
+#[derive(Default)]
+struct Subscribers {
+    /// 最近一次发布的输入法, 新的订阅者先收到它.
+    engine: Option<String>,
+    subscribers: Vec<Subscriber>,
+}
+
+/// 写入一行, 失败时返回 false.
+fn send(subscriber: &mut Subscriber, engine: &str) -> bool {
+    match subscriber
+        .write_all(format!("{engine}\n").as_bytes())
+        .and_then(|()| subscriber.flush())
+    {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("Drop subscriber: {e}");
+            false
+        }
+    }
+}
+
+/// Connections subscribed to engine changes, shared by the switch server and the switcher.
+/// Each subscriber receives the engine in use, then every change, one engine name per line.
+#[derive(Clone, Default)]
+pub struct EngineBroadcast {
+    inner: Arc<Mutex<Subscribers>>,
+}
+
+impl EngineBroadcast {
+    /// Write the last published engine, if any, and every following change to `subscriber`.
+    pub fn subscribe(&self, subscriber: impl Write + Send + 'static) {
+        let mut inner = self.inner.lock().unwrap();
+        let mut subscriber: Subscriber = Box::new(subscriber);
+        if inner
+            .engine
+            .clone()
+            .is_none_or(|engine| send(&mut subscriber, &engine))
+        {
+            inner.subscribers.push(subscriber);
+        }
+    }
+
+    /// Record `engine` as the one in use and, if it changed, write it to every subscriber,
+    /// dropping those failing to write (disconnected, or not reading within their write timeout).
+    pub fn publish(&self, engine: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.engine.as_deref() == Some(engine) {
+            return;
+        }
+        inner.engine = Some(engine.to_owned());
+        inner
+            .subscribers
+            .retain_mut(|subscriber| send(subscriber, engine));
+    }
+
+    /// Number of subscribers.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,123 @@
+//! `--check`: 逐项检查切换服务器依赖的环境, 帮助排查切换不生效的原因.
+
+use crate::{
+    backend::{create_backend, current_engine},
+    config::{Backend, Config, HotkeyBackend},
+    fcitx5::FCITX5_REMOTE_PROGRAM,
+    hotkey::GlobalHotKeys,
+    ibus::IBUS_PROGRAM,
+    protocol::{self, Command},
+    server,
+};
+
+/// A single check, `Ok` holds what was found and `Err` why it failed.
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<String, String>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<String, String>) -> Check {
+        Check { name, result }
+    }
+}
+
+/// Run all checks for `config`. A running switch server passes the socket check if it replies,
+/// it only listens after its backend is ready.
+pub fn run_checks(config: &Config) -> Vec<Check> {
+    vec![
+        Check::new("backend", check_backend(config)),
+        Check::new("x11", check_x11()),
+        Check::new("socket", check_socket(config)),
+        Check::new("hotkeys", check_hotkeys(config)),
+    ]
+}
+
+/// 输入法框架的程序能找到, 并且能查询到当前输入法.
+fn check_backend(config: &Config) -> Result<String, String> {
+    let program = match config.backend {
+        Backend::Ibus => IBUS_PROGRAM,
+        Backend::Fcitx5 => FCITX5_REMOTE_PROGRAM,
+    };
+    let path = program.resolve()?;
+    let engine = current_engine(create_backend(config).as_ref())
+        .map_err(|e| format!("{} doesn't respond: {e}", path.display()))?;
+    Ok(format!("{}, current engine {engine}", path.display()))
+}
+
+/// 能连接 X 服务器并读取活动窗口.
+#[cfg(feature = "x11")]
+fn check_x11() -> Result<String, String> {
+    if crate::session::Session::detect() == crate::session::Session::PureWayland {
+        return Err(
+            "Wayland session without XWayland ($DISPLAY is not set), window focus tracking is unavailable"
+                .to_owned(),
+        );
+    }
+    match crate::get_active_window_id_directly() {
+        Ok(Some(id)) => Ok(format!("active window 0x{id:08x}")),
+        Ok(None) => Ok("no active window".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 没有 `x11` feature 时不监听窗口, 不算失败.
+#[cfg(not(feature = "x11"))]
+fn check_x11() -> Result<String, String> {
+    Ok("skipped, built without the x11 feature".to_owned())
+}
+
+/// 切换服务器的端口可以绑定, 或者已经运行的切换服务器能回复.
+fn check_socket(config: &Config) -> Result<String, String> {
+    match server::bind(config.bind_addr) {
+        Ok(listener) => Ok(listener
+            .local_addr()
+            .map_or_else(|e| e.to_string(), |addr| format!("{addr} is bindable"))),
+        Err(e) => match query_status(config) {
+            Ok(engine) => Ok(format!(
+                "switch server is running and ready, current engine {engine}"
+            )),
+            Err(_) => Err(format!(
+                "can't bind port {} and no switch server replies on it: {e}",
+                server::PORT
+            )),
+        },
+    }
+}
+
+/// `global_hotkey` 后端能注册所有绑定, 失败时列出每个组合键和原因.
+fn check_hotkeys(config: &Config) -> Result<String, String> {
+    if config.hotkey_backend != HotkeyBackend::GlobalHotkey {
+        return Ok(format!(
+            "skipped, hotkey_backend is {:?}",
+            config.hotkey_backend
+        ));
+    }
+    // 已注册的组合键不能再注册, 运行中的切换服务器会让每个绑定都失败.
+    if query_status(config).is_ok() {
+        return Ok(
+            "skipped, the running switch server holds the hotkeys, see its log for failures"
+                .to_owned(),
+        );
+    }
+    // 没有 X 服务器时 global_hotkey 的注册不会报错.
+    if std::env::var_os("DISPLAY").is_none() {
+        return Err("$DISPLAY is not set, global hotkeys need an X server or XWayland".to_owned());
+    }
+    let hotkeys = GlobalHotKeys::register(&config.bindings).map_err(|e| e.to_string())?;
+    match hotkeys.failures() {
+        [] => Ok(format!("{} binding(s) registered", config.bindings.len())),
+        failures => Err(failures
+            .iter()
+            .map(|f| format!("{}: {}", f.combo, f.reason))
+            .collect::<Vec<_>>()
+            .join("; ")),
+    }
+}
+
+fn query_status(config: &Config) -> Result<String, anyhow::Error> {
+    let mut client = server::connect(config.bind_addr)?;
+    client.set_read_timeout(Some(server::CLIENT_TIMEOUT))?;
+    protocol::write_frame(&mut client, &Command::Status.to_string())?;
+    Ok(protocol::read_message(&mut client)?)
+}
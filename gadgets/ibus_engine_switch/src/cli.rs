@@ -0,0 +1,2064 @@
+#[cfg(any(feature = "x11", feature = "wayland"))]
+use crate::focus_backend::{FocusedWindow, app_engine};
+#[cfg(feature = "x11")]
+use crate::{
+    ListenOptions, WatchHandle, WindowOrDesktopEvent, activate_window, engine::once_target,
+    listen_desktop_changes, poll_active_window_once, poll_current_desktop_once,
+};
+use crate::{
+    autoswitch::AutoswitchTimer,
+    backend::{
+        DryRun, InputMethodBackend, check_engines, create_backend, current_engine, needs_switch,
+        set_engine,
+    },
+    bindings::{Action, KeyBindings},
+    broadcast::EngineBroadcast,
+    check::run_checks,
+    config::{Backend, Config, EffectiveConfig, FocusBackendKind, HotkeyBackend},
+    daemon,
+    engine::engine_index,
+    event_log::{EventLog, LogEvent, Trigger},
+    focus::FocusHistory,
+    hook::SwitchHook,
+    hotkey::{self, GlobalHotKeys},
+    ibus,
+    lock::PidLock,
+    log_file,
+    metrics::Metrics,
+    notifier::Notifier,
+    protocol::{self, Command},
+    server,
+    session::Session,
+    shutdown::Shutdown,
+    state::{StartupEngine, State, default_status_file, startup_engine, write_atomic},
+    throttle::Throttle,
+};
+use gadgets_common::logging;
+use rdev::{
+    Event,
+    EventType::{KeyPress, KeyRelease},
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader},
+    net::TcpListener,
+    path::PathBuf,
+    process::exit,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{Span, debug, error, field, info, instrument, level_filters::LevelFilter, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{EnvFilter, fmt::writer::BoxMakeWriter};
+
+struct Switcher {
+    engines: Vec<String>,
+    /// 当前输入法在 `engines` 中的索引.
+    current: usize,
+    retries: usize,
+    /// 与监听快捷键的线程共享, 重新加载配置时替换.
+    key_bindings: Arc<Mutex<KeyBindings>>,
+    hotkey_backend: HotkeyBackend,
+    backend: Box<dyn InputMethodBackend>,
+    state_path: Option<PathBuf>,
+    notifier: Option<Notifier>,
+    /// 写入当前输入法名的文件, 供状态栏读取.
+    status_file: Option<PathBuf>,
+    /// 输入法改变后运行的命令.
+    on_switch: Option<SwitchHook>,
+    /// 切换窗口后自动切换到英文的计时, None 表示不自动切换.
+    autoswitch: Option<AutoswitchTimer>,
+    /// 是否把弹出的菜单等临时窗口获得焦点视为切换窗口.
+    #[cfg_attr(not(feature = "x11"), allow(dead_code))]
+    focus_transient_windows: bool,
+    /// 最近获得焦点的窗口, 由监听窗口变化的线程发来.
+    #[cfg_attr(not(feature = "x11"), allow(dead_code))]
+    focus_history: FocusHistory,
+    /// 是否监听所有 X 屏幕的活动窗口.
+    #[cfg_attr(not(feature = "x11"), allow(dead_code))]
+    all_screens: bool,
+    /// 新获得焦点的窗口缺少 `WM_CLASS` 或标题时重新读取的次数.
+    #[cfg_attr(not(feature = "x11"), allow(dead_code))]
+    focus_settle_retries: usize,
+    /// 重新读取窗口属性的间隔.
+    #[cfg_attr(not(feature = "x11"), allow(dead_code))]
+    focus_settle_delay: Duration,
+    /// 是否监听输入法框架中输入法的变化, 以同步外部的切换.
+    watch_engine: bool,
+    /// 切换到工作区时使用的输入法, 值为 `engines` 中的索引.
+    desktop_engines: HashMap<u32, usize>,
+    /// 应用获得焦点时使用的输入法, 键为 app_id (X11 下为 `WM_CLASS` 的 class), 值为 `engines` 中的索引.
+    #[cfg_attr(not(any(feature = "x11", feature = "wayland")), allow(dead_code))]
+    app_engines: HashMap<String, usize>,
+    /// 监听获得焦点的窗口的方式.
+    focus_backend: FocusBackendKind,
+    /// 限制客户端切换命令的频率.
+    throttle: Throttle,
+    /// 运行统计.
+    metrics: Metrics,
+    /// 当前的配置, 重新加载时用来判断哪些设置需要重启.
+    config: Config,
+    /// 收到 SIGHUP 时重新读取的配置文件.
+    config_path: Option<PathBuf>,
+    /// 使用 global-hotkey 时注册的快捷键, 重新加载配置时重新注册.
+    global_hotkeys: Arc<Mutex<Option<GlobalHotKeys>>>,
+    /// `subscribe` 的客户端.
+    broadcast: EngineBroadcast,
+    /// 记录焦点变化和输入法切换的文件.
+    event_log: Option<EventLog>,
+    /// 正在处理的请求的来源, 记录在 `event_log` 中.
+    trigger: Trigger,
+}
+
+/// 打开配置的 `event_log`, 失败时只警告, 不记录事件.
+fn event_log(config: &Config) -> Option<EventLog> {
+    let path = config.event_log.clone()?;
+    EventLog::open(path.clone(), config.event_log_max_bytes)
+        .inspect_err(|e| warn!("Failed to open event log {}: {e}", path.display()))
+        .ok()
+}
+
+/// 工作区对应的输入法在 `engines` 中的索引, 忽略未知的输入法.
+fn desktop_engines(config: &Config) -> HashMap<u32, usize> {
+    config
+        .desktop_engines
+        .iter()
+        .filter_map(
+            |(&desktop, engine)| match engine_index(&config.engines, engine) {
+                Some(idx) => Some((desktop, idx)),
+                None => {
+                    warn!("Unknown engine for workspace {desktop}: {engine}");
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// 应用对应的输入法在 `engines` 中的索引, 忽略未知的输入法.
+fn app_engines(config: &Config) -> HashMap<String, usize> {
+    config
+        .app_engines
+        .iter()
+        .filter_map(
+            |(app, engine)| match engine_index(&config.engines, engine) {
+                Some(idx) => Some((app.clone(), idx)),
+                None => {
+                    warn!("Unknown engine for application {app}: {engine}");
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+impl Switcher {
+    /// `dry_run` 时不真正切换输入法, 也不保存状态.
+    fn new(config: &Config, dry_run: bool) -> Result<Switcher, anyhow::Error> {
+        let mut backend = create_backend(config);
+        // --dry-run 不需要真正切换, 只警告.
+        match backend.check_available() {
+            Err(e) if dry_run => warn!("{e}"),
+            result => result?,
+        }
+        if dry_run {
+            backend = Box::new(DryRun::new(backend));
+        }
+        let state_path = State::default_path().filter(|_| !dry_run);
+        Switcher::with_backend(config, backend, state_path, dry_run)
+    }
+
+    /// 使用 `backend` 切换, 状态保存到 `state_path`, 见 [`Switcher::new`].
+    fn with_backend(
+        config: &Config,
+        backend: Box<dyn InputMethodBackend>,
+        state_path: Option<PathBuf>,
+        dry_run: bool,
+    ) -> Result<Switcher, anyhow::Error> {
+        check_engines(config, backend.as_ref())?;
+        let state = state_path.as_deref().map(State::load).unwrap_or_default();
+        let mut s = Switcher {
+            engines: config.engines.clone(),
+            current: 0,
+            retries: config.retries,
+            key_bindings: Arc::new(Mutex::new(KeyBindings::new(config.bindings.clone()))),
+            hotkey_backend: config.hotkey_backend,
+            backend,
+            state_path,
+            notifier: config.notifications.then(Notifier::default),
+            status_file: config.status_file.then(|| {
+                config
+                    .status_file_path
+                    .clone()
+                    .unwrap_or_else(default_status_file)
+            }),
+            on_switch: config
+                .on_switch_command
+                .as_deref()
+                .and_then(SwitchHook::parse),
+            autoswitch: config
+                .autoswitch
+                .then(|| AutoswitchTimer::new(Duration::from_millis(config.autoswitch_delay_ms))),
+            focus_transient_windows: config.focus_transient_windows,
+            focus_history: FocusHistory::new(config.focus_history_depth),
+            all_screens: config.all_screens,
+            focus_settle_retries: config.focus_settle_retries,
+            focus_settle_delay: Duration::from_millis(config.focus_settle_delay_ms),
+            watch_engine: config.backend == Backend::Ibus,
+            desktop_engines: desktop_engines(config),
+            app_engines: app_engines(config),
+            focus_backend: config.focus_backend,
+            throttle: Throttle::new(Duration::from_millis(config.min_switch_interval_ms)),
+            metrics: Metrics::new(config.engines.len()),
+            config: config.clone(),
+            config_path: None,
+            global_hotkeys: Arc::new(Mutex::new(None)),
+            broadcast: EngineBroadcast::default(),
+            event_log: event_log(config),
+            trigger: Trigger::Startup,
+        };
+        // 查询不到当前输入法时切换也不会成功, 不启动切换服务器.
+        let current = match current_engine(s.backend.as_ref()) {
+            Ok(engine) => Some(engine),
+            Err(e) if dry_run => {
+                warn!("Failed to query the current engine: {e}");
+                None
+            }
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to query the current engine, is the input method framework running? {e}"
+                ));
+            }
+        };
+        match startup_engine(
+            &s.engines,
+            state.engine,
+            current.as_deref(),
+            config.apply_on_start,
+        ) {
+            StartupEngine::Apply(idx) => s.switch_engine_to(idx),
+            StartupEngine::Adopt(idx) => {
+                info!("Adopt the current engine {}.", s.engines[idx]);
+                s.set_current(idx);
+            }
+            StartupEngine::Unknown => {
+                info!(
+                    "Current engine {} is not configured, assume {}.",
+                    current.as_deref().unwrap_or("unknown"),
+                    s.engines[0]
+                );
+                s.set_current(0);
+            }
+        }
+        Ok(s)
+    }
+
+    /// 重新读取配置文件并应用, 读取失败时保留当前配置, 返回是否读取成功.
+    fn reload_config(&mut self) -> bool {
+        let Some(path) = &self.config_path else {
+            warn!("No config file to reload.");
+            return false;
+        };
+        match Config::load(path) {
+            Ok(config) => {
+                self.apply_config(config.with_overrides());
+                true
+            }
+            Err(e) => {
+                error!(
+                    "Failed to reload config {}, keep the current one: {e}",
+                    path.display()
+                );
+                false
+            }
+        }
+    }
+
+    /// 应用重新加载的配置, 需要重启才能生效的设置只警告.
+    /// 当前输入法仍在新的 `engines` 中时保持不变, 否则切换到英文.
+    fn apply_config(&mut self, config: Config) {
+        for setting in self.config.restart_required(&config) {
+            warn!("Changing {setting} requires restarting the switch server.");
+        }
+        if let Err(e) = check_engines(&config, self.backend.as_ref()) {
+            error!("Keep the current config: {e}");
+            return;
+        }
+        let current = self.engines[self.current].clone();
+        self.metrics.remap_engines(&self.engines, &config.engines);
+        let kept = config.engines.iter().position(|e| *e == current);
+        self.current = kept.unwrap_or(0);
+        self.engines = config.engines.clone();
+        self.retries = config.retries;
+        *self.key_bindings.lock().unwrap() = KeyBindings::new(config.bindings.clone());
+        if let Some(hotkeys) = self.global_hotkeys.lock().unwrap().as_mut()
+            && let Err(e) = hotkeys.reregister(&config.bindings)
+        {
+            error!("Failed to re-register global hotkeys: {e}");
+        }
+        if config.notifications != self.notifier.is_some() {
+            self.notifier = config.notifications.then(Notifier::default);
+        }
+        self.on_switch = config
+            .on_switch_command
+            .as_deref()
+            .and_then(SwitchHook::parse);
+        if let Some(timer) = &mut self.autoswitch
+            && config.autoswitch_delay_ms != self.config.autoswitch_delay_ms
+        {
+            *timer = AutoswitchTimer::new(Duration::from_millis(config.autoswitch_delay_ms));
+        }
+        if config.min_switch_interval_ms != self.config.min_switch_interval_ms {
+            self.throttle = Throttle::new(Duration::from_millis(config.min_switch_interval_ms));
+        }
+        self.desktop_engines = desktop_engines(&config);
+        self.app_engines = app_engines(&config);
+        self.event_log = event_log(&config);
+        self.config = config;
+        info!("Config reloaded, engines: {:?}.", self.engines);
+        if kept.is_none() {
+            info!("Engine {current} is no longer configured.");
+            self.switch_engine(Some(true));
+        }
+    }
+
+    /// 切换输入法, Some(true) 切换到英文 (第一个输入法), Some(false) 切换到第二个输入法,
+    /// 输入 None 则默认切换到下一个输入法.
+    fn switch_engine(&mut self, english: Option<bool>) {
+        let idx = match english {
+            Some(true) => 0,
+            Some(false) => 1.min(self.engines.len() - 1),
+            None => (self.current + 1) % self.engines.len(),
+        };
+        self.switch_engine_to(idx);
+    }
+
+    /// 自动切换 (工作区, 应用和 autoswitch) 时使用: 缓存的当前输入法已经是 `idx` 时不调用后端,
+    /// 例如每次切换窗口后都切换到英文时. 只有监听 IBus 的信号时缓存才与实际同步, 否则总是调用后端.
+    /// 用户主动的切换总是调用 [`Switcher::switch_engine_to`], 以纠正可能过时的缓存.
+    fn switch_engine_auto(&mut self, idx: usize) {
+        if needs_switch(
+            &self.engines[self.current],
+            &self.engines[idx],
+            !self.watch_engine,
+        ) {
+            self.switch_engine_to(idx);
+        } else {
+            debug!("Engine is already {}, skip switching.", self.engines[idx]);
+        }
+    }
+
+    #[instrument(skip(self), fields(engine = %self.engines[idx], outcome = field::Empty))]
+    fn switch_engine_to(&mut self, idx: usize) {
+        let engine = &self.engines[idx];
+        let start = Instant::now();
+        let result = set_engine(self.backend.as_ref(), engine, self.retries);
+        let span = Span::current();
+        match result {
+            Ok(actual) if actual == *engine => {
+                span.record("outcome", "switched");
+                info!("Switch to {engine} in {:?}.", start.elapsed());
+                self.set_current(idx);
+            }
+            Ok(actual) => {
+                span.record("outcome", "mismatch");
+                error!(
+                    "Failed to switch to {engine} after {} retries, current engine is {actual}.",
+                    self.retries
+                );
+                // 和实际的输入法保持一致.
+                if let Some(actual_idx) = self.engines.iter().position(|e| *e == actual) {
+                    self.set_current(actual_idx);
+                }
+            }
+            Err(e) => {
+                span.record("outcome", "unverified");
+                warn!("Can not verify switching to {engine}: {e}");
+                self.set_current(idx);
+            }
+        }
+    }
+
+    /// 输入法被外部改变 (例如 IBus 重启后恢复默认输入法) 时同步内部状态, 而不是改回去.
+    fn reconcile_engine(&mut self, engine: &str) {
+        let current = self.current;
+        if self.engines[current] == engine {
+            return;
+        }
+        match self.engines.iter().position(|e| e == engine) {
+            Some(idx) => {
+                info!(
+                    "Engine changed to {engine} externally, reconcile from {}.",
+                    self.engines[current]
+                );
+                self.set_current(idx);
+            }
+            None => info!("Engine changed to {engine} externally, which is not configured."),
+        }
+    }
+
+    /// 记录当前输入法, 并保存到状态文件.
+    fn set_current(&mut self, idx: usize) {
+        let last = std::mem::replace(&mut self.current, idx);
+        if last != idx {
+            self.metrics.record_switch(idx);
+            self.log_event(&LogEvent::Switch {
+                from: &self.engines[last],
+                to: &self.engines[idx],
+                trigger: self.trigger,
+            });
+        }
+        if last != idx
+            && let Some(notifier) = &mut self.notifier
+        {
+            notifier.notify_engine(&self.engines[idx]);
+        }
+        if last != idx
+            && let Some(hook) = &self.on_switch
+        {
+            hook.run(&self.engines[idx]);
+        }
+        self.broadcast.publish(&self.engines[idx]);
+        if let Some(path) = &self.state_path
+            && let Err(e) = (State { engine: idx }).save(path)
+        {
+            warn!("Failed to save state to {}: {e}", path.display());
+        }
+        if let Some(path) = &self.status_file
+            && let Err(e) = write_atomic(path, format!("{}\n", self.engines[idx]).as_bytes())
+        {
+            warn!("Failed to write status file {}: {e}", path.display());
+        }
+    }
+
+    /// 写入 `event_log`, 失败时只警告.
+    fn log_event(&self, event: &LogEvent) {
+        if let Some(log) = &self.event_log
+            && let Err(e) = log.append(event)
+        {
+            warn!("Failed to write event log {}: {e}", log.path().display());
+        }
+    }
+
+    fn apply_action(&mut self, action: Action) {
+        info!("Key binding action: {action:?}.");
+        match action {
+            Action::ToEnglish => {
+                self.cancel_autoswitch();
+                self.switch_engine(Some(true));
+            }
+            Action::ToOther => {
+                self.cancel_autoswitch();
+                self.switch_engine(Some(false));
+            }
+            Action::Toggle => self.toggle(),
+            Action::Set(ref engine) => match action.set_target(&self.engines) {
+                Some(idx) => {
+                    self.cancel_autoswitch();
+                    self.switch_engine_to(idx);
+                }
+                None => warn!("Unknown engine in key binding: {engine}"),
+            },
+        }
+    }
+
+    /// 取消待执行的自动切换, 返回是否有待执行的自动切换.
+    fn cancel_autoswitch(&mut self) -> bool {
+        self.autoswitch.as_mut().is_some_and(|timer| timer.cancel())
+    }
+
+    fn toggle(&mut self) {
+        // 自动切换到英文之前手动切换, 说明用户想要的是另一个输入法.
+        if self.cancel_autoswitch() {
+            self.switch_engine(Some(false));
+        } else {
+            self.switch_engine(None);
+        }
+    }
+
+    /// 处理来自客户端的命令, 返回回复的内容.
+    fn handle_command(&mut self, command: Command) -> String {
+        if command.switches_engine() && !self.throttle.allow(Instant::now()) {
+            debug!(
+                "Throttle {command}, less than {:?} since the last switch command.",
+                self.throttle.min_interval()
+            );
+            return "throttled".to_owned();
+        }
+        match command {
+            Command::Toggle => {
+                self.toggle();
+                "ok".to_owned()
+            }
+            Command::English | Command::Other => {
+                self.cancel_autoswitch();
+                self.switch_engine(Some(command == Command::English));
+                "ok".to_owned()
+            }
+            // subscribe 由切换服务器交给 broadcast, 不会到达这里.
+            Command::Status | Command::Subscribe => self.engines[self.current].clone(),
+            Command::Set(engine) => match self.engine_index(&engine) {
+                Some(idx) => {
+                    self.cancel_autoswitch();
+                    self.switch_engine_to(idx);
+                    "ok".to_owned()
+                }
+                None => {
+                    warn!("Unknown engine: {engine}");
+                    "unknown engine".to_owned()
+                }
+            },
+            Command::PrevWindow => self.activate_previous_window(),
+            Command::Metrics => self.metrics.snapshot(&self.engines).to_reply(),
+            Command::Reload => {
+                info!("Reload requested by client.");
+                self.trigger = Trigger::Reload;
+                if self.reload_config() {
+                    "ok".to_owned()
+                } else {
+                    "failed to reload config".to_owned()
+                }
+            }
+            // 由切换服务器在回复之后退出.
+            Command::Quit => {
+                info!("Quit requested by client.");
+                "ok".to_owned()
+            }
+        }
+    }
+
+    /// 激活焦点历史中的上一个窗口, 返回给客户端的回复.
+    #[cfg(feature = "x11")]
+    fn activate_previous_window(&self) -> String {
+        let previous = self.focus_history.previous();
+        match previous {
+            Some(window) => match activate_window(window) {
+                Ok(()) => "ok".to_owned(),
+                Err(e) => {
+                    warn!("Failed to activate window {window}: {e}");
+                    "failed to activate window".to_owned()
+                }
+            },
+            None => "no previous window".to_owned(),
+        }
+    }
+
+    /// 没有 `x11` feature 时不记录焦点历史.
+    #[cfg(not(feature = "x11"))]
+    fn activate_previous_window(&self) -> String {
+        "prev-window needs the x11 feature".to_owned()
+    }
+
+    fn engine_index(&self, engine: &str) -> Option<usize> {
+        engine_index(&self.engines, engine)
+    }
+
+    /// 切换到工作区 `desktop` 对应的输入法, 没有配置时保持不变.
+    #[cfg(feature = "x11")]
+    #[instrument(skip(self))]
+    fn on_desktop_change(&mut self, desktop: u32) {
+        if let Some(&idx) = self.desktop_engines.get(&desktop) {
+            info!(
+                "Workspace {desktop} becomes current, switch to {}.",
+                self.engines[idx]
+            );
+            // 工作区的输入法优先于切换窗口后的自动切换.
+            self.cancel_autoswitch();
+            self.switch_engine_auto(idx);
+        }
+    }
+
+    /// 处理一个请求.
+    fn handle(&mut self, command: SwitchCommand) {
+        self.trigger = command.trigger();
+        match command {
+            SwitchCommand::Action(action) => self.apply_action(action),
+            SwitchCommand::Client(command, reply) => {
+                let _ = reply.send(self.handle_command(command));
+            }
+            SwitchCommand::Reconcile(engine) => self.reconcile_engine(&engine),
+            #[cfg(feature = "x11")]
+            SwitchCommand::Focus(history, id, window) => self.on_focus(history, id, &window),
+            #[cfg(feature = "wayland")]
+            SwitchCommand::Toplevel(window) => {
+                self.metrics.record_focus();
+                self.log_focus(None, &window);
+                self.on_window_focus(&window);
+            }
+            #[cfg(feature = "x11")]
+            SwitchCommand::Desktop(desktop) => self.on_desktop_change(desktop),
+            SwitchCommand::Reload => {
+                self.reload_config();
+            }
+        }
+    }
+
+    /// 记录焦点历史, 切换到窗口时见 [`Switcher::on_window_focus`].
+    /// id 为 0 表示没有窗口获得焦点 (例如桌面), 按 `on_no_window` 保持或切换到英文.
+    #[cfg(feature = "x11")]
+    fn on_focus(&mut self, history: FocusHistory, id: u32, window: &FocusedWindow) {
+        self.metrics.record_focus();
+        self.log_focus(Some(id), window);
+        self.focus_history = history;
+        if id != 0 {
+            self.on_window_focus(window);
+        } else if let Some(idx) = self.config.on_no_window.target() {
+            self.cancel_autoswitch();
+            debug!("No window focused, switch to {}.", self.engines[idx]);
+            self.switch_engine_auto(idx);
+        }
+    }
+
+    #[cfg(any(feature = "x11", feature = "wayland"))]
+    fn log_focus(&self, id: Option<u32>, window: &FocusedWindow) {
+        self.log_event(&LogEvent::Focus {
+            window: id,
+            wm_class: window.app_id.as_deref(),
+            title: window.title.as_deref(),
+        });
+    }
+
+    /// 切换到应用对应的输入法, 没有配置时开始自动切换的计时.
+    #[cfg(any(feature = "x11", feature = "wayland"))]
+    fn on_window_focus(&mut self, window: &FocusedWindow) {
+        if let Some(app_id) = &window.app_id
+            && let Some(idx) = app_engine(&self.app_engines, app_id)
+        {
+            // 应用的输入法优先于自动切换.
+            self.cancel_autoswitch();
+            debug!("{app_id} focused, its engine is {}.", self.engines[idx]);
+            self.switch_engine_auto(idx);
+            return;
+        }
+        if let Some(timer) = &mut self.autoswitch {
+            timer.focus(Instant::now());
+        }
+    }
+
+    /// 依次处理 `commands`, 自动切换的计时结束且没有被取消时切换到英文.
+    fn run(mut self, commands: Receiver<SwitchCommand>) -> ! {
+        loop {
+            let timeout = self
+                .autoswitch
+                .as_ref()
+                .and_then(|timer| timer.timeout(Instant::now()));
+            let received = match timeout {
+                Some(timeout) => commands.recv_timeout(timeout),
+                None => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(command) => self.handle(command),
+                Err(RecvTimeoutError::Timeout) => {
+                    let due = self
+                        .autoswitch
+                        .as_mut()
+                        .is_some_and(|timer| timer.tick(Instant::now()));
+                    if due {
+                        info!("Auto switch to English.");
+                        self.trigger = Trigger::Autoswitch;
+                        self.switch_engine_auto(0);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => unreachable!("listen holds a sender"),
+            }
+        }
+    }
+
+    /// 启动监听快捷键, 窗口和客户端的线程, 它们都把请求发送到 `commands_tx`,
+    /// 然后在当前线程中依次处理, 不会同时切换输入法.
+    fn listen(
+        self,
+        listener: TcpListener,
+        commands_tx: Sender<SwitchCommand>,
+        commands: Receiver<SwitchCommand>,
+    ) -> ! {
+        if self.watch_engine {
+            let tx = commands_tx.clone();
+            thread::spawn(move || {
+                ibus::listen_engine_changes(|engine| {
+                    let _ = tx.send(SwitchCommand::Reconcile(engine.to_owned()));
+                })
+            });
+        }
+
+        let session = Session::detect();
+        let watch_x11_focus = self.focus_backend == FocusBackendKind::X11;
+        // 不自动切换时也要记录焦点历史, 供 prev-window 使用.
+        #[cfg(feature = "x11")]
+        if session == Session::PureWayland {
+            if watch_x11_focus {
+                warn!(
+                    "Wayland session without XWayland ($DISPLAY is not set), window focus tracking is unavailable: autoswitch, app_engines, desktop_engines and prev-window are ignored. Set focus_backend = \"wlroots\" on wlroots based compositors."
+                );
+            } else {
+                warn!(
+                    "Wayland session without XWayland ($DISPLAY is not set), desktop_engines and prev-window are ignored."
+                );
+            }
+        } else {
+            use tracing::info_span;
+            let watch_desktop = !self.desktop_engines.is_empty();
+            if watch_x11_focus {
+                let options = ListenOptions {
+                    include_transient: self.focus_transient_windows,
+                    history_depth: self.focus_history.depth(),
+                    all_screens: self.all_screens,
+                    settle_retries: self.focus_settle_retries,
+                    settle_delay: self.focus_settle_delay,
+                    ..ListenOptions::default()
+                };
+                let tx = commands_tx.clone();
+                thread::spawn(move || {
+                    // 窗口和工作区共用一个连接, 变化按发生的顺序处理.
+                    let result = WatchHandle::new(options, watch_desktop, |event| {
+                        if let WindowOrDesktopEvent::Focus { current, .. } = &event {
+                            let _span = info_span!(
+                                "focus",
+                                window = current.id,
+                                wm_class = ?current.class.as_ref().map(|(_, class)| class),
+                            )
+                            .entered();
+                            debug!("Window focused.");
+                        }
+                        if let Some(command) = watch_command(event) {
+                            let _ = tx.send(command);
+                        }
+                    })
+                    .and_then(WatchHandle::run);
+                    if let Err(e) = result {
+                        error!("Stop listening window and workspace changes: {e}");
+                    }
+                });
+            } else if watch_desktop {
+                let tx = commands_tx.clone();
+                thread::spawn(move || {
+                    let result = listen_desktop_changes(|last, current| {
+                        if last.is_some() {
+                            let _ = tx.send(SwitchCommand::Desktop(current));
+                        }
+                    });
+                    if let Err(e) = result {
+                        error!("Stop listening workspace changes: {e}");
+                    }
+                });
+            }
+        }
+        if !watch_x11_focus {
+            listen_toplevels(commands_tx.clone());
+        }
+        #[cfg(not(feature = "x11"))]
+        if (watch_x11_focus && (self.autoswitch.is_some() || !self.app_engines.is_empty()))
+            || !self.desktop_engines.is_empty()
+        {
+            // 没有窗口监听, 收不到焦点和工作区的变化.
+            warn!(
+                "Built without the x11 feature, autoswitch, app_engines and desktop_engines are ignored."
+            );
+        }
+
+        let tx = commands_tx.clone();
+        let broadcast = self.broadcast.clone();
+        thread::spawn(move || {
+            match listener.local_addr() {
+                Ok(addr) => info!("Switch server started on {addr}."),
+                Err(_) => info!("Switch server started."),
+            }
+            server::serve(listener, broadcast, client_handler(tx));
+        });
+
+        let hotkey_backend = self.hotkey_backend;
+        let key_bindings = Arc::clone(&self.key_bindings);
+        let global_hotkeys = Arc::clone(&self.global_hotkeys);
+        let tx = commands_tx.clone();
+        // 没有 grab feature 时 rdev-grab 退回到 rdev.
+        let grabs = cfg!(feature = "grab") && hotkey_backend == HotkeyBackend::RdevGrab;
+        if session == Session::PureWayland && !grabs {
+            // global-hotkey 和 rdev 都通过 X 服务器获取按键.
+            warn!(
+                "Key bindings need the X server unless hotkey_backend is rdev-grab, they are ignored. Bind system shortcuts to the client commands instead."
+            );
+        } else {
+            thread::spawn(move || listen_hotkeys(hotkey_backend, key_bindings, global_hotkeys, tx));
+        }
+
+        self.run(commands)
+    }
+}
+
+/// [`WatchHandle`] 报告的变化对应的请求.
+#[cfg(feature = "x11")]
+fn watch_command(event: WindowOrDesktopEvent) -> Option<SwitchCommand> {
+    match event {
+        WindowOrDesktopEvent::Focus {
+            history, current, ..
+        } => Some(SwitchCommand::Focus(
+            history,
+            current.id,
+            current.as_ref().into(),
+        )),
+        // 首次回调是启动时所在的工作区, 不覆盖恢复的输入法.
+        WindowOrDesktopEvent::Desktop { last, current } => {
+            last.is_some().then_some(SwitchCommand::Desktop(current))
+        }
+    }
+}
+
+/// 发送给持有 [`Switcher`] 的线程的请求, 改变输入法的操作都经过这里, 按到达的顺序依次执行.
+enum SwitchCommand {
+    /// 快捷键触发的动作.
+    Action(Action),
+    /// 客户端的命令, 回复发送到附带的 `Sender`.
+    Client(Command, Sender<String>),
+    /// 输入法被外部改变.
+    Reconcile(String),
+    /// 活动窗口改变, 附带最新的焦点历史, 窗口 id 和窗口.
+    #[cfg(feature = "x11")]
+    Focus(FocusHistory, u32, FocusedWindow),
+    /// wlroots 混成器中获得焦点的窗口改变.
+    #[cfg(feature = "wayland")]
+    Toplevel(FocusedWindow),
+    /// 工作区改变.
+    #[cfg(feature = "x11")]
+    Desktop(u32),
+    /// 重新加载配置.
+    Reload,
+}
+
+impl SwitchCommand {
+    /// 由这个请求引起的切换在 `event_log` 中记录的来源.
+    fn trigger(&self) -> Trigger {
+        match self {
+            SwitchCommand::Action(_) => Trigger::Binding,
+            SwitchCommand::Client(..) => Trigger::Client,
+            SwitchCommand::Reconcile(_) => Trigger::External,
+            #[cfg(feature = "x11")]
+            SwitchCommand::Focus(..) => Trigger::Focus,
+            #[cfg(feature = "wayland")]
+            SwitchCommand::Toplevel(_) => Trigger::Focus,
+            #[cfg(feature = "x11")]
+            SwitchCommand::Desktop(_) => Trigger::Desktop,
+            SwitchCommand::Reload => Trigger::Reload,
+        }
+    }
+}
+
+/// 在连接线程中把客户端的命令发送到 `commands`, 等待持有 [`Switcher`] 的线程回复.
+fn client_handler(
+    commands: Sender<SwitchCommand>,
+) -> impl Fn(Command) -> Option<String> + Send + Sync + 'static {
+    move |command| {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        commands
+            .send(SwitchCommand::Client(command, reply_tx))
+            .ok()?;
+        reply_rx.recv().ok()
+    }
+}
+
+/// 在新线程中通过 wlroots 的协议监听获得焦点的窗口, 发送到 `commands`.
+#[cfg(feature = "wayland")]
+fn listen_toplevels(commands: Sender<SwitchCommand>) {
+    use crate::{focus_backend::FocusBackend, wayland::WlrootsFocus};
+    thread::spawn(move || {
+        let result = WlrootsFocus::connect().and_then(|mut focus| {
+            focus.listen(&mut |window| {
+                tracing::debug!(app_id = ?window.app_id, "Toplevel focused.");
+                let _ = commands.send(SwitchCommand::Toplevel(window.clone()));
+            })
+        });
+        if let Err(e) = result {
+            error!("Stop listening focused toplevels: {e:#}");
+        }
+    });
+}
+
+#[cfg(not(feature = "wayland"))]
+fn listen_toplevels(_commands: Sender<SwitchCommand>) {
+    error!("focus_backend wlroots needs the wayland feature, windows are not watched.");
+}
+
+/// 监听快捷键, 把触发的动作发送到 `commands`, 监听失败时退出进程.
+fn listen_hotkeys(
+    hotkey_backend: HotkeyBackend,
+    key_bindings: Arc<Mutex<KeyBindings>>,
+    global_hotkeys: Arc<Mutex<Option<GlobalHotKeys>>>,
+    commands: Sender<SwitchCommand>,
+) -> ! {
+    match hotkey_backend {
+        HotkeyBackend::GlobalHotkey => {
+            let bindings = key_bindings.lock().unwrap().bindings().to_vec();
+            let hotkeys = GlobalHotKeys::register(&bindings).unwrap_or_else(|e| {
+                error!("Failed to register global hotkeys: {e}");
+                exit(1);
+            });
+            match hotkeys.failures().len() {
+                0 => info!("Global hotkeys registered."),
+                failed => warn!(
+                    "Global hotkeys registered, {failed} of {} binding(s) failed.",
+                    bindings.len()
+                ),
+            }
+            *global_hotkeys.lock().unwrap() = Some(hotkeys);
+            loop {
+                // 等待时不持有锁, 重新加载配置时可以重新注册.
+                let id = hotkey::next_pressed().unwrap_or_else(|e| {
+                    error!("Stop listening global hotkeys: {e}");
+                    exit(1);
+                });
+                let action = global_hotkeys
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|hotkeys| hotkeys.action(id).cloned());
+                if let Some(action) = action {
+                    let _ = commands.send(SwitchCommand::Action(action));
+                }
+            }
+        }
+        HotkeyBackend::Rdev => listen_rdev(key_bindings, commands),
+        #[cfg(feature = "grab")]
+        HotkeyBackend::RdevGrab => {
+            if let Err(e) =
+                rdev::grab(move |event| on_grabbed_event(&key_bindings, &commands, event))
+            {
+                error!(
+                    "Failed to grab keys, check the permissions of /dev/input and /dev/uinput: {e:?}"
+                );
+                exit(1);
+            }
+            unreachable!();
+        }
+        #[cfg(not(feature = "grab"))]
+        HotkeyBackend::RdevGrab => {
+            error!("hotkey_backend rdev-grab needs the grab feature, fall back to rdev.");
+            listen_rdev(key_bindings, commands)
+        }
+    }
+}
+
+fn listen_rdev(key_bindings: Arc<Mutex<KeyBindings>>, commands: Sender<SwitchCommand>) -> ! {
+    if let Err(e) = rdev::listen(move |event| on_rdev_event(&key_bindings, &commands, event)) {
+        error!("Failed to listen keys: {e:?}");
+        exit(1);
+    }
+    unreachable!();
+}
+
+/// rdev 拦截按键时的回调, 返回 None 表示拦截该事件.
+#[cfg(feature = "grab")]
+fn on_grabbed_event(
+    key_bindings: &Mutex<KeyBindings>,
+    commands: &Sender<SwitchCommand>,
+    event: Event,
+) -> Option<Event> {
+    let (key, pressed) = match event.event_type {
+        KeyPress(key) => (key, true),
+        KeyRelease(key) => (key, false),
+        _ => return Some(event),
+    };
+    let (consume, action) =
+        key_bindings
+            .lock()
+            .unwrap()
+            .on_grabbed_key(key, event.name.as_deref(), pressed);
+    if let Some(action) = action {
+        let _ = commands.send(SwitchCommand::Action(action));
+    }
+    (!consume).then_some(event)
+}
+
+fn on_rdev_event(
+    key_bindings: &Mutex<KeyBindings>,
+    commands: &Sender<SwitchCommand>,
+    event: Event,
+) {
+    let (key, pressed) = match event.event_type {
+        KeyPress(key) => (key, true),
+        KeyRelease(key) => (key, false),
+        _ => {
+            return;
+        }
+    };
+    let action = key_bindings
+        .lock()
+        .unwrap()
+        .on_key(key, event.name.as_deref(), pressed)
+        .cloned();
+    if let Some(action) = action {
+        let _ = commands.send(SwitchCommand::Action(action));
+    }
+}
+
+#[derive(clap::Parser, Debug)]
+#[command(name = "ibus_engine_switch", author, version, about, long_about = None)]
+pub struct Args {
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Let switch server switch to the next engine."
+    )]
+    toggle: bool,
+    #[clap(
+        short,
+        long,
+        default_value_t = false,
+        help = "Deprecated alias of --toggle."
+    )]
+    switch: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Let switch server switch to the English engine, i.e. the first one."
+    )]
+    english: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Let switch server switch to the second engine."
+    )]
+    other: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Query the current engine from switch server."
+    )]
+    status: bool,
+    #[clap(
+        long,
+        value_name = "ENGINE",
+        help = "Let switch server switch to the engine, by its name or index in config."
+    )]
+    set: Option<String>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Let switch server activate the previously focused window."
+    )]
+    prev_window: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Query the counters of switch server as JSON, such as the number of switches and uptime."
+    )]
+    metrics: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Print the current engine, then a line each time the engine changes, until switch server exits."
+    )]
+    subscribe: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Let switch server reload its config file, same as sending SIGHUP."
+    )]
+    reload: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Let switch server clean up and exit, same as sending SIGTERM."
+    )]
+    quit: bool,
+    #[clap(
+        short,
+        long,
+        help = "Config file, defaults to $XDG_CONFIG_HOME/ibus_engine_switch/config.toml."
+    )]
+    config: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "FILTER",
+        help = "Log filter such as `debug` or `ibus_engine_switch=trace`, overrides RUST_LOG."
+    )]
+    log_level: Option<String>,
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Also write the logs to the file, rotated to <PATH>.1 beyond --log-file-max-bytes."
+    )]
+    log_file: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "BYTES",
+        default_value_t = log_file::DEFAULT_MAX_BYTES,
+        requires = "log_file",
+        help = "Size cap of --log-file."
+    )]
+    log_file_max_bytes: u64,
+    #[clap(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Log more, -v for debug and -vv for trace. RUST_LOG and --log-level take precedence."
+    )]
+    verbose: u8,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Run the switch server in the background, logging to $XDG_STATE_HOME/ibus_engine_switch/daemon.log."
+    )]
+    daemon: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Only log the engines the switch server would switch to, e.g. to try out config."
+    )]
+    dry_run: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Check the input method framework, the X server and the server port, then exit."
+    )]
+    check: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with = "daemon",
+        help = "Apply the engine for the current workspace and focused window once, as the switch server would, then exit."
+    )]
+    once: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Print the config in effect (defaults, the config file and environment overrides) as TOML, then exit."
+    )]
+    print_config: bool,
+}
+
+impl Args {
+    /// The command to send to the switch server, None to run as the server.
+    pub fn command(&self) -> Option<Command> {
+        if self.status {
+            Some(Command::Status)
+        } else if self.metrics {
+            Some(Command::Metrics)
+        } else if self.subscribe {
+            Some(Command::Subscribe)
+        } else if self.reload {
+            Some(Command::Reload)
+        } else if self.quit {
+            Some(Command::Quit)
+        } else if let Some(engine) = &self.set {
+            Some(Command::Set(engine.clone()))
+        } else if self.prev_window {
+            Some(Command::PrevWindow)
+        } else if self.english {
+            Some(Command::English)
+        } else if self.other {
+            Some(Command::Other)
+        } else if self.toggle || self.switch {
+            Some(Command::Toggle)
+        } else {
+            None
+        }
+    }
+
+    pub fn log_level(&self) -> Option<&str> {
+        self.log_level.as_deref()
+    }
+
+    /// `--log-file` and its size cap.
+    pub fn log_file(&self) -> Option<(PathBuf, u64)> {
+        self.log_file
+            .clone()
+            .map(|path| (path, self.log_file_max_bytes))
+    }
+
+    /// `-v` count, each raises the log level once, see [`init_tracing`].
+    pub fn verbose(&self) -> u8 {
+        self.verbose
+    }
+
+    /// Whether to run as the switch server, rather than a client or `--check`, `--once`, `--print-config`.
+    fn serves(&self) -> bool {
+        self.command().is_none() && !self.print_config && !self.check && !self.once
+    }
+
+    /// Whether the server is going to run in the background, whose logs shouldn't be colored.
+    pub fn daemon(&self) -> bool {
+        self.daemon && self.command().is_none()
+    }
+}
+
+/// The log filter: `--log-level`, or [`logging::env_filter`] starting at `info` if not given.
+/// Exits if `--log-level` is invalid.
+fn log_filter(args: &Args) -> EnvFilter {
+    match args.log_level() {
+        Some(level) => EnvFilter::try_new(level).unwrap_or_else(|e| {
+            eprintln!("Invalid log level {level}: {e}");
+            exit(2);
+        }),
+        None => logging::env_filter(LevelFilter::INFO, args.verbose()),
+    }
+}
+
+/// Install the global tracing subscriber: [`log_filter`], logging to the terminal (stdout),
+/// colored unless [`Args::daemon`], and without colors to `--log-file` if given, see [`log_file::open`].
+/// Keep the returned guard until exiting.
+fn init_tracing(args: &Args) -> Option<WorkerGuard> {
+    let (writer, guard) = args
+        .log_file()
+        .map(|(path, max_bytes)| {
+            log_file::open(path.clone(), max_bytes).unwrap_or_else(|e| {
+                eprintln!("Failed to open log file {}: {e}", path.display());
+                exit(2);
+            })
+        })
+        .unzip();
+    logging::init(
+        log_filter(args),
+        !args.daemon(),
+        io::stdout,
+        writer.map(BoxMakeWriter::new),
+    );
+    guard
+}
+
+/// 加载 `path` 或默认位置的配置并应用环境变量的覆盖, 都不存在时使用默认配置, 配置无效时退出.
+fn load_config(path: Option<PathBuf>) -> Config {
+    effective_config(path).config
+}
+
+fn effective_config(path: Option<PathBuf>) -> EffectiveConfig {
+    EffectiveConfig::load(path).unwrap_or_else(|e| {
+        error!("{e}");
+        exit(1);
+    })
+}
+
+/// 按当前的工作区和活动窗口切换一次输入法, 返回切换后的输入法, 不需要切换时返回 None.
+#[cfg(feature = "x11")]
+fn apply_once(config: &Config, dry_run: bool) -> Result<Option<String>, anyhow::Error> {
+    let window = poll_active_window_once()?;
+    let desktop = poll_current_desktop_once()?;
+    info!(
+        "Workspace {desktop:?}, focused window {:?}.",
+        window.as_ref().map(|w| (w.id, &w.class))
+    );
+    let mut backend = create_backend(config);
+    if dry_run {
+        backend = Box::new(DryRun::new(backend));
+    }
+    apply_once_with(config, window.as_ref(), desktop, backend.as_ref())
+}
+
+/// [`apply_once`] 中查询到工作区 `desktop` 和活动窗口 `window` 之后的部分, 用 `backend` 切换.
+#[cfg(feature = "x11")]
+fn apply_once_with(
+    config: &Config,
+    window: Option<&crate::WindowInfo>,
+    desktop: Option<u32>,
+    backend: &dyn InputMethodBackend,
+) -> Result<Option<String>, anyhow::Error> {
+    // 与切换服务器一样忽略弹出的菜单等临时窗口.
+    let focused = window.is_some_and(|w| config.focus_transient_windows || !w.is_transient());
+    let Some(idx) = once_target(config, desktop, focused) else {
+        return Ok(None);
+    };
+    Ok(Some(set_engine(
+        backend,
+        &config.engines[idx],
+        config.retries,
+    )?))
+}
+
+#[cfg(not(feature = "x11"))]
+fn apply_once(_config: &Config, _dry_run: bool) -> Result<Option<String>, anyhow::Error> {
+    Err(anyhow::anyhow!("--once needs the x11 feature."))
+}
+
+/// Run as a client if any command is given, otherwise as the switch server.
+/// Installs the tracing subscriber first, see [`init_tracing`].
+pub fn run(args: Args) {
+    // 切换服务器在阻塞信号之后才安装, 见下面.
+    let _log_guard = if args.serves() {
+        None
+    } else {
+        init_tracing(&args)
+    };
+    if args.switch {
+        warn!("--switch is deprecated, use --toggle instead.");
+    }
+    if args.print_config {
+        match effective_config(args.config).to_toml() {
+            Ok(toml) => print!("{toml}"),
+            Err(e) => {
+                error!("Failed to serialize the config: {e}");
+                exit(1);
+            }
+        }
+        return;
+    }
+    if args.check {
+        let checks = run_checks(&load_config(args.config));
+        for check in &checks {
+            match &check.result {
+                Ok(found) => println!("[PASS] {}: {found}", check.name),
+                Err(e) => println!("[FAIL] {}: {e}", check.name),
+            }
+        }
+        if checks.iter().any(|check| check.result.is_err()) {
+            exit(1);
+        }
+        return;
+    }
+    if args.once {
+        match apply_once(&load_config(args.config), args.dry_run) {
+            Ok(Some(engine)) => println!("{engine}"),
+            Ok(None) => info!("No engine applies to the current workspace and window."),
+            Err(e) => {
+                error!("{e}");
+                exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(command) = args.command() {
+        let config = load_config(args.config.clone());
+        let addr = config.bind_addr;
+        let unreachable = |e: io::Error| -> ! {
+            error!("Failed to reach the switch server on {addr}: {e}");
+            exit(1);
+        };
+        let mut client = server::connect(addr).unwrap_or_else(|e| unreachable(e));
+        protocol::write_frame(&mut client, &command.to_string()).unwrap_or_else(|e| unreachable(e));
+        if command == Command::Subscribe {
+            for line in BufReader::new(client).lines() {
+                let Ok(engine) = line else { break };
+                println!("{engine}");
+            }
+            return;
+        }
+        let reply = protocol::read_message(&mut client).unwrap_or_else(|e| unreachable(e));
+        match command {
+            Command::Status | Command::Metrics => println!("{reply}"),
+            _ if reply != "ok" => {
+                eprintln!("{reply}");
+                exit(1);
+            }
+            _ => {}
+        }
+    } else {
+        // 必须在创建任何线程之前阻塞信号, 之后的线程 (包括写 `--log-file` 的线程) 都会继承.
+        let shutdown = Shutdown::block();
+        // 写 `--log-file` 的线程在子进程中不存在, 所以 fork 之后才安装, 在这之前只在当前线程输出到终端.
+        let before_fork = args.daemon.then(|| {
+            tracing::subscriber::set_default(logging::subscriber(
+                log_filter(&args),
+                true,
+                io::stdout,
+                None,
+            ))
+        });
+        let mut log_guard = if args.daemon {
+            None
+        } else {
+            init_tracing(&args)
+        };
+        let shutdown = match shutdown {
+            Ok(shutdown) => Some(shutdown),
+            Err(e) => {
+                warn!("Failed to block termination signals, no cleanup on exit: {e}");
+                None
+            }
+        };
+        let mut lock = match PidLock::acquire(&PidLock::default_path()) {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("{e}");
+                exit(1);
+            }
+        };
+        info!("Acquired lock {}.", lock.path().display());
+        let config_path = args.config.clone().or_else(Config::default_path);
+        let config = load_config(config_path.clone());
+        // 在连接输入法框架和 X 服务器之前 fork, 这些都在子进程中初始化.
+        if args.daemon {
+            let log_path = daemon::default_log_path();
+            println!(
+                "Running in the background, logging to {}.",
+                log_path.display()
+            );
+            if let Err(e) = daemon::daemonize(&log_path) {
+                error!("Failed to run in the background: {e}");
+                exit(1);
+            }
+            drop(before_fork);
+            log_guard = init_tracing(&args);
+            if let Err(e) = lock.write_pid() {
+                warn!("Failed to write PID to {}: {e}", lock.path().display());
+            }
+            info!("Daemon started with PID {}.", std::process::id());
+        }
+        if args.dry_run {
+            info!("Dry run, engines are not actually switched.");
+        }
+        let (commands_tx, commands) = mpsc::channel();
+        // 输入法框架可用之后才监听端口, 客户端连上时切换服务器已经就绪.
+        let (mut switcher, listener) = match server::bind_when_ready(config.bind_addr, || {
+            Switcher::new(&config, args.dry_run)
+        }) {
+            Ok(ready) => ready,
+            Err(e) => {
+                error!("{e}");
+                exit(1);
+            }
+        };
+        switcher.config_path = config_path;
+        if let Some(shutdown) = shutdown {
+            let status_file = switcher.status_file.clone();
+            let reload_tx = commands_tx.clone();
+            let reload = move || {
+                let _ = reload_tx.send(SwitchCommand::Reload);
+            };
+            shutdown.on_signal(reload, move || {
+                if let Some(path) = status_file
+                    && let Err(e) = fs::remove_file(&path)
+                {
+                    warn!("Failed to remove status file {}: {e}", path.display());
+                }
+                drop(lock);
+                // 等后台线程写完还在排队的日志.
+                drop(log_guard);
+            });
+        }
+        switcher.listen(listener, commands_tx, commands);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeBackend;
+    use std::{
+        env,
+        net::{Ipv4Addr, TcpStream},
+    };
+
+    #[test]
+    fn concurrent_clients_switch_in_order() {
+        // 每个客户端依次切换到自己的三个输入法.
+        let clients: Vec<Vec<String>> = (0..4)
+            .map(|c| (0..3).map(|i| format!("engine-{c}-{i}")).collect())
+            .collect();
+        let mut engines = vec!["xkb:us::eng"];
+        engines.extend(clients.iter().flatten().map(String::as_str));
+        let config = Config {
+            engines: engines.iter().map(|&e| e.to_owned()).collect(),
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let mut switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, commands) = mpsc::channel();
+        thread::spawn(move || {
+            server::serve(listener, EngineBroadcast::default(), client_handler(tx))
+        });
+        let senders: Vec<_> = clients
+            .iter()
+            .cloned()
+            .map(|client| {
+                thread::spawn(move || {
+                    for engine in client {
+                        let mut stream = TcpStream::connect(addr).unwrap();
+                        protocol::write_frame(&mut stream, &format!("set {engine}")).unwrap();
+                        assert_eq!(protocol::read_message(&mut stream).unwrap(), "ok");
+                    }
+                })
+            })
+            .collect();
+        for command in commands.iter().take(clients.len() * 3) {
+            switcher.handle(command);
+        }
+        for sender in senders {
+            sender.join().unwrap();
+        }
+
+        let switched = switched.lock().unwrap();
+        assert_eq!(switched.len(), clients.len() * 3);
+        for client in &clients {
+            let order: Vec<&String> = switched.iter().filter(|e| client.contains(e)).collect();
+            assert_eq!(order, client.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn subscriber_writes_log_file() {
+        let dir = env::temp_dir().join(format!("ibus_engine_switch-log-{}", std::process::id()));
+        let path = dir.join("switch.log");
+        let _ = fs::remove_file(&path);
+        let (writer, guard) = log_file::open(path.clone(), log_file::DEFAULT_MAX_BYTES).unwrap();
+        let subscriber = logging::subscriber(
+            EnvFilter::new("info"),
+            true,
+            io::stdout,
+            Some(BoxMakeWriter::new(writer)),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            info!("to the log file");
+            debug!("filtered out");
+        });
+        // 等后台线程写完.
+        drop(guard);
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(content.contains("INFO"), "{content}");
+        assert!(content.contains("to the log file"), "{content}");
+        assert!(!content.contains("filtered out"), "{content}");
+        // 终端的输出有颜色, 文件中没有.
+        assert!(!content.contains('\x1b'), "{content:?}");
+    }
+
+    #[test]
+    fn client_args_to_commands() {
+        use clap::Parser;
+        let command = |args: &[&str]| {
+            Args::try_parse_from(["ibus_engine_switch"].iter().chain(args))
+                .unwrap()
+                .command()
+        };
+        assert_eq!(command(&[]), None);
+        assert_eq!(command(&["--daemon"]), None);
+        assert_eq!(command(&["--toggle"]), Some(Command::Toggle));
+        // --switch 是 --toggle 的旧名称
+        assert_eq!(command(&["--switch"]), Some(Command::Toggle));
+        assert_eq!(command(&["-s"]), Some(Command::Toggle));
+        assert_eq!(command(&["--english"]), Some(Command::English));
+        assert_eq!(command(&["--other"]), Some(Command::Other));
+        assert_eq!(
+            command(&["--set", "rime"]),
+            Some(Command::Set("rime".to_owned()))
+        );
+        assert_eq!(command(&["--status"]), Some(Command::Status));
+        assert_eq!(command(&["--prev-window"]), Some(Command::PrevWindow));
+        assert_eq!(command(&["--metrics"]), Some(Command::Metrics));
+        assert_eq!(command(&["--subscribe"]), Some(Command::Subscribe));
+        assert_eq!(command(&["--reload"]), Some(Command::Reload));
+        assert_eq!(command(&["--quit"]), Some(Command::Quit));
+        // 同时给出多个时, 查询优先于切换
+        assert_eq!(command(&["--toggle", "--status"]), Some(Command::Status));
+    }
+
+    #[test]
+    fn switching_updates_the_status_file() {
+        let dir = env::temp_dir().join(format!("ibus_engine_switch-status-{}", std::process::id()));
+        let path = dir.join("current");
+        let engines = ["xkb:us::eng", "rime", "anthy"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            status_file: true,
+            status_file_path: Some(path.clone()),
+            ..Config::default()
+        };
+        let backend = Box::new(FakeBackend::new(&engines));
+        let mut switcher = Switcher::with_backend(&config, backend, None, false).unwrap();
+        let read = || fs::read_to_string(&path).unwrap();
+        switcher.switch_engine_to(1);
+        assert_eq!(read(), "rime\n");
+        switcher.switch_engine(None);
+        assert_eq!(read(), "anthy\n");
+        switcher.switch_engine(Some(true));
+        assert_eq!(read(), "xkb:us::eng\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 不需要 X 连接, 没有 x11 feature 时也运行.
+    #[test]
+    fn socket_commands_without_window_tracking() {
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = Box::new(FakeBackend::new(&engines));
+        let mut switcher = Switcher::with_backend(&config, backend, None, false).unwrap();
+        assert_eq!(switcher.handle_command(Command::Toggle), "ok");
+        assert_eq!(switcher.handle_command(Command::Status), "rime");
+        assert_eq!(
+            switcher.handle_command(Command::Set("mozc".to_owned())),
+            "unknown engine"
+        );
+        assert_eq!(switcher.handle_command(Command::English), "ok");
+        assert_eq!(switcher.handle_command(Command::Status), "xkb:us::eng");
+        #[cfg(not(feature = "x11"))]
+        {
+            assert_eq!(
+                switcher.handle_command(Command::PrevWindow),
+                "prev-window needs the x11 feature"
+            );
+            assert!(apply_once(&config, true).is_err());
+        }
+    }
+
+    #[test]
+    fn metrics_count_simulated_events() {
+        let engines = ["xkb:us::eng", "rime", "anthy"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = Box::new(FakeBackend::new(&engines));
+        let mut switcher = Switcher::with_backend(&config, backend, None, false).unwrap();
+        let (reply, _replies) = mpsc::channel();
+        switcher.handle(SwitchCommand::Client(Command::Toggle, reply));
+        switcher.handle(SwitchCommand::Action(Action::ToEnglish));
+        // 外部切换也计数, 没有变化的不计
+        switcher.handle(SwitchCommand::Reconcile("anthy".to_owned()));
+        switcher.handle(SwitchCommand::Reconcile("anthy".to_owned()));
+        #[cfg(feature = "x11")]
+        {
+            let window = FocusedWindow::default();
+            switcher.handle(SwitchCommand::Focus(
+                FocusHistory::new(2),
+                0x3a00003,
+                window.clone(),
+            ));
+            switcher.handle(SwitchCommand::Focus(FocusHistory::new(2), 0, window));
+        }
+        let snapshot = switcher.metrics.snapshot(&switcher.engines);
+        assert_eq!(snapshot.switches, 3);
+        assert_eq!(
+            snapshot.focus_events,
+            if cfg!(feature = "x11") { 2 } else { 0 }
+        );
+        let engines = snapshot.engines.unwrap();
+        assert_eq!(engines.values().copied().collect::<Vec<_>>(), [1, 1, 1]);
+        let reply: serde_json::Value =
+            serde_json::from_str(&switcher.handle_command(Command::Metrics)).unwrap();
+        assert_eq!(reply["switches"], 3);
+        assert_eq!(reply["engines"]["rime"], 1);
+    }
+
+    #[test]
+    fn reload_swaps_in_the_new_config() {
+        let path = env::temp_dir().join(format!(
+            "ibus_engine_switch-reload-{}.toml",
+            std::process::id()
+        ));
+        let engines = ["xkb:us::eng", "rime", "anthy"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let mut switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+        switcher.config_path = Some(path.clone());
+        switcher.switch_engine_to(1);
+
+        // 当前输入法仍在新的列表中, 保持不变
+        fs::write(
+            &path,
+            r#"
+engines = ["xkb:us::eng", "anthy", "rime"]
+retries = 3
+
+[[bindings]]
+key = "F5"
+action = "toggle"
+"#,
+        )
+        .unwrap();
+        assert!(switcher.reload_config());
+        assert_eq!(switcher.engines, ["xkb:us::eng", "anthy", "rime"]);
+        assert_eq!((switcher.current, switcher.retries), (2, 3));
+        let bindings = switcher.key_bindings.lock().unwrap().bindings().to_vec();
+        assert_eq!(bindings[0].combo(), "F5");
+
+        // 读取失败时保留当前配置
+        fs::write(&path, "engines = []").unwrap();
+        assert!(!switcher.reload_config());
+        assert_eq!(switcher.engines.len(), 3);
+
+        // 当前输入法被移除时切换到英文
+        switcher.apply_config(Config {
+            engines: vec!["xkb:us::eng".to_owned(), "anthy".to_owned()],
+            ..Config::default()
+        });
+        assert_eq!(switcher.engines, ["xkb:us::eng", "anthy"]);
+        assert_eq!(switcher.current, 0);
+        assert_eq!(*switched.lock().unwrap(), ["rime", "xkb:us::eng"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "x11")]
+    #[test]
+    fn once_applies_one_decision() {
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            desktop_engines: HashMap::from([(2, "rime".to_owned())]),
+            autoswitch: true,
+            ..Config::default()
+        };
+        let window = crate::WindowInfo {
+            id: 0x3a00003,
+            ..crate::WindowInfo::none()
+        };
+        let menu = crate::WindowInfo {
+            window_type: vec!["_NET_WM_WINDOW_TYPE_POPUP_MENU".to_owned()],
+            ..window.clone()
+        };
+        // 返回切换到的输入法, 以及后端实际的切换
+        let once = |window: Option<&crate::WindowInfo>, desktop| {
+            let backend = FakeBackend::new(&engines);
+            let switched = backend.switched();
+            let engine = apply_once_with(&config, window, desktop, &backend).unwrap();
+            let switched = switched.lock().unwrap().clone();
+            (engine, switched)
+        };
+        // 工作区的输入法优先
+        assert_eq!(
+            once(Some(&window), Some(2)),
+            (Some("rime".to_owned()), vec!["rime".to_owned()])
+        );
+        assert_eq!(
+            once(Some(&window), Some(1)),
+            (
+                Some("xkb:us::eng".to_owned()),
+                vec!["xkb:us::eng".to_owned()]
+            )
+        );
+        assert_eq!(once(None, Some(1)), (None, Vec::new()));
+        assert_eq!(once(Some(&menu), None), (None, Vec::new()));
+    }
+
+    #[test]
+    fn rapid_commands_are_throttled() {
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            min_switch_interval_ms: 60_000,
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let mut switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+        assert_eq!(switcher.handle_command(Command::Toggle), "ok");
+        assert_eq!(switcher.handle_command(Command::Toggle), "throttled");
+        assert_eq!(switcher.handle_command(Command::English), "throttled");
+        // 不切换的命令不受限制
+        assert_eq!(switcher.handle_command(Command::Status), "rime");
+        assert_eq!(*switched.lock().unwrap(), ["rime"]);
+    }
+
+    #[test]
+    fn redundant_switches_skip_the_backend() {
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let mut switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+        // 监听 IBus 信号时缓存可信, 已经是英文时不调用后端.
+        switcher.watch_engine = true;
+        switcher.switch_engine_auto(0);
+        assert!(switched.lock().unwrap().is_empty());
+        switcher.switch_engine_auto(1);
+        assert_eq!(*switched.lock().unwrap(), ["rime"]);
+        // 用户主动的切换总是调用后端.
+        switcher.switch_engine_to(1);
+        assert_eq!(*switched.lock().unwrap(), ["rime", "rime"]);
+        // 不监听信号时强制切换.
+        switcher.watch_engine = false;
+        switcher.switch_engine_auto(1);
+        assert_eq!(*switched.lock().unwrap(), ["rime", "rime", "rime"]);
+        assert!(!needs_switch("rime", "rime", false));
+        assert!(needs_switch("rime", "rime", true));
+        assert!(needs_switch("rime", "xkb:us::eng", false));
+    }
+
+    #[test]
+    fn apply_on_start_or_adopt() {
+        let engines = ["xkb:us::eng", "rime"];
+        let start = |apply_on_start| {
+            let config = Config {
+                engines: engines.map(str::to_owned).into(),
+                apply_on_start,
+                ..Config::default()
+            };
+            // 启动时正在使用中文.
+            let backend = FakeBackend::new(&["rime", "xkb:us::eng"]);
+            let switched = backend.switched();
+            let switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+            let switched = switched.lock().unwrap().clone();
+            (switcher.current, switched)
+        };
+        assert_eq!(start(false), (1, Vec::new()));
+        assert_eq!(start(true), (0, vec!["xkb:us::eng".to_owned()]));
+
+        let engines = engines.map(str::to_owned);
+        assert_eq!(
+            startup_engine(&engines, 1, Some("xkb:us::eng"), true),
+            StartupEngine::Apply(1)
+        );
+        // 配置变化后保存的索引越界
+        assert_eq!(
+            startup_engine(&engines, 5, None, true),
+            StartupEngine::Apply(0)
+        );
+        assert_eq!(
+            startup_engine(&engines, 0, Some("rime"), false),
+            StartupEngine::Adopt(1)
+        );
+        assert_eq!(
+            startup_engine(&engines, 0, Some("anthy"), false),
+            StartupEngine::Unknown
+        );
+        assert_eq!(
+            startup_engine(&engines, 0, None, false),
+            StartupEngine::Unknown
+        );
+    }
+
+    #[test]
+    fn set_action_switches_to_the_engine() {
+        let engines = ["xkb:us::eng", "rime", "anthy"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let mut switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+        switcher.apply_action(Action::Set("anthy".to_owned()));
+        assert_eq!(switcher.current, 2);
+        switcher.apply_action(Action::Set("1".to_owned()));
+        assert_eq!(switcher.current, 1);
+        // 未知的输入法不切换
+        switcher.apply_action(Action::Set("mozc".to_owned()));
+        assert_eq!(switcher.current, 1);
+        assert_eq!(*switched.lock().unwrap(), ["anthy", "rime"]);
+    }
+
+    #[cfg(feature = "x11")]
+    #[test]
+    fn watch_events_demux_to_commands() {
+        use crate::{WindowInfo, watcher::ChangeTime};
+        let focus = |id: u32, class: &str| WindowOrDesktopEvent::Focus {
+            history: FocusHistory::new(2),
+            last: None,
+            current: Box::new(WindowInfo {
+                id,
+                class: Some((class.to_lowercase(), class.to_owned())),
+                ..WindowInfo::none()
+            }),
+            time: ChangeTime {
+                server: None,
+                received: Instant::now(),
+            },
+        };
+        let events = [
+            WindowOrDesktopEvent::Desktop {
+                last: None,
+                current: 0,
+            },
+            focus(0x3a00003, "Alacritty"),
+            WindowOrDesktopEvent::Desktop {
+                last: Some(0),
+                current: 1,
+            },
+            focus(0x3c00016, "firefox"),
+        ];
+        let commands: Vec<String> = events
+            .into_iter()
+            .filter_map(watch_command)
+            .map(|command| match command {
+                SwitchCommand::Focus(_, id, window) => {
+                    format!("focus 0x{id:x} {}", window.app_id.unwrap())
+                }
+                SwitchCommand::Desktop(desktop) => format!("desktop {desktop}"),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "focus 0x3a00003 Alacritty",
+                "desktop 1",
+                "focus 0x3c00016 firefox"
+            ]
+        );
+    }
+
+    #[test]
+    fn failing_backend_is_detected_before_binding() {
+        use crate::fake::BrokenBackend;
+        // 无法绑定的地址 (TEST-NET-1), 只有尝试绑定时才会失败.
+        let unbindable = Ipv4Addr::new(192, 0, 2, 1).into();
+        let config = Config {
+            engines: vec!["xkb:us::eng".to_owned(), "rime".to_owned()],
+            ..Config::default()
+        };
+        let err = server::bind_when_ready(unbindable, || {
+            Switcher::with_backend(&config, Box::new(BrokenBackend), None, false)
+        })
+        .map(|_| ())
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Failed to query the current engine"),
+            "{err}"
+        );
+        // dry run 时查询失败也能启动, 之后才绑定端口.
+        let err = server::bind_when_ready(unbindable, || {
+            Switcher::with_backend(&config, Box::new(BrokenBackend), None, true)
+        })
+        .map(|_| ())
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Failed to bind port"), "{err}");
+    }
+
+    #[test]
+    fn switches_are_written_to_subscribers() {
+        #[derive(Clone, Default)]
+        struct Stream(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Stream {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        /// 已经断开的连接.
+        struct Closed;
+        impl io::Write for Closed {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = Box::new(FakeBackend::new(&engines));
+        let mut switcher = Switcher::with_backend(&config, backend, None, false).unwrap();
+        let stream = Stream::default();
+        switcher.broadcast.subscribe(stream.clone());
+        switcher.broadcast.subscribe(Closed);
+        assert_eq!(switcher.broadcast.len(), 1);
+        switcher.handle_command(Command::Toggle);
+        switcher.handle_command(Command::Toggle);
+        // 没有改变时不写入
+        switcher.handle_command(Command::English);
+        assert_eq!(
+            String::from_utf8(stream.0.lock().unwrap().clone()).unwrap(),
+            "xkb:us::eng\nrime\nxkb:us::eng\n"
+        );
+    }
+
+    #[cfg(feature = "x11")]
+    #[test]
+    fn no_window_follows_the_policy() {
+        use crate::config::OnNoWindow;
+        let engines = ["xkb:us::eng", "rime"];
+        let run = |on_no_window| {
+            let config = Config {
+                engines: engines.map(str::to_owned).into(),
+                app_engines: HashMap::from([("firefox".to_owned(), "rime".to_owned())]),
+                on_no_window,
+                ..Config::default()
+            };
+            let backend = Box::new(FakeBackend::new(&engines));
+            let mut switcher = Switcher::with_backend(&config, backend, None, false).unwrap();
+            let firefox = FocusedWindow {
+                app_id: Some("firefox".to_owned()),
+                title: None,
+            };
+            let mut engines = Vec::new();
+            for (id, window) in [
+                (0x3c00016, firefox.clone()),
+                (0, FocusedWindow::default()),
+                (0x3c00016, firefox),
+            ] {
+                switcher.handle(SwitchCommand::Focus(FocusHistory::new(2), id, window));
+                engines.push(switcher.engines[switcher.current].clone());
+            }
+            engines
+        };
+        assert_eq!(run(OnNoWindow::Keep), ["rime", "rime", "rime"]);
+        assert_eq!(run(OnNoWindow::Default), ["rime", "xkb:us::eng", "rime"]);
+    }
+
+    #[test]
+    fn focus_and_switch_are_logged_as_json_lines() {
+        let dir = env::temp_dir().join(format!("ibus_engine_switch-events-{}", std::process::id()));
+        let path = dir.join("events.jsonl");
+        let _ = fs::remove_file(&path);
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            event_log: Some(path.clone()),
+            ..Config::default()
+        };
+        let backend = Box::new(FakeBackend::new(&engines));
+        let mut switcher = Switcher::with_backend(&config, backend, None, false).unwrap();
+        #[cfg(feature = "x11")]
+        switcher.handle(SwitchCommand::Focus(
+            FocusHistory::new(2),
+            0x3c00016,
+            FocusedWindow {
+                app_id: Some("firefox".to_owned()),
+                title: Some("Firefox".to_owned()),
+            },
+        ));
+        switcher.handle(SwitchCommand::Action(Action::Toggle));
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(content.ends_with('\n'));
+        let mut events: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        for event in &mut events {
+            assert!(event["timestamp"].as_u64().unwrap() > 0);
+            event.as_object_mut().unwrap().remove("timestamp");
+        }
+        let switch = serde_json::json!({
+            "event": "switch", "from": "xkb:us::eng", "to": "rime", "trigger": "binding"
+        });
+        #[cfg(feature = "x11")]
+        assert_eq!(
+            events,
+            [
+                serde_json::json!({
+                    "event": "focus", "window": 0x3c00016, "wm_class": "firefox", "title": "Firefox"
+                }),
+                switch
+            ]
+        );
+        #[cfg(not(feature = "x11"))]
+        assert_eq!(events, [switch]);
+    }
+
+    #[test]
+    fn quit_is_accepted() {
+        let engines = ["xkb:us::eng", "rime"];
+        let config = Config {
+            engines: engines.map(str::to_owned).into(),
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let mut switcher = Switcher::with_backend(&config, Box::new(backend), None, false).unwrap();
+        assert_eq!(switcher.handle_command(Command::Quit), "ok");
+        assert!(switched.lock().unwrap().is_empty());
+    }
+}
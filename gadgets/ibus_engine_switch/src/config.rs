@@ -0,0 +1,341 @@
+use crate::{
+    bindings::{Binding, check_bindings},
+    event_log::DEFAULT_MAX_BYTES,
+    focus::DEFAULT_FOCUS_HISTORY_DEPTH,
+    server::{self, DEFAULT_BIND_ADDR},
+};
+use gadgets_common::xdg::config_home;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+/// The input method framework in use.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Ibus,
+    Fcitx5,
+}
+
+/// How to talk to the input method daemon.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// 通过 D-Bus 直接调用输入法框架, 不可用时退回到 `Process`.
+    #[default]
+    Dbus,
+    /// 每次切换都调用 `ibus` / `fcitx5-remote` 程序.
+    Process,
+}
+
+/// Where key bindings are listened.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HotkeyBackend {
+    /// 注册为全局快捷键, 只会收到绑定的组合键.
+    #[default]
+    GlobalHotkey,
+    /// 通过 rdev 监听所有按键.
+    Rdev,
+    /// 通过 rdev 拦截所有按键, 触发绑定的按键不会再传给应用, 需要 `grab` feature.
+    RdevGrab,
+}
+
+/// Where the focused window is tracked, see [`crate::focus_backend`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusBackendKind {
+    /// `_NET_ACTIVE_WINDOW` of the X server (or XWayland).
+    #[default]
+    X11,
+    /// `wlr-foreign-toplevel-management` of wlroots based compositors, needs the `wayland` feature.
+    Wlroots,
+}
+
+/// What to do when focus moves to no window, e.g. the desktop or the last window closed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnNoWindow {
+    /// 保持当前的输入法.
+    #[default]
+    Keep,
+    /// 切换到英文输入法 (第一个输入法).
+    Default,
+}
+
+impl OnNoWindow {
+    /// Index of the engine to switch to when no window is focused, None to keep the current one.
+    pub fn target(self) -> Option<usize> {
+        match self {
+            OnNoWindow::Keep => None,
+            OnNoWindow::Default => Some(0),
+        }
+    }
+}
+
+/// Environment variable overriding [`Config::focus_backend`], `x11` or `wlroots`.
+pub const FOCUS_BACKEND_ENV: &str = "IBUS_ENGINE_SWITCH_FOCUS_BACKEND";
+
+impl FocusBackendKind {
+    /// Parse the value of [`FOCUS_BACKEND_ENV`].
+    pub fn parse(value: &str) -> Option<FocusBackendKind> {
+        match value {
+            "x11" => Some(FocusBackendKind::X11),
+            "wlroots" => Some(FocusBackendKind::Wlroots),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub backend: Backend,
+    pub transport: Transport,
+    /// Engines to switch between, the first one is the English engine.
+    pub engines: Vec<String>,
+    /// Refuse to start if any of `engines` is not installed, instead of only warning.
+    pub strict: bool,
+    /// How many times to retry when the engine didn't change after switching.
+    pub retries: usize,
+    /// Switch to the engine saved in the state file on startup, instead of adopting the engine in use.
+    pub apply_on_start: bool,
+    /// Post a desktop notification when the engine changes.
+    pub notifications: bool,
+    /// Write the current engine to `status_file_path` on every change, for status bars.
+    pub status_file: bool,
+    /// Defaults to [`crate::state::default_status_file`].
+    pub status_file_path: Option<PathBuf>,
+    /// Append focus changes and engine switches to this JSON Lines file, see [`crate::event_log`].
+    pub event_log: Option<PathBuf>,
+    /// Size cap of `event_log`, the file is rotated to `<event_log>.1` beyond it.
+    pub event_log_max_bytes: u64,
+    /// Command run after every engine change, see [`crate::hook::SwitchHook`].
+    pub on_switch_command: Option<String>,
+    /// Switch to the English engine after focusing another window.
+    pub autoswitch: bool,
+    /// Delay of the automatic switching, cancelled if focus changes again or switched manually.
+    pub autoswitch_delay_ms: u64,
+    /// Treat focusing transient windows (tooltips, menus, ...) as window switches.
+    pub focus_transient_windows: bool,
+    /// What to do when focus moves to no window, only reported under X11.
+    pub on_no_window: OnNoWindow,
+    /// Number of recently focused windows to remember, used by `prev-window`.
+    pub focus_history_depth: usize,
+    /// Watch the active window of every X screen, for multi-screen (Zaphod) setups.
+    pub all_screens: bool,
+    /// Re-read a newly focused X11 window missing its `WM_CLASS` or title up to this many times,
+    /// see [`crate::ListenOptions::settle_retries`].
+    pub focus_settle_retries: usize,
+    /// Delay before each re-read of `focus_settle_retries`.
+    pub focus_settle_delay_ms: u64,
+    /// Key bindings, see [`Binding`].
+    pub bindings: Vec<Binding>,
+    pub hotkey_backend: HotkeyBackend,
+    /// Engine to switch to when a workspace becomes current, keyed by workspace index.
+    /// Engines are given by name or index in `engines`, workspaces without an entry leave the engine unchanged.
+    pub desktop_engines: HashMap<u32, String>,
+    pub focus_backend: FocusBackendKind,
+    /// Engine to switch to when a window of the application is focused, keyed by Wayland app_id
+    /// or X11 `WM_CLASS` class, see [`crate::focus_backend::app_engine`].
+    pub app_engines: HashMap<String, String>,
+    /// Address the switch server listens on and clients connect to, `127.0.0.1` or `::1`.
+    pub bind_addr: IpAddr,
+    /// Allow a non-loopback `bind_addr`, anyone who can reach it can switch the engine.
+    pub allow_remote: bool,
+    /// Client commands switching the engine within this interval since the last accepted one
+    /// are replied `throttled` and ignored, 0 disables throttling.
+    pub min_switch_interval_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backend: Backend::default(),
+            transport: Transport::default(),
+            engines: vec!["xkb:us::eng".to_owned(), "rime".to_owned()],
+            strict: false,
+            retries: 2,
+            apply_on_start: false,
+            notifications: false,
+            status_file: false,
+            status_file_path: None,
+            event_log: None,
+            event_log_max_bytes: DEFAULT_MAX_BYTES,
+            on_switch_command: None,
+            autoswitch: false,
+            autoswitch_delay_ms: 1300,
+            focus_transient_windows: false,
+            on_no_window: OnNoWindow::default(),
+            focus_history_depth: DEFAULT_FOCUS_HISTORY_DEPTH,
+            all_screens: false,
+            focus_settle_retries: 3,
+            focus_settle_delay_ms: 50,
+            bindings: Binding::defaults(),
+            hotkey_backend: HotkeyBackend::default(),
+            desktop_engines: HashMap::new(),
+            focus_backend: FocusBackendKind::default(),
+            app_engines: HashMap::new(),
+            bind_addr: DEFAULT_BIND_ADDR,
+            allow_remote: false,
+            min_switch_interval_ms: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Default config file path: `$XDG_CONFIG_HOME/ibus_engine_switch/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        config_home().map(|dir| dir.join("ibus_engine_switch").join("config.toml"))
+    }
+
+    /// Settings changed from `self` to `new` which only take effect after restarting the switch server,
+    /// the others are applied by reloading.
+    pub fn restart_required(&self, new: &Config) -> Vec<&'static str> {
+        [
+            ("backend", self.backend != new.backend),
+            ("transport", self.transport != new.transport),
+            ("status_file", self.status_file != new.status_file),
+            (
+                "status_file_path",
+                self.status_file_path != new.status_file_path,
+            ),
+            ("autoswitch", self.autoswitch != new.autoswitch),
+            (
+                "focus_transient_windows",
+                self.focus_transient_windows != new.focus_transient_windows,
+            ),
+            (
+                "focus_history_depth",
+                self.focus_history_depth != new.focus_history_depth,
+            ),
+            ("all_screens", self.all_screens != new.all_screens),
+            (
+                "focus_settle_retries",
+                self.focus_settle_retries != new.focus_settle_retries,
+            ),
+            (
+                "focus_settle_delay_ms",
+                self.focus_settle_delay_ms != new.focus_settle_delay_ms,
+            ),
+            ("focus_backend", self.focus_backend != new.focus_backend),
+            ("hotkey_backend", self.hotkey_backend != new.hotkey_backend),
+            ("bind_addr", self.bind_addr != new.bind_addr),
+            ("allow_remote", self.allow_remote != new.allow_remote),
+        ]
+        .into_iter()
+        .filter_map(|(setting, changed)| changed.then_some(setting))
+        .collect()
+    }
+
+    /// [`Config::focus_backend`], unless overridden by [`FOCUS_BACKEND_ENV`].
+    /// An invalid value of the variable is warned and ignored.
+    pub fn effective_focus_backend(&self) -> FocusBackendKind {
+        match env::var(FOCUS_BACKEND_ENV) {
+            Ok(value) => FocusBackendKind::parse(&value).unwrap_or_else(|| {
+                tracing::warn!("Invalid {FOCUS_BACKEND_ENV} {value:?}, expected x11 or wlroots.");
+                self.focus_backend
+            }),
+            Err(_) => self.focus_backend,
+        }
+    }
+
+    /// Apply the overrides from the environment, i.e. [`Config::effective_focus_backend`].
+    pub fn with_overrides(mut self) -> Config {
+        self.focus_backend = self.effective_focus_backend();
+        self
+    }
+
+    /// Load config from `path`, a missing file yields the default config.
+    pub fn load(path: &Path) -> Result<Config, anyhow::Error> {
+        let config: Config = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+            Err(e) => Err(e)?,
+        };
+        if config.engines.is_empty() {
+            return Err(anyhow::anyhow!("At least one engine must be configured."));
+        }
+        check_bindings(&config.bindings, &config.engines)?;
+        server::check_bind_addr(config.bind_addr, config.allow_remote)?;
+        Ok(config)
+    }
+}
+
+/// The settings in effect: the defaults, overridden by the config file and then by the environment.
+/// Printed by `--print-config`.
+#[derive(Clone, Debug)]
+pub struct EffectiveConfig {
+    /// The given config file or [`Config::default_path`], None without either.
+    pub path: Option<PathBuf>,
+    pub config: Config,
+}
+
+impl EffectiveConfig {
+    /// Load `path`, defaults to [`Config::default_path`], and apply [`Config::with_overrides`].
+    pub fn load(path: Option<PathBuf>) -> Result<EffectiveConfig, anyhow::Error> {
+        let path = path.or_else(Config::default_path);
+        let config = match &path {
+            Some(path) => Config::load(path)
+                .map_err(|e| anyhow::anyhow!("Invalid config {}: {e}", path.display()))?,
+            None => Config::default(),
+        };
+        Ok(EffectiveConfig {
+            path,
+            config: config.with_overrides(),
+        })
+    }
+
+    /// The config as TOML, which can be used as a config file, after a comment naming the file.
+    pub fn to_toml(&self) -> Result<String, anyhow::Error> {
+        let source = match &self.path {
+            Some(path) if path.exists() => format!("# Loaded from {}.\n", path.display()),
+            Some(path) => format!("# {} doesn't exist, using the defaults.\n", path.display()),
+            None => "# No config file, using the defaults.\n".to_owned(),
+        };
+        Ok(source + &toml::to_string(&self.config)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_settings_are_dumped() {
+        let dir = env::temp_dir().join(format!("ibus_engine_switch-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "engines = [\"xkb:us::eng\", \"rime\"]\nretries = 5\n",
+        )
+        .unwrap();
+        let effective = EffectiveConfig::load(Some(path.clone())).unwrap();
+        let toml = effective.to_toml().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            toml.starts_with(&format!("# Loaded from {}.\n", path.display())),
+            "{toml}"
+        );
+        // 输出可以作为配置文件使用, 没有写在文件中的是默认值
+        let dumped: Config = toml::from_str(&toml).unwrap();
+        assert_eq!(dumped.engines, ["xkb:us::eng", "rime"]);
+        assert_eq!(dumped.retries, 5);
+        assert_eq!(dumped.event_log_max_bytes, DEFAULT_MAX_BYTES);
+        assert_eq!(dumped.focus_backend, effective.config.focus_backend);
+
+        let missing = EffectiveConfig::load(Some(dir.join("missing.toml"))).unwrap();
+        assert!(
+            missing
+                .to_toml()
+                .unwrap()
+                .contains("doesn't exist, using the defaults.")
+        );
+    }
+}
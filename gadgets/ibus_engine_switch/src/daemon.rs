@@ -0,0 +1,72 @@
+//! 以守护进程方式运行: 两次 fork 并脱离控制终端, 输出重定向到日志文件.
+
+use gadgets_common::xdg::{runtime_dir, state_home};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// Default log file of the daemon: `$XDG_STATE_HOME/ibus_engine_switch/daemon.log`.
+pub fn default_log_path() -> PathBuf {
+    state_home()
+        .unwrap_or_else(runtime_dir)
+        .join("ibus_engine_switch")
+        .join("daemon.log")
+}
+
+/// Detach into the background, stdout and stderr are appended to `log_path`.
+/// Only the grandchild returns, so everything that spawns threads or opens connections
+/// (D-Bus, X11, hotkeys) must be set up after this, including the tracing subscriber writing
+/// `--log-file` in the background.
+///
+/// The parents leave with `_exit`, without running destructors, so locks shared with the child
+/// (e.g. a [`crate::lock::PidLock`] acquired before) stay held.
+pub fn daemonize(log_path: &Path) -> Result<(), io::Error> {
+    if let Some(dir) = log_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
+    let null = File::open("/dev/null")?;
+    // SAFETY: 调用时没有其他线程 (`--log-file` 的写入线程在 fork 之后才创建),
+    // fork 后子进程只调用异步信号安全的函数或重新初始化.
+    unsafe {
+        fork_and_exit_parent()?;
+        // 成为新会话的首进程, 脱离控制终端.
+        if libc::setsid() < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // 再 fork 一次, 使进程不再是会话首进程, 不会重新获得控制终端.
+        fork_and_exit_parent()?;
+        for (fd, target) in [
+            (null.as_raw_fd(), libc::STDIN_FILENO),
+            (log.as_raw_fd(), libc::STDOUT_FILENO),
+            (log.as_raw_fd(), libc::STDERR_FILENO),
+        ] {
+            if libc::dup2(fd, target) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    std::env::set_current_dir("/")?;
+    Ok(())
+}
+
+/// fork, 父进程直接退出, 子进程返回.
+///
+/// # Safety
+///
+/// 同 [`daemonize`] 中的说明: 调用时不能有其他线程正在持有锁或使用 fork 后不可用的资源.
+unsafe fn fork_and_exit_parent() -> Result<(), io::Error> {
+    // SAFETY: 由调用者保证没有其他活动的线程, 见函数的 Safety 说明.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        // SAFETY: _exit 不运行析构函数和 atexit 处理函数, 父进程不再使用与子进程共享的任何状态.
+        _ => unsafe { libc::_exit(0) },
+    }
+}
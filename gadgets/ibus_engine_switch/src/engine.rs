@@ -0,0 +1,121 @@
+//! 不经过切换服务器, 直接在当前进程中执行切换命令, 供作为库使用.
+
+use crate::{
+    backend::{InputMethodBackend, create_backend, needs_switch, set_engine},
+    config::Config,
+    protocol::Command,
+};
+
+/// The engine in use after [`switch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineState {
+    /// Index in [`Config::engines`], None if the engine is not one of them.
+    pub index: Option<usize>,
+    pub engine: String,
+}
+
+/// 按输入法名或索引查找输入法.
+pub fn engine_index(engines: &[String], engine: &str) -> Option<usize> {
+    engines.iter().position(|e| e == engine).or_else(|| {
+        engine
+            .parse::<usize>()
+            .ok()
+            .filter(|&idx| idx < engines.len())
+    })
+}
+
+/// Index of the engine `command` switches to when `current` is in use, None if it doesn't switch.
+pub fn target_index(
+    engines: &[String],
+    current: Option<usize>,
+    command: &Command,
+) -> Result<Option<usize>, anyhow::Error> {
+    if engines.is_empty() {
+        return Err(anyhow::anyhow!("No engines configured."));
+    }
+    match command {
+        // 当前输入法不在列表中时切换到第一个.
+        Command::Toggle => Ok(Some(current.map_or(0, |idx| (idx + 1) % engines.len()))),
+        Command::English => Ok(Some(0)),
+        Command::Other => Ok(Some(1.min(engines.len() - 1))),
+        Command::Set(engine) => engine_index(engines, engine)
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("Unknown engine: {engine}")),
+        Command::Status => Ok(None),
+        Command::PrevWindow
+        | Command::Metrics
+        | Command::Subscribe
+        | Command::Reload
+        | Command::Quit => Err(anyhow::anyhow!("{command} doesn't switch engines.")),
+    }
+}
+
+/// Index of the engine the switch server would apply for the current state: the engine of `desktop`
+/// in [`Config::desktop_engines`], otherwise English if [`Config::autoswitch`] and a window is `focused`.
+/// None if nothing applies.
+pub fn once_target(config: &Config, desktop: Option<u32>, focused: bool) -> Option<usize> {
+    desktop
+        .and_then(|desktop| config.desktop_engines.get(&desktop))
+        .and_then(|engine| engine_index(&config.engines, engine))
+        .or_else(|| (config.autoswitch && focused).then_some(0))
+}
+
+/// Run `command` in this process with the backend selected by `config`, instead of asking the switch server.
+pub fn switch(config: &Config, command: &Command) -> Result<EngineState, anyhow::Error> {
+    switch_with(create_backend(config).as_ref(), config, command)
+}
+
+/// Like [`switch`], with the given backend.
+pub fn switch_with(
+    backend: &dyn InputMethodBackend,
+    config: &Config,
+    command: &Command,
+) -> Result<EngineState, anyhow::Error> {
+    let position = |engine: &str| config.engines.iter().position(|e| e == engine);
+    let current = backend.current_engine()?;
+    // 刚查询的当前输入法就是目标时不再设置.
+    let engine = match target_index(&config.engines, position(&current), command)? {
+        Some(idx) if needs_switch(&current, &config.engines[idx], false) => {
+            set_engine(backend, &config.engines[idx], config.retries)?
+        }
+        _ => current,
+    };
+    Ok(EngineState {
+        index: position(&engine),
+        engine,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeBackend;
+
+    #[test]
+    fn switch_with_fake_backend() {
+        let engines = ["xkb:us::eng", "rime", "anthy"];
+        let config = Config {
+            engines: engines.iter().map(|&e| e.to_owned()).collect(),
+            ..Config::default()
+        };
+        let backend = FakeBackend::new(&engines);
+        let switched = backend.switched();
+        let run = |command: Command| switch_with(&backend, &config, &command).unwrap();
+        let state = |index: Option<usize>, engine: &str| EngineState {
+            index,
+            engine: engine.to_owned(),
+        };
+        assert_eq!(run(Command::Toggle), state(Some(1), "rime"));
+        assert_eq!(
+            run(Command::Set("anthy".to_owned())),
+            state(Some(2), "anthy")
+        );
+        assert_eq!(run(Command::Status), state(Some(2), "anthy"));
+        // 已经是目标输入法时不再设置
+        assert_eq!(run(Command::Set("2".to_owned())), state(Some(2), "anthy"));
+        assert_eq!(run(Command::English), state(Some(0), "xkb:us::eng"));
+        assert_eq!(*switched.lock().unwrap(), ["rime", "anthy", "xkb:us::eng"]);
+        assert!(switch_with(&backend, &config, &Command::Set("mozc".to_owned())).is_err());
+        assert!(switch_with(&backend, &config, &Command::Quit).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError};
+
+/// Errors of the X11 helpers and listeners in this crate.
+#[derive(thiserror::Error, Debug)]
+pub enum IbusSwitchError {
+    #[error("Failed to connect to the X server: {0}")]
+    Connect(#[from] ConnectError),
+    #[error("X11 connection error: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error("X11 error: {0}")]
+    X11(#[from] ReplyError),
+    #[error("Failed to acquire {0} atom.")]
+    AtomNotFound(String),
+    #[error("Failed to get property {property} of window 0x{window:08x}: {source}")]
+    Property {
+        window: u32,
+        property: String,
+        source: ReplyError,
+    },
+    /// `type_atom` is the raw type of the reply, 0 (`None`) if the property has another
+    /// type than requested.
+    #[error(
+        "Unexpected property {property} of window 0x{window:08x}: type={type_atom}, format={format}, len={len}"
+    )]
+    PropertyFormat {
+        window: u32,
+        property: String,
+        type_atom: u32,
+        format: u8,
+        len: usize,
+    },
+}
@@ -0,0 +1,104 @@
+//! `event_log`: 以 JSON Lines 格式记录窗口焦点的变化和输入法的切换, 用于排查自动切换的问题.
+//! 写入 [`RotatingFile`], 大小受 `event_log_max_bytes` 限制.
+
+use crate::rotating::RotatingFile;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Default size cap of the event log, 1 MiB.
+pub const DEFAULT_MAX_BYTES: u64 = 1 << 20;
+
+/// What caused an engine switch.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    /// Applying or adopting the engine on startup.
+    Startup,
+    /// A key binding.
+    Binding,
+    /// A command of a client.
+    Client,
+    /// The engine changed outside the switch server.
+    External,
+    /// Focus moved to a window (`app_engines` or `on_no_window`).
+    Focus,
+    /// A workspace became current (`desktop_engines`).
+    Desktop,
+    /// The automatic switching after focusing another window.
+    Autoswitch,
+    /// Reloading a config without the current engine.
+    Reload,
+}
+
+/// An entry of the event log.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum LogEvent<'a> {
+    /// Focus moved to a window, `window` is None if the window system doesn't report ids (Wayland)
+    /// and 0 if no window is focused.
+    Focus {
+        window: Option<u32>,
+        wm_class: Option<&'a str>,
+        title: Option<&'a str>,
+    },
+    /// The engine changed from `from` to `to`.
+    Switch {
+        from: &'a str,
+        to: &'a str,
+        trigger: Trigger,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    /// Unix 时间戳, 单位为毫秒.
+    timestamp: u128,
+    #[serde(flatten)]
+    event: &'a LogEvent<'a>,
+}
+
+/// One line of the event log for `event` happening at `time`, including the trailing newline:
+/// a JSON object with `timestamp` (unix milliseconds), `event` (`focus` or `switch`) and the fields of the event.
+pub fn event_line(time: SystemTime, event: &LogEvent) -> String {
+    let timestamp = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let mut line = serde_json::to_string(&Record { timestamp, event })
+        .expect("event log records serialize to JSON");
+    line.push('\n');
+    line
+}
+
+/// Appends events to a JSON Lines [`RotatingFile`].
+#[derive(Debug)]
+pub struct EventLog {
+    path: PathBuf,
+    file: Mutex<RotatingFile>,
+}
+
+impl EventLog {
+    /// Open `path` with the size cap `max_bytes`, see [`RotatingFile::open`].
+    pub fn open(path: PathBuf, max_bytes: u64) -> Result<EventLog, io::Error> {
+        let file = RotatingFile::open(path.clone(), max_bytes)?;
+        Ok(EventLog {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `event`, happening now.
+    pub fn append(&self, event: &LogEvent) -> Result<(), io::Error> {
+        let line = event_line(SystemTime::now(), event);
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
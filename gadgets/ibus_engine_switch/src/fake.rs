@@ -0,0 +1,78 @@
+//! 测试用的输入法框架: 记录依次切换到的输入法, 两次切换同时进行时 panic.
+
+use crate::backend::InputMethodBackend;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "x11")]
+pub mod x11;
+
+pub struct FakeBackend {
+    engines: Vec<String>,
+    current: Mutex<String>,
+    switched: Arc<Mutex<Vec<String>>>,
+    busy: AtomicBool,
+}
+
+impl FakeBackend {
+    /// `engines` are installed, the first is in use.
+    pub fn new(engines: &[&str]) -> FakeBackend {
+        FakeBackend {
+            engines: engines.iter().map(|&e| e.to_owned()).collect(),
+            current: Mutex::new(engines[0].to_owned()),
+            switched: Arc::default(),
+            busy: AtomicBool::new(false),
+        }
+    }
+
+    /// The engines switched to so far, in order, still readable after the backend is moved.
+    pub fn switched(&self) -> Arc<Mutex<Vec<String>>> {
+        Arc::clone(&self.switched)
+    }
+}
+
+impl InputMethodBackend for FakeBackend {
+    fn current_engine(&self) -> Result<String, anyhow::Error> {
+        Ok(self.current.lock().unwrap().clone())
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<(), anyhow::Error> {
+        assert!(
+            !self.busy.swap(true, Ordering::SeqCst),
+            "concurrent set_engine"
+        );
+        // 留出时间让同时进行的切换被发现.
+        thread::sleep(Duration::from_millis(1));
+        *self.current.lock().unwrap() = engine.to_owned();
+        self.switched.lock().unwrap().push(engine.to_owned());
+        self.busy.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn list_engines(&self) -> Result<Vec<String>, anyhow::Error> {
+        Ok(self.engines.clone())
+    }
+}
+
+/// 没有运行的输入法框架, 所有操作都失败.
+pub struct BrokenBackend;
+
+impl InputMethodBackend for BrokenBackend {
+    fn current_engine(&self) -> Result<String, anyhow::Error> {
+        Err(anyhow::anyhow!("ibus-daemon is not running"))
+    }
+
+    fn set_engine(&self, _engine: &str) -> Result<(), anyhow::Error> {
+        Err(anyhow::anyhow!("ibus-daemon is not running"))
+    }
+
+    fn list_engines(&self) -> Result<Vec<String>, anyhow::Error> {
+        Err(anyhow::anyhow!("ibus-daemon is not running"))
+    }
+}
@@ -0,0 +1,369 @@
+//! 测试用的 X 连接: 属性保存在内存中, 只回答 InternAtom, GetAtomName 和 GetProperty,
+//! 其他需要回复的请求得到 BadImplementation 错误, 不需要回复的请求只记录下来. 不支持任何扩展.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::{self, IoSlice},
+};
+use x11rb::{
+    connection::{Connection, DiscardMode, ReplyOrError, RequestConnection, RequestKind},
+    cookie::{Cookie, CookieWithFds, VoidCookie},
+    errors::{ConnectionError, ParseError, ReplyOrIdError},
+    protocol::{
+        Event,
+        xproto::{self, AtomEnum, GetAtomNameReply, GetPropertyReply, InternAtomReply, Setup},
+    },
+    utils::RawFdContainer,
+    x11_utils::{ExtInfoProvider, ExtensionInformation, Serialize, TryParse, TryParseFd, X11Error},
+};
+
+/// 根窗口, 总是存在.
+pub const ROOT: u32 = 0x1e3;
+
+/// 新的 atom 从这里开始编号, 之前的是预定义的 atom.
+const FIRST_ATOM: u32 = 1000;
+
+/// 没有加载任何扩展.
+struct NoExtensions;
+
+impl ExtInfoProvider for NoExtensions {
+    fn get_from_major_opcode(&self, _: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+
+    fn get_from_event_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+
+    fn get_from_error_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+}
+
+struct Property {
+    type_: u32,
+    format: u8,
+    value: Vec<u8>,
+}
+
+pub struct FakeConnection {
+    setup: Setup,
+    /// 索引加上 FIRST_ATOM 就是 atom.
+    atoms: RefCell<Vec<Vec<u8>>>,
+    properties: RefCell<HashMap<(u32, u32), Property>>,
+    windows: RefCell<Vec<u32>>,
+    replies: RefCell<HashMap<u64, ReplyOrError<Vec<u8>>>>,
+    sequence: Cell<u64>,
+    /// 每个请求的 major opcode, 按发送顺序.
+    requests: RefCell<Vec<u8>>,
+}
+
+impl FakeConnection {
+    /// A connection to a screen with [`ROOT`], which has no properties.
+    pub fn new() -> FakeConnection {
+        let mut setup = Setup::default();
+        setup.roots.push(xproto::Screen {
+            root: ROOT,
+            ..Default::default()
+        });
+        FakeConnection {
+            setup,
+            atoms: RefCell::default(),
+            properties: RefCell::default(),
+            windows: RefCell::new(vec![ROOT]),
+            replies: RefCell::default(),
+            sequence: Cell::new(0),
+            requests: RefCell::default(),
+        }
+    }
+
+    /// The atom of `name`, created if needed, without sending a request.
+    pub fn atom(&self, name: &[u8]) -> u32 {
+        let mut atoms = self.atoms.borrow_mut();
+        let idx = match atoms.iter().position(|a| a == name) {
+            Some(idx) => idx,
+            None => {
+                atoms.push(name.to_owned());
+                atoms.len() - 1
+            }
+        };
+        FIRST_ATOM + idx as u32
+    }
+
+    /// Set the property `name` of `window`, `window` exists afterwards.
+    pub fn set_property(
+        &self,
+        window: u32,
+        name: &[u8],
+        type_: impl Into<u32>,
+        format: u8,
+        value: Vec<u8>,
+    ) {
+        self.add_window(window);
+        let property = Property {
+            type_: type_.into(),
+            format,
+            value,
+        };
+        self.properties
+            .borrow_mut()
+            .insert((window, self.atom(name)), property);
+    }
+
+    /// Set a property of 32-bit `values`, e.g. a CARDINAL or WINDOW.
+    pub fn set_property32(&self, window: u32, name: &[u8], type_: impl Into<u32>, values: &[u32]) {
+        let value = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        self.set_property(window, name, type_, 32, value);
+    }
+
+    pub fn add_window(&self, window: u32) {
+        let mut windows = self.windows.borrow_mut();
+        if !windows.contains(&window) {
+            windows.push(window);
+        }
+    }
+
+    /// Number of requests sent with `opcode`, e.g. [`xproto::INTERN_ATOM_REQUEST`].
+    pub fn count(&self, opcode: u8) -> usize {
+        self.requests
+            .borrow()
+            .iter()
+            .filter(|&&op| op == opcode)
+            .count()
+    }
+
+    fn next_sequence(&self) -> u64 {
+        let sequence = self.sequence.get() + 1;
+        self.sequence.set(sequence);
+        sequence
+    }
+
+    /// 按请求内容生成回复, 窗口不存在时生成 BadWindow 错误.
+    fn answer(&self, request: &[u8], sequence: u64) -> ReplyOrError<Vec<u8>> {
+        let u32_at = |pos: usize| u32::from_ne_bytes(request[pos..pos + 4].try_into().unwrap());
+        let seq = sequence as u16;
+        match request[0] {
+            xproto::INTERN_ATOM_REQUEST => {
+                let len = u16::from_ne_bytes([request[4], request[5]]) as usize;
+                let atom = self.atom(&request[8..8 + len]);
+                ReplyOrError::Reply(reply_bytes(
+                    InternAtomReply {
+                        sequence: seq,
+                        length: 0,
+                        atom,
+                    }
+                    .serialize()
+                    .into(),
+                ))
+            }
+            xproto::GET_ATOM_NAME_REQUEST => {
+                let name = self
+                    .atoms
+                    .borrow()
+                    .get(u32_at(4).wrapping_sub(FIRST_ATOM) as usize)
+                    .cloned()
+                    .unwrap_or_default();
+                ReplyOrError::Reply(reply_bytes(
+                    GetAtomNameReply {
+                        sequence: seq,
+                        length: 0,
+                        name,
+                    }
+                    .serialize(),
+                ))
+            }
+            xproto::GET_PROPERTY_REQUEST => {
+                let (window, atom, type_) = (u32_at(4), u32_at(8), u32_at(12));
+                if !self.windows.borrow().contains(&window) {
+                    return ReplyOrError::Error(x_error(BAD_WINDOW, window, seq, request[0]));
+                }
+                let properties = self.properties.borrow();
+                let reply = match properties.get(&(window, atom)) {
+                    None => property_reply(seq, u32::from(AtomEnum::NONE), 0, Vec::new(), 0),
+                    // 类型不符时和 X 服务器一样, 不返回数据, 只在 bytes_after 中给出长度.
+                    Some(p) if type_ != u32::from(AtomEnum::ANY) && type_ != p.type_ => {
+                        property_reply(seq, p.type_, p.format, Vec::new(), p.value.len() as u32)
+                    }
+                    Some(p) => {
+                        let (offset, length) = (u32_at(16) as usize * 4, u32_at(20) as usize);
+                        let value: Vec<u8> = p
+                            .value
+                            .iter()
+                            .skip(offset)
+                            .take(length.saturating_mul(4))
+                            .copied()
+                            .collect();
+                        let bytes_after = p.value.len().saturating_sub(offset + value.len());
+                        property_reply(seq, p.type_, p.format, value, bytes_after as u32)
+                    }
+                };
+                ReplyOrError::Reply(reply_bytes(reply.serialize()))
+            }
+            opcode => ReplyOrError::Error(x_error(BAD_IMPLEMENTATION, 0, seq, opcode)),
+        }
+    }
+}
+
+fn property_reply(
+    sequence: u16,
+    type_: u32,
+    format: u8,
+    value: Vec<u8>,
+    bytes_after: u32,
+) -> GetPropertyReply {
+    let value_len = match format {
+        0 => 0,
+        format => value.len() as u32 / (format as u32 / 8),
+    };
+    GetPropertyReply {
+        format,
+        sequence,
+        length: 0,
+        type_,
+        bytes_after,
+        value_len,
+        value,
+    }
+}
+
+/// 补齐到 4 字节并填入回复的长度, 序列化时它被留为 0.
+fn reply_bytes(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.resize(bytes.len().max(32).next_multiple_of(4), 0);
+    let length = (bytes.len() as u32 - 32) / 4;
+    bytes[4..8].copy_from_slice(&length.to_ne_bytes());
+    bytes
+}
+
+const BAD_WINDOW: u8 = 3;
+const BAD_IMPLEMENTATION: u8 = 17;
+
+fn x_error(code: u8, bad_value: u32, sequence: u16, major_opcode: u8) -> Vec<u8> {
+    let mut error = vec![0; 32];
+    error[1] = code;
+    error[2..4].copy_from_slice(&sequence.to_ne_bytes());
+    error[4..8].copy_from_slice(&bad_value.to_ne_bytes());
+    error[10] = major_opcode;
+    error
+}
+
+impl RequestConnection for FakeConnection {
+    type Buf = Vec<u8>;
+
+    fn send_request_with_reply<R>(
+        &self,
+        bufs: &[IoSlice<'_>],
+        _fds: Vec<RawFdContainer>,
+    ) -> Result<Cookie<'_, Self, R>, ConnectionError>
+    where
+        R: TryParse,
+    {
+        let request: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        self.requests.borrow_mut().push(request[0]);
+        let sequence = self.next_sequence();
+        let reply = self.answer(&request, sequence);
+        self.replies.borrow_mut().insert(sequence, reply);
+        Ok(Cookie::new(self, sequence))
+    }
+
+    fn send_request_with_reply_with_fds<R>(
+        &self,
+        _bufs: &[IoSlice<'_>],
+        _fds: Vec<RawFdContainer>,
+    ) -> Result<CookieWithFds<'_, Self, R>, ConnectionError>
+    where
+        R: TryParseFd,
+    {
+        Err(ConnectionError::FdPassingFailed)
+    }
+
+    fn send_request_without_reply(
+        &self,
+        bufs: &[IoSlice<'_>],
+        _fds: Vec<RawFdContainer>,
+    ) -> Result<VoidCookie<'_, Self>, ConnectionError> {
+        self.requests.borrow_mut().push(bufs[0][0]);
+        Ok(VoidCookie::new(self, self.next_sequence()))
+    }
+
+    fn discard_reply(&self, sequence: u64, _kind: RequestKind, _mode: DiscardMode) {
+        self.replies.borrow_mut().remove(&sequence);
+    }
+
+    fn prefetch_extension_information(&self, _: &'static str) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn extension_information(
+        &self,
+        _: &'static str,
+    ) -> Result<Option<ExtensionInformation>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn wait_for_reply_or_raw_error(
+        &self,
+        sequence: u64,
+    ) -> Result<ReplyOrError<Vec<u8>>, ConnectionError> {
+        self.replies
+            .borrow_mut()
+            .remove(&sequence)
+            .ok_or(ConnectionError::UnknownError)
+    }
+
+    fn wait_for_reply(&self, sequence: u64) -> Result<Option<Vec<u8>>, ConnectionError> {
+        Ok(match self.wait_for_reply_or_raw_error(sequence)? {
+            ReplyOrError::Reply(reply) => Some(reply),
+            ReplyOrError::Error(_) => None,
+        })
+    }
+
+    fn wait_for_reply_with_fds_raw(
+        &self,
+        _: u64,
+    ) -> Result<ReplyOrError<(Vec<u8>, Vec<RawFdContainer>), Vec<u8>>, ConnectionError> {
+        Err(ConnectionError::FdPassingFailed)
+    }
+
+    fn check_for_raw_error(&self, _: u64) -> Result<Option<Vec<u8>>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn prefetch_maximum_request_bytes(&self) {}
+
+    fn maximum_request_bytes(&self) -> usize {
+        1 << 20
+    }
+
+    fn parse_error(&self, error: &[u8]) -> Result<X11Error, ParseError> {
+        X11Error::try_parse(error, &NoExtensions)
+    }
+
+    fn parse_event(&self, event: &[u8]) -> Result<Event, ParseError> {
+        Event::parse(event, &NoExtensions)
+    }
+}
+
+impl Connection for FakeConnection {
+    fn wait_for_raw_event_with_sequence(&self) -> Result<(Vec<u8>, u64), ConnectionError> {
+        Err(ConnectionError::IoError(
+            io::ErrorKind::UnexpectedEof.into(),
+        ))
+    }
+
+    fn poll_for_raw_event_with_sequence(&self) -> Result<Option<(Vec<u8>, u64)>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    fn generate_id(&self) -> Result<u32, ReplyOrIdError> {
+        Err(ReplyOrIdError::IdsExhausted)
+    }
+}
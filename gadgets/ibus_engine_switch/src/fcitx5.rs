@@ -0,0 +1,183 @@
+use crate::{
+    backend::InputMethodBackend,
+    config::Transport,
+    process::{DEFAULT_TIMEOUT, Program, call_with_timeout},
+};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+use zbus::blocking::Connection;
+
+const FCITX5_SERVICE: &str = "org.fcitx.Fcitx5";
+const FCITX5_PATH: &str = "/controller";
+const FCITX5_INTERFACE: &str = "org.fcitx.Fcitx.Controller1";
+
+/// The `fcitx5-remote` program, used by the process transport.
+pub const FCITX5_REMOTE_PROGRAM: Program = Program {
+    name: "fcitx5-remote",
+    env_var: "FCITX5_REMOTE_PATH",
+    hint: "Install fcitx5 (e.g. sudo apt install fcitx5), or set backend = \"ibus\" in the config to use ibus.",
+};
+
+lazy_static::lazy_static! {
+    static ref FCITX5_REMOTE: Result<PathBuf, String> = FCITX5_REMOTE_PROGRAM.resolve();
+}
+
+fn fcitx5_remote() -> Result<&'static Path, anyhow::Error> {
+    FCITX5_REMOTE.as_deref().map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+/// (unique name, name, native name, icon, label, language code, configurable)
+type InputMethodEntry = (String, String, String, String, String, String, bool);
+
+/// Client of the fcitx5 daemon, over the session bus or `fcitx5-remote`.
+pub struct Fcitx5 {
+    conn: Option<Connection>,
+}
+
+impl Fcitx5 {
+    /// D-Bus falls back to spawning `fcitx5-remote` if the session bus can't be reached.
+    pub fn new(transport: Transport) -> Fcitx5 {
+        let conn = match transport {
+            Transport::Dbus => match Connection::session() {
+                Ok(conn) => {
+                    info!("Connected to fcitx5 over D-Bus.");
+                    Some(conn)
+                }
+                Err(e) => {
+                    warn!("Failed to connect to session bus, fall back to process: {e}");
+                    None
+                }
+            },
+            Transport::Process => None,
+        };
+        Fcitx5 { conn }
+    }
+
+    fn call_method<B>(
+        &self,
+        conn: &Connection,
+        method: &str,
+        body: &B,
+    ) -> zbus::Result<zbus::Message>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        conn.call_method(
+            Some(FCITX5_SERVICE),
+            FCITX5_PATH,
+            Some(FCITX5_INTERFACE),
+            method,
+            body,
+        )
+    }
+}
+
+impl InputMethodBackend for Fcitx5 {
+    fn current_engine(&self) -> Result<String, anyhow::Error> {
+        match &self.conn {
+            Some(conn) => Ok(self
+                .call_method(conn, "CurrentInputMethod", &())?
+                .body()
+                .deserialize()?),
+            None => {
+                let state =
+                    call_with_timeout(fcitx5_remote()?, Some(&["-n"]), Some(DEFAULT_TIMEOUT))?;
+                if !state.exit_status.success() {
+                    return Err(anyhow::anyhow!(
+                        "fcitx5-remote -n failed: {}",
+                        state.error.trim()
+                    ));
+                }
+                parse_current_input_method(&state.output)
+                    .ok_or_else(|| anyhow::anyhow!("No input method is active."))
+            }
+        }
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<(), anyhow::Error> {
+        match &self.conn {
+            Some(conn) => {
+                self.call_method(conn, "SetCurrentIM", &(engine,))?;
+            }
+            None => {
+                let state = call_with_timeout(
+                    fcitx5_remote()?,
+                    Some(&["-s", engine]),
+                    Some(DEFAULT_TIMEOUT),
+                )?;
+                if !state.exit_status.success() {
+                    return Err(anyhow::anyhow!(
+                        "fcitx5-remote -s {engine} failed: {}",
+                        state.error.trim()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `fcitx5-remote` can't list input methods, so this always requires D-Bus.
+    fn list_engines(&self) -> Result<Vec<String>, anyhow::Error> {
+        let conn = match &self.conn {
+            Some(conn) => conn,
+            None => &Connection::session()?,
+        };
+        let entries: Vec<InputMethodEntry> = self
+            .call_method(conn, "AvailableInputMethods", &())?
+            .body()
+            .deserialize()?;
+        Ok(input_method_names(&entries))
+    }
+
+    fn check_available(&self) -> Result<(), anyhow::Error> {
+        match self.conn {
+            Some(_) => Ok(()),
+            None => fcitx5_remote().map(drop),
+        }
+    }
+}
+
+/// Parse the output of `fcitx5-remote -n`, which is the input method name on a single line.
+pub fn parse_current_input_method(output: &str) -> Option<String> {
+    let name = output.trim();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+fn input_method_names(entries: &[InputMethodEntry]) -> Vec<String> {
+    entries.iter().map(|entry| entry.0.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_input_method_sample() {
+        assert_eq!(
+            parse_current_input_method("keyboard-us\n").as_deref(),
+            Some("keyboard-us")
+        );
+        assert_eq!(parse_current_input_method("rime").as_deref(), Some("rime"));
+        assert_eq!(parse_current_input_method("\n"), None);
+    }
+
+    #[test]
+    fn available_input_methods_sample() {
+        let entry = |name: &str, label: &str, lang: &str| -> InputMethodEntry {
+            (
+                name.to_owned(),
+                label.to_owned(),
+                String::new(),
+                format!("fcitx-{name}"),
+                label.to_owned(),
+                lang.to_owned(),
+                name == "rime",
+            )
+        };
+        let entries = [
+            entry("keyboard-us", "English (US)", "en"),
+            entry("rime", "Rime", "zh"),
+        ];
+        assert_eq!(input_method_names(&entries), ["keyboard-us", "rime"]);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+
+/// Default number of windows kept in [`FocusHistory`].
+pub const DEFAULT_FOCUS_HISTORY_DEPTH: usize = 16;
+
+/// Recently focused windows, most recent first, each window appears at most once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FocusHistory {
+    depth: usize,
+    windows: VecDeque<u32>,
+}
+
+impl Default for FocusHistory {
+    fn default() -> Self {
+        FocusHistory::new(DEFAULT_FOCUS_HISTORY_DEPTH)
+    }
+}
+
+impl FocusHistory {
+    /// Keep at most `depth` windows, older ones are dropped.
+    pub fn new(depth: usize) -> FocusHistory {
+        FocusHistory {
+            depth,
+            windows: VecDeque::with_capacity(depth),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Record that `window_id` got focus, moving it to the front if it's already present.
+    pub fn push(&mut self, window_id: u32) {
+        if self.windows.front() == Some(&window_id) {
+            return;
+        }
+        self.windows.retain(|&w| w != window_id);
+        self.windows.push_front(window_id);
+        self.windows.truncate(self.depth);
+    }
+
+    /// Forget `window_id`, e.g. after it's destroyed.
+    pub fn remove(&mut self, window_id: u32) {
+        self.windows.retain(|&w| w != window_id);
+    }
+
+    /// The currently focused window.
+    pub fn current(&self) -> Option<u32> {
+        self.windows.front().copied()
+    }
+
+    /// The window focused before the current one.
+    pub fn previous(&self) -> Option<u32> {
+        self.windows.get(1).copied()
+    }
+
+    /// Windows from the most recent to the least recent.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.windows.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(history: &FocusHistory) -> Vec<u32> {
+        history.iter().collect()
+    }
+
+    #[test]
+    fn push_dedups_and_moves_to_front() {
+        let mut history = FocusHistory::default();
+        assert!(history.is_empty());
+        assert_eq!(history.previous(), None);
+        for window in [1, 2, 3, 3, 1] {
+            history.push(window);
+        }
+        assert_eq!(windows(&history), [1, 3, 2]);
+        assert_eq!(history.current(), Some(1));
+        assert_eq!(history.previous(), Some(3));
+        history.remove(3);
+        assert_eq!(windows(&history), [1, 2]);
+        assert_eq!(history.previous(), Some(2));
+    }
+
+    #[test]
+    fn push_drops_the_oldest_beyond_depth() {
+        let mut history = FocusHistory::new(3);
+        for window in 1..=5 {
+            history.push(window);
+        }
+        assert_eq!(windows(&history), [5, 4, 3]);
+        // 已在历史中的窗口不会挤掉其他窗口
+        history.push(3);
+        assert_eq!(windows(&history), [3, 5, 4]);
+        assert_eq!(history.len(), history.depth());
+    }
+}
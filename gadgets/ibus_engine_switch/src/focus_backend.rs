@@ -0,0 +1,112 @@
+//! 与窗口系统无关的活动窗口接口, X11 通过 EWMH 属性实现, wlroots 系的 Wayland 混成器
+//! (sway, Hyprland 等) 通过 `wlr-foreign-toplevel-management` 协议实现.
+
+use std::collections::HashMap;
+
+/// The focused top-level window, in terms shared by X11 and Wayland.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FocusedWindow {
+    /// Wayland `app_id`, or the class of `WM_CLASS` under X11.
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Reports the focused window of a window system.
+pub trait FocusBackend {
+    /// The window focused now, None if no window is focused.
+    fn current_window(&mut self) -> Result<Option<FocusedWindow>, anyhow::Error>;
+
+    /// Call `on_focus` each time another window is focused, the window focused when called is reported first.
+    /// Only returns on errors.
+    fn listen(&mut self, on_focus: &mut dyn FnMut(&FocusedWindow)) -> Result<(), anyhow::Error>;
+}
+
+/// Engine configured for `app_id` in `app_engines` (values are indexes in `engines`).
+/// Matches exactly first, then ignoring case, since X11 classes are usually capitalized (`Firefox`)
+/// while Wayland app_ids are not (`firefox`).
+pub fn app_engine(app_engines: &HashMap<String, usize>, app_id: &str) -> Option<usize> {
+    app_engines.get(app_id).copied().or_else(|| {
+        app_engines
+            .iter()
+            .find(|(app, _)| app.eq_ignore_ascii_case(app_id))
+            .map(|(_, &idx)| idx)
+    })
+}
+
+#[cfg(feature = "x11")]
+mod x11 {
+    use super::{FocusBackend, FocusedWindow};
+    use crate::{
+        ListenOptions, WindowInfo, listen_active_window_changes_with, poll_active_window_once,
+    };
+
+    impl From<&WindowInfo> for FocusedWindow {
+        fn from(window: &WindowInfo) -> FocusedWindow {
+            FocusedWindow {
+                app_id: window.class.as_ref().map(|(_, class)| class.clone()),
+                title: window.title.clone(),
+            }
+        }
+    }
+
+    /// [`FocusBackend`] over `_NET_ACTIVE_WINDOW`, see [`listen_active_window_changes_with`].
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct X11Focus {
+        pub options: ListenOptions,
+    }
+
+    impl FocusBackend for X11Focus {
+        fn current_window(&mut self) -> Result<Option<FocusedWindow>, anyhow::Error> {
+            Ok(poll_active_window_once()?.as_ref().map(FocusedWindow::from))
+        }
+
+        fn listen(
+            &mut self,
+            on_focus: &mut dyn FnMut(&FocusedWindow),
+        ) -> Result<(), anyhow::Error> {
+            listen_active_window_changes_with(self.options, |_, window| on_focus(&window.into()))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "x11")]
+pub use x11::X11Focus;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_id_rules() {
+        let rules = HashMap::from([
+            ("firefox".to_owned(), 1),
+            ("Firefox".to_owned(), 2),
+            ("org.wezfurlong.wezterm".to_owned(), 0),
+        ]);
+        // 完全相同的优先
+        assert_eq!(app_engine(&rules, "Firefox"), Some(2));
+        assert_eq!(app_engine(&rules, "firefox"), Some(1));
+        assert_eq!(app_engine(&rules, "ORG.wezfurlong.WezTerm"), Some(0));
+        assert_eq!(app_engine(&rules, "firefox-esr"), None);
+        assert_eq!(app_engine(&HashMap::new(), "firefox"), None);
+    }
+
+    #[cfg(feature = "x11")]
+    #[test]
+    fn x11_windows_use_the_class() {
+        let window = crate::WindowInfo {
+            id: 0x3c00016,
+            class: Some(("Navigator".to_owned(), "firefox".to_owned())),
+            title: Some("Mozilla Firefox".to_owned()),
+            ..crate::WindowInfo::none()
+        };
+        assert_eq!(
+            FocusedWindow::from(&window),
+            FocusedWindow {
+                app_id: Some("firefox".to_owned()),
+                title: Some("Mozilla Firefox".to_owned()),
+            }
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! `on_switch_command`: 每次输入法改变后运行的外部命令.
+
+use crate::process::{DEFAULT_TIMEOUT, call_with_timeout};
+use std::thread;
+use tracing::warn;
+
+/// Placeholder of the engine name in the command template.
+pub const ENGINE_PLACEHOLDER: &str = "{engine}";
+
+/// A command template such as `tray-icon --set {engine}`.
+/// The template is split on whitespace and run without a shell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwitchHook {
+    args: Vec<String>,
+}
+
+impl SwitchHook {
+    /// None if `template` has no program.
+    pub fn parse(template: &str) -> Option<SwitchHook> {
+        let args: Vec<String> = template.split_whitespace().map(str::to_owned).collect();
+        (!args.is_empty()).then_some(SwitchHook { args })
+    }
+
+    /// The program and arguments to run for `engine`.
+    /// `{engine}` is replaced inside each argument, so the engine name never splits into more arguments.
+    pub fn command(&self, engine: &str) -> (String, Vec<String>) {
+        let mut args = self
+            .args
+            .iter()
+            .map(|arg| arg.replace(ENGINE_PLACEHOLDER, engine));
+        // parse 保证至少有程序名
+        let prog = args.next().unwrap();
+        (prog, args.collect())
+    }
+
+    /// Run the command for `engine` in the background, failures are logged.
+    pub fn run(&self, engine: &str) {
+        let (prog, args) = self.command(engine);
+        // 非零退出状态已由 call 记录
+        thread::spawn(move || {
+            if let Err(e) = call_with_timeout(&prog, Some(&args), Some(DEFAULT_TIMEOUT)) {
+                warn!("Failed to run on_switch_command {prog}: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(template: &str, engine: &str) -> (String, Vec<String>) {
+        SwitchHook::parse(template).unwrap().command(engine)
+    }
+
+    #[test]
+    fn placeholder_and_arg_splitting() {
+        assert_eq!(
+            command("  tray-icon  --set {engine} ", "rime"),
+            (
+                "tray-icon".to_owned(),
+                vec!["--set".to_owned(), "rime".to_owned()]
+            )
+        );
+        // 占位符在参数内部也被替换, 可以出现多次
+        assert_eq!(
+            command("notify engine={engine} {engine}{engine}", "anthy").1,
+            ["engine=anthy", "anthyanthy"]
+        );
+        // 输入法名称不会被拆分, 也不经过 shell
+        assert_eq!(
+            command("echo {engine}", "a b; rm -rf ~").1,
+            ["a b; rm -rf ~"]
+        );
+        assert_eq!(command("{engine}", "rime"), ("rime".to_owned(), vec![]));
+        assert_eq!(SwitchHook::parse(""), None);
+        assert_eq!(SwitchHook::parse(" \t "), None);
+    }
+}
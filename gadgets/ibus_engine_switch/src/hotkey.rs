@@ -0,0 +1,270 @@
+use crate::bindings::{Action, Binding, Modifier};
+use global_hotkey::{
+    GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState,
+    hotkey::{Code, HotKey, Modifiers},
+};
+use rdev::Key;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Key bindings registered as global hotkeys, only the bound combinations reach us.
+pub struct GlobalHotKeys {
+    // 注销后快捷键失效, 需要一直持有.
+    manager: GlobalHotKeyManager,
+    hotkeys: Vec<HotKey>,
+    actions: HashMap<u32, Action>,
+    failures: Vec<RegisterFailure>,
+}
+
+/// A binding that couldn't be registered, e.g. the combination is already grabbed by the window manager.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisterFailure {
+    /// See [`Binding::combo`].
+    pub combo: String,
+    pub reason: String,
+}
+
+/// Register each of `bindings` with `register`, a failing binding is warned and the rest are still registered.
+/// Returns the failures in the order of `bindings`.
+pub fn register_each(
+    bindings: &[Binding],
+    mut register: impl FnMut(&Binding) -> Result<(), String>,
+) -> Vec<RegisterFailure> {
+    let mut failures = Vec::new();
+    for binding in bindings {
+        if let Err(reason) = register(binding) {
+            let combo = binding.combo();
+            warn!("Failed to register global hotkey {combo}: {reason}");
+            failures.push(RegisterFailure { combo, reason });
+        }
+    }
+    failures
+}
+
+impl GlobalHotKeys {
+    /// Register all bindings, bindings that can't be registered are skipped with a warning,
+    /// see [`GlobalHotKeys::failures`]. Only fails if the hotkey manager can't be created.
+    pub fn register(bindings: &[Binding]) -> Result<GlobalHotKeys, anyhow::Error> {
+        let mut hotkeys = GlobalHotKeys {
+            manager: GlobalHotKeyManager::new()?,
+            hotkeys: Vec::new(),
+            actions: HashMap::new(),
+            failures: Vec::new(),
+        };
+        hotkeys.register_bindings(bindings);
+        Ok(hotkeys)
+    }
+
+    /// Unregister the current bindings and register `bindings` instead, e.g. after reloading config.
+    pub fn reregister(&mut self, bindings: &[Binding]) -> Result<(), anyhow::Error> {
+        self.manager.unregister_all(&self.hotkeys)?;
+        self.hotkeys.clear();
+        self.actions.clear();
+        self.register_bindings(bindings);
+        Ok(())
+    }
+
+    fn register_bindings(&mut self, bindings: &[Binding]) {
+        let (manager, hotkeys, actions) = (&self.manager, &mut self.hotkeys, &mut self.actions);
+        self.failures = register_each(bindings, |binding| {
+            let hotkey = to_hotkey(binding)
+                .ok_or_else(|| "the key can't be registered as a global hotkey".to_owned())?;
+            manager.register(hotkey).map_err(|e| e.to_string())?;
+            hotkeys.push(hotkey);
+            actions.insert(hotkey.id(), binding.action.clone());
+            Ok(())
+        });
+    }
+
+    /// Bindings that failed to register in the last [`register`](GlobalHotKeys::register) or
+    /// [`reregister`](GlobalHotKeys::reregister).
+    pub fn failures(&self) -> &[RegisterFailure] {
+        &self.failures
+    }
+
+    /// Block until a registered hotkey is pressed, returns its action.
+    pub fn next_action(&self) -> Result<Action, anyhow::Error> {
+        loop {
+            if let Some(action) = self.action(next_pressed()?) {
+                return Ok(action.clone());
+            }
+        }
+    }
+
+    /// The action of the hotkey `id` reported by [`next_pressed`].
+    pub fn action(&self, id: u32) -> Option<&Action> {
+        self.actions.get(&id)
+    }
+}
+
+/// Block until any global hotkey is pressed, returns its id. Unlike [`GlobalHotKeys::next_action`]
+/// it doesn't borrow the hotkeys, which can be re-registered meanwhile.
+pub fn next_pressed() -> Result<u32, anyhow::Error> {
+    loop {
+        let event = GlobalHotKeyEvent::receiver().recv()?;
+        if event.state() == HotKeyState::Pressed {
+            return Ok(event.id());
+        }
+    }
+}
+
+pub fn to_hotkey(binding: &Binding) -> Option<HotKey> {
+    let modifiers = binding
+        .modifiers
+        .iter()
+        .fold(Modifiers::empty(), |modifiers, m| {
+            modifiers
+                | match m {
+                    Modifier::Ctrl => Modifiers::CONTROL,
+                    Modifier::Shift => Modifiers::SHIFT,
+                    Modifier::Alt => Modifiers::ALT,
+                    Modifier::Super => Modifiers::SUPER,
+                }
+        });
+    let modifiers = (!modifiers.is_empty()).then_some(modifiers);
+    // 全局快捷键按物理位置注册, 字符按 US 布局的位置换算.
+    Some(HotKey::new(modifiers, to_code(binding.key.physical()?)?))
+}
+
+/// rdev 的按键对应的 (物理位置) 键码, 修饰键等不能单独注册的按键返回 None.
+pub fn to_code(key: Key) -> Option<Code> {
+    let code = match key {
+        Key::KeyA => Code::KeyA,
+        Key::KeyB => Code::KeyB,
+        Key::KeyC => Code::KeyC,
+        Key::KeyD => Code::KeyD,
+        Key::KeyE => Code::KeyE,
+        Key::KeyF => Code::KeyF,
+        Key::KeyG => Code::KeyG,
+        Key::KeyH => Code::KeyH,
+        Key::KeyI => Code::KeyI,
+        Key::KeyJ => Code::KeyJ,
+        Key::KeyK => Code::KeyK,
+        Key::KeyL => Code::KeyL,
+        Key::KeyM => Code::KeyM,
+        Key::KeyN => Code::KeyN,
+        Key::KeyO => Code::KeyO,
+        Key::KeyP => Code::KeyP,
+        Key::KeyQ => Code::KeyQ,
+        Key::KeyR => Code::KeyR,
+        Key::KeyS => Code::KeyS,
+        Key::KeyT => Code::KeyT,
+        Key::KeyU => Code::KeyU,
+        Key::KeyV => Code::KeyV,
+        Key::KeyW => Code::KeyW,
+        Key::KeyX => Code::KeyX,
+        Key::KeyY => Code::KeyY,
+        Key::KeyZ => Code::KeyZ,
+        Key::Num0 => Code::Digit0,
+        Key::Num1 => Code::Digit1,
+        Key::Num2 => Code::Digit2,
+        Key::Num3 => Code::Digit3,
+        Key::Num4 => Code::Digit4,
+        Key::Num5 => Code::Digit5,
+        Key::Num6 => Code::Digit6,
+        Key::Num7 => Code::Digit7,
+        Key::Num8 => Code::Digit8,
+        Key::Num9 => Code::Digit9,
+        Key::F1 => Code::F1,
+        Key::F2 => Code::F2,
+        Key::F3 => Code::F3,
+        Key::F4 => Code::F4,
+        Key::F5 => Code::F5,
+        Key::F6 => Code::F6,
+        Key::F7 => Code::F7,
+        Key::F8 => Code::F8,
+        Key::F9 => Code::F9,
+        Key::F10 => Code::F10,
+        Key::F11 => Code::F11,
+        Key::F12 => Code::F12,
+        Key::Kp0 => Code::Numpad0,
+        Key::Kp1 => Code::Numpad1,
+        Key::Kp2 => Code::Numpad2,
+        Key::Kp3 => Code::Numpad3,
+        Key::Kp4 => Code::Numpad4,
+        Key::Kp5 => Code::Numpad5,
+        Key::Kp6 => Code::Numpad6,
+        Key::Kp7 => Code::Numpad7,
+        Key::Kp8 => Code::Numpad8,
+        Key::Kp9 => Code::Numpad9,
+        Key::Backspace => Code::Backspace,
+        Key::CapsLock => Code::CapsLock,
+        Key::Delete => Code::Delete,
+        Key::DownArrow => Code::ArrowDown,
+        Key::End => Code::End,
+        Key::Escape => Code::Escape,
+        Key::Home => Code::Home,
+        Key::LeftArrow => Code::ArrowLeft,
+        Key::PageDown => Code::PageDown,
+        Key::PageUp => Code::PageUp,
+        Key::Return => Code::Enter,
+        Key::RightArrow => Code::ArrowRight,
+        Key::Space => Code::Space,
+        Key::Tab => Code::Tab,
+        Key::UpArrow => Code::ArrowUp,
+        Key::PrintScreen => Code::PrintScreen,
+        Key::ScrollLock => Code::ScrollLock,
+        Key::Pause => Code::Pause,
+        Key::NumLock => Code::NumLock,
+        Key::BackQuote => Code::Backquote,
+        Key::Minus => Code::Minus,
+        Key::Equal => Code::Equal,
+        Key::LeftBracket => Code::BracketLeft,
+        Key::RightBracket => Code::BracketRight,
+        Key::SemiColon => Code::Semicolon,
+        Key::Quote => Code::Quote,
+        Key::BackSlash => Code::Backslash,
+        Key::IntlBackslash => Code::IntlBackslash,
+        Key::Comma => Code::Comma,
+        Key::Dot => Code::Period,
+        Key::Slash => Code::Slash,
+        Key::Insert => Code::Insert,
+        Key::KpReturn => Code::NumpadEnter,
+        Key::KpMinus => Code::NumpadSubtract,
+        Key::KpPlus => Code::NumpadAdd,
+        Key::KpMultiply => Code::NumpadMultiply,
+        Key::KpDivide => Code::NumpadDivide,
+        Key::KpDelete => Code::NumpadDecimal,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::KeySpec;
+
+    #[test]
+    fn failing_bindings_do_not_stop_the_rest() {
+        let binding = |key, action| Binding {
+            modifiers: vec![Modifier::Ctrl],
+            key: KeySpec::Physical(key),
+            action,
+        };
+        let bindings = [
+            binding(Key::LeftBracket, Action::ToEnglish),
+            binding(Key::F5, Action::Toggle),
+            binding(Key::Space, Action::ToOther),
+        ];
+        let grabbed = bindings[1].combo();
+        let mut registered = Vec::new();
+        let failures = register_each(&bindings, |binding| {
+            if binding.combo() == grabbed {
+                return Err("already grabbed".to_owned());
+            }
+            registered.push(binding.action.clone());
+            Ok(())
+        });
+        assert_eq!(registered, [Action::ToEnglish, Action::ToOther]);
+        assert_eq!(
+            failures,
+            [RegisterFailure {
+                combo: grabbed,
+                reason: "already grabbed".to_owned(),
+            }]
+        );
+        assert!(register_each(&bindings, |_| Ok(())).is_empty());
+        assert_eq!(register_each(&bindings, |_| Err(String::new())).len(), 3);
+    }
+}
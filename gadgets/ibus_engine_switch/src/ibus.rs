@@ -0,0 +1,331 @@
+use crate::{
+    backend::InputMethodBackend,
+    config::Transport,
+    process::{DEFAULT_TIMEOUT, Program, call_with_timeout},
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use tracing::{info, warn};
+use zbus::{
+    MatchRule,
+    blocking::{Connection, MessageIterator, connection},
+    message::Type,
+    zvariant::{OwnedValue, Value},
+};
+
+const IBUS_SERVICE: &str = "org.freedesktop.IBus";
+const IBUS_OBJECT_PATH: &str = "/org/freedesktop/IBus";
+const IBUS_INTERFACE: &str = "org.freedesktop.IBus";
+
+/// The `ibus` program, used by the process transport and to find the IBus bus.
+pub const IBUS_PROGRAM: Program = Program {
+    name: "ibus",
+    env_var: "IBUS_PATH",
+    hint: "Install ibus (e.g. sudo apt install ibus), or set backend = \"fcitx5\" in the config to use fcitx5.",
+};
+
+lazy_static::lazy_static! {
+    static ref IBUS: Result<PathBuf, String> = IBUS_PROGRAM.resolve();
+}
+
+/// 找不到 ibus 时只有调用它的操作失败, 不影响其他功能.
+fn ibus() -> Result<&'static Path, anyhow::Error> {
+    IBUS.as_deref().map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+enum Channel {
+    Dbus(Mutex<Connection>),
+    Process,
+}
+
+/// Client of the IBus daemon.
+pub struct IBus {
+    channel: Channel,
+}
+
+impl IBus {
+    /// Connect to IBus with the preferred transport.
+    /// D-Bus falls back to spawning the `ibus` program if the IBus bus can't be reached.
+    pub fn new(transport: Transport) -> IBus {
+        let channel = match transport {
+            Transport::Dbus => match connect_bus() {
+                Ok(conn) => {
+                    info!("Connected to IBus over D-Bus.");
+                    Channel::Dbus(Mutex::new(conn))
+                }
+                Err(e) => {
+                    warn!("Failed to connect to IBus over D-Bus, fall back to process: {e}");
+                    Channel::Process
+                }
+            },
+            Transport::Process => Channel::Process,
+        };
+        IBus { channel }
+    }
+
+    /// The transport actually in use.
+    pub fn transport(&self) -> Transport {
+        match self.channel {
+            Channel::Dbus(_) => Transport::Dbus,
+            Channel::Process => Transport::Process,
+        }
+    }
+
+    /// Call over D-Bus, reconnect and retry once if failed (e.g. the IBus daemon restarted).
+    fn with_bus<T>(
+        conn: &Mutex<Connection>,
+        f: impl Fn(&Connection) -> Result<T, anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        let mut conn = conn.lock().unwrap();
+        match f(&conn) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                warn!("IBus D-Bus call failed, reconnecting: {e}");
+                *conn = connect_bus()?;
+                f(&conn)
+            }
+        }
+    }
+
+    pub fn set_global_engine(&self, engine: &str) -> Result<(), anyhow::Error> {
+        match &self.channel {
+            Channel::Dbus(conn) => Self::with_bus(conn, |conn| {
+                conn.call_method(
+                    Some(IBUS_SERVICE),
+                    IBUS_OBJECT_PATH,
+                    Some(IBUS_INTERFACE),
+                    "SetGlobalEngine",
+                    &(engine,),
+                )?;
+                Ok(())
+            }),
+            Channel::Process => {
+                let state =
+                    call_with_timeout(ibus()?, Some(&["engine", engine]), Some(DEFAULT_TIMEOUT))?;
+                if !state.exit_status.success() {
+                    return Err(anyhow::anyhow!(
+                        "ibus engine {engine} failed: {}",
+                        state.error.trim()
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_global_engine(&self) -> Result<String, anyhow::Error> {
+        match &self.channel {
+            Channel::Dbus(conn) => Self::with_bus(conn, get_global_engine),
+            Channel::Process => {
+                let state = call_with_timeout(ibus()?, Some(&["engine"]), Some(DEFAULT_TIMEOUT))?;
+                // 没有设置输入法时 ibus 输出 "No engine is set." 到标准错误流并以非零状态退出.
+                if !state.exit_status.success() {
+                    return Err(anyhow::anyhow!(
+                        "ibus engine failed: {}",
+                        state.error.trim()
+                    ));
+                }
+                parse_engine(&state.output).ok_or_else(|| anyhow::anyhow!("No engine is set."))
+            }
+        }
+    }
+}
+
+fn get_global_engine(conn: &Connection) -> Result<String, anyhow::Error> {
+    let reply = conn.call_method(
+        Some(IBUS_SERVICE),
+        IBUS_OBJECT_PATH,
+        Some(IBUS_INTERFACE),
+        "GetGlobalEngine",
+        &(),
+    )?;
+    let desc: OwnedValue = reply.body().deserialize()?;
+    engine_name_of_desc(&desc).ok_or_else(|| anyhow::anyhow!("Unexpected IBusEngineDesc: {desc:?}"))
+}
+
+/// Listen `GlobalEngineChanged` of the IBus daemon, changes made by ourselves are included.
+/// When the daemon restarts (the IBus bus goes away), reconnect and report the current engine,
+/// since a restarted daemon resets to its default engine.
+pub fn listen_engine_changes(mut on_change: impl FnMut(&str)) -> ! {
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+    let mut backoff = Duration::from_secs(1);
+    let mut reconnecting = false;
+    loop {
+        match watch_global_engine(&mut on_change, reconnecting) {
+            Ok(()) => {
+                info!("IBus bus closed, the daemon may be restarting.");
+                backoff = Duration::from_secs(1);
+            }
+            Err(e) => {
+                warn!("Failed to watch IBus engine changes: {e}");
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        reconnecting = true;
+        thread::sleep(backoff);
+    }
+}
+
+/// 监听直到总线断开, `reconnecting` 时先报告一次当前的输入法.
+fn watch_global_engine(
+    on_change: &mut impl FnMut(&str),
+    reconnecting: bool,
+) -> Result<(), anyhow::Error> {
+    let conn = connect_bus()?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(IBUS_INTERFACE)?
+        .member("GlobalEngineChanged")?
+        .build();
+    let messages = MessageIterator::for_match_rule(rule, &conn, None)?;
+    if reconnecting {
+        on_change(&get_global_engine(&conn)?);
+    }
+    for message in messages {
+        let engine: String = message?.body().deserialize()?;
+        on_change(&engine);
+    }
+    Ok(())
+}
+
+impl InputMethodBackend for IBus {
+    fn current_engine(&self) -> Result<String, anyhow::Error> {
+        self.get_global_engine()
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<(), anyhow::Error> {
+        self.set_global_engine(engine)
+    }
+
+    fn list_engines(&self) -> Result<Vec<String>, anyhow::Error> {
+        list_engines()
+    }
+
+    fn check_available(&self) -> Result<(), anyhow::Error> {
+        match self.channel {
+            Channel::Dbus(_) => Ok(()),
+            Channel::Process => ibus().map(drop),
+        }
+    }
+}
+
+/// Parse the output of `ibus engine`, which is the engine id on a single line, e.g. `xkb:us::eng`.
+/// None if the output is empty.
+pub fn parse_engine(output: &str) -> Option<String> {
+    let engine = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+    Some(engine.to_owned())
+}
+
+/// List ids of the installed engines, i.e. `ibus list-engine`.
+pub fn list_engines() -> Result<Vec<String>, anyhow::Error> {
+    let state = call_with_timeout(ibus()?, Some(&["list-engine"]), Some(DEFAULT_TIMEOUT))?;
+    if !state.exit_status.success() {
+        return Err(anyhow::anyhow!(
+            "ibus list-engine failed: {}",
+            state.error.trim()
+        ));
+    }
+    Ok(parse_list_engine(&state.output))
+}
+
+/// Parse engine ids from `ibus list-engine` output, which looks like:
+///
+/// ```text
+/// language: English
+///   xkb:us::eng - English (US)
+/// language: Chinese
+///   rime - 中州韻
+/// ```
+pub fn parse_list_engine(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.starts_with(char::is_whitespace))
+        .filter_map(|line| {
+            let line = line.trim();
+            let id = line.split_once(" - ").map_or(line, |(id, _)| id).trim();
+            (!id.is_empty()).then(|| id.to_owned())
+        })
+        .collect()
+}
+
+/// IBus 使用独立的总线, 地址优先取 `IBUS_ADDRESS`, 否则询问 `ibus address`.
+fn connect_bus() -> Result<Connection, anyhow::Error> {
+    let address = match env::var("IBUS_ADDRESS") {
+        Ok(address) if !address.is_empty() => address,
+        _ => call_with_timeout(ibus()?, Some(&["address"]), Some(DEFAULT_TIMEOUT))?
+            .output
+            .trim()
+            .to_owned(),
+    };
+    if address.is_empty() || address == "(null)" {
+        return Err(anyhow::anyhow!("IBus daemon address is unknown"));
+    }
+    Ok(connection::Builder::address(address.as_str())?.build()?)
+}
+
+/// IBusEngineDesc 序列化为 `(sa{sv}sss...)`, 第三个字段是引擎名.
+fn engine_name_of_desc(value: &Value) -> Option<String> {
+    let mut value = value;
+    while let Value::Value(inner) = value {
+        value = inner;
+    }
+    match value {
+        Value::Structure(desc) => match desc.fields().get(2)? {
+            Value::Str(name) => Some(name.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_engine_sample() {
+        let output = "language: English
+  xkb:us::eng - English (US)
+  xkb:us:dvorak:eng - English (Dvorak)
+language: Chinese
+  rime - 中州韻
+  libpinyin - Intelligent Pinyin
+language: Other
+  xkb:us:intl:eng
+";
+        assert_eq!(
+            parse_list_engine(output),
+            [
+                "xkb:us::eng",
+                "xkb:us:dvorak:eng",
+                "rime",
+                "libpinyin",
+                "xkb:us:intl:eng"
+            ]
+        );
+        assert!(parse_list_engine("").is_empty());
+        assert!(parse_list_engine("language: English\n").is_empty());
+    }
+
+    #[test]
+    fn engine_sample() {
+        assert_eq!(
+            parse_engine("xkb:us::eng\n").as_deref(),
+            Some("xkb:us::eng")
+        );
+        assert_eq!(parse_engine("rime").as_deref(), Some("rime"));
+        assert_eq!(parse_engine("\n  rime \n").as_deref(), Some("rime"));
+        // 没有设置输入法时输出为空
+        assert_eq!(parse_engine(""), None);
+        assert_eq!(parse_engine("\n"), None);
+        assert_eq!(parse_engine(" \n\t\n"), None);
+    }
+}
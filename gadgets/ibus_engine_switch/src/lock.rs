@@ -0,0 +1,91 @@
+use gadgets_common::xdg::runtime_dir;
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// An exclusive `flock` on a file, held until dropped.
+/// Used to keep only one daemon instance running.
+pub struct PidLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl PidLock {
+    /// Default lock file path: `$XDG_RUNTIME_DIR/ibus_engine_switch.lock`.
+    pub fn default_path() -> PathBuf {
+        runtime_dir().join("ibus_engine_switch.lock")
+    }
+
+    /// Lock `path` and write our PID into it, fails if another process holds the lock.
+    pub fn acquire(path: &Path) -> Result<PidLock, anyhow::Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(anyhow::anyhow!(
+                    "Another instance is already running (lock {} is held).",
+                    path.display()
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+        Ok(PidLock {
+            file,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Rewrite the PID, e.g. after [`crate::daemon::daemonize`] forked.
+    pub fn write_pid(&mut self) -> Result<(), io::Error> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", process::id())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn second_acquire_fails_while_held() {
+        let path = env::temp_dir().join(format!("ibus_engine_switch-{}.lock", process::id()));
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+        let error = PidLock::acquire(&path).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Another instance is already running"),
+            "{error}"
+        );
+        drop(lock);
+        // 释放之后可以再次获取.
+        drop(PidLock::acquire(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,17 @@
+//! `--log-file`: 日志除了照常输出到终端, 还由 `tracing_appender` 的后台线程写入 [`RotatingFile`],
+//! 不阻塞记录日志的线程.
+
+use crate::rotating::RotatingFile;
+use std::{io, path::PathBuf};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+
+/// Default size cap of the log file, 10 MiB.
+pub const DEFAULT_MAX_BYTES: u64 = 10 << 20;
+
+/// Open `path` (see [`RotatingFile::open`]) and write to it in a background thread.
+/// Keep the guard until exiting, dropping it writes out the logs still queued.
+pub fn open(path: PathBuf, max_bytes: u64) -> Result<(NonBlocking, WorkerGuard), io::Error> {
+    Ok(tracing_appender::non_blocking(RotatingFile::open(
+        path, max_bytes,
+    )?))
+}
@@ -0,0 +1,104 @@
+//! 切换服务器的运行统计, 通过 `metrics` 命令查询.
+
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+/// Counters of the switch server, updated from the server, hotkey and window listener threads.
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    switches: AtomicU64,
+    focus_events: AtomicU64,
+    /// 每个输入法被切换到的次数, 下标与配置的 engines 相同.
+    engine_switches: Vec<AtomicU64>,
+}
+
+/// A snapshot of [`Metrics`], the reply of the `metrics` command as JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MetricsSnapshot {
+    pub uptime_secs: u64,
+    /// Times the engine changed, including external changes. Failed attempts are not counted.
+    pub switches: u64,
+    /// Window focus changes handled.
+    pub focus_events: u64,
+    /// Switches per engine, omitted if the reply would exceed a protocol frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engines: Option<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn new(engines: usize) -> Metrics {
+        Metrics {
+            started: Instant::now(),
+            switches: AtomicU64::new(0),
+            focus_events: AtomicU64::new(0),
+            engine_switches: (0..engines).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// Record that the engine changed to the `idx`th engine.
+    pub fn record_switch(&self, idx: usize) {
+        self.switches.fetch_add(1, Ordering::Relaxed);
+        if let Some(count) = self.engine_switches.get(idx) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Keep the per-engine counters of engines still in `new` after the engine list changed from `old`.
+    pub fn remap_engines(&mut self, old: &[String], new: &[String]) {
+        let counts: Vec<u64> = self
+            .engine_switches
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        self.engine_switches = new
+            .iter()
+            .map(|engine| {
+                let count = old
+                    .iter()
+                    .position(|e| e == engine)
+                    .map_or(0, |i| counts[i]);
+                AtomicU64::new(count)
+            })
+            .collect();
+    }
+
+    pub fn record_focus(&self) {
+        self.focus_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `engines` are the names of the configured engines, in order.
+    pub fn snapshot(&self, engines: &[String]) -> MetricsSnapshot {
+        MetricsSnapshot {
+            uptime_secs: self.started.elapsed().as_secs(),
+            switches: self.switches.load(Ordering::Relaxed),
+            focus_events: self.focus_events.load(Ordering::Relaxed),
+            engines: Some(
+                engines
+                    .iter()
+                    .zip(&self.engine_switches)
+                    .map(|(engine, count)| (engine.clone(), count.load(Ordering::Relaxed)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// JSON that fits in a protocol frame (255 bytes), dropping the per-engine counts if needed.
+    pub fn to_reply(&self) -> String {
+        let json = serde_json::to_string(self).unwrap();
+        if json.len() <= u8::MAX as usize {
+            return json;
+        }
+        let snapshot = MetricsSnapshot {
+            engines: None,
+            ..self.clone()
+        };
+        serde_json::to_string(&snapshot).unwrap()
+    }
+}
@@ -0,0 +1,136 @@
+//! 通过 RandR 找到窗口所在的显示器, 多个显示器组合为一个 X 屏幕时活动窗口是全局的.
+
+use crate::{IbusSwitchError, atoms::AtomCache};
+use x11rb::{
+    connection::Connection,
+    protocol::{randr::ConnectionExt as _, xproto::ConnectionExt as _},
+};
+
+/// A monitor of the RandR 1.5 monitor list, in root window coordinates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Monitor {
+    /// Output name such as `HDMI-1`.
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Monitor {
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        x >= self.x
+            && y >= self.y
+            && i64::from(x) < i64::from(self.x) + i64::from(self.width)
+            && i64::from(y) < i64::from(self.y) + i64::from(self.height)
+    }
+}
+
+/// The monitor containing `point`, the first one if monitors overlap (e.g. mirrored).
+pub fn monitor_at(monitors: &[Monitor], point: (i32, i32)) -> Option<&Monitor> {
+    monitors.iter().find(|monitor| monitor.contains(point))
+}
+
+/// Active monitors of the screen whose root window is `root`.
+pub fn list_monitors(
+    conn: &impl Connection,
+    atoms: &AtomCache,
+    root: u32,
+) -> Result<Vec<Monitor>, IbusSwitchError> {
+    conn.randr_get_monitors(root, true)?
+        .reply()?
+        .monitors
+        .into_iter()
+        .map(|info| {
+            Ok(Monitor {
+                name: atoms.name(conn, info.name)?,
+                x: info.x.into(),
+                y: info.y.into(),
+                width: info.width.into(),
+                height: info.height.into(),
+            })
+        })
+        .collect()
+}
+
+/// Center of the window in root window coordinates, and the root window.
+fn window_center(
+    conn: &impl Connection,
+    window: u32,
+) -> Result<((i32, i32), u32), IbusSwitchError> {
+    let geometry = conn.get_geometry(window)?.reply()?;
+    // 几何位置相对于父窗口 (通常是窗口管理器的边框), 转换为根窗口坐标.
+    let origin = conn
+        .translate_coordinates(window, geometry.root, 0, 0)?
+        .reply()?;
+    let center = center_of(
+        (origin.dst_x, origin.dst_y),
+        geometry.width,
+        geometry.height,
+    );
+    Ok((center, geometry.root))
+}
+
+/// 左上角在 `origin`, 大小为 `width`x`height` 的矩形的中心.
+fn center_of(origin: (i16, i16), width: u16, height: u16) -> (i32, i32) {
+    (
+        i32::from(origin.0) + i32::from(width) / 2,
+        i32::from(origin.1) + i32::from(height) / 2,
+    )
+}
+
+/// Name of the monitor showing `window`.
+/// A window spanning several monitors is on the one containing its center, None if no monitor does.
+pub fn window_monitor(
+    conn: &impl Connection,
+    atoms: &AtomCache,
+    window: u32,
+) -> Result<Option<String>, IbusSwitchError> {
+    let (center, root) = window_center(conn, window)?;
+    let monitors = list_monitors(conn, atoms, root)?;
+    Ok(monitor_at(&monitors, center).map(|monitor| monitor.name.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, y: i32, width: u32, height: u32) -> Monitor {
+        Monitor {
+            name: name.to_owned(),
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn center_picks_the_monitor() {
+        // 左边 1920x1080, 右边 2560x1440, 顶端对齐
+        let monitors = [
+            monitor("eDP-1", 0, 0, 1920, 1080),
+            monitor("HDMI-1", 1920, 0, 2560, 1440),
+        ];
+        let at = |origin, width, height| {
+            monitor_at(&monitors, center_of(origin, width, height)).map(|m| m.name.as_str())
+        };
+        assert_eq!(at((100, 100), 800, 600), Some("eDP-1"));
+        // 跨两个显示器时看中心: 大部分在右边
+        assert_eq!(at((1500, 0), 1000, 800), Some("HDMI-1"));
+        assert_eq!(at((1000, 0), 1000, 800), Some("eDP-1"));
+        // 中心恰好在边界上时属于右边的显示器
+        assert_eq!(at((1420, 0), 1000, 800), Some("HDMI-1"));
+        // 中心在左边显示器下方的空白处
+        assert_eq!(at((0, 1000), 800, 600), None);
+        // 窗口部分在屏幕外
+        assert_eq!(at((-300, -200), 800, 600), Some("eDP-1"));
+        // 重叠 (镜像) 时取第一个
+        let mirrored = [
+            monitor("eDP-1", 0, 0, 1920, 1080),
+            monitor("HDMI-1", 0, 0, 1920, 1080),
+        ];
+        assert_eq!(monitor_at(&mirrored, (10, 10)).unwrap().name, "eDP-1");
+        assert!(monitor_at(&[], (0, 0)).is_none());
+    }
+}
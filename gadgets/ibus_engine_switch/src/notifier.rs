@@ -0,0 +1,42 @@
+use notify_rust::{Notification, NotificationHandle, Timeout};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Notifications posted within this interval replace the previous one instead of stacking up.
+const DEBOUNCE: Duration = Duration::from_millis(1500);
+const TIMEOUT: Timeout = Timeout::Milliseconds(1500);
+
+/// Posts desktop notifications on engine changes.
+#[derive(Default)]
+pub struct Notifier {
+    last: Option<(Instant, NotificationHandle)>,
+}
+
+impl Notifier {
+    /// Show the engine, failures (e.g. no notification daemon) are only logged.
+    pub fn notify_engine(&mut self, engine: &str) {
+        let body = format!("Input: {engine}");
+        // 距离上次通知太近时直接更新上次的通知, 避免快速切换时刷屏.
+        if let Some((time, handle)) = &mut self.last
+            && time.elapsed() < DEBOUNCE
+        {
+            handle.body = body.clone();
+            match handle.update() {
+                Ok(()) => {
+                    *time = Instant::now();
+                    return;
+                }
+                Err(e) => warn!("Failed to update notification: {e}"),
+            }
+        }
+        match Notification::new()
+            .summary("ibus_engine_switch")
+            .body(&body)
+            .timeout(TIMEOUT)
+            .show()
+        {
+            Ok(handle) => self.last = Some((Instant::now(), handle)),
+            Err(e) => warn!("Failed to post notification: {e}"),
+        }
+    }
+}
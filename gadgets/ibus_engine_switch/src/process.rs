@@ -0,0 +1,306 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    io::{self, Read},
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// An external program the daemon calls, located by [`Program::resolve`].
+#[derive(Clone, Copy, Debug)]
+pub struct Program {
+    pub name: &'static str,
+    /// Environment variable giving the path of the program, overriding the search in `$PATH`.
+    pub env_var: &'static str,
+    /// Appended to the not-found error, e.g. how to install the program.
+    pub hint: &'static str,
+}
+
+impl Program {
+    /// Path of the program from [`Program::env_var`] or `$PATH`.
+    pub fn resolve(&self) -> Result<PathBuf, String> {
+        self.resolve_with(env::var_os(self.env_var))
+    }
+
+    /// Like [`Program::resolve`], with the value of [`Program::env_var`] given.
+    /// An empty value is treated as unset.
+    pub fn resolve_with(&self, env_value: Option<OsString>) -> Result<PathBuf, String> {
+        let (name, env_var, hint) = (self.name, self.env_var, self.hint);
+        match env_value.filter(|value| !value.is_empty()) {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                if path.is_file() {
+                    Ok(path)
+                } else {
+                    Err(format!(
+                        "{env_var} is set to {}, which is not a file. {hint}",
+                        path.display()
+                    ))
+                }
+            }
+            None => which::which(name).map_err(|e| {
+                format!("{name} is not found in $PATH ({e}), set {env_var} to its path. {hint}")
+            }),
+        }
+    }
+}
+
+/// Timeout of calls made by the daemon, long enough for a busy input method framework.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 等待子进程退出时轮询的间隔.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Appended to output cut at [`CallOptions::max_output`].
+pub const TRUNCATED_MARKER: &str = "\n[output truncated]";
+
+pub struct CallState {
+    pub output: String,
+    pub error: String,
+    pub exit_status: ExitStatus,
+    /// Whether `output` or `error` exceeded [`CallOptions::max_output`] and was cut.
+    pub truncated: bool,
+}
+
+/// Options of [`call_with`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CallOptions {
+    /// Kill the program if it doesn't exit in time, None waits forever.
+    pub timeout: Option<Duration>,
+    /// Keep at most this many bytes of stdout and of stderr each, None keeps everything.
+    /// The rest is still read and discarded so the program never blocks on a full pipe.
+    pub max_output: Option<usize>,
+}
+
+/// 调用外部程序, 等待其退出并收集 stdout 和 stderr.
+pub fn call(
+    prog: impl AsRef<OsStr>,
+    args: Option<&[impl AsRef<OsStr>]>,
+) -> Result<CallState, io::Error> {
+    call_with_timeout(prog, args, None)
+}
+
+/// Like [`call`], but kills the program and returns a [`io::ErrorKind::TimedOut`] error
+/// if it doesn't exit within `timeout`. None waits forever.
+pub fn call_with_timeout(
+    prog: impl AsRef<OsStr>,
+    args: Option<&[impl AsRef<OsStr>]>,
+    timeout: Option<Duration>,
+) -> Result<CallState, io::Error> {
+    call_with(
+        prog,
+        args,
+        CallOptions {
+            timeout,
+            ..CallOptions::default()
+        },
+    )
+}
+
+/// Call the program with `options`, both streams are read concurrently.
+pub fn call_with(
+    prog: impl AsRef<OsStr>,
+    args: Option<&[impl AsRef<OsStr>]>,
+    options: CallOptions,
+) -> Result<CallState, io::Error> {
+    let prog_name = prog.as_ref().to_string_lossy().to_string();
+    let args_line = args
+        .unwrap_or(&[])
+        .iter()
+        .map(|x| x.as_ref().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+    let mut cmd = Command::new(&prog);
+    if let Some(args) = args {
+        cmd.args(args);
+    }
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    // 在单独的线程读取, 这样等待子进程时可以超时, 也不会因为其中一个管道满了而互相阻塞.
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| read_in_background(pipe, options.max_output));
+    if stdout.is_none() {
+        warn!("Can not read stdout of {prog_name}.");
+    }
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| read_in_background(pipe, options.max_output));
+    if stderr.is_none() {
+        warn!("Can not read stderr of {prog_name}.");
+    }
+    let exit_status = match options.timeout {
+        None => child.wait()?,
+        Some(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    // 杀死后读取线程会因管道关闭而结束, 但孙进程可能仍持有管道, 所以不等待它们.
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("Calling {prog_name} {args_line} timed out after {timeout:?}"),
+                    ));
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    };
+    let (output, output_truncated) = join_reader(stdout)?;
+    let (error, error_truncated) = join_reader(stderr)?;
+    if !exit_status.success() {
+        warn!(
+            "Calling {prog_name} {args_line} exit with code {}.",
+            exit_status.code().unwrap_or(-1)
+        );
+    }
+    Ok(CallState {
+        output,
+        error,
+        exit_status,
+        truncated: output_truncated || error_truncated,
+    })
+}
+
+type Reader = JoinHandle<io::Result<(String, bool)>>;
+
+fn read_in_background(pipe: impl Read + Send + 'static, max_output: Option<usize>) -> Reader {
+    thread::spawn(move || read_bounded(pipe, max_output))
+}
+
+/// Read `pipe` to the end, keeping at most `max_output` bytes, returns (content, truncated).
+pub fn read_bounded(
+    mut pipe: impl Read,
+    max_output: Option<usize>,
+) -> Result<(String, bool), io::Error> {
+    let mut content = Vec::new();
+    let truncated = match max_output {
+        None => {
+            pipe.read_to_end(&mut content)?;
+            false
+        }
+        Some(max_output) => {
+            (&mut pipe)
+                .take(max_output as u64)
+                .read_to_end(&mut content)?;
+            // 丢弃剩余的输出, 直到管道关闭
+            io::copy(&mut pipe, &mut io::sink())? > 0
+        }
+    };
+    // 截断处可能切开了 UTF-8 字符, 所以按有损方式解码
+    let mut content = String::from_utf8_lossy(&content).into_owned();
+    if truncated {
+        content.push_str(TRUNCATED_MARKER);
+    }
+    Ok((content, truncated))
+}
+
+fn join_reader(reader: Option<Reader>) -> Result<(String, bool), io::Error> {
+    match reader {
+        Some(reader) => reader
+            .join()
+            .map_err(|_| io::Error::other("Output reader panicked"))?,
+        None => Ok((String::new(), false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: Program = Program {
+        name: "ibus-engine-switch-no-such-program",
+        env_var: "NO_SUCH_PROGRAM_PATH",
+        hint: "Install it.",
+    };
+
+    #[test]
+    fn resolve_with_env_override() {
+        let exe = env::current_exe().unwrap();
+        assert_eq!(PROGRAM.resolve_with(Some(exe.clone().into())), Ok(exe));
+        let sh = Program {
+            name: "sh",
+            ..PROGRAM
+        };
+        // 空值视为未设置, 在 $PATH 中查找.
+        assert!(sh.resolve_with(Some(OsString::new())).unwrap().is_file());
+    }
+
+    #[test]
+    fn resolve_with_not_a_file() {
+        let dir = env::temp_dir();
+        let message = PROGRAM.resolve_with(Some(dir.clone().into())).unwrap_err();
+        assert_eq!(
+            message,
+            format!(
+                "NO_SUCH_PROGRAM_PATH is set to {}, which is not a file. Install it.",
+                dir.display()
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_with_not_in_path() {
+        let message = PROGRAM.resolve_with(None).unwrap_err();
+        assert!(
+            message.starts_with("ibus-engine-switch-no-such-program is not found in $PATH ("),
+            "{message}"
+        );
+        assert!(
+            message.ends_with("), set NO_SUCH_PROGRAM_PATH to its path. Install it."),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn slow_program_is_killed_at_timeout() {
+        let start = Instant::now();
+        let error = call_with_timeout("sleep", Some(&["5"]), Some(Duration::from_millis(100)))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(error.to_string().contains("sleep 5 timed out"), "{error}");
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let state = call_with_timeout("echo", Some(&["hi"]), Some(Duration::from_secs(5))).unwrap();
+        assert!(state.exit_status.success());
+        assert_eq!(state.output, "hi\n");
+    }
+
+    #[test]
+    fn output_over_the_cap_is_truncated() {
+        let (content, truncated) = read_bounded(&b"abcdef"[..], Some(4)).unwrap();
+        assert!(truncated);
+        assert_eq!(content, format!("abcd{TRUNCATED_MARKER}"));
+        assert_eq!(
+            read_bounded(&b"abcd"[..], Some(4)).unwrap(),
+            ("abcd".to_owned(), false)
+        );
+        assert_eq!(
+            read_bounded(&b"abcdef"[..], None).unwrap(),
+            ("abcdef".to_owned(), false)
+        );
+
+        // 两个管道都写得比管道缓冲区多, 同时读取才不会死锁
+        let script =
+            "head -c 200000 /dev/zero | tr '\\0' o; head -c 200000 /dev/zero | tr '\\0' e >&2";
+        let options = CallOptions {
+            timeout: Some(Duration::from_secs(10)),
+            max_output: Some(10),
+        };
+        let state = call_with("sh", Some(&["-c", script]), options).unwrap();
+        assert!(state.exit_status.success());
+        assert!(state.truncated);
+        assert_eq!(state.output, format!("oooooooooo{TRUNCATED_MARKER}"));
+        assert_eq!(state.error, format!("eeeeeeeeee{TRUNCATED_MARKER}"));
+    }
+}
@@ -0,0 +1,166 @@
+//! 与切换服务器通信的协议: 每条消息由 1 字节长度和紧随其后的 UTF-8 文本组成,
+//! 客户端发送命令, 服务器回复一条消息. `subscribe` 例外, 见 [`Command::Subscribe`].
+
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+};
+
+/// Commands accepted by the switch server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// 切换到下一个输入法. 旧的客户端发送的 `switch` 也解析为此命令.
+    Toggle,
+    /// 切换到英文输入法 (第一个输入法).
+    English,
+    /// 切换到第二个输入法.
+    Other,
+    /// 查询当前输入法.
+    Status,
+    /// 切换到指定的输入法, 可以是输入法名或其在配置中的索引.
+    Set(String),
+    /// 激活上一个获得焦点的窗口.
+    PrevWindow,
+    /// 查询运行统计, 回复 JSON.
+    Metrics,
+    /// 保持连接, 先回复当前输入法, 之后每次输入法改变时回复新的输入法, 每个输入法名占一行 (不分帧).
+    Subscribe,
+    /// 重新读取配置文件, 与 SIGHUP 相同.
+    Reload,
+    /// 回复之后清理并退出, 与 SIGTERM 相同.
+    Quit,
+}
+
+impl Command {
+    /// Whether the command switches the engine, see [`crate::throttle::Throttle`].
+    pub fn switches_engine(&self) -> bool {
+        matches!(
+            self,
+            Command::Toggle | Command::English | Command::Other | Command::Set(_)
+        )
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Toggle => write!(f, "toggle"),
+            Command::English => write!(f, "english"),
+            Command::Other => write!(f, "other"),
+            Command::Status => write!(f, "status"),
+            Command::Set(engine) => write!(f, "set {engine}"),
+            Command::PrevWindow => write!(f, "prev-window"),
+            Command::Metrics => write!(f, "metrics"),
+            Command::Subscribe => write!(f, "subscribe"),
+            Command::Reload => write!(f, "reload"),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+/// Parse a framed command, i.e. the length byte followed by the command text.
+/// Returns None for short, overlong, non UTF-8 or unknown commands.
+pub fn parse_command(frame: &[u8]) -> Option<Command> {
+    let (&len, text) = frame.split_first()?;
+    if text.len() != len as usize {
+        return None;
+    }
+    match std::str::from_utf8(text).ok()? {
+        "toggle" | "switch" => Some(Command::Toggle),
+        "english" => Some(Command::English),
+        "other" => Some(Command::Other),
+        "status" => Some(Command::Status),
+        "prev-window" => Some(Command::PrevWindow),
+        "metrics" => Some(Command::Metrics),
+        "subscribe" => Some(Command::Subscribe),
+        "reload" => Some(Command::Reload),
+        "quit" => Some(Command::Quit),
+        text => {
+            let engine = text.strip_prefix("set ")?.trim();
+            (!engine.is_empty()).then(|| Command::Set(engine.to_owned()))
+        }
+    }
+}
+
+/// Frame `text` as the length byte followed by its bytes, `text` must not exceed 255 bytes.
+pub fn encode(text: &str) -> Result<Vec<u8>, io::Error> {
+    let len = u8::try_from(text.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message is too long"))?;
+    let mut frame = Vec::with_capacity(text.len() + 1);
+    frame.push(len);
+    frame.extend_from_slice(text.as_bytes());
+    Ok(frame)
+}
+
+/// Read a whole frame, including the length byte.
+pub fn read_frame(reader: &mut impl Read) -> Result<Vec<u8>, io::Error> {
+    let mut len = [0u8; 1];
+    reader.read_exact(&mut len)?;
+    let mut frame = vec![0u8; len[0] as usize + 1];
+    frame[0] = len[0];
+    reader.read_exact(&mut frame[1..])?;
+    Ok(frame)
+}
+
+pub fn write_frame(writer: &mut impl Write, text: &str) -> Result<(), io::Error> {
+    writer.write_all(&encode(text)?)
+}
+
+/// Read a frame and decode its text.
+pub fn read_message(reader: &mut impl Read) -> Result<String, io::Error> {
+    let frame = read_frame(reader)?;
+    Ok(String::from_utf8_lossy(&frame[1..]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<Command> {
+        parse_command(&encode(text).unwrap())
+    }
+
+    #[test]
+    fn valid_commands() {
+        assert_eq!(parse("toggle"), Some(Command::Toggle));
+        assert_eq!(parse("switch"), Some(Command::Toggle));
+        assert_eq!(parse("english"), Some(Command::English));
+        assert_eq!(parse("other"), Some(Command::Other));
+        assert_eq!(parse("status"), Some(Command::Status));
+        assert_eq!(parse("set rime"), Some(Command::Set("rime".to_owned())));
+        assert_eq!(parse("set  1 "), Some(Command::Set("1".to_owned())));
+        for command in [
+            Command::Toggle,
+            Command::Set("xkb:us::eng".to_owned()),
+            Command::Quit,
+        ] {
+            assert_eq!(parse(&command.to_string()), Some(command));
+        }
+    }
+
+    #[test]
+    fn malformed_commands() {
+        assert_eq!(parse_command(b""), None);
+        // 长度与内容不符.
+        assert_eq!(parse_command(b"\x06togg"), None);
+        assert_eq!(parse_command(b"\x02toggle"), None);
+        assert_eq!(parse_command(b"\x02\xff\xfe"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("Toggle"), None);
+        assert_eq!(parse("toggle "), None);
+        assert_eq!(parse("set"), None);
+        assert_eq!(parse("set  "), None);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, "set rime").unwrap();
+        write_frame(&mut buf, "").unwrap();
+        let mut reader = &buf[..];
+        assert_eq!(read_message(&mut reader).unwrap(), "set rime");
+        assert_eq!(read_message(&mut reader).unwrap(), "");
+        assert!(read_frame(&mut reader).is_err());
+        assert!(encode(&"x".repeat(256)).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! 有大小上限的追加写入的文件, `--log-file` 和 `event_log` 共用.
+//! 写入会超过上限时把文件改名为 `<path>.1` (覆盖之前的), 然后写入新的文件.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// A file appended to, rotated to `<path>.1` when a write would exceed `max_bytes`.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Open `path` for appending, creating it and its directory if needed.
+    pub fn open(path: PathBuf, max_bytes: u64) -> Result<RotatingFile, io::Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            file,
+            size,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file `path` is rotated to.
+    pub fn rotated_path(&self) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(".1");
+        PathBuf::from(path)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // 空文件不轮转, 否则一行超过上限时每次都会轮转.
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            fs::rename(&self.path, self.rotated_path())?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = 0;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn rotates_before_exceeding_the_cap() {
+        let dir = env::temp_dir().join(format!(
+            "ibus_engine_switch-rotating-{}",
+            std::process::id()
+        ));
+        let path = dir.join("app.log");
+        let _ = fs::remove_dir_all(&dir);
+        let mut file = RotatingFile::open(path.clone(), 8).unwrap();
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        // 超过上限的一行也写入空文件, 不会再次轮转.
+        file.write_all(b"a long line\n").unwrap();
+        let rotated = fs::read_to_string(file.rotated_path()).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rotated, "second\n");
+        assert_eq!(content, "a long line\n");
+    }
+}
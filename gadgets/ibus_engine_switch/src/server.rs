@@ -0,0 +1,270 @@
+//! 切换服务器: 每个连接在单独的线程中读写, 命令由 `handle` 交给持有切换器的线程依次执行.
+
+use crate::{
+    broadcast::{EngineBroadcast, SUBSCRIBER_TIMEOUT},
+    protocol::{self, Command, parse_command},
+    shutdown::request_shutdown,
+};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use tracing::{debug, field, info, info_span, warn};
+
+/// Port of the switch server.
+pub const PORT: u16 = 14568;
+
+/// Default address of the switch server. An IP rather than `localhost`,
+/// which may resolve to either IPv4 or IPv6 depending on the hosts file.
+pub const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Clients that don't finish sending the command or reading the reply in time are dropped.
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Check that the switch server may listen on `addr`: any client that can connect controls
+/// the input method, so non-loopback addresses are refused unless `allow_remote`.
+pub fn check_bind_addr(addr: IpAddr, allow_remote: bool) -> Result<(), anyhow::Error> {
+    if !addr.is_loopback() && !allow_remote {
+        return Err(anyhow::anyhow!(
+            "bind_addr {addr} is not a loopback address, set allow_remote = true to use it anyway."
+        ));
+    }
+    Ok(())
+}
+
+/// Bind the switch server's port on `addr`.
+pub fn bind(addr: IpAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(SocketAddr::new(addr, PORT))
+}
+
+/// Run `init` (the switcher and its backend) and bind `addr` only if it succeeds,
+/// so clients can't connect before the input method framework is known to work.
+pub fn bind_when_ready<T>(
+    addr: IpAddr,
+    init: impl FnOnce() -> Result<T, anyhow::Error>,
+) -> Result<(T, TcpListener), anyhow::Error> {
+    let ready = init()?;
+    let listener = bind(addr).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to bind port {PORT} on {addr}, is the switch server already running? {e}"
+        )
+    })?;
+    Ok((ready, listener))
+}
+
+/// Connect to the switch server on `addr`.
+pub fn connect(addr: IpAddr) -> io::Result<TcpStream> {
+    TcpStream::connect(SocketAddr::new(addr, PORT))
+}
+
+/// Accept clients on `listener` and reply with what `handle` returns for their commands.
+/// `handle` is called in the thread of each connection, so a slow client only holds its own thread;
+/// it returns None when the command can't be handled any more, the client is then dropped.
+/// Clients sending `subscribe` are handed to `broadcast` instead,
+/// after replying `ok` to `quit` the process is shut down, see [`request_shutdown`].
+pub fn serve(
+    listener: TcpListener,
+    broadcast: EngineBroadcast,
+    handle: impl Fn(Command) -> Option<String> + Send + Sync + 'static,
+) -> ! {
+    let handle = Arc::new(handle);
+    loop {
+        let (mut client, addr) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Socket accept error: {e}");
+                continue;
+            }
+        };
+        let handle = Arc::clone(&handle);
+        let broadcast = broadcast.clone();
+        thread::spawn(move || {
+            serve_client(
+                &mut client,
+                addr,
+                handle.as_ref(),
+                &broadcast,
+                &request_shutdown,
+            )
+        });
+    }
+}
+
+fn serve_client(
+    client: &mut TcpStream,
+    addr: SocketAddr,
+    handle: &dyn Fn(Command) -> Option<String>,
+    broadcast: &EngineBroadcast,
+    shutdown: &dyn Fn() -> Result<(), io::Error>,
+) {
+    let span = info_span!(
+        "client",
+        %addr,
+        command = field::Empty,
+        reply = field::Empty
+    );
+    let _span = span.enter();
+    info!("Connection from {addr}");
+    if let Err(e) = client
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .and_then(|()| client.set_write_timeout(Some(CLIENT_TIMEOUT)))
+    {
+        warn!("Failed to set client timeout: {e}");
+        return;
+    }
+    let frame = match protocol::read_frame(client) {
+        Ok(frame) => frame,
+        Err(e) => {
+            warn!("Client read error: {e}");
+            return;
+        }
+    };
+    let command = parse_command(&frame);
+    let reply = match command.clone() {
+        Some(Command::Subscribe) => {
+            span.record("command", field::display(&Command::Subscribe));
+            subscribe(client, broadcast);
+            return;
+        }
+        Some(command) => {
+            span.record("command", field::display(&command));
+            match handle(command) {
+                Some(reply) => reply,
+                None => return,
+            }
+        }
+        None => {
+            warn!(
+                "Invalid command: {:?}",
+                String::from_utf8_lossy(&frame[1..])
+            );
+            "invalid command".to_owned()
+        }
+    };
+    span.record("reply", reply.as_str());
+    debug!("Reply to client.");
+    if let Err(e) = protocol::write_frame(client, &reply) {
+        warn!("Client write error: {e}");
+    }
+    // 回复之后再退出, 客户端能知道命令已被接受.
+    if command == Some(Command::Quit)
+        && reply == "ok"
+        && let Err(e) = shutdown()
+    {
+        warn!("Failed to shut down: {e}");
+    }
+}
+
+/// 把连接交给 `broadcast`, 由它回复当前输入法和之后的变化, 连接线程随即结束.
+fn subscribe(client: &TcpStream, broadcast: &EngineBroadcast) {
+    let subscriber = match client.try_clone() {
+        Ok(subscriber) => subscriber,
+        Err(e) => {
+            warn!("Failed to keep subscriber: {e}");
+            return;
+        }
+    };
+    if let Err(e) = subscriber.set_write_timeout(Some(SUBSCRIBER_TIMEOUT)) {
+        warn!("Failed to set subscriber timeout: {e}");
+        return;
+    }
+    broadcast.subscribe(subscriber);
+    info!("Subscribed, {} subscribers.", broadcast.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, time::Instant};
+
+    #[test]
+    fn stalled_client_does_not_block_others() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            serve(listener, EngineBroadcast::default(), |command| {
+                Some(format!("ok {command}"))
+            })
+        });
+        // 一个客户端只连接, 另一个只发送了帧的一部分, 都不再继续.
+        let _silent = TcpStream::connect(addr).unwrap();
+        let mut partial = TcpStream::connect(addr).unwrap();
+        let frame = protocol::encode("status").unwrap();
+        partial.write_all(&frame[..3]).unwrap();
+
+        let start = Instant::now();
+        let mut client = TcpStream::connect(addr).unwrap();
+        protocol::write_frame(&mut client, "status").unwrap();
+        assert_eq!(protocol::read_message(&mut client).unwrap(), "ok status");
+        assert!(start.elapsed() < CLIENT_TIMEOUT);
+    }
+
+    #[test]
+    fn bind_addr_must_be_loopback() {
+        let check = |toml: &str, allow_remote| {
+            let config: crate::config::Config = toml::from_str(toml).unwrap();
+            check_bind_addr(config.bind_addr, allow_remote).map(|_| config.bind_addr)
+        };
+        assert_eq!(check("", false).unwrap(), IpAddr::from(Ipv4Addr::LOCALHOST));
+        assert_eq!(
+            check(r#"bind_addr = "127.0.0.1""#, false)
+                .unwrap()
+                .to_string(),
+            "127.0.0.1"
+        );
+        assert_eq!(
+            check(r#"bind_addr = "::1""#, false).unwrap().to_string(),
+            "::1"
+        );
+        for remote in [
+            r#"bind_addr = "0.0.0.0""#,
+            r#"bind_addr = "192.168.1.2""#,
+            r#"bind_addr = "::""#,
+        ] {
+            assert!(check(remote, false).is_err(), "{remote}");
+            assert!(check(remote, true).is_ok(), "{remote}");
+        }
+        // 只接受地址, 不解析主机名
+        assert!(toml::from_str::<crate::config::Config>(r#"bind_addr = "localhost""#).is_err());
+    }
+
+    #[test]
+    fn quit_requests_shutdown_after_replying() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requested = Arc::new(AtomicUsize::new(0));
+        let shutdowns = Arc::clone(&requested);
+        let server = thread::spawn(move || {
+            for reply in ["ok", "ok", "busy"] {
+                let (mut client, addr) = listener.accept().unwrap();
+                let handle = |_command| Some(reply.to_owned());
+                let shutdown = || {
+                    shutdowns.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                };
+                serve_client(
+                    &mut client,
+                    addr,
+                    &handle,
+                    &EngineBroadcast::default(),
+                    &shutdown,
+                );
+            }
+        });
+        let send = |command: &str| {
+            let mut client = TcpStream::connect(addr).unwrap();
+            protocol::write_frame(&mut client, command).unwrap();
+            protocol::read_message(&mut client).unwrap()
+        };
+        assert_eq!(send("status"), "ok");
+        assert_eq!(send("quit"), "ok");
+        // 切换器拒绝时不退出
+        assert_eq!(send("quit"), "busy");
+        server.join().unwrap();
+        assert_eq!(requested.load(Ordering::SeqCst), 1);
+    }
+}
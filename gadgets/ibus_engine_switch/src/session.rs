@@ -0,0 +1,74 @@
+//! 检测图形会话的类型: 纯 Wayland 会话 (没有 XWayland) 中无法连接 X 服务器, 也就无法监听活动窗口.
+
+use std::{env, ffi::OsString};
+
+/// The kind of graphical session the switch server runs in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Session {
+    /// `$DISPLAY` is set: an X11 session, or a Wayland session with XWayland.
+    X11,
+    /// A Wayland session without XWayland, the X server isn't reachable.
+    PureWayland,
+    /// Neither, e.g. started from a tty. Connecting to X is still attempted.
+    Unknown,
+}
+
+impl Session {
+    /// Detect the session from the environment of the current process.
+    pub fn detect() -> Session {
+        Session::from_env(|name| env::var_os(name))
+    }
+
+    /// Detect the session from `DISPLAY`, `WAYLAND_DISPLAY` and `XDG_SESSION_TYPE` looked up by `var`.
+    pub fn from_env(var: impl Fn(&str) -> Option<OsString>) -> Session {
+        // 变量存在但为空视为未设置.
+        let value = |name| var(name).filter(|value| !value.is_empty());
+        if value("DISPLAY").is_some() {
+            Session::X11
+        } else if value("WAYLAND_DISPLAY").is_some()
+            || value("XDG_SESSION_TYPE").is_some_and(|kind| kind == "wayland")
+        {
+            Session::PureWayland
+        } else {
+            Session::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 只设置了 `vars` 的环境中检测到的会话.
+    fn detect(vars: &[(&str, &str)]) -> Session {
+        Session::from_env(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| OsString::from(value))
+        })
+    }
+
+    #[test]
+    fn session_from_env() {
+        assert_eq!(
+            detect(&[("DISPLAY", ":0"), ("XDG_SESSION_TYPE", "x11")]),
+            Session::X11
+        );
+        // 有 XWayland
+        assert_eq!(
+            detect(&[("DISPLAY", ":0"), ("WAYLAND_DISPLAY", "wayland-0")]),
+            Session::X11
+        );
+        assert_eq!(
+            detect(&[("WAYLAND_DISPLAY", "wayland-0")]),
+            Session::PureWayland
+        );
+        assert_eq!(
+            detect(&[("DISPLAY", ""), ("XDG_SESSION_TYPE", "wayland")]),
+            Session::PureWayland
+        );
+        assert_eq!(detect(&[("XDG_SESSION_TYPE", "tty")]), Session::Unknown);
+        assert_eq!(detect(&[("WAYLAND_DISPLAY", "")]), Session::Unknown);
+        assert_eq!(detect(&[]), Session::Unknown);
+    }
+}
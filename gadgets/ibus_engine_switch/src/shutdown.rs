@@ -0,0 +1,71 @@
+//! 收到 SIGINT / SIGTERM 时清理并退出, 收到 SIGHUP 时重新加载配置.
+//! 客户端的 `quit` 命令通过 [`request_shutdown`] 向自身发送 SIGTERM, 走同样的清理流程.
+
+use std::{io, mem::MaybeUninit, process::exit, thread};
+use tracing::info;
+
+/// Signals handled by [`Shutdown::on_signal`], SIGHUP requests a reload rather than a shutdown.
+const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Termination signals blocked by [`Shutdown::block`], handled by [`Shutdown::on_signal`].
+pub struct Shutdown {
+    set: libc::sigset_t,
+}
+
+impl Shutdown {
+    /// Block the termination signals in the current thread and all threads spawned afterwards,
+    /// so they are only received by [`Shutdown::on_signal`]. Call it before spawning any thread.
+    pub fn block() -> Result<Shutdown, io::Error> {
+        // SAFETY: sigset_t 由 sigemptyset 初始化.
+        unsafe {
+            let mut set = MaybeUninit::<libc::sigset_t>::uninit();
+            libc::sigemptyset(set.as_mut_ptr());
+            let mut set = set.assume_init();
+            for signal in SIGNALS {
+                libc::sigaddset(&mut set, signal);
+            }
+            let err = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+            if err != 0 {
+                return Err(io::Error::from_raw_os_error(err));
+            }
+            Ok(Shutdown { set })
+        }
+    }
+
+    /// Wait for signals in a background thread: run `reload` on SIGHUP,
+    /// run `cleanup` and exit on a termination signal.
+    pub fn on_signal(
+        self,
+        mut reload: impl FnMut() + Send + 'static,
+        cleanup: impl FnOnce() + Send + 'static,
+    ) {
+        thread::spawn(move || {
+            loop {
+                let mut signal = 0;
+                // SAFETY: set 已初始化, 且这些信号在所有线程中都被阻塞.
+                let err = unsafe { libc::sigwait(&self.set, &mut signal) };
+                if err == 0 && signal == libc::SIGHUP {
+                    info!("Received SIGHUP, reloading config.");
+                    reload();
+                    continue;
+                }
+                if err == 0 {
+                    info!("Received signal {signal}, shutting down.");
+                }
+                break;
+            }
+            cleanup();
+            exit(0);
+        });
+    }
+}
+
+/// Send SIGTERM to the current process, so [`Shutdown::on_signal`] cleans up and exits.
+/// Without it (blocking the signals failed) the process is terminated directly.
+pub fn request_shutdown() -> Result<(), io::Error> {
+    // SAFETY: 只是向自身发送信号.
+    if unsafe { libc::kill(libc::getpid(), libc::SIGTERM) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
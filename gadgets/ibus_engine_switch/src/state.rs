@@ -0,0 +1,91 @@
+use gadgets_common::xdg::{runtime_dir, state_home};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// State kept across daemon restarts.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct State {
+    /// Index of the last used engine in the configured engines.
+    pub engine: usize,
+}
+
+impl State {
+    /// Default state file path: `$XDG_STATE_HOME/ibus_engine_switch/state.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        state_home().map(|dir| dir.join("ibus_engine_switch").join("state.json"))
+    }
+
+    /// Load state from `path`, a missing or corrupt file yields the default state.
+    pub fn load(path: &Path) -> State {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return State::default(),
+            Err(e) => {
+                warn!("Failed to read state file {}: {e}", path.display());
+                return State::default();
+            }
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Corrupt state file {}: {e}", path.display());
+            State::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        write_atomic(path, serde_json::to_string(self)?.as_bytes())
+    }
+}
+
+/// What the switch server does with the engine on startup, see [`startup_engine`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartupEngine {
+    /// Switch to the engine at the index.
+    Apply(usize),
+    /// The engine at the index is already in use, only record it.
+    Adopt(usize),
+    /// The engine in use is not configured or couldn't be queried, record the English engine
+    /// without switching. Later changes are reconciled by watching the engine.
+    Unknown,
+}
+
+/// Decide the startup engine from the configured `engines`, the `saved` index of [`State`]
+/// and the engine in use (`current`, None if the query failed).
+/// With `apply_on_start` the saved engine is applied, the English one if `saved` is out of range,
+/// otherwise the engine in use is adopted.
+pub fn startup_engine(
+    engines: &[String],
+    saved: usize,
+    current: Option<&str>,
+    apply_on_start: bool,
+) -> StartupEngine {
+    if apply_on_start {
+        // 配置变化导致索引越界时使用英文.
+        return StartupEngine::Apply(if saved < engines.len() { saved } else { 0 });
+    }
+    current
+        .and_then(|current| engines.iter().position(|e| e == current))
+        .map_or(StartupEngine::Unknown, StartupEngine::Adopt)
+}
+
+/// Default path of the file holding the current engine for status bars:
+/// `$XDG_RUNTIME_DIR/ibus_engine_switch/current`.
+pub fn default_status_file() -> PathBuf {
+    runtime_dir().join("ibus_engine_switch").join("current")
+}
+
+/// 先写入同目录下的临时文件再重命名, 避免崩溃时留下被截断的文件.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), io::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
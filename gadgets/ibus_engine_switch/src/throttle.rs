@@ -0,0 +1,57 @@
+//! 限制客户端切换命令的频率, 避免循环调用的脚本或卡住的按键连续地切换输入法.
+
+use std::time::{Duration, Instant};
+
+/// Rejects commands arriving within `min_interval` of the last accepted one.
+#[derive(Clone, Copy, Debug)]
+pub struct Throttle {
+    min_interval: Duration,
+    /// 上一个被接受的命令的时间.
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    /// `Duration::ZERO` accepts every command.
+    pub fn new(min_interval: Duration) -> Throttle {
+        Throttle {
+            min_interval,
+            last: None,
+        }
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    /// Whether a command arriving at `now` is accepted, which is then recorded.
+    /// Rejected commands don't restart the interval.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last
+            && now.saturating_duration_since(last) < self.min_interval
+        {
+            return false;
+        }
+        self.last = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_within_the_interval() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut throttle = Throttle::new(ms(100));
+        assert!(throttle.allow(start));
+        assert!(!throttle.allow(start + ms(50)));
+        // 被拒绝的命令不重新计时
+        assert!(!throttle.allow(start + ms(99)));
+        assert!(throttle.allow(start + ms(100)));
+        let mut unlimited = Throttle::new(Duration::ZERO);
+        assert!(unlimited.allow(start));
+        assert!(unlimited.allow(start));
+    }
+}
@@ -0,0 +1,552 @@
+use crate::{IbusSwitchError, atoms::AtomCache};
+use std::{
+    os::fd::AsRawFd,
+    thread,
+    time::{Duration, Instant},
+};
+use x11rb::{
+    connection::Connection,
+    errors::ReplyError,
+    protocol::{
+        ErrorKind, Event,
+        xproto::{self, ConnectionExt as _},
+    },
+    rust_connection::RustConnection,
+};
+
+/// When a watched property changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChangeTime {
+    /// X server timestamp (milliseconds, wraps around) of the last merged `PropertyNotify`,
+    /// None for the initial report.
+    pub server: Option<u32>,
+    /// When that event was received, or when the initial report was made.
+    pub received: Instant,
+}
+
+impl ChangeTime {
+    /// Time of `event` received at `received`.
+    pub fn of(event: &xproto::PropertyNotifyEvent, received: Instant) -> ChangeTime {
+        ChangeTime {
+            server: Some(event.time),
+            received,
+        }
+    }
+}
+
+/// A change of a watched property, passed to its handler.
+pub struct PropertyChange<'c> {
+    pub conn: &'c RustConnection,
+    /// Atoms interned on `conn`, shared by all handlers.
+    pub atoms: &'c AtomCache,
+    /// Screen number of `root`.
+    pub screen: usize,
+    pub root: u32,
+    pub atom: u32,
+    pub time: ChangeTime,
+}
+
+type Handler<'a> = Box<dyn FnMut(&PropertyChange) + 'a>;
+
+/// Watches properties of the root window over a single connection and event loop.
+///
+/// Dispatch contract:
+/// - every handler is called once when [`PropertyWatcher::run`] starts, to report the initial value;
+/// - after a `PropertyNotify` of a watched atom, further events are drained until no watched atom
+///   changes within the debounce interval, then each changed atom is dispatched once,
+///   in the order they first changed;
+/// - handlers of an atom are called in the order they were registered, events of other atoms are ignored.
+///
+/// Handlers read the property themselves, the events only tell which atoms changed.
+/// When watching all screens, the above applies to each (screen, atom) pair.
+pub struct PropertyWatcher<'a> {
+    conn: RustConnection,
+    atoms: AtomCache,
+    /// (屏幕编号, 根窗口), 默认只有连接的首选屏幕.
+    roots: Vec<(usize, u32)>,
+    debounce: Duration,
+    handlers: Vec<(u32, Handler<'a>)>,
+    stop: Option<Box<dyn FnMut() -> bool + 'a>>,
+}
+
+impl<'a> PropertyWatcher<'a> {
+    /// Connect to the X server and select property changes of the root window of the preferred screen.
+    /// `Duration::ZERO` disables debouncing, only the already queued events are merged.
+    pub fn new(debounce: Duration) -> Result<PropertyWatcher<'a>, IbusSwitchError> {
+        PropertyWatcher::with_screens(debounce, false)
+    }
+
+    /// Like [`PropertyWatcher::new`], with `all_screens` the root windows of all screens are watched,
+    /// which only matters for multi-screen (Zaphod) setups.
+    /// Multiple monitors combined by RandR/Xinerama share a single screen.
+    pub fn with_screens(
+        debounce: Duration,
+        all_screens: bool,
+    ) -> Result<PropertyWatcher<'a>, IbusSwitchError> {
+        let (conn, screen_num) = crate::x11::connect()?;
+        let roots: Vec<(usize, u32)> = conn
+            .setup()
+            .roots
+            .iter()
+            .enumerate()
+            .filter(|&(screen, _)| all_screens || screen == screen_num)
+            .map(|(screen, s)| (screen, s.root))
+            .collect();
+        for &(_, root) in &roots {
+            // PropertyChangeMask 允许我们接收属性变化的通知
+            conn.change_window_attributes(
+                root,
+                &xproto::ChangeWindowAttributesAux::new()
+                    .event_mask(xproto::EventMask::PROPERTY_CHANGE),
+            )?;
+        }
+        conn.flush()?; // 确保请求被发送到 X Server
+        Ok(PropertyWatcher {
+            conn,
+            atoms: AtomCache::new(),
+            roots,
+            debounce,
+            handlers: Vec::new(),
+            stop: None,
+        })
+    }
+
+    /// Call `handler` whenever the property `name` of a watched root window changes, returns its atom.
+    pub fn watch(
+        &mut self,
+        name: &[u8],
+        handler: impl FnMut(&PropertyChange) + 'a,
+    ) -> Result<u32, IbusSwitchError> {
+        let atom = self.atoms.intern(&self.conn, name)?;
+        self.handlers.push((atom, Box::new(handler)));
+        Ok(atom)
+    }
+
+    /// Make [`PropertyWatcher::run`] return after a dispatch once `stop` returns true.
+    pub fn stop_when(&mut self, stop: impl FnMut() -> bool + 'a) {
+        self.stop = Some(Box::new(stop));
+    }
+
+    /// Run the event loop, only returns on connection errors or as requested by [`PropertyWatcher::stop_when`].
+    pub fn run(self) -> Result<(), IbusSwitchError> {
+        self.run_until(None).map(|_| ())
+    }
+
+    /// Like [`PropertyWatcher::run`], also returns false once `deadline` passes.
+    /// Returns true when stopped by [`PropertyWatcher::stop_when`].
+    pub fn run_until(mut self, deadline: Option<Instant>) -> Result<bool, IbusSwitchError> {
+        let mut initial = Vec::new();
+        let time = ChangeTime {
+            server: None,
+            received: Instant::now(),
+        };
+        for &(screen, _) in &self.roots {
+            for &(atom, _) in &self.handlers {
+                merge_change(&mut initial, screen, atom, time);
+            }
+        }
+        self.dispatch(&initial);
+        while !self.should_stop() {
+            let Some(event) = self.wait_for_event_until(deadline)? else {
+                return Ok(false);
+            };
+            let mut changed = Vec::new();
+            if self.record_change(&mut changed, &event) {
+                // 合并快速连续的变化 (例如 alt-tab 时), 避免频繁查询
+                self.wait_for_settle(&mut changed)?;
+                self.dispatch(&changed);
+            }
+            // 确保事件队列被处理，避免阻塞
+            self.conn.flush()?;
+        }
+        Ok(true)
+    }
+
+    /// 等待下一个事件, 超过 `deadline` 时返回 None.
+    fn wait_for_event_until(
+        &self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Event>, IbusSwitchError> {
+        let Some(deadline) = deadline else {
+            return Ok(Some(self.conn.wait_for_event()?));
+        };
+        next_until(
+            deadline,
+            || Ok(self.conn.poll_for_event()?),
+            |remaining| {
+                let mut fd = libc::pollfd {
+                    fd: self.conn.stream().as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let timeout = i32::try_from(remaining.as_millis())
+                    .unwrap_or(i32::MAX)
+                    .max(1);
+                // 超时或被信号打断时回到循环开头重新检查.
+                // SAFETY: fd 是有效的 pollfd, 数量为 1, 连接的 fd 在调用期间保持打开.
+                unsafe { libc::poll(&mut fd, 1, timeout) };
+            },
+        )
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.stop.as_mut().is_some_and(|stop| stop())
+    }
+
+    /// 若 `event` 是被监听属性的变化, 记录 (屏幕, 属性, 时间) 到 `changed` 并返回 true.
+    fn record_change(&self, changed: &mut Vec<(usize, u32, ChangeTime)>, event: &Event) -> bool {
+        let is_watched = |atom| self.handlers.iter().any(|(a, _)| *a == atom);
+        let Some((screen, event)) = watched_change(&self.roots, is_watched, event) else {
+            return false;
+        };
+        merge_change(
+            changed,
+            screen,
+            event.atom,
+            ChangeTime::of(event, Instant::now()),
+        );
+        true
+    }
+
+    /// 等待连续的属性变化事件平息: 每隔 `debounce` 取出所有已到达的事件,
+    /// 直到某个间隔内没有新的被监听属性的变化.
+    fn wait_for_settle(
+        &self,
+        changed: &mut Vec<(usize, u32, ChangeTime)>,
+    ) -> Result<(), IbusSwitchError> {
+        loop {
+            if !self.debounce.is_zero() {
+                thread::sleep(self.debounce);
+            }
+            let mut any = false;
+            while let Some(event) = self.conn.poll_for_event()? {
+                any |= self.record_change(changed, &event);
+            }
+            if !any || self.debounce.is_zero() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn dispatch(&mut self, changed: &[(usize, u32, ChangeTime)]) {
+        for &(screen, atom, time) in changed {
+            let Some(&(_, root)) = self.roots.iter().find(|(s, _)| *s == screen) else {
+                continue;
+            };
+            let change = PropertyChange {
+                conn: &self.conn,
+                atoms: &self.atoms,
+                screen,
+                root,
+                atom,
+                time,
+            };
+            for (_, handler) in self.handlers.iter_mut().filter(|(a, _)| *a == atom) {
+                handler(&change);
+            }
+        }
+    }
+}
+
+/// 取出 `poll` 得到的下一个事件, 没有时用 `wait` 等待最多剩余的时间, 超过 `deadline` 时返回 None.
+fn next_until<E>(
+    deadline: Instant,
+    mut poll: impl FnMut() -> Result<Option<E>, IbusSwitchError>,
+    mut wait: impl FnMut(Duration),
+) -> Result<Option<E>, IbusSwitchError> {
+    loop {
+        // 事件可能已经读入连接的缓冲区, 先取出再等待 socket 可读.
+        if let Some(event) = poll()? {
+            return Ok(Some(event));
+        }
+        let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+            return Ok(None);
+        };
+        wait(remaining);
+    }
+}
+
+/// 若 `event` 是 `roots` 中某个根窗口上被监听 (`is_watched`) 的属性的变化, 返回 (屏幕编号, 事件).
+fn watched_change<'e>(
+    roots: &[(usize, u32)],
+    is_watched: impl Fn(u32) -> bool,
+    event: &'e Event,
+) -> Option<(usize, &'e xproto::PropertyNotifyEvent)> {
+    let Event::PropertyNotify(event) = event else {
+        return None;
+    };
+    let &(screen, _) = roots.iter().find(|(_, root)| *root == event.window)?;
+    is_watched(event.atom).then_some((screen, event))
+}
+
+/// Record that `atom` of `screen` changed at `time` in `changed`, which keeps the order atoms first changed in.
+/// A repeated change only updates the time, so handlers get the time of the latest one.
+pub fn merge_change(
+    changed: &mut Vec<(usize, u32, ChangeTime)>,
+    screen: usize,
+    atom: u32,
+    time: ChangeTime,
+) {
+    match changed
+        .iter_mut()
+        .find(|(s, a, _)| (*s, *a) == (screen, atom))
+    {
+        Some((_, _, last)) => *last = time,
+        None => changed.push((screen, atom, time)),
+    }
+}
+
+/// A change of the property watched by [`listen_window_property_changes`].
+pub struct WindowPropertyChange<'c> {
+    pub conn: &'c RustConnection,
+    pub atoms: &'c AtomCache,
+    pub window: u32,
+    pub atom: u32,
+    /// The property was deleted rather than changed.
+    pub deleted: bool,
+}
+
+/// How [`listen_window_property_changes`] returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowWatchEnd {
+    /// The window was destroyed, or didn't exist when the listening started.
+    Destroyed,
+    /// The callback returned false.
+    Stopped,
+}
+
+/// An event relevant to a watched window, see [`classify_window_event`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowEvent {
+    PropertyChanged { deleted: bool },
+    Destroyed,
+}
+
+/// Whether `event` changes the property `atom` of `window` or destroys `window`,
+/// None for events of other windows or properties.
+pub fn classify_window_event(event: &Event, window: u32, atom: u32) -> Option<WindowEvent> {
+    match event {
+        Event::PropertyNotify(e) if e.window == window && e.atom == atom => {
+            Some(WindowEvent::PropertyChanged {
+                deleted: e.state == xproto::Property::DELETE,
+            })
+        }
+        Event::DestroyNotify(e) if e.window == window => Some(WindowEvent::Destroyed),
+        _ => None,
+    }
+}
+
+/// Call `on_change` when the property `name` of `window_id` changes, until it returns false
+/// or the window is destroyed. Like [`PropertyWatcher`], it's called once at first to report
+/// the initial value and reads the property itself. Changes are not debounced.
+///
+/// Useful for applications whose input window is not the top-level one reported as active.
+pub fn listen_window_property_changes(
+    window_id: u32,
+    name: &[u8],
+    mut on_change: impl FnMut(&WindowPropertyChange) -> bool,
+) -> Result<WindowWatchEnd, IbusSwitchError> {
+    let (conn, _) = crate::x11::connect()?;
+    let atoms = AtomCache::new();
+    let atom = atoms.intern(&conn, name)?;
+    // StructureNotify 用于收到窗口被销毁的通知
+    let selected = conn
+        .change_window_attributes(
+            window_id,
+            &xproto::ChangeWindowAttributesAux::new().event_mask(
+                xproto::EventMask::PROPERTY_CHANGE | xproto::EventMask::STRUCTURE_NOTIFY,
+            ),
+        )?
+        .check();
+    match selected {
+        Err(ReplyError::X11Error(e)) if e.error_kind == ErrorKind::Window => {
+            return Ok(WindowWatchEnd::Destroyed);
+        }
+        result => result?,
+    }
+    let mut change = WindowPropertyChange {
+        conn: &conn,
+        atoms: &atoms,
+        window: window_id,
+        atom,
+        deleted: false,
+    };
+    if !on_change(&change) {
+        return Ok(WindowWatchEnd::Stopped);
+    }
+    loop {
+        match classify_window_event(&conn.wait_for_event()?, window_id, atom) {
+            Some(WindowEvent::PropertyChanged { deleted }) => {
+                change.deleted = deleted;
+                if !on_change(&change) {
+                    return Ok(WindowWatchEnd::Stopped);
+                }
+            }
+            Some(WindowEvent::Destroyed) => return Ok(WindowWatchEnd::Destroyed),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOTS: [(usize, u32); 2] = [(0, 0x1e3), (1, 0x2e3)];
+    const ACTIVE_WINDOW: u32 = 400;
+    const CURRENT_DESKTOP: u32 = 401;
+    const OTHER: u32 = 402;
+
+    fn notify(window: u32, atom: u32, time: u32) -> Event {
+        Event::PropertyNotify(xproto::PropertyNotifyEvent {
+            response_type: xproto::PROPERTY_NOTIFY_EVENT,
+            sequence: 0,
+            window,
+            atom,
+            time,
+            state: xproto::Property::NEW_VALUE,
+        })
+    }
+
+    /// 像事件循环一样把 `events` 合并为要分发的 (屏幕, 属性, 服务器时间).
+    fn route(events: &[Event]) -> Vec<(usize, u32, Option<u32>)> {
+        let is_watched = |atom| [ACTIVE_WINDOW, CURRENT_DESKTOP].contains(&atom);
+        let mut changed = Vec::new();
+        for event in events {
+            if let Some((screen, event)) = watched_change(&ROOTS, is_watched, event) {
+                merge_change(
+                    &mut changed,
+                    screen,
+                    event.atom,
+                    ChangeTime::of(event, Instant::now()),
+                );
+            }
+        }
+        changed
+            .into_iter()
+            .map(|(screen, atom, time)| (screen, atom, time.server))
+            .collect()
+    }
+
+    #[test]
+    fn atoms_route_to_their_screen_in_first_change_order() {
+        let events = [
+            notify(0x1e3, CURRENT_DESKTOP, 1),
+            // 没有监听的属性
+            notify(0x1e3, OTHER, 2),
+            notify(0x1e3, ACTIVE_WINDOW, 3),
+            // 不是根窗口
+            notify(0x3a00003, ACTIVE_WINDOW, 4),
+            notify(0x2e3, ACTIVE_WINDOW, 5),
+            // 重复的变化只更新时间
+            notify(0x1e3, CURRENT_DESKTOP, 6),
+            Event::Unknown(Vec::new()),
+        ];
+        assert_eq!(
+            route(&events),
+            [
+                (0, CURRENT_DESKTOP, Some(6)),
+                (0, ACTIVE_WINDOW, Some(3)),
+                (1, ACTIVE_WINDOW, Some(5)),
+            ]
+        );
+        assert!(route(&[notify(0x1e3, OTHER, 1)]).is_empty());
+    }
+
+    #[test]
+    fn window_events_filter_by_window_and_atom() {
+        let window = 0x3a00003;
+        let classify = |event: &Event| classify_window_event(event, window, ACTIVE_WINDOW);
+        let deleted = Event::PropertyNotify(xproto::PropertyNotifyEvent {
+            response_type: xproto::PROPERTY_NOTIFY_EVENT,
+            sequence: 0,
+            window,
+            atom: ACTIVE_WINDOW,
+            time: 2,
+            state: xproto::Property::DELETE,
+        });
+        let destroyed = |window| {
+            Event::DestroyNotify(xproto::DestroyNotifyEvent {
+                response_type: xproto::DESTROY_NOTIFY_EVENT,
+                sequence: 0,
+                event: window,
+                window,
+            })
+        };
+        assert_eq!(
+            classify(&notify(window, ACTIVE_WINDOW, 1)),
+            Some(WindowEvent::PropertyChanged { deleted: false })
+        );
+        assert_eq!(
+            classify(&deleted),
+            Some(WindowEvent::PropertyChanged { deleted: true })
+        );
+        assert_eq!(classify(&destroyed(window)), Some(WindowEvent::Destroyed));
+        // 其他属性, 其他窗口
+        assert_eq!(classify(&notify(window, OTHER, 3)), None);
+        assert_eq!(classify(&notify(0x1e3, ACTIVE_WINDOW, 4)), None);
+        assert_eq!(classify(&destroyed(0x3c00016)), None);
+        assert_eq!(classify(&Event::Unknown(Vec::new())), None);
+    }
+
+    #[test]
+    fn deadline_without_events_is_none() {
+        let timeout = Duration::from_millis(30);
+        let start = Instant::now();
+        let mut waits = 0;
+        let next = next_until(
+            start + timeout,
+            || Ok(None::<Event>),
+            |remaining| {
+                assert!(remaining <= timeout);
+                waits += 1;
+                thread::sleep(remaining.min(Duration::from_millis(10)));
+            },
+        );
+        assert!(next.unwrap().is_none());
+        assert!(start.elapsed() >= timeout);
+        assert!(waits >= 3);
+        // 已经过了期限也先取出已到达的事件
+        let mut events = vec![notify(0x1e3, ACTIVE_WINDOW, 1)];
+        let next = next_until(start, || Ok(events.pop()), |_| panic!("waited"));
+        assert!(matches!(next, Ok(Some(Event::PropertyNotify(_)))));
+        let next = next_until(start, || Ok(events.pop()), |_| panic!("waited"));
+        assert!(next.unwrap().is_none());
+    }
+
+    #[test]
+    fn timestamps_are_threaded_from_events() {
+        let received = Instant::now();
+        let later = received + Duration::from_millis(20);
+        let mut changed = Vec::new();
+        for (event, at) in [
+            (notify(0x1e3, ACTIVE_WINDOW, u32::MAX), received),
+            (notify(0x1e3, ACTIVE_WINDOW, 16), later),
+        ] {
+            let (screen, event) =
+                watched_change(&ROOTS, |atom| atom == ACTIVE_WINDOW, &event).unwrap();
+            merge_change(&mut changed, screen, event.atom, ChangeTime::of(event, at));
+            if at == received {
+                assert_eq!(
+                    changed[0].2,
+                    ChangeTime {
+                        server: Some(u32::MAX),
+                        received,
+                    }
+                );
+            }
+        }
+        // 合并后是最后一个事件的两个时间, 服务器时间会回绕
+        assert_eq!(
+            changed,
+            [(
+                0,
+                ACTIVE_WINDOW,
+                ChangeTime {
+                    server: Some(16),
+                    received: later,
+                }
+            )]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! 通过 `wlr-foreign-toplevel-management` 协议读取 wlroots 系混成器中获得焦点的窗口, 需要 `wayland` feature.
+
+use crate::focus_backend::{FocusBackend, FocusedWindow};
+use anyhow::Context;
+use std::collections::HashMap;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+    backend::ObjectId,
+    event_created_child,
+    globals::{GlobalListContents, registry_queue_init},
+    protocol::wl_registry::{self, WlRegistry},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+/// Whether the `state` array of a toplevel handle contains `activated`.
+/// The array holds native-endian u32 values of `zwlr_foreign_toplevel_handle_v1.state`.
+pub fn is_activated(state: &[u8]) -> bool {
+    let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+    state
+        .chunks_exact(4)
+        .any(|value| u32::from_ne_bytes([value[0], value[1], value[2], value[3]]) == activated)
+}
+
+/// 一个窗口的属性, 收到 done 事件之前的修改先记录在 `pending`.
+#[derive(Default)]
+struct Toplevel {
+    window: FocusedWindow,
+    activated: bool,
+    pending: FocusedWindow,
+    pending_activated: bool,
+}
+
+#[derive(Default)]
+struct Toplevels {
+    toplevels: HashMap<ObjectId, Toplevel>,
+    /// 获得焦点的窗口.
+    activated: Option<ObjectId>,
+    /// 获得焦点的窗口改变后还没有报告.
+    changed: bool,
+    /// 混成器不再发送窗口的事件.
+    finished: bool,
+}
+
+impl Toplevels {
+    fn activated_window(&self) -> Option<FocusedWindow> {
+        let id = self.activated.as_ref()?;
+        self.toplevels
+            .get(id)
+            .map(|toplevel| toplevel.window.clone())
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Toplevels {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Toplevels {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.toplevels.insert(toplevel.id(), Toplevel::default());
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(Toplevels, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Toplevels {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let id = handle.id();
+        if let zwlr_foreign_toplevel_handle_v1::Event::Closed = event {
+            state.toplevels.remove(&id);
+            if state.activated.as_ref() == Some(&id) {
+                state.activated = None;
+            }
+            handle.destroy();
+            return;
+        }
+        let Some(toplevel) = state.toplevels.get_mut(&id) else {
+            return;
+        };
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                toplevel.pending.title = Some(title)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                toplevel.pending.app_id = Some(app_id)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                toplevel.pending_activated = is_activated(&state)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                toplevel.window = toplevel.pending.clone();
+                toplevel.activated = toplevel.pending_activated;
+                // 只有获得焦点的窗口改变才报告, 标题的变化不算.
+                if toplevel.activated && state.activated.as_ref() != Some(&id) {
+                    state.activated = Some(id);
+                    state.changed = true;
+                } else if !toplevel.activated && state.activated.as_ref() == Some(&id) {
+                    state.activated = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// [`FocusBackend`] for wlroots based compositors (sway, Hyprland, ...), which implement
+/// `wlr-foreign-toplevel-management`.
+pub struct WlrootsFocus {
+    queue: EventQueue<Toplevels>,
+    toplevels: Toplevels,
+    _manager: ZwlrForeignToplevelManagerV1,
+}
+
+impl WlrootsFocus {
+    /// Connect to the compositor of `$WAYLAND_DISPLAY` and receive the current toplevels.
+    pub fn connect() -> Result<WlrootsFocus, anyhow::Error> {
+        let conn =
+            Connection::connect_to_env().context("Failed to connect to the Wayland compositor")?;
+        let (globals, mut queue) = registry_queue_init::<Toplevels>(&conn)?;
+        let manager = globals
+            .bind::<ZwlrForeignToplevelManagerV1, _, _>(&queue.handle(), 1..=3, ())
+            .context("The compositor doesn't support wlr-foreign-toplevel-management")?;
+        let mut toplevels = Toplevels::default();
+        // 第一次收到所有窗口, 第二次收到它们的属性.
+        queue.roundtrip(&mut toplevels)?;
+        queue.roundtrip(&mut toplevels)?;
+        Ok(WlrootsFocus {
+            queue,
+            toplevels,
+            _manager: manager,
+        })
+    }
+}
+
+impl FocusBackend for WlrootsFocus {
+    fn current_window(&mut self) -> Result<Option<FocusedWindow>, anyhow::Error> {
+        self.queue.roundtrip(&mut self.toplevels)?;
+        Ok(self.toplevels.activated_window())
+    }
+
+    fn listen(&mut self, on_focus: &mut dyn FnMut(&FocusedWindow)) -> Result<(), anyhow::Error> {
+        self.toplevels.changed = self.toplevels.activated.is_some();
+        loop {
+            if self.toplevels.changed {
+                self.toplevels.changed = false;
+                if let Some(window) = self.toplevels.activated_window() {
+                    on_focus(&window);
+                }
+            }
+            if self.toplevels.finished {
+                anyhow::bail!("The compositor stopped reporting toplevels.");
+            }
+            self.queue.blocking_dispatch(&mut self.toplevels)?;
+        }
+    }
+}
@@ -0,0 +1,1131 @@
+//! 通过 EWMH 属性读取和监听活动窗口与工作区, 需要 `x11` feature.
+
+use crate::{
+    IbusSwitchError,
+    atoms::AtomCache,
+    focus::{DEFAULT_FOCUS_HISTORY_DEPTH, FocusHistory},
+    monitor,
+    watcher::{ChangeTime, PropertyWatcher},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env,
+    fmt::Display,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{self, ConnectionExt as _, GetPropertyReply},
+    rust_connection::RustConnection,
+};
+
+/// Attempts of [`connect`], the X server may not accept connections yet right after login.
+pub const CONNECT_ATTEMPTS: usize = 5;
+
+/// Delay before the second attempt of [`connect`], doubled after each failure.
+pub const CONNECT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Call `connect` up to `max_attempts` times (at least once) until it succeeds,
+/// sleeping `backoff` after the first failure and twice as long after each following one.
+/// Returns the last error if all attempts fail.
+pub fn retry_connect<C, E: Display>(
+    max_attempts: usize,
+    backoff: Duration,
+    mut connect: impl FnMut() -> Result<C, E>,
+) -> Result<C, E> {
+    let mut delay = backoff;
+    let mut attempt = 1;
+    loop {
+        match connect() {
+            Ok(conn) => return Ok(conn),
+            Err(e) if attempt < max_attempts => {
+                warn!(
+                    "Failed to connect to the X server (attempt {attempt}), retry in {delay:?}: {e}"
+                );
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Connect to the X server given by `$DISPLAY`, retrying with [`retry_connect`].
+/// Returns the connection and the number of the default screen.
+/// Without `$DISPLAY` there is nothing to wait for, only one attempt is made.
+pub fn connect_with_retry(
+    max_attempts: usize,
+    backoff: Duration,
+) -> Result<(RustConnection, usize), IbusSwitchError> {
+    let max_attempts = if env::var_os("DISPLAY").is_some_and(|d| !d.is_empty()) {
+        max_attempts
+    } else {
+        1
+    };
+    Ok(retry_connect(max_attempts, backoff, || {
+        x11rb::connect(None)
+    })?)
+}
+
+/// [`connect_with_retry`] with [`CONNECT_ATTEMPTS`] and [`CONNECT_BACKOFF`], used by every connection of this crate.
+pub(crate) fn connect() -> Result<(RustConnection, usize), IbusSwitchError> {
+    connect_with_retry(CONNECT_ATTEMPTS, CONNECT_BACKOFF)
+}
+
+/// Get active window id over a new connection, None if the window manager hasn't set one
+/// or no window is focused, see [`active_window_id`].
+/// This is an alternative method, listeners use the connection of their [`PropertyWatcher`].
+pub fn get_active_window_id_directly() -> Result<Option<u32>, IbusSwitchError> {
+    let (conn, screen_num) = connect()?;
+    let root_window = conn.setup().roots[screen_num].root;
+    let active_window_atom = intern_atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+    get_active_window_id(&conn, root_window, active_window_atom)
+}
+
+/// Query the active window over a new connection, which is closed afterwards.
+/// None if the window manager hasn't set one or no window is focused (e.g. the desktop).
+/// Unlike [`get_active_window_id_directly`], the class, title and so on are also queried.
+pub fn poll_active_window_once() -> Result<Option<WindowInfo>, IbusSwitchError> {
+    let (conn, screen_num) = connect()?;
+    let root_window = conn.setup().roots[screen_num].root;
+    let mut window = get_active_window(&conn, &AtomCache::new(), root_window)?;
+    if let Some(window) = &mut window {
+        window.screen = screen_num;
+    }
+    Ok(window)
+}
+
+/// 读取 `root_window` 上的活动窗口并查询其信息, `_NET_ACTIVE_WINDOW` 为空, 为 0 或根窗口时返回 None.
+pub fn get_active_window(
+    conn: &impl Connection,
+    atoms: &AtomCache,
+    root_window: u32,
+) -> Result<Option<WindowInfo>, IbusSwitchError> {
+    let active_window_atom = atoms.intern(conn, b"_NET_ACTIVE_WINDOW")?;
+    Ok(get_active_window_id(conn, root_window, active_window_atom)?
+        .map(|id| WindowInfo::query(conn, atoms, id)))
+}
+
+/// The active window given the value of `_NET_ACTIVE_WINDOW` on `root_window`.
+/// Some window managers set `0` or the root window when nothing is focused instead of
+/// removing the property, both are None like a missing property.
+pub fn active_window_id(value: Option<u32>, root_window: u32) -> Option<u32> {
+    value.filter(|&id| id != 0 && id != root_window)
+}
+
+/// `_NET_ACTIVE_WINDOW` 的原始值, 可能是 0 或根窗口.
+fn get_active_window_property(
+    conn: &impl Connection,
+    root_window: u32,
+    active_window_atom: u32,
+) -> Result<Option<u32>, IbusSwitchError> {
+    get_property_u32(
+        conn,
+        root_window,
+        active_window_atom,
+        "_NET_ACTIVE_WINDOW",
+        xproto::AtomEnum::WINDOW, // 期望的类型是 Window
+    )
+}
+
+fn get_active_window_id(
+    conn: &impl Connection,
+    root_window: u32,
+    active_window_atom: u32,
+) -> Result<Option<u32>, IbusSwitchError> {
+    Ok(active_window_id(
+        get_active_window_property(conn, root_window, active_window_atom)?,
+        root_window,
+    ))
+}
+
+/// 读取单个 32 位的属性值 (例如 CARDINAL 或 WINDOW), 属性不存在或为空时返回 None.
+/// `name` 是 `property` 的名称, 用于错误信息.
+fn get_property_u32(
+    conn: &impl Connection,
+    window_id: u32,
+    property: u32,
+    name: &str,
+    type_: impl Into<u32>,
+) -> Result<Option<u32>, IbusSwitchError> {
+    let reply = conn
+        .get_property(
+            false, // delete
+            window_id, property, type_, 0, // offset
+            1, // length, 单位为 4 字节
+        )?
+        .reply()
+        .map_err(|source| property_error(window_id, name, source))?;
+    parse_u32_property(window_id, name, &reply)
+}
+
+fn property_error(window: u32, name: &str, source: x11rb::errors::ReplyError) -> IbusSwitchError {
+    IbusSwitchError::Property {
+        window,
+        property: name.to_owned(),
+        source,
+    }
+}
+
+/// Parse the first value of the 32-bit property `property` of `window` (only used for errors),
+/// None if the property is absent or empty. A property of another type than requested is an error.
+pub fn parse_u32_property(
+    window: u32,
+    property: &str,
+    reply: &GetPropertyReply,
+) -> Result<Option<u32>, IbusSwitchError> {
+    let value = &reply.value;
+    // 类型不符时 X 服务器不返回数据, 只在 bytes_after 中给出属性的长度.
+    if value.is_empty() && reply.bytes_after == 0 {
+        return Ok(None);
+    }
+    match value.get(0..4) {
+        Some(&[a, b, c, d]) if reply.format == 32 => Ok(Some(u32::from_ne_bytes([a, b, c, d]))),
+        _ => Err(IbusSwitchError::PropertyFormat {
+            window,
+            property: property.to_owned(),
+            type_atom: reply.type_,
+            format: reply.format,
+            len: value.len(),
+        }),
+    }
+}
+
+pub(crate) fn intern_atom(conn: &impl Connection, name: &[u8]) -> Result<u32, IbusSwitchError> {
+    let atom = conn.intern_atom(false, name)?.reply()?.atom;
+    if atom == 0
+    /* xproto::AtomEnum::NONE */
+    {
+        return Err(IbusSwitchError::AtomNotFound(
+            String::from_utf8_lossy(name).into_owned(),
+        ));
+    }
+    Ok(atom)
+}
+
+/// 读取窗口的完整属性值, 属性不存在或为空时返回 None.
+fn get_property_bytes(
+    conn: &impl Connection,
+    window_id: u32,
+    property: impl Into<u32>,
+    name: &str,
+    type_: impl Into<u32>,
+) -> Result<Option<Vec<u8>>, IbusSwitchError> {
+    let reply = conn
+        .get_property(
+            false, // delete
+            window_id,
+            property,
+            type_,
+            0,        // offset
+            u32::MAX, // length, 读取整个属性
+        )?
+        .reply()
+        .map_err(|source| property_error(window_id, name, source))?;
+    if reply.value.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(reply.value))
+    }
+}
+
+/// Get window title.
+/// Reads `_NET_WM_NAME` (UTF-8) first, and falls back to `WM_NAME` (latin1) when absent.
+pub fn get_window_title(
+    conn: &impl Connection,
+    atoms: &AtomCache,
+    window_id: u32,
+) -> Result<Option<String>, IbusSwitchError> {
+    let net_wm_name = atoms.intern(conn, b"_NET_WM_NAME")?;
+    let utf8_string = atoms.intern(conn, b"UTF8_STRING")?;
+    if let Some(value) =
+        get_property_bytes(conn, window_id, net_wm_name, "_NET_WM_NAME", utf8_string)?
+    {
+        return Ok(Some(String::from_utf8_lossy(&value).into_owned()));
+    }
+
+    // WM_NAME 可能是 STRING 或 COMPOUND_TEXT, 这里按 latin1 解码.
+    match get_property_bytes(
+        conn,
+        window_id,
+        xproto::AtomEnum::WM_NAME,
+        "WM_NAME",
+        xproto::AtomEnum::ANY,
+    )? {
+        Some(value) => Ok(Some(value.iter().map(|&b| b as char).collect())),
+        None => Ok(None),
+    }
+}
+
+/// Get window class, i.e. the (instance, class) pair stored in `WM_CLASS`.
+pub fn get_window_class(
+    conn: &impl Connection,
+    window_id: u32,
+) -> Result<Option<(String, String)>, IbusSwitchError> {
+    let Some(value) = get_property_bytes(
+        conn,
+        window_id,
+        xproto::AtomEnum::WM_CLASS,
+        "WM_CLASS",
+        xproto::AtomEnum::STRING,
+    )?
+    else {
+        return Ok(None);
+    };
+    // WM_CLASS 是两个以 \0 结尾的字符串: instance 和 class.
+    let mut parts = value
+        .split(|&b| b == 0)
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next().unwrap_or_default();
+    let class = parts.next().unwrap_or_default();
+    Ok(Some((instance, class)))
+}
+
+/// Get window types, i.e. atom names in `_NET_WM_WINDOW_TYPE`, empty if the property is absent.
+pub fn get_window_type(
+    conn: &impl Connection,
+    atoms: &AtomCache,
+    window_id: u32,
+) -> Result<Vec<String>, IbusSwitchError> {
+    let net_wm_window_type = atoms.intern(conn, b"_NET_WM_WINDOW_TYPE")?;
+    let Some(value) = get_property_bytes(
+        conn,
+        window_id,
+        net_wm_window_type,
+        "_NET_WM_WINDOW_TYPE",
+        xproto::AtomEnum::ATOM,
+    )?
+    else {
+        return Ok(Vec::new());
+    };
+    parse_atoms(&value)
+        .into_iter()
+        .map(|atom| atoms.name(conn, atom))
+        .collect()
+}
+
+/// 32 位格式的 ATOM 列表, 按本机字节序排列.
+fn parse_atoms(value: &[u8]) -> Vec<u32> {
+    value
+        .chunks_exact(4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Window types of popups which grab focus only briefly.
+pub const TRANSIENT_WINDOW_TYPES: &[&str] = &[
+    "_NET_WM_WINDOW_TYPE_TOOLTIP",
+    "_NET_WM_WINDOW_TYPE_MENU",
+    "_NET_WM_WINDOW_TYPE_POPUP_MENU",
+    "_NET_WM_WINDOW_TYPE_DROPDOWN_MENU",
+    "_NET_WM_WINDOW_TYPE_COMBO",
+];
+
+/// Whether a window of `window_type` is transient, see [`TRANSIENT_WINDOW_TYPES`].
+/// 按 EWMH, 第一个能识别的类型为准, 这里只看第一个.
+pub fn is_transient_type(window_type: &[String]) -> bool {
+    window_type
+        .first()
+        .is_some_and(|t| TRANSIENT_WINDOW_TYPES.contains(&t.as_str()))
+}
+
+/// Information about a window, queried when it becomes active.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub id: u32,
+    /// (instance, class) from `WM_CLASS`.
+    pub class: Option<(String, String)>,
+    pub title: Option<String>,
+    /// Atom names in `_NET_WM_WINDOW_TYPE`, empty for normal windows without the property.
+    pub window_type: Vec<String>,
+    /// Screen number whose `_NET_ACTIVE_WINDOW` reported the window, see [`ListenOptions::all_screens`].
+    pub screen: usize,
+    /// Name of the RandR monitor showing the window, see [`monitor::window_monitor`].
+    pub monitor: Option<String>,
+}
+
+impl WindowInfo {
+    /// Query class, title, type and monitor of the window, `screen` is left as 0.
+    /// Fields that can't be acquired (e.g. the window has been destroyed) are left as None or empty.
+    pub fn query(conn: &impl Connection, atoms: &AtomCache, window_id: u32) -> WindowInfo {
+        WindowInfo {
+            id: window_id,
+            class: get_window_class(conn, window_id).ok().flatten(),
+            title: get_window_title(conn, atoms, window_id).ok().flatten(),
+            window_type: get_window_type(conn, atoms, window_id).unwrap_or_default(),
+            screen: 0,
+            monitor: monitor::window_monitor(conn, atoms, window_id)
+                .ok()
+                .flatten(),
+        }
+    }
+
+    /// Like [`WindowInfo::query`], but re-query up to `retries` times, `delay` apart, while it isn't
+    /// [settled](WindowInfo::is_settled). Some window managers set `_NET_ACTIVE_WINDOW` before the new
+    /// window's `WM_CLASS` and title, see [`read_settled`].
+    pub fn query_settled(
+        conn: &impl Connection,
+        atoms: &AtomCache,
+        window_id: u32,
+        retries: usize,
+        delay: Duration,
+    ) -> WindowInfo {
+        read_settled(
+            retries,
+            delay,
+            || WindowInfo::query(conn, atoms, window_id),
+            WindowInfo::is_settled,
+        )
+    }
+
+    /// Both `WM_CLASS` and a non-empty title are known.
+    pub fn is_settled(&self) -> bool {
+        self.class.is_some() && self.title.as_deref().is_some_and(|title| !title.is_empty())
+    }
+
+    /// Id 0 with nothing else known, reported by listeners when no window is focused.
+    pub fn none() -> WindowInfo {
+        WindowInfo {
+            id: 0,
+            class: None,
+            title: None,
+            window_type: Vec::new(),
+            screen: 0,
+            monitor: None,
+        }
+    }
+
+    /// Tooltips, menus and the like, see [`is_transient_type`].
+    pub fn is_transient(&self) -> bool {
+        is_transient_type(&self.window_type)
+    }
+}
+
+/// Default interval used to coalesce `_NET_ACTIVE_WINDOW` change bursts.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Default number of re-reads of a newly focused window missing its class or title, see [`ListenOptions::settle_retries`].
+pub const DEFAULT_SETTLE_RETRIES: usize = 3;
+
+/// Default delay between re-reads of a newly focused window, see [`ListenOptions::settle_delay`].
+pub const DEFAULT_SETTLE_DELAY: Duration = Duration::from_millis(50);
+
+/// Call `read` until `settled` accepts the result, at most `retries` more times after the first,
+/// sleeping `delay` before each retry. Returns the last result, even if it isn't settled.
+pub fn read_settled<T>(
+    retries: usize,
+    delay: Duration,
+    mut read: impl FnMut() -> T,
+    settled: impl Fn(&T) -> bool,
+) -> T {
+    let mut value = read();
+    for retry in 1..=retries {
+        if settled(&value) {
+            break;
+        }
+        debug!("Window properties are not set yet, re-read in {delay:?} (retry {retry}).");
+        thread::sleep(delay);
+        value = read();
+    }
+    value
+}
+
+/// Options of [`listen_active_window_changes_with`].
+#[derive(Clone, Copy, Debug)]
+pub struct ListenOptions {
+    /// After a change is noticed, further changes are drained until none arrives within `debounce`,
+    /// then the active window is queried once. `Duration::ZERO` only drains the already queued events.
+    pub debounce: Duration,
+    /// Report transient windows (see [`WindowInfo::is_transient`]) too.
+    /// They are ignored by default, so focusing a menu and going back is not a window switch.
+    pub include_transient: bool,
+    /// Number of windows kept in the [`FocusHistory`] of [`listen_focus_changes`].
+    pub history_depth: usize,
+    /// Watch the active window of every screen instead of only the preferred one,
+    /// for multi-screen (Zaphod) setups. Multiple monitors combined by RandR share a single screen
+    /// and need nothing.
+    pub all_screens: bool,
+    /// Re-read a newly focused window missing its `WM_CLASS` or title up to this many times,
+    /// see [`WindowInfo::query_settled`]. 0 reads it once.
+    pub settle_retries: usize,
+    /// Delay before each re-read of `settle_retries`.
+    pub settle_delay: Duration,
+    /// Called with (previous window, current window) when the active window changes,
+    /// the callback is skipped if it returns false. The first window is always reported.
+    /// Defaults to [`any_change`], e.g. [`different_class`] only reports switching between applications.
+    pub should_handle: fn(&WindowInfo, &WindowInfo) -> bool,
+}
+
+/// Report every change of the active window.
+pub fn any_change(_old: &WindowInfo, _new: &WindowInfo) -> bool {
+    true
+}
+
+/// Only report changes between windows of different applications, i.e. `WM_CLASS` class.
+/// Windows without `WM_CLASS` are always reported.
+pub fn different_class(old: &WindowInfo, new: &WindowInfo) -> bool {
+    match (&old.class, &new.class) {
+        (Some((_, old)), Some((_, new))) => old != new,
+        _ => true,
+    }
+}
+
+impl Default for ListenOptions {
+    fn default() -> Self {
+        ListenOptions {
+            debounce: DEFAULT_DEBOUNCE,
+            include_transient: false,
+            history_depth: DEFAULT_FOCUS_HISTORY_DEPTH,
+            all_screens: false,
+            settle_retries: DEFAULT_SETTLE_RETRIES,
+            settle_delay: DEFAULT_SETTLE_DELAY,
+            should_handle: any_change,
+        }
+    }
+}
+
+/// Listen active window changes, `on_window_switch` receives (previous window, current window).
+/// Uses the default [`ListenOptions`], see [`listen_active_window_changes_with`].
+pub fn listen_active_window_changes(
+    on_window_switch: impl FnMut(Option<&WindowInfo>, &WindowInfo),
+) -> Result<(), IbusSwitchError> {
+    listen_active_window_changes_with(ListenOptions::default(), on_window_switch)
+}
+
+/// Listen active window changes, coalescing changes within `debounce`.
+pub fn listen_active_window_changes_with_debounce(
+    debounce: Duration,
+    on_window_switch: impl FnMut(Option<&WindowInfo>, &WindowInfo),
+) -> Result<(), IbusSwitchError> {
+    let options = ListenOptions {
+        debounce,
+        ..ListenOptions::default()
+    };
+    listen_active_window_changes_with(options, on_window_switch)
+}
+
+/// Listen active window changes with `options`.
+pub fn listen_active_window_changes_with(
+    options: ListenOptions,
+    mut on_window_switch: impl FnMut(Option<&WindowInfo>, &WindowInfo),
+) -> Result<(), IbusSwitchError> {
+    listen_focus_changes(options, |_, last, current| on_window_switch(last, current))
+}
+
+/// Like [`listen_active_window_changes_with`], `on_focus` also receives the focus history,
+/// which already includes the current window.
+pub fn listen_focus_changes(
+    options: ListenOptions,
+    mut on_focus: impl FnMut(&FocusHistory, Option<&WindowInfo>, &WindowInfo),
+) -> Result<(), IbusSwitchError> {
+    listen_focus_changes_timed(options, |history, last, current, _| {
+        on_focus(history, last, current)
+    })
+}
+
+/// Like [`listen_focus_changes`], `on_focus` also receives when the active window changed,
+/// e.g. to measure how long each window stays focused. With debouncing, it's the time of the last
+/// `_NET_ACTIVE_WINDOW` change merged.
+pub fn listen_focus_changes_timed(
+    options: ListenOptions,
+    on_focus: impl FnMut(&FocusHistory, Option<&WindowInfo>, &WindowInfo, ChangeTime),
+) -> Result<(), IbusSwitchError> {
+    let mut watcher = PropertyWatcher::with_screens(options.debounce, options.all_screens)?;
+    watch_focus_changes(&mut watcher, options, on_focus)?;
+    watcher.run()
+}
+
+/// Block until the active window changes once, returns the new window,
+/// or None if nothing changed within `timeout` (None waits forever).
+/// The window active when called is not reported. Uses the default [`ListenOptions`].
+pub fn wait_for_next_window_change(
+    timeout: Option<Duration>,
+) -> Result<Option<WindowInfo>, IbusSwitchError> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let options = ListenOptions::default();
+    let next = RefCell::new(None);
+    let mut watcher = PropertyWatcher::with_screens(options.debounce, options.all_screens)?;
+    watch_focus_changes(&mut watcher, options, |_, last, current, _| {
+        // 第一次回调报告的是调用时的活动窗口
+        if last.is_some() {
+            next.borrow_mut().get_or_insert_with(|| current.clone());
+        }
+    })?;
+    watcher.stop_when(|| next.borrow().is_some());
+    watcher.run_until(deadline)?;
+    Ok(next.take())
+}
+
+/// 第一个窗口总是报告, 之后由 [`ListenOptions::should_handle`] 决定.
+fn should_report(options: ListenOptions, last: Option<&WindowInfo>, current: &WindowInfo) -> bool {
+    last.is_none_or(|last| (options.should_handle)(last, current))
+}
+
+/// 在 `watcher` 上注册 [`listen_focus_changes`] 的处理函数, 由调用者运行 `watcher`.
+pub(crate) fn watch_focus_changes<'a>(
+    watcher: &mut PropertyWatcher<'a>,
+    options: ListenOptions,
+    mut on_focus: impl FnMut(&FocusHistory, Option<&WindowInfo>, &WindowInfo, ChangeTime) + 'a,
+) -> Result<(), IbusSwitchError> {
+    // 上一个报告的窗口 (任意屏幕), 以及每个屏幕上一次的活动窗口 ID
+    let mut last_active_window: Option<WindowInfo> = None;
+    let mut last_ids: HashMap<usize, u32> = HashMap::new();
+    let mut history = FocusHistory::new(options.history_depth);
+    watcher.watch(b"_NET_ACTIVE_WINDOW", move |change| {
+        // 获取新的前台窗口 ID, 设置为 0 或根窗口都表示没有窗口获得焦点, 统一报告为 0
+        match get_active_window_property(change.conn, change.root, change.atom) {
+            Ok(Some(value)) => {
+                let current_active_id = active_window_id(Some(value), change.root).unwrap_or(0);
+                // 只有当窗口 ID 确实改变时才触发函数
+                if last_ids.get(&change.screen) != Some(&current_active_id) {
+                    let mut current = if current_active_id == 0 {
+                        WindowInfo::none()
+                    } else {
+                        WindowInfo::query_settled(
+                            change.conn,
+                            change.atoms,
+                            current_active_id,
+                            options.settle_retries,
+                            options.settle_delay,
+                        )
+                    };
+                    current.screen = change.screen;
+                    // 忽略弹出的菜单等, 之后回到原窗口时也不算切换
+                    if !options.include_transient && current.is_transient() {
+                        return;
+                    }
+                    // id 为 0 表示没有窗口获得焦点 (例如桌面), 不记录
+                    if current_active_id != 0 {
+                        history.push(current_active_id);
+                    }
+                    // 被跳过的窗口仍作为下一次比较的上一个窗口
+                    if should_report(options, last_active_window.as_ref(), &current) {
+                        on_focus(&history, last_active_window.as_ref(), &current, change.time);
+                    }
+                    last_ids.insert(change.screen, current_active_id);
+                    last_active_window = Some(current);
+                }
+            }
+            Ok(None) => {
+                // 窗口管理器可能暂时没有设置活动窗口
+                last_ids.remove(&change.screen);
+                if last_active_window
+                    .as_ref()
+                    .is_some_and(|w| w.screen == change.screen)
+                {
+                    last_active_window = None;
+                }
+            }
+            Err(e) => warn!("Failed to get active window id: {}", e),
+        }
+    })?;
+    Ok(())
+}
+
+/// Ask the window manager to activate `window_id`, by sending `_NET_ACTIVE_WINDOW` to the root window.
+pub fn activate_window(window_id: u32) -> Result<(), IbusSwitchError> {
+    let (conn, screen_num) = connect()?;
+    let root = conn.setup().roots[screen_num].root;
+    let net_active_window = intern_atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+    // data: 来源 (2 表示 pager 等直接代表用户的程序), 时间戳 (0 表示当前), 请求者当前的活动窗口
+    let event = xproto::ClientMessageEvent::new(
+        32,
+        window_id,
+        net_active_window,
+        [2, x11rb::CURRENT_TIME, 0, 0, 0],
+    );
+    conn.send_event(
+        false,
+        root,
+        xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Query the current desktop (workspace) over a new connection, None if the window manager hasn't set it.
+pub fn poll_current_desktop_once() -> Result<Option<u32>, IbusSwitchError> {
+    let (conn, screen_num) = connect()?;
+    let root_window = conn.setup().roots[screen_num].root;
+    let current_desktop_atom = intern_atom(&conn, b"_NET_CURRENT_DESKTOP")?;
+    get_current_desktop(&conn, root_window, current_desktop_atom)
+}
+
+/// 读取根窗口上的 `_NET_CURRENT_DESKTOP`, 未设置时返回 None.
+fn get_current_desktop(
+    conn: &impl Connection,
+    root_window: u32,
+    current_desktop_atom: u32,
+) -> Result<Option<u32>, IbusSwitchError> {
+    get_property_u32(
+        conn,
+        root_window,
+        current_desktop_atom,
+        "_NET_CURRENT_DESKTOP",
+        xproto::AtomEnum::CARDINAL,
+    )
+}
+
+/// Listen current desktop (workspace) changes, `on_change` receives (previous desktop, current desktop).
+/// Like [`listen_active_window_changes`], it's called once with the initial desktop.
+pub fn listen_desktop_changes(
+    on_change: impl FnMut(Option<u32>, u32),
+) -> Result<(), IbusSwitchError> {
+    let mut watcher = PropertyWatcher::new(Duration::ZERO)?;
+    watch_desktop_changes(&mut watcher, on_change)?;
+    watcher.run()
+}
+
+/// 在 `watcher` 上注册 [`listen_desktop_changes`] 的处理函数, 由调用者运行 `watcher`.
+fn watch_desktop_changes<'a>(
+    watcher: &mut PropertyWatcher<'a>,
+    mut on_change: impl FnMut(Option<u32>, u32) + 'a,
+) -> Result<(), IbusSwitchError> {
+    let mut last_desktop = None;
+    watcher.watch(
+        b"_NET_CURRENT_DESKTOP",
+        move |change| match get_current_desktop(change.conn, change.root, change.atom) {
+            Ok(Some(current)) if last_desktop != Some(current) => {
+                on_change(last_desktop, current);
+                last_desktop = Some(current);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to get current desktop: {}", e),
+        },
+    )?;
+    Ok(())
+}
+
+/// A change reported by [`WatchHandle`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WindowOrDesktopEvent {
+    /// The active window changed, as reported by [`listen_focus_changes_timed`].
+    Focus {
+        history: FocusHistory,
+        last: Option<Box<WindowInfo>>,
+        current: Box<WindowInfo>,
+        time: ChangeTime,
+    },
+    /// The current desktop changed, as reported by [`listen_desktop_changes`].
+    Desktop { last: Option<u32>, current: u32 },
+}
+
+/// Watches the active window and the current desktop over a single X connection and event loop,
+/// instead of one per [`listen_focus_changes`] and [`listen_desktop_changes`]. Events of both
+/// arrive at one callback in the order they are dispatched by the [`PropertyWatcher`],
+/// desktop changes are debounced like window changes.
+pub struct WatchHandle<'a> {
+    watcher: PropertyWatcher<'a>,
+}
+
+impl<'a> WatchHandle<'a> {
+    /// Watch the active window with `options`, and the current desktop too if `desktop`.
+    /// Both are reported once initially.
+    pub fn new(
+        options: ListenOptions,
+        desktop: bool,
+        on_event: impl FnMut(WindowOrDesktopEvent) + 'a,
+    ) -> Result<WatchHandle<'a>, IbusSwitchError> {
+        // 两个处理函数由同一个事件循环依次调用, 不会同时借用.
+        let on_event = Rc::new(RefCell::new(on_event));
+        let mut watcher = PropertyWatcher::with_screens(options.debounce, options.all_screens)?;
+        let on_focus = Rc::clone(&on_event);
+        watch_focus_changes(
+            &mut watcher,
+            options,
+            move |history, last, current, time| {
+                (on_focus.borrow_mut())(WindowOrDesktopEvent::Focus {
+                    history: history.clone(),
+                    last: last.cloned().map(Box::new),
+                    current: Box::new(current.clone()),
+                    time,
+                })
+            },
+        )?;
+        if desktop {
+            watch_desktop_changes(&mut watcher, move |last, current| {
+                (on_event.borrow_mut())(WindowOrDesktopEvent::Desktop { last, current })
+            })?;
+        }
+        Ok(WatchHandle { watcher })
+    }
+
+    /// Run the event loop, only returns on connection errors.
+    pub fn run(self) -> Result<(), IbusSwitchError> {
+        self.watcher.run()
+    }
+}
+
+/// Same as [`listen_active_window_changes`], for callers who only care about window ids.
+pub fn listen_active_window_id_changes(
+    mut on_window_switch: impl FnMut(Option<u32>, u32),
+) -> Result<(), IbusSwitchError> {
+    listen_active_window_changes(|last, current| on_window_switch(last.map(|w| w.id), current.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: u32 = 0x1e3;
+
+    /// GetProperty 的回复, `value_len` 按 `format` 由 `value` 的长度算出.
+    fn property_reply(
+        type_: impl Into<u32>,
+        format: u8,
+        value: Vec<u8>,
+        bytes_after: u32,
+    ) -> GetPropertyReply {
+        let value_len = match format {
+            0 => 0,
+            format => value.len() as u32 / (format as u32 / 8),
+        };
+        GetPropertyReply {
+            format,
+            sequence: 0,
+            length: 0,
+            type_: type_.into(),
+            bytes_after,
+            value_len,
+            value,
+        }
+    }
+
+    /// `_NET_ACTIVE_WINDOW` 的回复, 值为 `window`.
+    fn reply(window: Option<u32>) -> GetPropertyReply {
+        match window {
+            Some(window) => {
+                property_reply(xproto::AtomEnum::WINDOW, 32, window.to_ne_bytes().into(), 0)
+            }
+            None => property_reply(xproto::AtomEnum::NONE, 0, Vec::new(), 0),
+        }
+    }
+
+    fn active(window: Option<u32>) -> Option<u32> {
+        let value = parse_u32_property(ROOT, "_NET_ACTIVE_WINDOW", &reply(window)).unwrap();
+        active_window_id(value, ROOT)
+    }
+
+    #[test]
+    fn zero_or_root_is_no_active_window() {
+        assert_eq!(active(Some(0)), None);
+        assert_eq!(active(Some(ROOT)), None);
+        assert_eq!(active(None), None);
+        assert_eq!(active(Some(0x3a00003)), Some(0x3a00003));
+    }
+
+    #[test]
+    fn current_desktop_cardinal() {
+        let parse = |reply| parse_u32_property(ROOT, "_NET_CURRENT_DESKTOP", &reply).unwrap();
+        let cardinal = |value: u32| {
+            property_reply(
+                xproto::AtomEnum::CARDINAL,
+                32,
+                value.to_ne_bytes().into(),
+                0,
+            )
+        };
+        assert_eq!(parse(cardinal(0)), Some(0));
+        assert_eq!(parse(cardinal(3)), Some(3));
+        // 只读取第一个值
+        let mut two = cardinal(2);
+        two.value.extend(5u32.to_ne_bytes());
+        assert_eq!(parse(two), Some(2));
+        // 窗口管理器没有设置这个属性
+        assert_eq!(
+            parse(property_reply(xproto::AtomEnum::NONE, 0, Vec::new(), 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn window_types() {
+        let value: Vec<u8> = [301u32, 302].iter().flat_map(|a| a.to_ne_bytes()).collect();
+        assert_eq!(parse_atoms(&value), [301, 302]);
+        assert!(parse_atoms(&[]).is_empty());
+
+        let types = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect::<Vec<_>>();
+        for name in TRANSIENT_WINDOW_TYPES {
+            assert!(is_transient_type(&types(&[name])), "{name}");
+        }
+        // 没有 _NET_WM_WINDOW_TYPE 的窗口按普通窗口处理
+        assert!(!is_transient_type(&[]));
+        assert!(!is_transient_type(&types(&["_NET_WM_WINDOW_TYPE_NORMAL"])));
+        assert!(!is_transient_type(&types(&["_NET_WM_WINDOW_TYPE_DIALOG"])));
+        // 只看第一个类型, 后面的是旧窗口管理器的后备类型
+        assert!(!is_transient_type(&types(&[
+            "_KDE_NET_WM_WINDOW_TYPE_OVERRIDE",
+            "_NET_WM_WINDOW_TYPE_MENU",
+        ])));
+        assert!(is_transient_type(&types(&[
+            "_NET_WM_WINDOW_TYPE_DROPDOWN_MENU",
+            "_NET_WM_WINDOW_TYPE_NORMAL",
+        ])));
+    }
+
+    #[test]
+    fn errors_name_the_window_and_property() {
+        use crate::fake::x11::FakeConnection;
+        use x11rb::{errors::ReplyError, protocol::ErrorKind};
+
+        let conn = FakeConnection::new();
+        let missing = 0xdead;
+        match get_window_class(&conn, missing) {
+            Err(IbusSwitchError::Property {
+                window,
+                property,
+                source: ReplyError::X11Error(e),
+            }) => {
+                assert_eq!((window, property.as_str()), (missing, "WM_CLASS"));
+                assert_eq!(e.error_kind, ErrorKind::Window);
+            }
+            other => panic!("{other:?}"),
+        }
+
+        // 8 位格式的 _NET_ACTIVE_WINDOW
+        let window = 0x3a00003;
+        conn.set_property(
+            window,
+            b"_NET_ACTIVE_WINDOW",
+            xproto::AtomEnum::WINDOW,
+            8,
+            b"ab".into(),
+        );
+        let atom = conn.atom(b"_NET_ACTIVE_WINDOW");
+        assert!(matches!(
+            get_active_window_id(&conn, window, atom),
+            Err(IbusSwitchError::PropertyFormat { window: w, format: 8, len: 2, .. }) if w == window
+        ));
+        // 类型不符
+        conn.set_property32(
+            window,
+            b"_NET_ACTIVE_WINDOW",
+            xproto::AtomEnum::CARDINAL,
+            &[1],
+        );
+        assert!(matches!(
+            get_active_window_id(&conn, window, atom),
+            Err(IbusSwitchError::PropertyFormat { len: 0, .. })
+        ));
+        assert!(matches!(
+            intern_atom(&conn, b"_NET_ACTIVE_WINDOW"),
+            Ok(a) if a == atom
+        ));
+    }
+
+    #[test]
+    fn u32_property_payloads() {
+        let parse = |reply| parse_u32_property(ROOT, "_NET_ACTIVE_WINDOW", &reply);
+        let window = xproto::AtomEnum::WINDOW;
+        // 空: 属性不存在, 或存在但没有值
+        assert!(matches!(
+            parse(property_reply(xproto::AtomEnum::NONE, 0, Vec::new(), 0)),
+            Ok(None)
+        ));
+        assert!(matches!(
+            parse(property_reply(window, 32, Vec::new(), 0)),
+            Ok(None)
+        ));
+        // 不足 4 字节
+        assert!(matches!(
+            parse(property_reply(window, 8, vec![1, 2], 0)),
+            Err(IbusSwitchError::PropertyFormat {
+                format: 8,
+                len: 2,
+                ..
+            })
+        ));
+        // 长度足够但不是 32 位格式
+        assert!(matches!(
+            parse(property_reply(window, 16, vec![1, 0, 2, 0], 0)),
+            Err(IbusSwitchError::PropertyFormat {
+                format: 16,
+                len: 4,
+                ..
+            })
+        ));
+        // 类型不符时没有数据, 只有 bytes_after
+        assert!(matches!(
+            parse(property_reply(
+                xproto::AtomEnum::CARDINAL,
+                32,
+                Vec::new(),
+                4
+            )),
+            Err(IbusSwitchError::PropertyFormat { len: 0, .. })
+        ));
+        let valid = property_reply(window, 32, 0x3a00003u32.to_ne_bytes().into(), 0);
+        assert_eq!(parse(valid).unwrap(), Some(0x3a00003));
+    }
+
+    #[test]
+    fn predicate_suppresses_callbacks() {
+        let window = |id, class: &str| WindowInfo {
+            id,
+            class: Some((class.to_lowercase(), class.to_owned())),
+            ..WindowInfo::none()
+        };
+        // 和 watch_focus_changes 一样, 被跳过的窗口仍作为下一次比较的上一个窗口
+        let reported = |options: ListenOptions, windows: &[WindowInfo]| {
+            let mut last: Option<&WindowInfo> = None;
+            let mut reported = Vec::new();
+            for current in windows {
+                if should_report(options, last, current) {
+                    reported.push(current.id);
+                }
+                last = Some(current);
+            }
+            reported
+        };
+        let windows = [
+            window(1, "Firefox"),
+            window(2, "Firefox"),
+            window(3, "Code"),
+            window(4, "Code"),
+            WindowInfo::none(),
+            window(5, "Code"),
+        ];
+        assert_eq!(
+            reported(ListenOptions::default(), &windows),
+            [1, 2, 3, 4, 0, 5]
+        );
+        let by_class = ListenOptions {
+            should_handle: different_class,
+            ..ListenOptions::default()
+        };
+        // 没有 WM_CLASS 的窗口 (0) 总是报告, 回到 Code 也是
+        assert_eq!(reported(by_class, &windows), [1, 3, 0, 5]);
+        let never = ListenOptions {
+            should_handle: |_, _| false,
+            ..ListenOptions::default()
+        };
+        // 第一个窗口总是报告
+        assert_eq!(reported(never, &windows), [1]);
+    }
+
+    #[test]
+    fn no_active_window_is_none() {
+        use crate::fake::x11::{FakeConnection, ROOT};
+
+        let conn = FakeConnection::new();
+        let atoms = AtomCache::new();
+        let active = |values: &[u32]| {
+            conn.set_property32(
+                ROOT,
+                b"_NET_ACTIVE_WINDOW",
+                xproto::AtomEnum::WINDOW,
+                values,
+            );
+            get_active_window(&conn, &atoms, ROOT).unwrap()
+        };
+        // 属性不存在, 为空, 为 0 或根窗口
+        assert_eq!(get_active_window(&conn, &atoms, ROOT).unwrap(), None);
+        assert_eq!(active(&[]), None);
+        assert_eq!(active(&[0]), None);
+        assert_eq!(active(&[ROOT]), None);
+
+        let window = 0x3a00003;
+        let utf8_string = conn.atom(b"UTF8_STRING");
+        conn.set_property(window, b"_NET_WM_NAME", utf8_string, 8, "终端".into());
+        let info = active(&[window]).unwrap();
+        assert_eq!((info.id, info.title.as_deref()), (window, Some("终端")));
+        assert_eq!(info.class, None);
+    }
+
+    #[test]
+    fn malformed_reply_messages() {
+        use crate::fake::x11::FakeConnection;
+
+        let window = 0x3a00003;
+        // 8 位格式的 WINDOW (33)
+        let reply = property_reply(xproto::AtomEnum::WINDOW, 8, b"ab".into(), 0);
+        let e = parse_u32_property(window, "_NET_ACTIVE_WINDOW", &reply).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Unexpected property _NET_ACTIVE_WINDOW of window 0x03a00003: type=33, format=8, len=2"
+        );
+        let conn = FakeConnection::new();
+        let e = get_window_class(&conn, 0xdead).unwrap_err().to_string();
+        assert!(
+            e.starts_with("Failed to get property WM_CLASS of window 0x0000dead: "),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn connect_retries_until_success() {
+        let backoff = Duration::from_millis(1);
+        // 前两次失败, 第三次连接成功
+        let mut attempts = 0;
+        let start = Instant::now();
+        let conn = retry_connect(5, backoff, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err("refused")
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(conn, Ok(3));
+        // 等待 1ms 和 2ms
+        assert!(start.elapsed() >= Duration::from_millis(3));
+
+        let mut attempts = 0;
+        let conn: Result<(), _> = retry_connect(3, backoff, || {
+            attempts += 1;
+            Err(format!("refused {attempts}"))
+        });
+        assert_eq!(conn, Err("refused 3".to_owned()));
+        // 至少尝试一次
+        let mut attempts = 0;
+        let conn: Result<(), _> = retry_connect(0, backoff, || {
+            attempts += 1;
+            Err("refused")
+        });
+        assert_eq!((conn, attempts), (Err("refused"), 1));
+    }
+
+    #[test]
+    fn unsettled_windows_are_read_again() {
+        let window = |class: Option<&str>, title: &str| WindowInfo {
+            id: 0x3c00016,
+            class: class.map(|class| (class.to_lowercase(), class.to_owned())),
+            title: Some(title.to_owned()),
+            ..WindowInfo::none()
+        };
+        // 第一次读到空的 WM_CLASS, 第二次读到值
+        let mut reads = vec![window(Some("Firefox"), "Firefox"), window(None, "")];
+        let read = read_settled(
+            3,
+            Duration::ZERO,
+            || reads.pop().unwrap(),
+            WindowInfo::is_settled,
+        );
+        assert_eq!(read, window(Some("Firefox"), "Firefox"));
+        assert!(reads.is_empty());
+
+        // 重试用完后返回最后一次的结果
+        let mut count = 0;
+        let read = read_settled(
+            2,
+            Duration::ZERO,
+            || {
+                count += 1;
+                window(None, "")
+            },
+            WindowInfo::is_settled,
+        );
+        assert_eq!((read.class, count), (None, 3));
+        // 已经确定时不再读取
+        let mut count = 0;
+        read_settled(2, Duration::ZERO, || count += 1, |_| true);
+        assert_eq!(count, 1);
+        assert!(!window(Some("Firefox"), "").is_settled());
+    }
+}
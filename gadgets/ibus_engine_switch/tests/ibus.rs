@@ -0,0 +1,23 @@
+//! 需要正在运行的 IBus, 默认忽略, 用 `cargo test -p ibus_engine_switch -- --ignored` 运行.
+
+use ibus_engine_switch::{
+    backend::{InputMethodBackend, current_engine, set_engine},
+    config::Transport,
+    ibus::IBus,
+};
+
+#[test]
+#[ignore = "needs a running IBus daemon"]
+fn switch_and_read_back() {
+    for transport in [Transport::Dbus, Transport::Process] {
+        let ibus = IBus::new(transport);
+        let original = current_engine(&ibus).unwrap();
+        let engines = ibus.list_engines().unwrap();
+        assert!(engines.contains(&original), "{original} in {engines:?}");
+        // 在已安装的输入法间切换一次, 最后恢复原来的输入法.
+        for engine in engines.iter().take(2).chain([&original]) {
+            assert_eq!(&set_engine(&ibus, engine, 1).unwrap(), engine);
+            assert_eq!(&current_engine(&ibus).unwrap(), engine);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! `--activate-on-switch`: 切换之后激活目标工作区上的窗口, 否则切换后往往没有窗口获得焦点.
+
+use crate::{BackendWrapper, Error, WindowEntry, WorkspaceBackend, warn};
+use tracing::debug;
+
+/// The window to activate after switching to workspace `idx`: the last one `wmctrl -l` lists there,
+/// i.e. the most recently mapped. Sticky windows are skipped, None if the workspace has no windows.
+pub fn window_to_activate(windows: &[WindowEntry], idx: usize) -> Option<u32> {
+    windows
+        .iter()
+        .rev()
+        .find(|w| usize::try_from(w.desktop) == Ok(idx))
+        .map(|w| w.id)
+}
+
+/// Wraps a backend and activates [`window_to_activate`] after every switch.
+/// Moving the viewport doesn't activate, the window may be in another viewport.
+/// Failing to activate is only warned, the switch itself succeeded.
+pub struct ActivateOnSwitch {
+    pub inner: Box<dyn WorkspaceBackend>,
+}
+
+impl BackendWrapper for ActivateOnSwitch {
+    fn inner(&self) -> &dyn WorkspaceBackend {
+        self.inner.as_ref()
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.inner.switch_to(idx)?;
+        let result =
+            self.inner
+                .windows()
+                .and_then(|windows| match window_to_activate(&windows, idx) {
+                    Some(window) => self.inner.activate_window(window),
+                    None => {
+                        debug!("No window on workspace {idx} to activate.");
+                        Ok(())
+                    }
+                });
+        if let Err(e) = result {
+            warn(format_args!(
+                "failed to activate a window after switching: {e}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake, parse_windows};
+
+    #[test]
+    fn last_listed_window_of_the_target() {
+        let windows = parse_windows(fake::WMCTRL_L).unwrap();
+        assert_eq!(window_to_activate(&windows, 0), Some(0x03a00003));
+        // 最后列出的是最近映射的窗口
+        assert_eq!(window_to_activate(&windows, 1), Some(0x03c0002a));
+        assert_eq!(window_to_activate(&windows, 3), Some(0x04200004));
+        // 只有 sticky 窗口的工作区
+        assert_eq!(window_to_activate(&windows, 2), None);
+        assert_eq!(window_to_activate(&[], 0), None);
+    }
+}
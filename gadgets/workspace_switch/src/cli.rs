@@ -0,0 +1,1123 @@
+use crate::{
+    CountedWorkspace, Cycle, Direction, Error, Snapshot, StickyWindows, WindowEntry, Wmctrl,
+    WorkspaceBackend,
+    activate::ActivateOnSwitch,
+    active_workspace,
+    config::{CommandLine, Config, DefaultAction, EffectiveConfig},
+    find_by_filter, format_status,
+    fullscreen::UnlessFullscreen,
+    gnome::Gnome,
+    goto_window, grid_view, ime,
+    ime::PostSwitch,
+    listing::{ListStyle, render_list},
+    move_active_to,
+    notifier::Notifying,
+    old_and_new, parse_window_id, picker, pull_window, rename, reserved_wmctrl_args, resolve_index,
+    set_count, set_quiet, set_sticky,
+    stdin::{FromStdin, read_workspaces},
+    sticky_count, swap_workspaces, switch_by, switch_by_matching, switch_grid, switch_in_history,
+    switch_recorded, switch_to_last, switch_to_name, take_window,
+    template::Template,
+    verify::Verifying,
+    warn, window_counts, x11,
+};
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::{Shell, generate};
+use gadgets_common::logging;
+use regex::Regex;
+use std::{
+    cell::Cell,
+    env,
+    io::{self, IsTerminal, Write},
+    net::IpAddr,
+    path::PathBuf,
+    process::exit,
+    rc::Rc,
+};
+use tracing::{debug, info, level_filters::LevelFilter};
+use tracing_subscriber::EnvFilter;
+
+#[derive(clap::Parser, Clone, Debug)]
+#[command(name = "wsst", author, version, about, long_about = None)]
+pub struct Args {
+    #[clap(
+        short = 's',
+        long = "switch",
+        allow_negative_numbers = true,
+        help = "The workspace idx you want to switch to, use `-l` to have a look. Negative idx counts from the end, -1 is the last workspace."
+    )]
+    switch_to: Option<isize>,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "switch_to",
+        help = "Clamp the --switch idx to the first or last workspace instead of failing when it's out of range."
+    )]
+    clamp: bool,
+    #[clap(
+        short = 'n',
+        long = "next",
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        help = "Switch by n workspace, 1 if n is omitted. Requires that n > 0, switch to right workspace. If n exceeds range, it selects like a cycle unless --no-cycle specific."
+    )]
+    switch_by_next: Option<usize>,
+    #[clap(
+        short = 'p',
+        long = "prev",
+        conflicts_with = "switch_by_next",
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        help = "Switch by n workspace, 1 if n is omitted. Requires that n > 0, switch to left workspace. If n exceeds range, it selects like a cycle unless --no-cycle specific."
+    )]
+    switch_by_prev: Option<usize>,
+    #[clap(
+        long,
+        value_name = "REGEX",
+        conflicts_with = "filter",
+        help = "With --next/--prev, only count the workspaces whose name matches the regex, skipping the others."
+    )]
+    relative_name: Option<Regex>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Move one cell up in the workspace grid."
+    )]
+    up: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Move one cell down in the workspace grid."
+    )]
+    down: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Move one cell left in the workspace grid."
+    )]
+    left: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Move one cell right in the workspace grid."
+    )]
+    right: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Do not cycle around the workspace, see --prev/--next and --up/--down/--left/--right. Same as --cycle none."
+    )]
+    no_cycle: bool,
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with = "no_cycle",
+        help = "Which axes of the workspace grid to cycle around, defaults to both. --prev/--next move along the x axis."
+    )]
+    cycle: Option<Cycle>,
+    #[clap(
+        short = 'l',
+        long = "list",
+        default_value_t = false,
+        help = "List the available workspaces"
+    )]
+    list_workspaces: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "list_workspaces",
+        conflicts_with_all = ["format", "format_str", "filter"],
+        help = "Show --list as the grid --up/--down/--left/--right move in, the active cell is marked with *."
+    )]
+    grid: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "List the windows grouped by workspace."
+    )]
+    windows: bool,
+    #[clap(
+        short = 'i',
+        long,
+        default_value_t = false,
+        help = "Pick the workspace to switch to in a terminal menu, with the arrow keys and Enter. Esc cancels."
+    )]
+    interactive: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Print the idx of the active workspace."
+    )]
+    current: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Print the number of workspaces."
+    )]
+    count: bool,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Format::Human,
+        help = "Output format of --list."
+    )]
+    format: Format,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = ColorChoice::Auto,
+        help = "Human --list output: aligned with the active workspace highlighted (always) or tab separated fields (never). auto picks by whether stdout is a terminal."
+    )]
+    color: ColorChoice,
+    #[clap(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with = "format",
+        help = "Print each workspace of --list by the template, {idx}, {active}, {name}, {dg}, {vp} and {wa} are replaced. Use {{ and }} for literal braces."
+    )]
+    format_str: Option<Template>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Print an object with the count, the active idx and all workspaces as JSON."
+    )]
+    json: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["format_str", "grid"],
+        help = "Include the number of windows on each workspace in --list, --json and --notify. Sticky windows are counted separately, unless --count-sticky."
+    )]
+    window_count: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "window_count",
+        help = "Count sticky windows (shown on all workspaces) on every workspace for --window-count."
+    )]
+    count_sticky: bool,
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Only list the workspaces whose name matches the regex. Without --list, switch to the only matching workspace."
+    )]
+    filter: Option<Regex>,
+    #[clap(
+        long = "name",
+        value_name = "NAME",
+        help = "Switch to the workspace by its name, matches exactly, then case-insensitively, then by prefix. Repeating cycles through the workspaces matching the prefix."
+    )]
+    switch_to_name: Option<String>,
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Switch to the workspace of the window whose title matches the regex. Sticky windows are activated instead."
+    )]
+    goto_window: Option<Regex>,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "goto_window",
+        help = "Also activate the window after --goto-window."
+    )]
+    activate: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "goto_window",
+        help = "Pick the first window listed when --goto-window matches several, instead of failing."
+    )]
+    first: bool,
+    #[clap(
+        long,
+        value_name = "LABEL",
+        help = "Switch to the workspace idx the label is mapped to under [labels] in the config, regardless of the workspace names."
+    )]
+    goto: Option<String>,
+    #[clap(
+        long = "move-to",
+        value_name = "IDX",
+        help = "Move the active window to the workspace idx."
+    )]
+    move_to: Option<usize>,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "move_to",
+        help = "Also switch to the workspace after --move-to."
+    )]
+    follow: bool,
+    #[clap(
+        long,
+        value_name = "IDX",
+        conflicts_with = "move_to",
+        help = "Move the active window to the workspace idx and switch there, checking that both took effect and switching again if the window manager dropped the switch."
+    )]
+    take: Option<usize>,
+    #[clap(
+        long,
+        visible_alias = "move-here",
+        value_name = "WINDOW_ID",
+        value_parser = parse_window_id,
+        help = "Move the window to the active workspace and activate it, use --windows to find the id."
+    )]
+    pull: Option<u32>,
+    #[clap(
+        long,
+        value_name = "WINDOW_ID",
+        value_parser = parse_window_id,
+        conflicts_with = "unsticky",
+        help = "Show the window on all workspaces."
+    )]
+    sticky: Option<u32>,
+    #[clap(
+        long,
+        value_name = "WINDOW_ID",
+        value_parser = parse_window_id,
+        help = "Show the sticky window only on its own workspace again."
+    )]
+    unsticky: Option<u32>,
+    #[clap(
+        long,
+        num_args = 2,
+        value_names = ["IDX", "NAME"],
+        help = "Rename the workspace idx."
+    )]
+    rename: Option<Vec<String>>,
+    #[clap(
+        long,
+        num_args = 2,
+        value_names = ["A", "B"],
+        help = "Exchange the windows of workspaces A and B, sticky windows stay."
+    )]
+    swap: Option<Vec<usize>>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Switch to the previously active workspace."
+    )]
+    last: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with = "forward",
+        help = "Go back in the switch history, like a browser."
+    )]
+    back: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Go forward in the switch history, undoing --back."
+    )]
+    forward: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Don't switch while the active window is fullscreen, exit with 3 instead. Checks the window over X11 with any --backend."
+    )]
+    unless_fullscreen: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Change the number of workspaces, if the window manager allows it."
+    )]
+    set_count: Option<usize>,
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "set_count",
+        help = "Allow --set-count to remove the active workspace."
+    )]
+    force: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Keep running and print the active workspace each time it changes, for status bars. Always uses the x11 backend."
+    )]
+    watch: bool,
+    #[clap(
+        long,
+        value_name = "FORMAT",
+        default_value = "{idx}",
+        requires = "watch",
+        help = "Output format of --watch, {idx}, {name} and {num} (the number of workspaces) are replaced."
+    )]
+    watch_format: String,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["backend", "watch"],
+        help = "Read the workspaces from stdin in the format of `wmctrl -d` instead of querying the window manager, e.g. `wmctrl -d | wsst --from-stdin --json`. Only listing and querying work."
+    )]
+    from_stdin: bool,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = BackendKind::Wmctrl,
+        help = "How to talk to the window manager, x11 doesn't require wmctrl."
+    )]
+    backend: BackendKind,
+    #[clap(
+        long = "wmctrl-arg",
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Append the argument to every wmctrl invocation, can be repeated. Only for window manager quirks, may break parsing the output of wmctrl."
+    )]
+    wmctrl_args: Vec<String>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Post a desktop notification showing the workspace switched to."
+    )]
+    notify: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Fail if the workspace didn't change after switching, instead of only warning."
+    )]
+    verify: bool,
+    #[clap(
+        short = 'q',
+        long,
+        default_value_t = false,
+        help = "Don't print warnings, errors are still printed to stderr. Output like --list, --current and --json always goes to stdout."
+    )]
+    quiet: bool,
+    #[clap(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Log to stderr what wsst does, -v for info, -vv for debug (e.g. wmctrl invocations) and -vvv for trace. RUST_LOG takes precedence."
+    )]
+    verbose: u8,
+    #[clap(
+        long,
+        value_name = "ENGINE",
+        help = "After switching, ask the ibus_engine_switch switch server to switch to the input method (name or index). Only warns if the server isn't running."
+    )]
+    with_ime: Option<String>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "After switching, activate the last window wmctrl lists on the workspace, if any."
+    )]
+    activate_on_switch: bool,
+    #[clap(
+        long,
+        value_name = "IP",
+        default_value_t = ime::DEFAULT_ADDR,
+        requires = "with_ime",
+        help = "Address of the switch server for --with-ime, its bind_addr."
+    )]
+    ime_addr: IpAddr,
+    #[clap(
+        long,
+        value_name = "SHELL",
+        hide = true,
+        help = "Print the completion script for the shell."
+    )]
+    generate_completions: Option<Shell>,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Print the config file merged over the defaults and the options applying to every action as TOML, then exit."
+    )]
+    print_config: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["json", "list_workspaces", "windows", "current", "count", "watch", "print_config", "from_stdin"],
+        help = "After switching, print the active workspace idx before and after as `OLD NEW`, the same idx twice if nothing changed."
+    )]
+    print: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["backend", "from_stdin", "watch"],
+        help = "Print the output of `wmctrl -d` as is and exit, without parsing it. For bug reports, compare it with --list."
+    )]
+    dump_raw: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum BackendKind {
+    Wmctrl,
+    X11,
+    /// GNOME Shell over D-Bus, see the README.
+    Gnome,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Exit code when the switch requested changed nothing, e.g. already on the workspace,
+/// or at the edge with `--no-cycle`.
+pub const EXIT_UNCHANGED: i32 = 3;
+
+/// [`logging::env_filter`] starting at `warn`, wsst is quiet unless something goes wrong.
+/// Install it with [`logging::init`] writing to stderr, so the output stays parsable.
+pub fn env_log_filter(verbose: u8) -> EnvFilter {
+    logging::env_filter(LevelFilter::WARN, verbose)
+}
+
+/// The exit code of the result of [`run`]: 0 on success, 1 on errors, [`EXIT_UNCHANGED`] if nothing changed.
+/// Usage errors exit with 2.
+pub fn exit_code(result: &Result<bool, Error>) -> i32 {
+    match result {
+        Ok(true) => 0,
+        // --unless-fullscreen 拦下的切换也算什么都没改变.
+        Ok(false) | Err(Error::Fullscreen) => EXIT_UNCHANGED,
+        Err(Error::NoAction) => 2,
+        Err(_) => 1,
+    }
+}
+
+/// Exit with [`exit_code`] of `result`, reporting errors to stderr.
+pub fn exit_with(result: Result<bool, Error>) -> ! {
+    exit(report(result, &mut io::stderr()))
+}
+
+/// 把 `result` 的错误写入 `stderr`, 没有给出操作时写入帮助, 返回 [`exit_code`].
+fn report(result: Result<bool, Error>, stderr: &mut impl Write) -> i32 {
+    let code = exit_code(&result);
+    // 标准错误流被关闭时没有可输出的地方, 忽略.
+    match result {
+        Ok(true) => {}
+        Ok(false) => info!("Nothing changed, exit with {code}."),
+        Err(e @ Error::Fullscreen) => info!("{e} Exit with {code}."),
+        Err(Error::NoAction) => {
+            let _ = write!(stderr, "{}", Args::command().render_help());
+        }
+        Err(e) => {
+            debug!("{e:?}");
+            let _ = writeln!(stderr, "{e}");
+        }
+    }
+    code
+}
+
+/// Returns false if a switch was requested but the active workspace didn't change.
+pub fn run(args: Args) -> Result<bool, Error> {
+    run_with(args, crate::find_wmctrl)
+}
+
+/// [`run`], resolving the path of wmctrl with `find_wmctrl` only when wmctrl is used.
+fn run_with(args: Args, find_wmctrl: impl Fn() -> Result<PathBuf, Error>) -> Result<bool, Error> {
+    set_quiet(args.quiet);
+    if let Some(shell) = args.generate_completions {
+        generate(shell, &mut Args::command(), "wsst", &mut io::stdout());
+        return Ok(true);
+    }
+    if args.print_config {
+        print!(
+            "{}",
+            args.effective_config(Config::default_path(), load_config()?)
+                .to_toml()
+        );
+        return Ok(true);
+    }
+    if args.watch {
+        return watch(&args.watch_format).map(|_| true);
+    }
+    if args.dump_raw {
+        let raw = Wmctrl {
+            path: find_wmctrl()?,
+            extra_args: args.wmctrl_args.clone(),
+        }
+        .dump_raw()?;
+        // 标准输出被关闭时没有可输出的地方, 忽略.
+        let _ = io::stdout().write_all(&raw);
+        return Ok(true);
+    }
+    let mut backend: Box<dyn WorkspaceBackend> = if args.from_stdin {
+        info!("Reading the workspaces from stdin.");
+        Box::new(FromStdin {
+            workspaces: read_workspaces(io::stdin().lock())?,
+        })
+    } else {
+        info!("Using the {:?} backend.", args.backend);
+        match args.backend {
+            BackendKind::Wmctrl => {
+                let reserved = reserved_wmctrl_args(&args.wmctrl_args);
+                if !reserved.is_empty() {
+                    warn(format_args!(
+                        "--wmctrl-arg {} may conflict with the arguments wsst passes.",
+                        reserved.join(" ")
+                    ));
+                }
+                Box::new(Wmctrl {
+                    path: find_wmctrl()?,
+                    extra_args: args.wmctrl_args.clone(),
+                })
+            }
+            BackendKind::X11 => Box::new(x11::X11::connect()?),
+            BackendKind::Gnome => Box::new(Gnome::connect()?),
+        }
+    };
+    if args.unless_fullscreen {
+        backend = Box::new(UnlessFullscreen { inner: backend });
+    }
+    backend = Box::new(Verifying {
+        inner: backend,
+        strict: args.verify,
+    });
+    if args.notify {
+        backend = Box::new(Notifying {
+            inner: backend,
+            window_count: args.window_count.then(|| args.sticky_windows()),
+        });
+    }
+    if args.activate_on_switch {
+        backend = Box::new(ActivateOnSwitch { inner: backend });
+    }
+    if let Some(engine) = args.with_ime.clone() {
+        backend = Box::new(PostSwitch::with_ime(backend, args.ime_addr, engine));
+    }
+    if args.print {
+        // 记录最后一次切换到的工作区, 没有切换时输出两次切换前的工作区.
+        let target = Rc::new(Cell::new(None));
+        let hook_target = Rc::clone(&target);
+        let backend = PostSwitch {
+            inner: backend,
+            hook: Box::new(move |idx| hook_target.set(Some(idx))),
+        };
+        let (changed, old, new) = old_and_new(
+            || backend.query(),
+            || Ok((dispatch(&args, &backend)?, target.get())),
+        )?;
+        println!("{old} {new}");
+        return Ok(changed);
+    }
+    dispatch(&args, backend.as_ref())
+}
+
+impl Args {
+    /// Number of `-v` given.
+    pub fn verbose(&self) -> u8 {
+        self.verbose
+    }
+
+    /// `--cycle`, `none` with `--no-cycle`, `both` by default.
+    pub fn cycle(&self) -> Cycle {
+        match self.cycle {
+            Some(cycle) => cycle,
+            None if self.no_cycle => Cycle::None,
+            None => Cycle::Both,
+        }
+    }
+
+    /// Merge `config`, loaded from `path`, with the options of `self`, see [`EffectiveConfig`].
+    pub fn effective_config(&self, path: Option<PathBuf>, config: Config) -> EffectiveConfig {
+        EffectiveConfig {
+            path,
+            config,
+            command_line: CommandLine {
+                backend: value_name(self.backend),
+                cycle: self.cycle(),
+                verify: self.verify,
+                notify: self.notify,
+                unless_fullscreen: self.unless_fullscreen,
+                activate_on_switch: self.activate_on_switch,
+                color: value_name(self.color),
+                wmctrl_args: self.wmctrl_args.clone(),
+                with_ime: self.with_ime.clone(),
+                ime_addr: self.ime_addr,
+            },
+        }
+    }
+
+    /// The signed step of `--next` (positive) or `--prev` (negative), None if neither is given.
+    /// `-n`/`-p` without a value step by 1.
+    pub fn step(&self) -> Option<isize> {
+        match (self.switch_by_next, self.switch_by_prev) {
+            (Some(n), _) => Some(isize::try_from(n).unwrap_or(isize::MAX)),
+            (None, Some(n)) => Some(isize::try_from(n).map_or(isize::MIN, |n| -n)),
+            (None, None) => None,
+        }
+    }
+
+    /// 终端中对齐输出, `NO_COLOR` 不为空时不加颜色.
+    fn list_style(&self) -> ListStyle {
+        match self.color {
+            ColorChoice::Always => ListStyle::Aligned { color: true },
+            ColorChoice::Never => ListStyle::Plain,
+            ColorChoice::Auto if io::stdout().is_terminal() => ListStyle::Aligned {
+                color: env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+            },
+            ColorChoice::Auto => ListStyle::Plain,
+        }
+    }
+
+    fn sticky_windows(&self) -> StickyWindows {
+        if self.count_sticky {
+            StickyWindows::Everywhere
+        } else {
+            StickyWindows::Separate
+        }
+    }
+}
+
+/// 执行 `args` 指定的操作.
+fn dispatch(args: &Args, backend: &dyn WorkspaceBackend) -> Result<bool, Error> {
+    if args.json {
+        let snapshot = Snapshot::new(backend.query()?);
+        if args.window_count {
+            let snapshot = snapshot.counted(&backend.windows()?, args.sticky_windows());
+            println!("{}", serde_json::to_string(&snapshot).unwrap());
+        } else {
+            println!("{}", serde_json::to_string(&snapshot).unwrap());
+        }
+        return Ok(true);
+    }
+    if args.list_workspaces {
+        let mut workspaces = backend.query()?;
+        if args.grid {
+            match grid_view(&workspaces) {
+                Some(view) => {
+                    println!("{view}");
+                    return Ok(true);
+                }
+                None => warn("desktop geometry or viewport is N/A, fall back to the list."),
+            }
+        }
+        if let Some(pattern) = &args.filter {
+            workspaces.retain(|ws| pattern.is_match(&ws.name));
+        }
+        if let Some(template) = &args.format_str {
+            for ele in &workspaces {
+                println!("{}", template.render(ele));
+            }
+            return Ok(true);
+        }
+        if args.window_count {
+            let windows = backend.windows()?;
+            match args.format {
+                Format::Human => {
+                    let counts = window_counts(&workspaces, &windows, args.sticky_windows());
+                    let sticky = Some(sticky_count(&windows))
+                        .filter(|&sticky| !args.count_sticky && sticky > 0);
+                    for line in render_list(&workspaces, Some(&counts), sticky, args.list_style()) {
+                        println!("{line}");
+                    }
+                }
+                Format::Json => {
+                    let workspaces =
+                        CountedWorkspace::count(workspaces, &windows, args.sticky_windows());
+                    println!("{}", serde_json::to_string(&workspaces).unwrap());
+                }
+            }
+            return Ok(true);
+        }
+        match args.format {
+            Format::Human => {
+                for line in render_list(&workspaces, None, None, args.list_style()) {
+                    println!("{line}");
+                }
+            }
+            Format::Json => println!("{}", serde_json::to_string(&workspaces).unwrap()),
+        }
+        return Ok(true);
+    }
+    if args.windows {
+        let workspaces = backend.query()?;
+        let windows = backend.windows()?;
+        let print_windows = |desktop: isize| {
+            for window in windows.iter().filter(|w| w.desktop == desktop) {
+                println!("    0x{:08x}  {}  {}", window.id, window.host, window.title);
+            }
+        };
+        for ws in &workspaces {
+            println!("{}", ws);
+            print_windows(ws.idx as isize);
+        }
+        if windows.iter().any(WindowEntry::is_sticky) {
+            println!("-1  Sticky, on all workspaces");
+            print_windows(-1);
+        }
+        return Ok(true);
+    }
+    if let Some([idx, name]) = args.rename.as_deref() {
+        let idx: usize = idx.parse().unwrap_or_else(|_| {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!("invalid workspace idx {idx:?} for --rename"),
+                )
+                .exit()
+        });
+        return rename(backend, idx, name).map(|_| true);
+    }
+    if let Some(swap) = &args.swap {
+        let moved = swap_workspaces(backend, swap[0], swap[1])?;
+        println!("Moved {moved} windows.");
+        return Ok(true);
+    }
+    if let Some(num) = args.set_count {
+        return set_count(backend, num, args.force).map(|_| true);
+    }
+    if args.interactive {
+        let workspaces = backend.query()?;
+        let from = active_workspace(&workspaces).ok().map(|ws| ws.idx);
+        return match picker::pick(&workspaces)? {
+            Some(idx) => switch_recorded(backend, from, idx),
+            None => Ok(false),
+        };
+    }
+    if args.current {
+        println!("{}", active_workspace(&backend.query()?)?.idx);
+        return Ok(true);
+    }
+    if args.count {
+        println!("{}", backend.query()?.len());
+        return Ok(true);
+    }
+    if let Some(idx) = args.switch_to {
+        let workspaces = backend.query()?;
+        let idx = resolve_index(idx, workspaces.len(), args.clamp)?;
+        let from = active_workspace(&workspaces).ok().map(|ws| ws.idx);
+        return switch_recorded(backend, from, idx);
+    }
+    if let Some(label) = &args.goto {
+        let workspaces = backend.query()?;
+        let idx = load_config()?.resolve_label(label, workspaces.len())?;
+        let from = active_workspace(&workspaces).ok().map(|ws| ws.idx);
+        return switch_recorded(backend, from, idx);
+    }
+    if let Some(pattern) = &args.goto_window {
+        return goto_window(backend, pattern, args.first, args.activate);
+    }
+    if let Some(name) = &args.switch_to_name {
+        return switch_to_name(backend, name);
+    }
+    if let Some(pattern) = &args.filter {
+        let workspaces = backend.query()?;
+        let from = active_workspace(&workspaces).ok().map(|ws| ws.idx);
+        return switch_recorded(backend, from, find_by_filter(&workspaces, pattern)?.idx);
+    }
+    if args.last {
+        return switch_to_last(backend);
+    }
+    if args.back || args.forward {
+        return switch_in_history(backend, args.forward);
+    }
+    if let Some(window) = args.pull {
+        return pull_window(backend, window).map(|_| true);
+    }
+    if let Some(window) = args.sticky {
+        return set_sticky(backend, window, true);
+    }
+    if let Some(window) = args.unsticky {
+        return set_sticky(backend, window, false);
+    }
+    if let Some(idx) = args.take {
+        return take_window(backend, idx);
+    }
+    if let Some(idx) = args.move_to {
+        move_active_to(backend, idx)?;
+        if args.follow {
+            let from = active_workspace(&backend.query()?).ok().map(|ws| ws.idx);
+            switch_recorded(backend, from, idx)?;
+        }
+        return Ok(true);
+    }
+    let cycle = args.cycle();
+    if let Some(delta) = args.step() {
+        return match &args.relative_name {
+            Some(pattern) => switch_by_matching(backend, pattern, delta, cycle.x()),
+            None => switch_by(backend, delta, cycle.x()),
+        };
+    }
+    if args.relative_name.is_some() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--relative-name needs --next or --prev",
+            )
+            .exit();
+    }
+    let direction = [
+        (args.up, Direction::Up),
+        (args.down, Direction::Down),
+        (args.left, Direction::Left),
+        (args.right, Direction::Right),
+    ]
+    .into_iter()
+    .find_map(|(flag, direction)| flag.then_some(direction));
+    if let Some(direction) = direction {
+        return switch_grid(backend, direction, cycle);
+    }
+    // 什么都没有执行, 按配置的默认操作执行, 默认由 [`exit_with`] 在标准错误流中输出帮助.
+    if let Some(args) = with_default_action(args.clone(), &load_config()?) {
+        return dispatch(&args, backend);
+    }
+    Err(Error::NoAction)
+}
+
+/// 加载默认位置的配置, 不存在时使用默认配置.
+fn load_config() -> Result<Config, Error> {
+    match Config::default_path() {
+        Some(path) => Config::load(&path),
+        None => Ok(Config::default()),
+    }
+}
+
+/// 命令行中的名字, 例如 `wmctrl`.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map_or_else(String::new, |v| v.get_name().to_owned())
+}
+
+/// 给没有指定任何操作的 `args` 加上配置的默认操作, [`DefaultAction::Help`] 时返回 None.
+fn with_default_action(mut args: Args, config: &Config) -> Option<Args> {
+    match config.default_action {
+        DefaultAction::Help => return None,
+        DefaultAction::Next => args.switch_by_next = Some(config.step),
+        DefaultAction::Prev => args.switch_by_prev = Some(config.step),
+        DefaultAction::List => args.list_workspaces = true,
+        DefaultAction::Last => args.last = true,
+    }
+    Some(args)
+}
+
+/// 每次变化时输出一行, 输出相同时不重复; 标准输出被关闭 (状态栏退出) 时正常结束.
+fn watch(format: &str) -> Result<(), Error> {
+    let mut last = None;
+    x11::X11::connect()?.watch(|workspaces| {
+        let line = format_status(format, workspaces);
+        if line.is_none() || line == last {
+            return true;
+        }
+        let mut stdout = io::stdout().lock();
+        let written = writeln!(stdout, "{}", line.as_deref().unwrap_or_default())
+            .and_then(|()| stdout.flush());
+        last = line;
+        written.is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::Fake;
+
+    #[test]
+    fn no_op_at_edge_exits_unchanged() {
+        let last = Fake::new(3, 2);
+        let result = switch_by(&last, 1, false);
+        assert!(matches!(result, Ok(false)));
+        assert_eq!(exit_code(&result), EXIT_UNCHANGED);
+        let first = Fake::new(3, 0);
+        assert_eq!(exit_code(&switch_by(&first, -1, false)), EXIT_UNCHANGED);
+        assert!(last.calls().is_empty() && first.calls().is_empty());
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(exit_code(&Ok(true)), 0);
+        assert_eq!(exit_code(&Err(Error::Fullscreen)), EXIT_UNCHANGED);
+        assert_eq!(exit_code(&Err(Error::WmctrlExit(1))), 1);
+        assert_eq!(exit_code(&Err(Error::NoWorkspaces)), 1);
+        assert_eq!(exit_code(&Err(Error::NoAction)), 2);
+    }
+
+    #[test]
+    fn rename_args() {
+        use clap::Parser;
+        let args = Args::try_parse_from(["wsst", "--rename", "2", "My Work"]).unwrap();
+        assert_eq!(args.rename.unwrap(), ["2", "My Work"]);
+        assert!(Args::try_parse_from(["wsst", "--rename", "2"]).is_err());
+
+        let fake = Fake::new(3, 0);
+        rename(&fake, 2, "My Work").unwrap();
+        assert_eq!(fake.calls(), ["rename 2 My Work"]);
+        assert!(matches!(
+            rename(&fake, 3, "x"),
+            Err(Error::OutOfRange { idx: 3, num: 3 })
+        ));
+    }
+
+    #[test]
+    fn pull_args() {
+        use clap::Parser;
+        let pull = |args: &[&str]| Args::try_parse_from(args).map(|args| args.pull);
+        assert_eq!(
+            pull(&["wsst", "--pull", "0x03c00016"]).unwrap(),
+            Some(0x03c00016)
+        );
+        assert_eq!(
+            pull(&["wsst", "--move-here", "62914582"]).unwrap(),
+            Some(62914582)
+        );
+        assert!(pull(&["wsst", "--pull", "firefox"]).is_err());
+        assert!(pull(&["wsst", "--pull"]).is_err());
+
+        // 拉到活动工作区 2
+        let fake = Fake::new(4, 2);
+        *fake.windows.borrow_mut() = crate::parse_windows(crate::fake::WMCTRL_L).unwrap();
+        pull_window(&fake, 0x03c00016).unwrap();
+        assert_eq!(fake.calls(), ["pull_window 0x03c00016 2"]);
+        assert_eq!(fake.windows.borrow()[3].desktop, 2);
+        assert!(matches!(
+            pull_window(&fake, 0x0badf00d),
+            Err(Error::NoSuchWindow(0x0badf00d))
+        ));
+        fake.workspaces.borrow_mut()[2].active = false;
+        assert!(matches!(
+            pull_window(&fake, 0x03c00016),
+            Err(Error::NoActiveWorkspace)
+        ));
+        assert_eq!(fake.calls().len(), 1);
+    }
+
+    #[test]
+    fn default_action_and_flag_precedence() {
+        use clap::Parser;
+        let config = Config::parse("default_action = \"next\"\nstep = 2\n").unwrap();
+        assert_eq!(
+            (config.default_action, config.step),
+            (DefaultAction::Next, 2)
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("default_action = \"jump\"").is_err());
+        assert!(Config::parse("step = 0").is_err());
+
+        let bare = Args::try_parse_from(["wsst"]).unwrap();
+        let args = with_default_action(bare.clone(), &config).unwrap();
+        assert_eq!((args.switch_by_next, args.switch_by_prev), (Some(2), None));
+        let list = Config {
+            default_action: DefaultAction::List,
+            ..Config::default()
+        };
+        assert!(
+            with_default_action(bare.clone(), &list)
+                .unwrap()
+                .list_workspaces
+        );
+        assert!(with_default_action(bare, &Config::default()).is_none());
+
+        // 明确的操作在默认操作之前执行, 不会读取配置
+        let fake = Fake::new(4, 2);
+        let args = Args::try_parse_from(["wsst", "--prev"]).unwrap();
+        assert!(dispatch(&args, &fake).unwrap());
+        assert_eq!(fake.calls(), ["switch_to 1"]);
+    }
+
+    #[test]
+    fn count_is_the_number_of_workspace_lines() {
+        use clap::Parser;
+        for sample in [crate::fake::WMCTRL_D, crate::fake::WMCTRL_D_VIEWPORTS] {
+            let fake = Fake::with(crate::parse_workspaces(sample).unwrap());
+            assert_eq!(fake.query().unwrap().len(), sample.lines().count());
+        }
+        let fake = Fake::with(crate::parse_workspaces(crate::fake::WMCTRL_D).unwrap());
+        let args = Args::try_parse_from(["wsst", "--count"]).unwrap();
+        assert!(dispatch(&args, &fake).unwrap());
+        assert!(fake.calls().is_empty());
+    }
+
+    #[test]
+    fn valueless_next_and_prev_step_by_one() {
+        use clap::Parser;
+        let parse = |args: &[&str]| {
+            let args = Args::try_parse_from(["wsst"].iter().chain(args)).unwrap();
+            (args.step(), args.cycle())
+        };
+        assert_eq!(parse(&["-n"]), (Some(1), Cycle::Both));
+        assert_eq!(parse(&["--next"]), (Some(1), Cycle::Both));
+        assert_eq!(parse(&["-n", "3"]), (Some(3), Cycle::Both));
+        assert_eq!(parse(&["--next=2"]), (Some(2), Cycle::Both));
+        assert_eq!(parse(&["-p"]), (Some(-1), Cycle::Both));
+        assert_eq!(parse(&["--prev", "2"]), (Some(-2), Cycle::Both));
+        // 后面的选项不会被当作步数
+        assert_eq!(parse(&["-n", "--no-cycle"]), (Some(1), Cycle::None));
+        assert_eq!(parse(&["--no-cycle", "-p"]), (Some(-1), Cycle::None));
+        assert_eq!(parse(&[]).0, None);
+
+        let fake = Fake::new(4, 3);
+        let args = Args::try_parse_from(["wsst", "-n", "--no-cycle"]).unwrap();
+        assert!(!dispatch(&args, &fake).unwrap());
+        let args = Args::try_parse_from(["wsst", "-n"]).unwrap();
+        assert!(dispatch(&args, &fake).unwrap());
+        assert_eq!(fake.calls(), ["switch_to 0"]);
+    }
+
+    #[test]
+    fn informational_paths_do_not_resolve_wmctrl() {
+        use clap::{Parser, error::ErrorKind};
+        use std::cell::Cell;
+        // clap 在解析时就输出帮助和版本, 不会运行到 run.
+        for (flag, kind) in [
+            ("--help", ErrorKind::DisplayHelp),
+            ("-h", ErrorKind::DisplayHelp),
+            ("--version", ErrorKind::DisplayVersion),
+        ] {
+            assert_eq!(
+                Args::try_parse_from(["wsst", flag]).unwrap_err().kind(),
+                kind
+            );
+        }
+
+        let resolved = Cell::new(0);
+        let find_wmctrl = || {
+            resolved.set(resolved.get() + 1);
+            Err(Error::WmctrlNotFound("wmctrl is not found".to_owned()))
+        };
+        let args = Args::try_parse_from(["wsst", "--generate-completions", "bash"]).unwrap();
+        assert!(run_with(args, find_wmctrl).unwrap());
+        assert_eq!(resolved.get(), 0);
+        // 需要 wmctrl 的操作得到错误而不是 panic
+        let args = Args::try_parse_from(["wsst", "--dump-raw"]).unwrap();
+        assert!(matches!(
+            run_with(args, find_wmctrl),
+            Err(Error::WmctrlNotFound(_))
+        ));
+        assert_eq!(resolved.get(), 1);
+    }
+
+    #[test]
+    fn command_line_overrides_are_dumped() {
+        use clap::Parser;
+        let config = Config::parse("default_action = \"next\"\nstep = 3\n").unwrap();
+        let args = Args::try_parse_from([
+            "wsst",
+            "--no-cycle",
+            "--verify",
+            "--backend",
+            "x11",
+            "--wmctrl-arg",
+            "-u",
+        ])
+        .unwrap();
+        let toml = args.effective_config(None, config.clone()).to_toml();
+        let dumped: toml::Value = toml::from_str(&toml).unwrap();
+        assert!(toml.starts_with("# No config file, using the defaults.\n"));
+        // 配置文件
+        assert_eq!(dumped["default_action"].as_str(), Some("next"));
+        assert_eq!(dumped["step"].as_integer(), Some(3));
+        // 命令行
+        let command_line = &dumped["command_line"];
+        assert_eq!(command_line["cycle"].as_str(), Some("none"));
+        assert_eq!(command_line["verify"].as_bool(), Some(true));
+        assert_eq!(command_line["backend"].as_str(), Some("x11"));
+        assert_eq!(command_line["wmctrl_args"][0].as_str(), Some("-u"));
+        // 没有给出的选项是默认值
+        let defaults = Args::try_parse_from(["wsst"]).unwrap();
+        let toml = defaults.effective_config(None, config).to_toml();
+        let dumped: toml::Value = toml::from_str(&toml).unwrap();
+        assert_eq!(dumped["command_line"]["cycle"].as_str(), Some("both"));
+        assert_eq!(dumped["command_line"]["backend"].as_str(), Some("wmctrl"));
+    }
+}
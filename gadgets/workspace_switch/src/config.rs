@@ -0,0 +1,167 @@
+//! `$XDG_CONFIG_HOME/workspace_switch/config.toml`: 不带任何操作运行 wsst 时执行的默认操作,
+//! 以及 `--goto` 使用的工作区标签.
+
+use crate::{Cycle, Error, check_index};
+use gadgets_common::xdg::config_home;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+/// What wsst does when run without any action, explicit flags always take precedence.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultAction {
+    /// Print the help to stderr and exit with 2.
+    #[default]
+    Help,
+    /// `--next <step>`.
+    Next,
+    /// `--prev <step>`.
+    Prev,
+    /// `--list`.
+    List,
+    /// `--last`.
+    Last,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default_action: DefaultAction,
+    /// Number of workspaces the `next` and `prev` default actions move by.
+    pub step: usize,
+    /// Labels for `--goto`, mapped to workspace idxs, independent of the workspace names.
+    pub labels: BTreeMap<String, usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            default_action: DefaultAction::default(),
+            step: 1,
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/workspace_switch/config.toml`, `$XDG_CONFIG_HOME` falls back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        config_home().map(|dir| dir.join("workspace_switch").join("config.toml"))
+    }
+
+    /// Load the config from `path`, a missing file yields the default config.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let invalid = |message: String| Error::Config {
+            path: path.to_owned(),
+            message,
+        };
+        match fs::read_to_string(path) {
+            Ok(content) => Config::parse(&content).map_err(invalid),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(invalid(e.to_string())),
+        }
+    }
+
+    /// Parse the content of the config file.
+    pub fn parse(content: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(content).map_err(|e| e.to_string())?;
+        if config.step == 0 {
+            return Err("step must be at least 1".to_owned());
+        }
+        Ok(config)
+    }
+
+    /// The workspace idx `label` is mapped to, checked against the `num` workspaces.
+    pub fn resolve_label(&self, label: &str, num: usize) -> Result<usize, Error> {
+        let &idx = self.labels.get(label).ok_or_else(|| Error::UnknownLabel {
+            label: label.to_owned(),
+            known: self.labels.keys().cloned().collect(),
+        })?;
+        check_index(idx, num, false).map_err(|e| match e {
+            Error::OutOfRange { idx, num } => Error::LabelOutOfRange {
+                label: label.to_owned(),
+                idx: idx as usize,
+                num,
+            },
+            e => e,
+        })
+    }
+}
+
+/// Command line options applying to every action, as resolved for `--print-config`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CommandLine {
+    pub backend: String,
+    /// `--cycle`, or `none` with `--no-cycle`.
+    pub cycle: Cycle,
+    pub verify: bool,
+    pub notify: bool,
+    pub unless_fullscreen: bool,
+    pub activate_on_switch: bool,
+    pub color: String,
+    pub wmctrl_args: Vec<String>,
+    pub with_ime: Option<String>,
+    pub ime_addr: IpAddr,
+}
+
+/// The settings in effect, printed by `--print-config`: the config file merged over the defaults,
+/// and the options given on the command line over their defaults.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct EffectiveConfig {
+    /// [`Config::default_path`], None without `$XDG_CONFIG_HOME` and `$HOME`.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    #[serde(flatten)]
+    pub config: Config,
+    /// Only printed, the config file doesn't accept it.
+    pub command_line: CommandLine,
+}
+
+impl EffectiveConfig {
+    /// TOML after a comment naming the config file. Without `[command_line]` it can be used as the config file.
+    pub fn to_toml(&self) -> String {
+        let source = match &self.path {
+            Some(path) if path.exists() => format!("# Loaded from {}.\n", path.display()),
+            Some(path) => format!("# {} doesn't exist, using the defaults.\n", path.display()),
+            None => "# No config file, using the defaults.\n".to_owned(),
+        };
+        source + &toml::to_string(self).expect("the effective config serializes to TOML")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_parse() {
+        let config = Config::parse("[labels]\ncode = 0\nweb = 2\n").unwrap();
+        assert_eq!(
+            config.labels,
+            BTreeMap::from([("code".to_owned(), 0), ("web".to_owned(), 2)])
+        );
+        assert!(Config::parse("[labels]\ncode = -1\n").is_err());
+        assert!(Config::parse("[labels]\ncode = \"0\"\n").is_err());
+    }
+
+    #[test]
+    fn labels_resolve_within_range() {
+        let config = Config::parse("[labels]\ncode = 0\nchat = 5\n").unwrap();
+        assert_eq!(config.resolve_label("code", 4).unwrap(), 0);
+        assert_eq!(config.resolve_label("chat", 6).unwrap(), 5);
+        // 映射的 idx 在切换时才检查
+        assert_eq!(
+            config.resolve_label("chat", 4).unwrap_err().to_string(),
+            "Label \"chat\" is mapped to workspace 5, but there are only 4 workspaces."
+        );
+        assert!(matches!(
+            config.resolve_label("web", 4),
+            Err(Error::UnknownLabel { label, known }) if label == "web" && known == ["chat", "code"]
+        ));
+    }
+}
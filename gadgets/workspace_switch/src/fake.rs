@@ -0,0 +1,167 @@
+//! 测试用的后端: 工作区和窗口保存在内存中, 并记录每个改变状态的调用.
+
+use crate::{Error, WindowEntry, Workspace, WorkspaceBackend};
+use std::cell::RefCell;
+
+/// Captured `wmctrl -d` output: wmctrl only knows the viewport of the current workspace,
+/// the third workspace has no name.
+pub const WMCTRL_D: &str = "\
+0  * DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  Main
+1  - DG: 1920x1080  VP: N/A  WA: N/A  Web
+2  - DG: 1920x1080  VP: N/A  WA: N/A  
+3  - DG: 1920x1080  VP: N/A  WA: N/A  工作区 4
+";
+
+/// Captured `wmctrl -d` output of Compiz: a single workspace of 3x2 viewports,
+/// the bottom middle one is current.
+pub const WMCTRL_D_VIEWPORTS: &str = "\
+0  * DG: 5760x2160  VP: 1920,1080  WA: 0,27 1920x1053  N/A
+";
+
+/// Captured `wmctrl -l` output: a panel and a desktop window on all workspaces (-1),
+/// a window without a title.
+pub const WMCTRL_L: &str = "\
+0x01e00003 -1 host Top Bar
+0x02000006 -1 host Desktop
+0x03a00003  0 host Terminal - ~/crate
+0x03c00016  1 host Firefox
+0x03c0002a  1 N/A
+0x04200004  3 host 新标签页  - 浏览器
+";
+
+/// A workspace without geometry information, e.g. from a window manager printing `N/A`.
+pub fn workspace(idx: usize, active: bool, name: &str) -> Workspace {
+    Workspace {
+        active,
+        idx,
+        dg: None,
+        vp: None,
+        available_area: None,
+        name: name.to_owned(),
+    }
+}
+
+#[derive(Default)]
+pub struct Fake {
+    pub workspaces: RefCell<Vec<Workspace>>,
+    pub windows: RefCell<Vec<WindowEntry>>,
+    /// 改变状态的调用, 例如 `switch_to 2`.
+    pub calls: RefCell<Vec<String>>,
+}
+
+impl Fake {
+    /// `num` workspaces named by their idx, `active` being the active one.
+    pub fn new(num: usize, active: usize) -> Fake {
+        Fake::with(
+            (0..num)
+                .map(|idx| workspace(idx, idx == active, &idx.to_string()))
+                .collect(),
+        )
+    }
+
+    pub fn with(workspaces: Vec<Workspace>) -> Fake {
+        Fake {
+            workspaces: RefCell::new(workspaces),
+            ..Fake::default()
+        }
+    }
+
+    pub fn active(&self) -> Option<usize> {
+        self.workspaces
+            .borrow()
+            .iter()
+            .find(|ws| ws.active)
+            .map(|ws| ws.idx)
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+
+    fn call(&self, call: String) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn move_to(&self, window: u32, desktop: isize) {
+        for entry in self.windows.borrow_mut().iter_mut() {
+            if entry.id == window {
+                entry.desktop = desktop;
+            }
+        }
+    }
+}
+
+impl WorkspaceBackend for Fake {
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        Ok(self.workspaces.borrow().clone())
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.call(format!("switch_to {idx}"));
+        for ws in self.workspaces.borrow_mut().iter_mut() {
+            ws.active = ws.idx == idx;
+        }
+        Ok(())
+    }
+
+    fn move_active_to(&self, idx: usize) -> Result<(), Error> {
+        self.call(format!("move_active_to {idx}"));
+        Ok(())
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        self.call(format!("set_viewport {x},{y}"));
+        for ws in self.workspaces.borrow_mut().iter_mut() {
+            if ws.active {
+                ws.vp = Some((x, y));
+            }
+        }
+        Ok(())
+    }
+
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error> {
+        self.call(format!("rename {idx} {name}"));
+        for ws in self.workspaces.borrow_mut().iter_mut() {
+            if ws.idx == idx {
+                ws.name = name.to_owned();
+            }
+        }
+        Ok(())
+    }
+
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        Ok(self.windows.borrow().clone())
+    }
+
+    fn pull_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        self.call(format!("pull_window 0x{window:08x} {idx}"));
+        self.move_to(window, idx as isize);
+        Ok(())
+    }
+
+    fn activate_window(&self, window: u32) -> Result<(), Error> {
+        self.call(format!("activate_window 0x{window:08x}"));
+        Ok(())
+    }
+
+    fn move_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        self.call(format!("move_window 0x{window:08x} {idx}"));
+        self.move_to(window, idx as isize);
+        Ok(())
+    }
+
+    fn set_count(&self, num: usize) -> Result<(), Error> {
+        self.call(format!("set_count {num}"));
+        let mut workspaces = self.workspaces.borrow_mut();
+        workspaces.truncate(num);
+        for idx in workspaces.len()..num {
+            workspaces.push(workspace(idx, false, &idx.to_string()));
+        }
+        Ok(())
+    }
+
+    fn set_sticky(&self, window: u32, sticky: bool) -> Result<(), Error> {
+        self.call(format!("set_sticky 0x{window:08x} {sticky}"));
+        Ok(())
+    }
+}
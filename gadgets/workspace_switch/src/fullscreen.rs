@@ -0,0 +1,35 @@
+//! `--unless-fullscreen`: 活动窗口全屏时 (例如看视频, 玩游戏) 不切换工作区.
+
+use crate::{BackendWrapper, Error, WorkspaceBackend, x11::X11};
+
+/// Wraps a backend and refuses to switch or move the viewport with [`Error::Fullscreen`]
+/// while the active window is fullscreen. The window is checked over X11 whatever the backend,
+/// wmctrl can't tell the window state.
+pub struct UnlessFullscreen {
+    pub inner: Box<dyn WorkspaceBackend>,
+}
+
+impl UnlessFullscreen {
+    fn check(&self) -> Result<(), Error> {
+        if X11::connect()?.active_window_fullscreen()? {
+            return Err(Error::Fullscreen);
+        }
+        Ok(())
+    }
+}
+
+impl BackendWrapper for UnlessFullscreen {
+    fn inner(&self) -> &dyn WorkspaceBackend {
+        self.inner.as_ref()
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.check()?;
+        self.inner.switch_to(idx)
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        self.check()?;
+        self.inner.set_viewport(x, y)
+    }
+}
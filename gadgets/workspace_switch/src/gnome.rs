@@ -0,0 +1,163 @@
+//! GNOME Shell 后端: 通过 `org.gnome.Shell.Eval` 调用 `global.workspace_manager`,
+//! 不依赖 EWMH, 因此在会忽略 `wmctrl -s` 的 GNOME Shell (包括 Wayland) 上也能切换.
+
+use crate::{Error, WindowEntry, Workspace, WorkspaceBackend};
+use serde::Deserialize;
+use tracing::{debug, trace};
+use zbus::blocking::Connection;
+
+const SHELL_SERVICE: &str = "org.gnome.Shell";
+const SHELL_PATH: &str = "/org/gnome/Shell";
+const SHELL_INTERFACE: &str = "org.gnome.Shell";
+
+const QUERY: &str = r#"(() => {
+    const wm = global.workspace_manager;
+    const active = wm.get_active_workspace_index();
+    const name = i => {
+        try {
+            return imports.gi.Meta.prefs_get_workspace_name(i);
+        } catch (e) {
+            return '';
+        }
+    };
+    return Array.from({ length: wm.get_n_workspaces() }, (_, i) => ({
+        idx: i,
+        active: i === active,
+        name: name(i),
+    }));
+})()"#;
+
+#[derive(Deserialize)]
+struct ShellWorkspace {
+    idx: usize,
+    active: bool,
+    name: String,
+}
+
+/// Talks to GNOME Shell over the session bus.
+/// Since GNOME 41, `Eval` only works when the shell runs in unsafe mode.
+pub struct Gnome {
+    conn: Connection,
+}
+
+impl Gnome {
+    pub fn connect() -> Result<Gnome, Error> {
+        Ok(Gnome {
+            conn: Connection::session()?,
+        })
+    }
+
+    /// 在 GNOME Shell 中执行 `code`, 返回 JSON 格式的结果, 结果为 undefined 时返回空字符串.
+    fn eval(&self, code: &str) -> Result<String, Error> {
+        debug!("Eval in GNOME Shell: {code}");
+        let reply = self.conn.call_method(
+            Some(SHELL_SERVICE),
+            SHELL_PATH,
+            Some(SHELL_INTERFACE),
+            "Eval",
+            &(code,),
+        )?;
+        let (success, result): (bool, String) = reply.body().deserialize()?;
+        trace!("Eval returned {success}: {result}");
+        match (success, result) {
+            (true, result) => Ok(result),
+            // 未开启 unsafe mode 时 Eval 直接返回 (false, "")
+            (false, result) if result.is_empty() => Err(Error::GnomeUnsafeMode),
+            (false, result) => Err(Error::GnomeEval(result)),
+        }
+    }
+}
+
+/// 把字符串转为 JavaScript 字符串字面量.
+fn js_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+impl WorkspaceBackend for Gnome {
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        let result = self.eval(QUERY)?;
+        let workspaces: Vec<ShellWorkspace> = serde_json::from_str(&result)
+            .map_err(|e| Error::GnomeEval(format!("unexpected result {result}: {e}")))?;
+        Ok(workspaces
+            .into_iter()
+            .map(|ws| Workspace {
+                active: ws.active,
+                idx: ws.idx,
+                dg: None,
+                vp: None,
+                available_area: None,
+                name: ws.name,
+            })
+            .collect())
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.eval(&format!(
+            r#"(() => {{
+                const ws = global.workspace_manager.get_workspace_by_index({idx});
+                if (!ws) throw new Error('No workspace {idx}.');
+                ws.activate(global.get_current_time());
+            }})()"#
+        ))?;
+        Ok(())
+    }
+
+    fn move_active_to(&self, idx: usize) -> Result<(), Error> {
+        self.eval(&format!(
+            r#"(() => {{
+                const window = global.display.focus_window;
+                if (!window) throw new Error('No window is active.');
+                window.change_workspace_by_index({idx}, false);
+            }})()"#
+        ))?;
+        Ok(())
+    }
+
+    /// GNOME Shell 没有视口.
+    fn set_viewport(&self, _x: isize, _y: isize) -> Result<(), Error> {
+        Err(Error::Unsupported("viewports"))
+    }
+
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error> {
+        self.eval(&format!(
+            "imports.gi.Meta.prefs_change_workspace_name({idx}, {})",
+            js_string(name)
+        ))?;
+        Ok(())
+    }
+
+    /// Mutter 的窗口 ID 不是 X11 窗口 ID, 不支持列出.
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        Err(Error::Unsupported("listing windows"))
+    }
+
+    fn pull_window(&self, _window: u32, _idx: usize) -> Result<(), Error> {
+        Err(Error::Unsupported("pulling windows by X11 id"))
+    }
+
+    fn activate_window(&self, _window: u32) -> Result<(), Error> {
+        Err(Error::Unsupported("activating windows by X11 id"))
+    }
+
+    fn move_window(&self, _window: u32, _idx: usize) -> Result<(), Error> {
+        Err(Error::Unsupported("moving windows by X11 id"))
+    }
+
+    fn set_sticky(&self, _window: u32, _sticky: bool) -> Result<(), Error> {
+        Err(Error::Unsupported("sticky windows by X11 id"))
+    }
+
+    fn set_count(&self, num: usize) -> Result<(), Error> {
+        self.eval(&format!(
+            r#"(() => {{
+                const wm = global.workspace_manager;
+                const time = global.get_current_time();
+                while (wm.get_n_workspaces() < {num})
+                    wm.append_new_workspace(false, time);
+                while (wm.get_n_workspaces() > {num})
+                    wm.remove_workspace(wm.get_workspace_by_index(wm.get_n_workspaces() - 1), time);
+            }})()"#
+        ))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Maximum number of workspaces kept in each of [`History::back`] and [`History::forward`].
+pub const MAX_DEPTH: usize = 32;
+
+/// Navigation history of switches, like the back and forward buttons of a browser,
+/// persisted between invocations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct History {
+    pub current: usize,
+    /// Workspaces switched away from, the most recent last.
+    pub back: Vec<usize>,
+    /// Workspaces gone back from, the most recent last. Cleared by a fresh switch.
+    pub forward: Vec<usize>,
+}
+
+/// `$XDG_STATE_HOME/workspace_switch`, `$XDG_STATE_HOME` falls back to `~/.local/state`.
+#[cfg(not(test))]
+pub fn state_dir() -> Option<PathBuf> {
+    gadgets_common::xdg::state_home().map(|dir| dir.join("workspace_switch"))
+}
+
+/// 测试不写入用户的状态目录, 每个测试 (线程) 使用单独的临时目录.
+#[cfg(test)]
+pub fn state_dir() -> Option<PathBuf> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static DIR: PathBuf = std::env::temp_dir().join(format!(
+            "workspace_switch-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+    }
+    Some(DIR.with(PathBuf::clone))
+}
+
+impl History {
+    /// `history` in [`state_dir`].
+    pub fn default_path() -> Option<PathBuf> {
+        Some(state_dir()?.join("history"))
+    }
+
+    /// Load history from `path`, None if the file is missing or corrupt.
+    pub fn load(path: &Path) -> Option<History> {
+        History::parse(&fs::read_to_string(path).ok()?)
+    }
+
+    /// 文件内容为三行: 当前工作区, back 和 forward (以空格分隔, 可以为空).
+    /// 兼容旧的单行格式 `<previous> <current>`.
+    fn parse(content: &str) -> Option<History> {
+        let numbers = |line: &str| -> Option<Vec<usize>> {
+            line.split_whitespace().map(|f| f.parse().ok()).collect()
+        };
+        let mut lines = content.lines();
+        let first = numbers(lines.next()?)?;
+        match first[..] {
+            [previous, current] if lines.next().is_none() => Some(History {
+                current,
+                back: vec![previous],
+                forward: Vec::new(),
+            }),
+            [current] => {
+                let history = History {
+                    current,
+                    back: numbers(lines.next().unwrap_or_default())?,
+                    forward: numbers(lines.next().unwrap_or_default())?,
+                };
+                lines.next().is_none().then_some(history)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let join = |stack: &[usize]| {
+            stack
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        fs::write(
+            path,
+            format!(
+                "{}\n{}\n{}\n",
+                self.current,
+                join(&self.back),
+                join(&self.forward)
+            ),
+        )
+    }
+
+    /// The workspace switched away from most recently.
+    pub fn previous(&self) -> Option<usize> {
+        self.back.last().copied()
+    }
+
+    /// Record a fresh switch from `from` to `to`, which clears the forward stack.
+    pub fn record(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        push_bounded(&mut self.back, from);
+        self.forward.clear();
+        self.current = to;
+    }
+
+    /// Go back from `current` (the active workspace), returns the workspace to switch to.
+    /// None if there is nothing to go back to.
+    pub fn go_back(&mut self, current: usize) -> Option<usize> {
+        let target = pop_other(&mut self.back, current)?;
+        push_bounded(&mut self.forward, current);
+        self.current = target;
+        Some(target)
+    }
+
+    /// Reverse of [`History::go_back`].
+    pub fn go_forward(&mut self, current: usize) -> Option<usize> {
+        let target = pop_other(&mut self.forward, current)?;
+        push_bounded(&mut self.back, current);
+        self.current = target;
+        Some(target)
+    }
+}
+
+/// The workspace `--name` last switched to, so that repeating it cycles through the prefix matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LastMatch {
+    /// The name given to `--name`, lowercased.
+    pub name: String,
+    pub idx: usize,
+}
+
+impl LastMatch {
+    /// `last_match` in [`state_dir`].
+    pub fn default_path() -> Option<PathBuf> {
+        Some(state_dir()?.join("last_match"))
+    }
+
+    /// Load from `path`, None if the file is missing or corrupt.
+    pub fn load(path: &Path) -> Option<LastMatch> {
+        // 第一行是工作区索引, 第二行是名称.
+        let content = fs::read_to_string(path).ok()?;
+        let (idx, name) = content.split_once('\n')?;
+        Some(LastMatch {
+            name: name.strip_suffix('\n').unwrap_or(name).to_owned(),
+            idx: idx.parse().ok()?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, format!("{}\n{}\n", self.idx, self.name))
+    }
+}
+
+fn push_bounded(stack: &mut Vec<usize>, idx: usize) {
+    stack.push(idx);
+    if stack.len() > MAX_DEPTH {
+        stack.remove(0);
+    }
+}
+
+/// 弹出第一个不是 `current` 的工作区, 在其他地方切换过工作区时栈顶可能就是当前工作区.
+fn pop_other(stack: &mut Vec<usize>, current: usize) -> Option<usize> {
+    while let Some(idx) = stack.pop() {
+        if idx != current {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_forward_and_fresh_switches() {
+        let mut history = History::default();
+        assert_eq!(history.go_back(0), None);
+        history.record(0, 1);
+        history.record(1, 2);
+        history.record(2, 3);
+        assert_eq!(history.go_back(3), Some(2));
+        assert_eq!(history.go_back(2), Some(1));
+        assert_eq!(history.go_forward(1), Some(2));
+        assert_eq!(
+            history,
+            History {
+                current: 2,
+                back: vec![0, 1],
+                forward: vec![3],
+            }
+        );
+        // 新的切换清空 forward, 切换到当前工作区不记录
+        history.record(2, 5);
+        history.record(5, 5);
+        assert_eq!(history.go_forward(5), None);
+        assert_eq!(history.go_back(5), Some(2));
+        assert_eq!(history.go_back(2), Some(1));
+        assert_eq!(history.go_back(1), Some(0));
+        assert_eq!(history.go_back(0), None);
+        assert_eq!(history.forward, [5, 2, 1]);
+        // 在其他地方切换到了栈顶的工作区 1 时跳过它
+        assert_eq!(history.go_forward(1), Some(2));
+        assert_eq!(history.forward, [5]);
+    }
+
+    #[test]
+    fn stacks_are_bounded() {
+        let mut history = History::default();
+        for idx in 0..MAX_DEPTH + 5 {
+            history.record(idx, idx + 1);
+        }
+        assert_eq!(history.back.len(), MAX_DEPTH);
+        assert_eq!(history.back[0], 5);
+    }
+
+    #[test]
+    fn file_format() {
+        let history = History {
+            current: 2,
+            back: vec![0, 1],
+            forward: Vec::new(),
+        };
+        let path =
+            std::env::temp_dir().join(format!("workspace_switch-history-{}", std::process::id()));
+        history.save(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2\n0 1\n\n");
+        assert_eq!(History::load(&path), Some(history));
+        fs::remove_file(&path).unwrap();
+        // 旧的单行格式
+        assert_eq!(
+            History::parse("3 4\n"),
+            Some(History {
+                current: 4,
+                back: vec![3],
+                forward: Vec::new(),
+            })
+        );
+        assert_eq!(History::parse("x\n"), None);
+        assert_eq!(History::parse(""), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! 切换工作区后通知 ibus_engine_switch 的切换服务器切换输入法.
+//! 协议与 ibus_engine_switch 相同: 每条消息由 1 字节长度和紧随其后的 UTF-8 文本组成.
+
+use crate::{BackendWrapper, Error, WorkspaceBackend, active_workspace, warn};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream},
+    time::Duration,
+};
+use tracing::debug;
+
+/// Port of the ibus_engine_switch switch server.
+pub const PORT: u16 = 14568;
+
+/// Default address of the switch server, same as its default `bind_addr`.
+pub const DEFAULT_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Frame the `set <engine>` command, None if it exceeds 255 bytes.
+pub fn set_engine_frame(engine: &str) -> Option<Vec<u8>> {
+    let text = format!("set {engine}");
+    let mut frame = vec![u8::try_from(text.len()).ok()?];
+    frame.extend_from_slice(text.as_bytes());
+    Some(frame)
+}
+
+/// Send `set <engine>` to the switch server on `addr`, returns its reply.
+pub fn set_engine(addr: IpAddr, engine: &str) -> Result<String, io::Error> {
+    let frame = set_engine_frame(engine)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Engine name is too long"))?;
+    let mut stream = TcpStream::connect_timeout(&SocketAddr::new(addr, PORT), TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(&frame)?;
+    let mut len = [0u8; 1];
+    stream.read_exact(&mut len)?;
+    let mut reply = vec![0u8; len[0] as usize];
+    stream.read_exact(&mut reply)?;
+    let reply = String::from_utf8_lossy(&reply).into_owned();
+    debug!("Switch server replied {reply:?} to set {engine}.");
+    Ok(reply)
+}
+
+/// Wraps a backend and calls `hook` with the workspace idx after every successful switch,
+/// including moving the viewport.
+pub struct PostSwitch {
+    pub inner: Box<dyn WorkspaceBackend>,
+    pub hook: Box<dyn Fn(usize)>,
+}
+
+impl PostSwitch {
+    /// Switch the input method to `engine` after every switch, see [`set_engine`].
+    /// Failures (e.g. the switch server isn't running) are only warned.
+    pub fn with_ime(inner: Box<dyn WorkspaceBackend>, addr: IpAddr, engine: String) -> PostSwitch {
+        PostSwitch {
+            inner,
+            hook: Box::new(move |_| {
+                if let Err(e) = set_engine(addr, &engine) {
+                    warn(format_args!(
+                        "failed to switch the input method to {engine}: {e}"
+                    ));
+                }
+            }),
+        }
+    }
+
+    /// 视口模式下切换的是活动工作区的视口.
+    fn active_idx(&self) -> Option<usize> {
+        let workspaces = self.inner.query().ok()?;
+        active_workspace(&workspaces).ok().map(|ws| ws.idx)
+    }
+}
+
+impl BackendWrapper for PostSwitch {
+    fn inner(&self) -> &dyn WorkspaceBackend {
+        self.inner.as_ref()
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.inner.switch_to(idx)?;
+        (self.hook)(idx);
+        Ok(())
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        self.inner.set_viewport(x, y)?;
+        (self.hook)(self.active_idx().unwrap_or_default());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Wmctrl, fake::Fake};
+    use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+    /// 用 `inner` 的 PostSwitch, 以及 hook 收到的工作区.
+    fn recording(
+        inner: Box<dyn WorkspaceBackend>,
+    ) -> (Box<dyn WorkspaceBackend>, Rc<RefCell<Vec<usize>>>) {
+        let called = Rc::new(RefCell::new(Vec::new()));
+        let hook_called = Rc::clone(&called);
+        let post = PostSwitch {
+            inner,
+            hook: Box::new(move |idx| hook_called.borrow_mut().push(idx)),
+        };
+        (Box::new(post), called)
+    }
+
+    #[test]
+    fn hook_runs_after_successful_switches() {
+        let (post, called) = recording(Box::new(Fake::new(3, 0)));
+        post.switch_to(2).unwrap();
+        post.move_active_to(1).unwrap();
+        post.set_viewport(0, 0).unwrap();
+        assert_eq!(*called.borrow(), [2, 2]);
+
+        let failing = Wmctrl {
+            path: PathBuf::from("false"),
+            extra_args: Vec::new(),
+        };
+        let (post, called) = recording(Box::new(failing));
+        assert!(post.switch_to(1).is_err());
+        assert!(called.borrow().is_empty());
+    }
+
+    #[test]
+    fn set_frames() {
+        assert_eq!(set_engine_frame("rime").unwrap(), b"\x08set rime");
+        assert!(set_engine_frame(&"x".repeat(251)).is_some());
+        assert!(set_engine_frame(&"x".repeat(252)).is_none());
+    }
+}
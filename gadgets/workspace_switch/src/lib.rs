@@ -0,0 +1,2355 @@
+pub mod activate;
+pub mod cli;
+pub mod config;
+#[cfg(test)]
+mod fake;
+pub mod fullscreen;
+pub mod gnome;
+pub mod history;
+pub mod ime;
+pub mod listing;
+pub mod notifier;
+pub mod picker;
+pub mod stdin;
+pub mod template;
+pub mod verify;
+pub mod x11;
+
+use clap::ValueEnum;
+use history::{History, LastMatch};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use template::Field;
+use tracing::{debug, trace};
+
+/// A workspace as listed by `wmctrl -d`.
+///
+/// The JSON representation (`--format json`, `--json`) is stable: an object with the fields
+/// `active`, `idx`, `dg` (`[width, height]`), `vp` (`[x, y]`), `available_area`
+/// (`[left, top, width, height]`) and `name`. `dg`, `vp` and `available_area` are always present,
+/// `null` when wmctrl prints `N/A`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workspace {
+    pub active: bool,
+    pub idx: usize,
+    pub dg: Option<(isize, isize)>,
+    pub vp: Option<(isize, isize)>,
+    /// (left, top, width, height)
+    pub available_area: Option<(isize, isize, isize, isize)>,
+    pub name: String,
+}
+
+/// All workspaces from a single query, printed by `--json`.
+/// `W` is [`CountedWorkspace`] with `--window-count`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Snapshot<W = Workspace> {
+    pub count: usize,
+    /// Index of the active workspace, None (`null`) if no workspace is active.
+    pub current: Option<usize>,
+    pub workspaces: Vec<W>,
+    /// Number of sticky windows when they are counted separately, omitted otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sticky_windows: Option<usize>,
+}
+
+impl Snapshot {
+    pub fn new(workspaces: Vec<Workspace>) -> Snapshot {
+        Snapshot {
+            count: workspaces.len(),
+            current: active_workspace(&workspaces).ok().map(|ws| ws.idx),
+            workspaces,
+            sticky_windows: None,
+        }
+    }
+
+    /// Attach the window count of every workspace, see [`window_counts`].
+    pub fn counted(
+        self,
+        windows: &[WindowEntry],
+        sticky: StickyWindows,
+    ) -> Snapshot<CountedWorkspace> {
+        Snapshot {
+            count: self.count,
+            current: self.current,
+            workspaces: CountedWorkspace::count(self.workspaces, windows, sticky),
+            sticky_windows: (sticky == StickyWindows::Separate).then(|| sticky_count(windows)),
+        }
+    }
+}
+
+/// A [`Workspace`] with the number of windows on it, serialized as the workspace object
+/// with an additional `window_count` field.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CountedWorkspace {
+    #[serde(flatten)]
+    pub workspace: Workspace,
+    pub window_count: usize,
+}
+
+impl CountedWorkspace {
+    pub fn count(
+        workspaces: Vec<Workspace>,
+        windows: &[WindowEntry],
+        sticky: StickyWindows,
+    ) -> Vec<CountedWorkspace> {
+        let counts = window_counts(&workspaces, windows, sticky);
+        workspaces
+            .into_iter()
+            .zip(counts)
+            .map(|(workspace, window_count)| CountedWorkspace {
+                workspace,
+                window_count,
+            })
+            .collect()
+    }
+}
+
+impl Display for CountedWorkspace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}  ({} windows)", self.workspace, self.window_count)
+    }
+}
+
+impl Display for Workspace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}  {} DG: {}  VP: {}  WA: {}  {}",
+            self.idx,
+            Field::Active.render(self),
+            Field::Dg.render(self),
+            Field::Vp.render(self),
+            Field::Wa.render(self),
+            self.name
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    #[error("Missing field(s)")]
+    FieldMissing,
+    #[error("Field type(s) incorrect")]
+    IncorrectFieldType,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("{kind} in line {line:?}")]
+pub struct WorkspaceParseError {
+    pub kind: ParseErrorKind,
+    /// 无法解析的 `wmctrl -d` 输出行.
+    pub line: String,
+}
+
+lazy_static::lazy_static! {
+    static ref PAT_WORKSPACE: Regex = Regex::new(r#"(?x)
+        # <工作区索引>  <活动状态> <DG: 几何尺寸> <VP: 视口位置> <WA: 可用区域> <工作区名称>
+        ^
+        (\d+)                           # workspace 索引
+        \s+
+        (\*|-)                          # 是否是活跃 workspace
+        \s+
+        DG:\s+(\d+x\d+|N/A)             # 几何尺寸
+        \s+
+        VP:\s+(\d+,\d+|N/A)             # 视口位置
+        \s+
+        WA:\s+(\d+,\d+\s+\d+x\d+|N/A)   # 可用区域
+        (?:\s+(.*?))?                   # 名称, wmctrl 用两个空格分隔 (也可能是 tab 或更多空格), 名称可以为空
+        $
+    "#).unwrap();
+    static ref PAT_NUM_PAIR: Regex = Regex::new(r#"(\d+)[x,](\d+)"#).unwrap();
+    static ref PAT_AREA: Regex = Regex::new(r#"(\d+),(\d+)\s+(\d+)x(\d+)"#).unwrap();
+    // <窗口 ID> <工作区索引> <主机名> <标题>
+    static ref PAT_WINDOW: Regex = Regex::new(r#"^0x([0-9a-fA-F]+)\s+(-?\d+)\s+(\S+)(?:\s(.*))?$"#).unwrap();
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress the warnings printed by [`warn`] (`--quiet`), errors are still returned.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Print a warning to stderr unless [`set_quiet`], stdout is left for the data output.
+pub fn warn(message: impl Display) {
+    if let Some(line) = warning_line(message, QUIET.load(Ordering::Relaxed)) {
+        eprintln!("{line}");
+    }
+}
+
+/// The line [`warn`] prints, None when quiet.
+pub fn warning_line(message: impl Display, quiet: bool) -> Option<String> {
+    (!quiet).then(|| format!("Warning: {message}"))
+}
+
+impl FromStr for Workspace {
+    type Err = WorkspaceParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Workspace::parse_fields(s).map_err(|kind| WorkspaceParseError {
+            kind,
+            line: s.to_owned(),
+        })
+    }
+}
+
+impl Workspace {
+    fn parse_fields(s: &str) -> Result<Workspace, ParseErrorKind> {
+        use ParseErrorKind::*;
+        let capture = PAT_WORKSPACE.captures(s).ok_or(FieldMissing)?;
+
+        let idx: usize = capture
+            .get(1)
+            .ok_or(FieldMissing)?
+            .as_str()
+            .parse()
+            .or(Err(IncorrectFieldType))?;
+
+        let active = capture.get(2).ok_or(FieldMissing)?.as_str();
+        let active: bool = if active == "*" {
+            true
+        } else if active == "-" {
+            false
+        } else {
+            Err(IncorrectFieldType)?
+        };
+
+        let dg = capture.get(3).ok_or(FieldMissing)?.as_str();
+        let dg: Option<(isize, isize)> = if dg == "N/A" {
+            None
+        } else {
+            let pair = PAT_NUM_PAIR.captures(dg).ok_or(IncorrectFieldType)?;
+            let w: isize = pair
+                .get(1)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            let h: isize = pair
+                .get(2)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            Some((w, h))
+        };
+
+        let vp = capture.get(4).ok_or(FieldMissing)?.as_str();
+        let vp: Option<(isize, isize)> = if vp == "N/A" {
+            None
+        } else {
+            let pair = PAT_NUM_PAIR.captures(vp).ok_or(IncorrectFieldType)?;
+            let x: isize = pair
+                .get(1)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            let y: isize = pair
+                .get(2)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            Some((x, y))
+        };
+
+        let area = capture.get(5).ok_or(FieldMissing)?.as_str();
+        let available_area: Option<(isize, isize, isize, isize)> = if area == "N/A" {
+            None
+        } else {
+            let capture = PAT_AREA.captures(area).ok_or(IncorrectFieldType)?;
+            let l: isize = capture
+                .get(1)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            let t: isize = capture
+                .get(2)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            let w: isize = capture
+                .get(3)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            let h: isize = capture
+                .get(4)
+                .ok_or(IncorrectFieldType)?
+                .as_str()
+                .parse()
+                .or(Err(IncorrectFieldType))?;
+            Some((l, t, w, h))
+        };
+
+        let name = capture.get(6).map(|x| x.as_str()).unwrap_or("").to_owned();
+
+        Ok(Workspace {
+            active,
+            idx,
+            dg,
+            vp,
+            available_area,
+            name,
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum NameMatchError {
+    #[error("No workspace matches name {0:?}")]
+    NotFound(String),
+    #[error("Name {0:?} is ambiguous, it matches: {matches}", matches = .1.join(", "))]
+    Ambiguous(String, Vec<String>),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum WindowMatchError {
+    #[error("No window title matches {0:?}, use --windows to have a look.")]
+    NotFound(String),
+    #[error("{0:?} matches several windows, use --first to pick the first one: {titles}", titles = .1.join(", "))]
+    Ambiguous(String, Vec<String>),
+}
+
+/// The window whose title matches `pattern`, in `wmctrl -l` order. Several matches are an error
+/// unless `first`, which picks the first of them.
+pub fn find_window<'a>(
+    windows: &'a [WindowEntry],
+    pattern: &Regex,
+    first: bool,
+) -> Result<&'a WindowEntry, WindowMatchError> {
+    let matches: Vec<&WindowEntry> = windows
+        .iter()
+        .filter(|w| pattern.is_match(&w.title))
+        .collect();
+    match matches[..] {
+        [] => Err(WindowMatchError::NotFound(pattern.to_string())),
+        [window] => Ok(window),
+        [window, ..] if first => Ok(window),
+        _ => Err(WindowMatchError::Ambiguous(
+            pattern.to_string(),
+            matches.iter().map(|w| w.title.clone()).collect(),
+        )),
+    }
+}
+
+/// The workspace `window` is on, None for sticky windows (shown on all workspaces).
+pub fn window_workspace(window: &WindowEntry) -> Option<usize> {
+    usize::try_from(window.desktop).ok()
+}
+
+/// 按名称查找 workspace, 依次尝试: 完全匹配, 忽略大小写匹配, 前缀匹配 (忽略大小写).
+/// 有多个前缀匹配时返回 `last` (上次匹配到的工作区) 之后的下一个 (循环), 见 [`switch_to_name`].
+pub fn find_by_name<'a>(
+    workspaces: &'a [Workspace],
+    name: &str,
+    last: Option<usize>,
+) -> Result<&'a Workspace, NameMatchError> {
+    if let Some(ws) = workspaces.iter().find(|ws| ws.name == name) {
+        return Ok(ws);
+    }
+    let lower = name.to_lowercase();
+    if let Some(ws) = workspaces.iter().find(|ws| ws.name.to_lowercase() == lower) {
+        return Ok(ws);
+    }
+    let matches: Vec<&Workspace> = workspaces
+        .iter()
+        .filter(|ws| ws.name.to_lowercase().starts_with(&lower))
+        .collect();
+    next_match(&matches, last).ok_or_else(|| NameMatchError::NotFound(name.to_owned()))
+}
+
+/// `matches` (按索引排序) 中 `last` 之后的第一个, 没有时回到第一个.
+pub fn next_match<'a>(matches: &[&'a Workspace], last: Option<usize>) -> Option<&'a Workspace> {
+    matches
+        .iter()
+        .find(|ws| last.is_some_and(|last| ws.idx > last))
+        .or_else(|| matches.first())
+        .copied()
+}
+
+/// Workspaces whose name matches `pattern` anywhere, use `^...$` to match whole names.
+pub fn filter_by_name<'a>(workspaces: &'a [Workspace], pattern: &Regex) -> Vec<&'a Workspace> {
+    workspaces
+        .iter()
+        .filter(|ws| pattern.is_match(&ws.name))
+        .collect()
+}
+
+/// The only workspace matched by [`filter_by_name`], matching none or several is an error.
+pub fn find_by_filter<'a>(
+    workspaces: &'a [Workspace],
+    pattern: &Regex,
+) -> Result<&'a Workspace, NameMatchError> {
+    let matches = filter_by_name(workspaces, pattern);
+    match matches[..] {
+        [] => Err(NameMatchError::NotFound(pattern.to_string())),
+        [ws] => Ok(ws),
+        _ => Err(NameMatchError::Ambiguous(
+            pattern.to_string(),
+            matches.iter().map(|ws| ws.name.clone()).collect(),
+        )),
+    }
+}
+
+/// Path of wmctrl: `WMCTRL_PATH` if set, otherwise wmctrl in PATH.
+/// Only resolved when the wmctrl backend is created, see [`Wmctrl::new`],
+/// so `--help`, `--version` and completions work without wmctrl.
+pub fn find_wmctrl() -> Result<PathBuf, Error> {
+    let found = match env::var_os("WMCTRL_PATH") {
+        Some(path) if !path.is_empty() => {
+            let path = PathBuf::from(path);
+            if path.is_file() {
+                Ok(path)
+            } else {
+                Err(format!(
+                    "WMCTRL_PATH is set to {}, but it's not a file.",
+                    path.display()
+                ))
+            }
+        }
+        _ => which::which("wmctrl").map_err(|_| {
+            "wmctrl is not found, install it with `sudo apt install wmctrl`, \
+            or set WMCTRL_PATH to its path."
+                .to_owned()
+        }),
+    };
+    found.map_err(Error::WmctrlNotFound)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    WmctrlNotFound(String),
+    #[error("Failed to run wmctrl: {0}")]
+    Wmctrl(#[from] io::Error),
+    #[error("wmctrl exited with code {0}.")]
+    WmctrlExit(i32),
+    #[error("Unexpected wmctrl output: {0}")]
+    Parse(#[from] WorkspaceParseError),
+    #[error("Unexpected wmctrl output in line {0:?}")]
+    WindowParse(String),
+    #[error(transparent)]
+    NameMatch(#[from] NameMatchError),
+    #[error(transparent)]
+    WindowMatch(#[from] WindowMatchError),
+    #[error("Workspace idx {idx} is out of range, there are {num} workspaces.")]
+    OutOfRange { idx: isize, num: usize },
+    #[error("No workspaces.")]
+    NoWorkspaces,
+    #[error("At least one workspace is required.")]
+    ZeroCount,
+    #[error(
+        "Shrinking to {num} workspaces would remove the active workspace {active}, use --force to do it anyway."
+    )]
+    RemovesActive { active: usize, num: usize },
+    #[error(
+        "Workspace {0} didn't become active after switching, the window manager may ignore the request, try another --backend."
+    )]
+    NotSwitched(usize),
+    #[error("The active window didn't move to workspace {0}, not switching.")]
+    NotMoved(usize),
+    #[error(
+        "The active window moved to workspace {0}, but the workspace didn't become active even after switching again."
+    )]
+    MovedNotSwitched(usize),
+    #[error("No workspace is marked active, the window manager may not set _NET_CURRENT_DESKTOP.")]
+    NoActiveWorkspace,
+    #[error("No window is active.")]
+    NoActiveWindow,
+    #[error("The active window is fullscreen, not switching.")]
+    Fullscreen,
+    #[error("Window 0x{0:08x} doesn't exist, use --windows to have a look.")]
+    NoSuchWindow(u32),
+    #[error("No action is given.")]
+    NoAction,
+    #[error("--interactive needs a terminal.")]
+    NotATerminal,
+    #[error("Terminal error: {0}")]
+    Terminal(io::Error),
+    #[error("Invalid window id {0:?}, expected hex like 0x03a00003 or decimal.")]
+    InvalidWindowId(String),
+    #[error("The window manager doesn't support {0}.")]
+    Unsupported(&'static str),
+    #[error("--from-stdin only lists workspaces, {0} needs a window manager.")]
+    NoWindowManager(&'static str),
+    #[error("Failed to read stdin: {0}")]
+    Stdin(io::Error),
+    #[error("Failed to connect to the X server: {0}")]
+    X11Connect(#[from] x11rb::errors::ConnectError),
+    #[error("X11 error: {0}")]
+    X11Connection(#[from] x11rb::errors::ConnectionError),
+    #[error("X11 error: {0}")]
+    X11(#[from] x11rb::errors::ReplyError),
+    #[error("Failed to call GNOME Shell over D-Bus: {0}")]
+    Dbus(#[from] zbus::Error),
+    #[error(
+        "GNOME Shell refused to evaluate, since GNOME 41 it only does in unsafe mode, see the README."
+    )]
+    GnomeUnsafeMode,
+    #[error("GNOME Shell failed to evaluate: {0}")]
+    GnomeEval(String),
+    #[error("Invalid config {}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+    #[error(
+        "Unknown label {label:?}, define it under [labels] in the config. Known labels: {}.",
+        if known.is_empty() { "none".to_owned() } else { known.join(", ") }
+    )]
+    UnknownLabel { label: String, known: Vec<String> },
+    #[error("Label {label:?} is mapped to workspace {idx}, but there are only {num} workspaces.")]
+    LabelOutOfRange {
+        label: String,
+        idx: usize,
+        num: usize,
+    },
+}
+
+/// A window listed by `wmctrl -l`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WindowEntry {
+    pub id: u32,
+    /// -1 表示窗口显示在所有工作区上 (sticky), 见 [`WindowEntry::is_sticky`].
+    pub desktop: isize,
+    pub host: String,
+    pub title: String,
+}
+
+impl WindowEntry {
+    /// Whether the window is shown on all workspaces.
+    pub fn is_sticky(&self) -> bool {
+        self.desktop == -1
+    }
+
+    /// Parse a line of `wmctrl -l` output, e.g. `0x03a00003  0 host Title`.
+    pub fn parse(line: &str) -> Option<WindowEntry> {
+        let capture = PAT_WINDOW.captures(line)?;
+        Some(WindowEntry {
+            id: u32::from_str_radix(capture.get(1)?.as_str(), 16).ok()?,
+            desktop: capture.get(2)?.as_str().parse().ok()?,
+            host: capture.get(3)?.as_str().to_owned(),
+            title: capture.get(4).map(|x| x.as_str()).unwrap_or("").to_owned(),
+        })
+    }
+}
+
+/// 解析 `wmctrl -l` 的输出, 跳过空行.
+pub fn parse_windows(output: &str) -> Result<Vec<WindowEntry>, Error> {
+    output
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| WindowEntry::parse(l).ok_or_else(|| Error::WindowParse(l.to_owned())))
+        .collect()
+}
+
+/// How sticky windows (shown on all workspaces) are counted by [`window_counts`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StickyWindows {
+    /// Excluded from the count of every workspace, see [`sticky_count`].
+    #[default]
+    Separate,
+    /// Counted on every workspace.
+    Everywhere,
+}
+
+/// Number of windows on each of `workspaces` (in the same order), correlating
+/// [`WindowEntry::desktop`] with [`Workspace::idx`]. Windows on no listed workspace are ignored.
+pub fn window_counts(
+    workspaces: &[Workspace],
+    windows: &[WindowEntry],
+    sticky: StickyWindows,
+) -> Vec<usize> {
+    let sticky = match sticky {
+        StickyWindows::Separate => 0,
+        StickyWindows::Everywhere => sticky_count(windows),
+    };
+    workspaces
+        .iter()
+        .map(|ws| {
+            let own = windows
+                .iter()
+                .filter(|w| usize::try_from(w.desktop) == Ok(ws.idx))
+                .count();
+            own + sticky
+        })
+        .collect()
+}
+
+/// Number of sticky windows in `windows`.
+pub fn sticky_count(windows: &[WindowEntry]) -> usize {
+    windows.iter().filter(|w| w.is_sticky()).count()
+}
+
+/// Queries and switches workspaces.
+pub trait WorkspaceBackend {
+    fn query(&self) -> Result<Vec<Workspace>, Error>;
+    fn switch_to(&self, idx: usize) -> Result<(), Error>;
+    /// Move the active window to workspace `idx`.
+    fn move_active_to(&self, idx: usize) -> Result<(), Error>;
+    /// Move the viewport of the current workspace to (x, y).
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error>;
+    /// Set the name of workspace `idx`.
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error>;
+    /// List managed windows.
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error>;
+    /// Move `window` to workspace `idx` and activate it.
+    fn pull_window(&self, window: u32, idx: usize) -> Result<(), Error>;
+    /// Raise and focus `window` on its workspace.
+    fn activate_window(&self, window: u32) -> Result<(), Error>;
+    /// Move `window` to workspace `idx` without activating it.
+    fn move_window(&self, window: u32, idx: usize) -> Result<(), Error>;
+    /// Change the number of workspaces to `num`.
+    fn set_count(&self, num: usize) -> Result<(), Error>;
+    /// Show `window` on all workspaces, or only on its own if not `sticky`.
+    fn set_sticky(&self, window: u32, sticky: bool) -> Result<(), Error>;
+}
+
+/// A backend adding behavior to [`BackendWrapper::inner`], e.g. notifying after switching.
+/// It is a [`WorkspaceBackend`] forwarding every method it doesn't override to `inner`.
+pub trait BackendWrapper {
+    fn inner(&self) -> &dyn WorkspaceBackend;
+
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        self.inner().query()
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.inner().switch_to(idx)
+    }
+
+    fn move_active_to(&self, idx: usize) -> Result<(), Error> {
+        self.inner().move_active_to(idx)
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        self.inner().set_viewport(x, y)
+    }
+
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error> {
+        self.inner().rename(idx, name)
+    }
+
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        self.inner().windows()
+    }
+
+    fn pull_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        self.inner().pull_window(window, idx)
+    }
+
+    fn activate_window(&self, window: u32) -> Result<(), Error> {
+        self.inner().activate_window(window)
+    }
+
+    fn move_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        self.inner().move_window(window, idx)
+    }
+
+    fn set_count(&self, num: usize) -> Result<(), Error> {
+        self.inner().set_count(num)
+    }
+
+    fn set_sticky(&self, window: u32, sticky: bool) -> Result<(), Error> {
+        self.inner().set_sticky(window, sticky)
+    }
+}
+
+impl<T: BackendWrapper> WorkspaceBackend for T {
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        BackendWrapper::query(self)
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        BackendWrapper::switch_to(self, idx)
+    }
+
+    fn move_active_to(&self, idx: usize) -> Result<(), Error> {
+        BackendWrapper::move_active_to(self, idx)
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        BackendWrapper::set_viewport(self, x, y)
+    }
+
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error> {
+        BackendWrapper::rename(self, idx, name)
+    }
+
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        BackendWrapper::windows(self)
+    }
+
+    fn pull_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        BackendWrapper::pull_window(self, window, idx)
+    }
+
+    fn activate_window(&self, window: u32) -> Result<(), Error> {
+        BackendWrapper::activate_window(self, window)
+    }
+
+    fn move_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        BackendWrapper::move_window(self, window, idx)
+    }
+
+    fn set_count(&self, num: usize) -> Result<(), Error> {
+        BackendWrapper::set_count(self, num)
+    }
+
+    fn set_sticky(&self, window: u32, sticky: bool) -> Result<(), Error> {
+        BackendWrapper::set_sticky(self, window, sticky)
+    }
+}
+
+/// Flags selecting what wmctrl does, passing them again in [`Wmctrl::extra_args`]
+/// makes wmctrl do something else or print output that can't be parsed.
+pub const WMCTRL_ACTION_FLAGS: &[&str] = &["-d", "-s", "-l", "-r", "-t", "-o", "-n", "-a", "-b"];
+
+/// 调用 wmctrl 程序.
+#[derive(Clone, Debug)]
+pub struct Wmctrl {
+    /// Path of the wmctrl executable.
+    pub path: PathBuf,
+    /// Appended to the arguments of every invocation, for window manager quirks.
+    pub extra_args: Vec<String>,
+}
+
+/// The wmctrl arguments adding or removing the sticky state of `window`.
+pub fn sticky_args(window: u32, sticky: bool) -> Vec<String> {
+    let action = if sticky {
+        "add,sticky"
+    } else {
+        "remove,sticky"
+    };
+    ["-i", "-r", &format!("0x{window:08x}"), "-b", action]
+        .map(str::to_owned)
+        .to_vec()
+}
+
+/// The arguments of a wmctrl invocation: `args` followed by `extra_args` in order.
+pub fn wmctrl_args(args: &[&str], extra_args: &[String]) -> Vec<String> {
+    args.iter()
+        .map(|&arg| arg.to_owned())
+        .chain(extra_args.iter().cloned())
+        .collect()
+}
+
+/// Flags of [`WMCTRL_ACTION_FLAGS`] found in `extra_args`.
+pub fn reserved_wmctrl_args(extra_args: &[String]) -> Vec<&str> {
+    extra_args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| WMCTRL_ACTION_FLAGS.contains(arg))
+        .collect()
+}
+
+impl Wmctrl {
+    /// Find wmctrl with [`find_wmctrl`], failing cleanly if it isn't installed.
+    pub fn new(extra_args: Vec<String>) -> Result<Wmctrl, Error> {
+        Ok(Wmctrl {
+            path: find_wmctrl()?,
+            extra_args,
+        })
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let args = wmctrl_args(args, &self.extra_args);
+        debug!("Run {} {}", self.path.display(), args.join(" "));
+        let mut command = Command::new(&self.path);
+        command.args(args);
+        command
+    }
+
+    /// 运行 wmctrl, 非零退出时返回 [`Error::WmctrlExit`].
+    fn run(&self, args: &[&str]) -> Result<(), Error> {
+        let es = self.command(args).status()?;
+        debug!("wmctrl finished, {es}.");
+        if !es.success() {
+            return Err(Error::WmctrlExit(es.code().unwrap_or(-1)));
+        }
+        Ok(())
+    }
+
+    /// `--dump-raw`: the stdout of `wmctrl -d` as is, see [`raw_stdout`].
+    pub fn dump_raw(&self) -> Result<Vec<u8>, Error> {
+        raw_stdout(self.command(&["-d"]), Command::output)
+    }
+}
+
+impl WorkspaceBackend for Wmctrl {
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        let output = self.command(&["-d"]).stdout(Stdio::piped()).output()?;
+        trace!(
+            "wmctrl -d: {}",
+            String::from_utf8_lossy(&output.stdout).trim_end()
+        );
+        parse_workspaces(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.run(&["-s", format!("{}", idx).as_str()])
+    }
+
+    fn move_active_to(&self, idx: usize) -> Result<(), Error> {
+        self.run(&["-r", ":ACTIVE:", "-t", format!("{}", idx).as_str()])
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        self.run(&["-o", format!("{x},{y}").as_str()])
+    }
+
+    fn set_count(&self, num: usize) -> Result<(), Error> {
+        self.run(&["-n", format!("{num}").as_str()])
+    }
+
+    /// wmctrl 不能设置工作区名称, 直接通过 X11 设置.
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error> {
+        x11::X11::connect()?.rename(idx, name)
+    }
+
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        let output = self.command(&["-l"]).stdout(Stdio::piped()).output()?;
+        parse_windows(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn pull_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        let window = format!("0x{window:08x}");
+        self.run(&["-i", "-r", &window, "-t", format!("{idx}").as_str()])?;
+        self.run(&["-i", "-a", &window])
+    }
+
+    fn activate_window(&self, window: u32) -> Result<(), Error> {
+        self.run(&["-i", "-a", &format!("0x{window:08x}")])
+    }
+
+    fn move_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        let window = format!("0x{window:08x}");
+        self.run(&["-i", "-r", &window, "-t", format!("{idx}").as_str()])
+    }
+
+    fn set_sticky(&self, window: u32, sticky: bool) -> Result<(), Error> {
+        let args = sticky_args(window, sticky);
+        self.run(&args.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+}
+
+/// The stdout of `command` run by `run` (e.g. [`Command::output`]), unchanged and unparsed.
+/// stderr is passed through, exiting non-zero is only warned since the output may still help debugging.
+pub fn raw_stdout(
+    mut command: Command,
+    run: impl FnOnce(&mut Command) -> Result<Output, io::Error>,
+) -> Result<Vec<u8>, Error> {
+    command.stdout(Stdio::piped()).stderr(Stdio::inherit());
+    let output = run(&mut command)?;
+    if !output.status.success() {
+        warn(format_args!(
+            "wmctrl exited with code {}",
+            output.status.code().unwrap_or(-1)
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// 解析 `wmctrl -d` 的输出, 跳过空行.
+pub fn parse_workspaces(output: &str) -> Result<Vec<Workspace>, Error> {
+    output
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| Ok(l.parse()?))
+        .collect()
+}
+
+/// 切换到工作区 `idx`, 并把 `from` -> `idx` 记录到历史中.
+/// Returns whether the active workspace changed, i.e. false if `from` is already `idx`.
+pub fn switch_recorded(
+    backend: &dyn WorkspaceBackend,
+    from: Option<usize>,
+    idx: usize,
+) -> Result<bool, Error> {
+    if from == Some(idx) {
+        return Ok(false);
+    }
+    backend.switch_to(idx)?;
+    if let Some(from) = from {
+        record_history(from, idx);
+    }
+    Ok(true)
+}
+
+/// `--print`: the active workspace idx before and after `switch`, with the result of `switch`.
+/// Before is read from `query`, after is the idx `switch` returns it switched to, or before if it didn't switch.
+pub fn old_and_new(
+    query: impl FnOnce() -> Result<Vec<Workspace>, Error>,
+    switch: impl FnOnce() -> Result<(bool, Option<usize>), Error>,
+) -> Result<(bool, usize, usize), Error> {
+    let old = active_workspace(&query()?)?.idx;
+    let (changed, target) = switch()?;
+    Ok((changed, old, target.unwrap_or(old)))
+}
+
+/// 把 `from` -> `idx` 记录到历史中.
+fn record_history(from: usize, idx: usize) {
+    if let Some(path) = History::default_path() {
+        let mut history = History::load(&path).unwrap_or_default();
+        history.record(from, idx);
+        save_history(&history, &path);
+    }
+}
+
+/// `--name`: switch to the workspace [`find_by_name`] finds. The match is saved as [`LastMatch`],
+/// so repeating the same name advances to the next workspace matching it as a prefix.
+pub fn switch_to_name(backend: &dyn WorkspaceBackend, name: &str) -> Result<bool, Error> {
+    let workspaces = backend.query()?;
+    let from = active_workspace(&workspaces).ok().map(|ws| ws.idx);
+    let path = LastMatch::default_path();
+    let lower = name.to_lowercase();
+    // 名称不同时从头开始匹配.
+    let last = path
+        .as_deref()
+        .and_then(LastMatch::load)
+        .filter(|last| last.name == lower)
+        .map(|last| last.idx);
+    let idx = find_by_name(&workspaces, name, last)?.idx;
+    if let Some(path) = path
+        && let Err(e) = (LastMatch { name: lower, idx }).save(&path)
+    {
+        warn(format_args!(
+            "failed to save the last match to {}: {e}",
+            path.display()
+        ));
+    }
+    switch_recorded(backend, from, idx)
+}
+
+/// `--take`: move the active window to workspace `idx` and switch there, see [`verify::take`].
+/// Returns false if `idx` is already active.
+pub fn take_window(backend: &dyn WorkspaceBackend, idx: usize) -> Result<bool, Error> {
+    let workspaces = backend.query()?;
+    let idx = check_index(idx, workspaces.len(), false)?;
+    let from = active_workspace(&workspaces)?.idx;
+    if from == idx {
+        return Ok(false);
+    }
+    verify::take(backend, idx)?;
+    record_history(from, idx);
+    Ok(true)
+}
+
+fn save_history(history: &History, path: &Path) {
+    if let Err(e) = history.save(path) {
+        warn(format_args!(
+            "failed to save history to {}: {e}",
+            path.display()
+        ));
+    }
+}
+
+/// 切换到标题匹配 `pattern` 的窗口所在的工作区, `activate` 时再激活该窗口, 见 [`find_window`].
+/// sticky 窗口在每个工作区上都有, 不切换, 只激活. 返回是否切换或激活了.
+pub fn goto_window(
+    backend: &dyn WorkspaceBackend,
+    pattern: &Regex,
+    first: bool,
+    activate: bool,
+) -> Result<bool, Error> {
+    let windows = backend.windows()?;
+    let window = find_window(&windows, pattern, first)?;
+    debug!("Window 0x{:08x} {:?} matches.", window.id, window.title);
+    let switched = match window_workspace(window) {
+        Some(idx) => {
+            let from = active_workspace(&backend.query()?).ok().map(|ws| ws.idx);
+            switch_recorded(backend, from, idx)?
+        }
+        None => false,
+    };
+    if activate || window.is_sticky() {
+        backend.activate_window(window.id)?;
+        return Ok(true);
+    }
+    Ok(switched)
+}
+
+/// 切换到上一个活动的工作区.
+/// Without a usable history (missing or corrupt) nothing is done and it counts as success, i.e. returns true.
+pub fn switch_to_last(backend: &dyn WorkspaceBackend) -> Result<bool, Error> {
+    // 历史不存在或已损坏时没有可回去的工作区, 什么都不做, 算作成功.
+    let Some(history) = History::default_path().and_then(|path| History::load(&path)) else {
+        debug!("No switch history, nothing to do.");
+        return Ok(true);
+    };
+    let Some(previous) = history.previous() else {
+        debug!("No previous workspace in the history, nothing to do.");
+        return Ok(true);
+    };
+    let current = active_workspace(&backend.query()?)?.idx;
+    // 在其他地方切换过工作区时, 历史中的 current 可能已经不是当前工作区.
+    let target = if current == previous {
+        history.current
+    } else {
+        previous
+    };
+    switch_recorded(backend, Some(current), target)
+}
+
+/// 沿历史后退 (`forward` 为 false) 或前进一步, 没有可去的工作区时什么都不做.
+/// Unlike [`switch_recorded`], the forward stack is kept.
+pub fn switch_in_history(backend: &dyn WorkspaceBackend, forward: bool) -> Result<bool, Error> {
+    let Some(path) = History::default_path() else {
+        return Ok(false);
+    };
+    let Some(mut history) = History::load(&path) else {
+        return Ok(false);
+    };
+    let current = active_workspace(&backend.query()?)?.idx;
+    let target = if forward {
+        history.go_forward(current)
+    } else {
+        history.go_back(current)
+    };
+    let Some(target) = target else {
+        return Ok(false);
+    };
+    backend.switch_to(target)?;
+    save_history(&history, &path);
+    Ok(true)
+}
+
+pub fn active_workspace(workspaces: &[Workspace]) -> Result<&Workspace, Error> {
+    let (active, others) = pick_active(workspaces)?;
+    if !others.is_empty() && !WARNED_MULTIPLE_ACTIVE.swap(true, Ordering::Relaxed) {
+        let others: Vec<String> = others.iter().map(usize::to_string).collect();
+        warn(format_args!(
+            "workspaces {} and {} are all marked active, use {}.",
+            active.idx,
+            others.join(", "),
+            active.idx
+        ));
+    }
+    Ok(active)
+}
+
+/// 同一次运行中多次查询时只警告一次.
+static WARNED_MULTIPLE_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The active workspace with the lowest idx, and the idxs of the other workspaces also marked
+/// active, which buggy window managers may report. Fails if none is active.
+pub fn pick_active(workspaces: &[Workspace]) -> Result<(&Workspace, Vec<usize>), Error> {
+    let mut active: Vec<&Workspace> = workspaces.iter().filter(|ws| ws.active).collect();
+    active.sort_by_key(|ws| ws.idx);
+    let (&first, rest) = active.split_first().ok_or(Error::NoActiveWorkspace)?;
+    Ok((first, rest.iter().map(|ws| ws.idx).collect()))
+}
+
+/// 把活动窗口移动到工作区 `idx`, 超出范围时返回错误.
+pub fn move_active_to(backend: &dyn WorkspaceBackend, idx: usize) -> Result<(), Error> {
+    let num = backend.query()?.len();
+    backend.move_active_to(check_index(idx, num, false)?)
+}
+
+/// Parse a window id as printed by `wmctrl -l` (`0x03a00003`) or in decimal.
+pub fn parse_window_id(s: &str) -> Result<u32, Error> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| Error::InvalidWindowId(s.to_owned()))
+}
+
+/// 把窗口 `window` 移动到活动工作区并激活, 窗口不存在时返回错误.
+pub fn pull_window(backend: &dyn WorkspaceBackend, window: u32) -> Result<(), Error> {
+    if !backend.windows()?.iter().any(|w| w.id == window) {
+        return Err(Error::NoSuchWindow(window));
+    }
+    let current = active_workspace(&backend.query()?)?.idx;
+    backend.pull_window(window, current)
+}
+
+/// The moves exchanging the windows of workspaces `a` and `b`, as (window, destination) pairs.
+/// Sticky windows and windows on other workspaces are left alone.
+pub fn plan_swap(windows: &[WindowEntry], a: usize, b: usize) -> Vec<(u32, usize)> {
+    windows
+        .iter()
+        .filter_map(|w| match usize::try_from(w.desktop) {
+            Ok(d) if d == a => Some((w.id, b)),
+            Ok(d) if d == b => Some((w.id, a)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 交换工作区 `a` 和 `b` 上的窗口, 返回移动的窗口数.
+/// 先记下两边的窗口再移动, 移过去的窗口不会被再移回来.
+pub fn swap_workspaces(backend: &dyn WorkspaceBackend, a: usize, b: usize) -> Result<usize, Error> {
+    let num = backend.query()?.len();
+    let (a, b) = (check_index(a, num, false)?, check_index(b, num, false)?);
+    if a == b {
+        return Ok(0);
+    }
+    let moves = plan_swap(&backend.windows()?, a, b);
+    for &(window, idx) in &moves {
+        backend.move_window(window, idx)?;
+    }
+    Ok(moves.len())
+}
+
+/// Make `window` sticky or not, returns false if it already was.
+pub fn set_sticky(
+    backend: &dyn WorkspaceBackend,
+    window: u32,
+    sticky: bool,
+) -> Result<bool, Error> {
+    let windows = backend.windows()?;
+    let entry = windows
+        .iter()
+        .find(|w| w.id == window)
+        .ok_or(Error::NoSuchWindow(window))?;
+    if entry.is_sticky() == sticky {
+        return Ok(false);
+    }
+    backend.set_sticky(window, sticky)?;
+    Ok(true)
+}
+
+/// 重命名工作区 `idx`, 超出范围时返回错误.
+pub fn rename(backend: &dyn WorkspaceBackend, idx: usize, name: &str) -> Result<(), Error> {
+    let num = backend.query()?.len();
+    backend.rename(check_index(idx, num, false)?, name)
+}
+
+/// Format the active workspace for status bars, replacing `{idx}`, `{name}` and `{num}`
+/// (the number of workspaces) in `format`. None if no workspace is active.
+pub fn format_status(format: &str, workspaces: &[Workspace]) -> Option<String> {
+    let active = active_workspace(workspaces).ok()?;
+    Some(
+        format
+            .replace("{idx}", &active.idx.to_string())
+            .replace("{name}", &active.name)
+            .replace("{num}", &workspaces.len().to_string()),
+    )
+}
+
+/// 检查能否把工作区数量改为 `num`: 至少要有一个工作区, 且除非 `force`, 不能删除活动工作区.
+pub fn check_count(num: usize, active: Option<usize>, force: bool) -> Result<(), Error> {
+    if num == 0 {
+        return Err(Error::ZeroCount);
+    }
+    match active {
+        Some(active) if active >= num && !force => Err(Error::RemovesActive { active, num }),
+        _ => Ok(()),
+    }
+}
+
+/// Change the number of workspaces to `num`, see [`check_count`].
+pub fn set_count(backend: &dyn WorkspaceBackend, num: usize, force: bool) -> Result<(), Error> {
+    let active = active_workspace(&backend.query()?).ok().map(|ws| ws.idx);
+    check_count(num, active, force)?;
+    backend.set_count(num)
+}
+
+/// 检查 `idx` 是否在 `0..num` 内, 超出时 `clamp` 为 true 则截断到最后一个工作区, 否则返回错误.
+pub fn check_index(idx: usize, num: usize, clamp: bool) -> Result<usize, Error> {
+    if idx < num {
+        Ok(idx)
+    } else if num == 0 {
+        Err(Error::NoWorkspaces)
+    } else if clamp {
+        Ok(num - 1)
+    } else {
+        Err(Error::OutOfRange {
+            idx: idx as isize,
+            num,
+        })
+    }
+}
+
+/// Like [`check_index`], but negative `idx` counts from the end, e.g. -1 is the last workspace.
+/// With `clamp`, too negative `idx` resolves to the first workspace.
+pub fn resolve_index(idx: isize, num: usize, clamp: bool) -> Result<usize, Error> {
+    if idx >= 0 {
+        return check_index(idx as usize, num, clamp);
+    }
+    let from_end = num as isize + idx;
+    if from_end >= 0 {
+        Ok(from_end as usize)
+    } else if num == 0 {
+        Err(Error::NoWorkspaces)
+    } else if clamp {
+        Ok(0)
+    } else {
+        Err(Error::OutOfRange { idx, num })
+    }
+}
+
+/// Switch `delta` workspaces away, returns whether the active workspace (or viewport) changed.
+pub fn switch_by(backend: &dyn WorkspaceBackend, delta: isize, cycle: bool) -> Result<bool, Error> {
+    let query_result = backend.query()?;
+    let num = query_result.len() as isize;
+    if num == 0 {
+        return Err(Error::NoWorkspaces);
+    }
+    let workspace = active_workspace(&query_result)?;
+    // 视口模式 (例如 Compiz/Unity): 只有一个工作区, 由多个视口组成, 按行优先顺序在视口间移动.
+    if num == 1
+        && let Some(grid) = Grid::of(workspace).filter(Grid::is_viewports)
+    {
+        return switch_viewport(backend, &grid, grid.offset(delta, cycle));
+    }
+    // 不假设索引连续且按顺序列出, 按排序后的位置移动.
+    let new_idx = offset_match(&sorted_idxs(&query_result), workspace.idx, delta, cycle)
+        .unwrap_or(workspace.idx);
+    switch_recorded(backend, Some(workspace.idx), new_idx)
+}
+
+/// The idxs of `workspaces` in ascending order. Window managers may list them out of order
+/// or with gaps (e.g. 0, 1, 3, 4), so neighbors are found by position in this list.
+pub fn sorted_idxs(workspaces: &[Workspace]) -> Vec<usize> {
+    let mut idxs: Vec<usize> = workspaces.iter().map(|ws| ws.idx).collect();
+    idxs.sort_unstable();
+    idxs.dedup();
+    idxs
+}
+
+/// Like [`switch_by`], only moving among the workspaces whose name matches `pattern`, i.e. skipping the others.
+/// Matching no workspace at all is an error.
+pub fn switch_by_matching(
+    backend: &dyn WorkspaceBackend,
+    pattern: &Regex,
+    delta: isize,
+    cycle: bool,
+) -> Result<bool, Error> {
+    let workspaces = backend.query()?;
+    let current = active_workspace(&workspaces)?.idx;
+    let mut matches: Vec<usize> = filter_by_name(&workspaces, pattern)
+        .iter()
+        .map(|ws| ws.idx)
+        .collect();
+    matches.sort_unstable();
+    if matches.is_empty() {
+        return Err(NameMatchError::NotFound(pattern.to_string()).into());
+    }
+    match offset_match(&matches, current, delta, cycle) {
+        Some(idx) => switch_recorded(backend, Some(current), idx),
+        None => Ok(false),
+    }
+}
+
+/// The idx `delta` steps away from `cur` among `matches` (sorted workspace idxs, not empty),
+/// wrapping around if `cycle`. When `cur` doesn't match, one step forward is the first match after it
+/// and one step back the last match before it. None if there is nowhere to go without cycling.
+pub fn offset_match(matches: &[usize], cur: usize, delta: isize, cycle: bool) -> Option<usize> {
+    let len = matches.len();
+    let insert = match matches.binary_search(&cur) {
+        Ok(pos) => return Some(matches[offset_index(pos, delta, len, cycle)]),
+        Err(insert) => insert,
+    };
+    if delta == 0 {
+        return None;
+    }
+    // 当前工作区位于 insert - 1 和 insert 之间, 向前第一步到达 insert.
+    let target = if delta > 0 {
+        insert as i128 - 1 + delta as i128
+    } else {
+        insert as i128 + delta as i128
+    };
+    if cycle {
+        Some(matches[target.rem_euclid(len as i128) as usize])
+    } else {
+        usize::try_from(target)
+            .ok()
+            .and_then(|pos| matches.get(pos).copied())
+    }
+}
+
+/// `cur` 移动 `delta` 后的索引, 超出 `0..num` 时循环或截断. `num` 必须大于 0.
+/// `delta` 为任意值 (包括远大于 `num`) 时都不会溢出.
+pub fn offset_index(cur: usize, delta: isize, num: usize, cycle: bool) -> usize {
+    let cur = cur.min(num - 1);
+    if cycle {
+        // 先对 delta 取模, 再相加时不会溢出
+        let step = delta.rem_euclid(num as isize) as usize;
+        (cur + step) % num
+    } else if delta < 0 {
+        cur.saturating_sub(delta.unsigned_abs())
+    } else {
+        cur.saturating_add(delta as usize).min(num - 1)
+    }
+}
+
+/// 网格导航在哪些方向上循环.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Cycle {
+    X,
+    Y,
+    Both,
+    None,
+}
+
+impl Cycle {
+    pub fn x(self) -> bool {
+        matches!(self, Cycle::X | Cycle::Both)
+    }
+
+    pub fn y(self) -> bool {
+        matches!(self, Cycle::Y | Cycle::Both)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// 视口网格, 坐标都以 (列, 行) 表示.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grid {
+    pub size: (usize, usize),
+    pub cell: (usize, usize),
+    /// 单个屏幕的尺寸, 即一个格子的尺寸.
+    pub screen: (isize, isize),
+}
+
+impl Grid {
+    /// Viewport grid of the workspace, i.e. its desktop geometry divided into screens.
+    /// The screen size is estimated from the available area.
+    pub fn of(workspace: &Workspace) -> Option<Grid> {
+        let (dg_w, dg_h) = workspace.dg?;
+        let (vp_x, vp_y) = workspace.vp?;
+        let (l, t, w, h) = workspace.available_area?;
+        let screen = (l + w, t + h);
+        if screen.0 <= 0 || screen.1 <= 0 {
+            return None;
+        }
+        let size = (
+            (dg_w / screen.0).max(1) as usize,
+            (dg_h / screen.1).max(1) as usize,
+        );
+        let cell = (
+            ((vp_x / screen.0) as usize).min(size.0 - 1),
+            ((vp_y / screen.1) as usize).min(size.1 - 1),
+        );
+        Some(Grid { size, cell, screen })
+    }
+
+    /// Whether the grid has more than one viewport.
+    pub fn is_viewports(&self) -> bool {
+        self.size != (1, 1)
+    }
+
+    /// The cell `delta` viewports away from the current one, counted in row-major order.
+    pub fn offset(&self, delta: isize, cycle: bool) -> (usize, usize) {
+        let (cols, rows) = self.size;
+        let cur = self.cell.1 * cols + self.cell.0;
+        let idx = offset_index(cur, delta, cols * rows, cycle);
+        (idx % cols, idx / cols)
+    }
+
+    /// Viewport coordinates of the top-left corner of `cell`.
+    pub fn origin(&self, cell: (usize, usize)) -> (isize, isize) {
+        (
+            cell.0 as isize * self.screen.0,
+            cell.1 as isize * self.screen.1,
+        )
+    }
+}
+
+/// 在 `size` 大小的网格中从 `cell` 向 `direction` 移动一格, 到达边缘时按 `cycle` 循环或停留.
+pub fn grid_move(
+    cell: (usize, usize),
+    size: (usize, usize),
+    direction: Direction,
+    cycle: Cycle,
+) -> (usize, usize) {
+    let step = |pos: usize, len: usize, forward: bool, cycle: bool| -> usize {
+        if forward {
+            if pos + 1 < len {
+                pos + 1
+            } else if cycle {
+                0
+            } else {
+                pos
+            }
+        } else if pos > 0 {
+            pos - 1
+        } else if cycle {
+            len - 1
+        } else {
+            pos
+        }
+    };
+    let (col, row) = cell;
+    let (cols, rows) = size;
+    match direction {
+        Direction::Up => (col, step(row, rows, false, cycle.y())),
+        Direction::Down => (col, step(row, rows, true, cycle.y())),
+        Direction::Left => (step(col, cols, false, cycle.x()), row),
+        Direction::Right => (step(col, cols, true, cycle.x()), row),
+    }
+}
+
+/// Render cells as rows of `[label]`, `labels` in row-major order with `cols` per row,
+/// the `active`th cell is marked with `*`. Labels are padded to the same width.
+pub fn render_grid(cols: usize, labels: &[String], active: Option<usize>) -> String {
+    let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    labels
+        .chunks(cols.max(1))
+        .enumerate()
+        .map(|(row, chunk)| {
+            chunk
+                .iter()
+                .enumerate()
+                .map(|(col, label)| {
+                    let marker = if active == Some(row * cols + col) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    format!("[{marker}{label:<width$}]")
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The grid `--up/--down/--left/--right` navigate, rendered by [`render_grid`]:
+/// the viewports of the active workspace if it has several, otherwise all workspaces as a row.
+/// None if the geometry of the active workspace is N/A.
+pub fn grid_view(workspaces: &[Workspace]) -> Option<String> {
+    let workspace = active_workspace(workspaces).ok()?;
+    let grid = Grid::of(workspace)?;
+    if grid.is_viewports() {
+        let (cols, rows) = grid.size;
+        let labels: Vec<String> = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| format!("{col},{row}")))
+            .collect();
+        Some(render_grid(
+            cols,
+            &labels,
+            Some(grid.cell.1 * cols + grid.cell.0),
+        ))
+    } else {
+        let labels: Vec<String> = workspaces
+            .iter()
+            .map(|ws| format!("{} {}", ws.idx, ws.name).trim_end().to_owned())
+            .collect();
+        // 索引可能不连续, 按列出的位置标记.
+        let active = workspaces.iter().position(|ws| ws.active);
+        Some(render_grid(workspaces.len(), &labels, active))
+    }
+}
+
+/// 移动到 `grid` 中的 `cell`, 已经在该视口时什么都不做并返回 false.
+fn switch_viewport(
+    backend: &dyn WorkspaceBackend,
+    grid: &Grid,
+    cell: (usize, usize),
+) -> Result<bool, Error> {
+    if cell == grid.cell {
+        return Ok(false);
+    }
+    let (x, y) = grid.origin(cell);
+    backend.set_viewport(x, y)?;
+    Ok(true)
+}
+
+/// 在工作区网格中移动一格, 返回是否移动了 (到达边缘且不循环时不会移动).
+/// 活动工作区由多个视口组成时在视口间移动, 否则把所有工作区看作一行.
+pub fn switch_grid(
+    backend: &dyn WorkspaceBackend,
+    direction: Direction,
+    cycle: Cycle,
+) -> Result<bool, Error> {
+    let query_result = backend.query()?;
+    let workspace = active_workspace(&query_result)?;
+    match Grid::of(workspace) {
+        Some(grid) if grid.is_viewports() => switch_viewport(
+            backend,
+            &grid,
+            grid_move(grid.cell, grid.size, direction, cycle),
+        ),
+        grid => {
+            if grid.is_none() {
+                warn("desktop geometry or viewport is N/A, treat workspaces as a single row.");
+            }
+            let idxs = sorted_idxs(&query_result);
+            let pos = idxs.binary_search(&workspace.idx).unwrap_or_default();
+            let (col, _) = grid_move((pos, 0), (idxs.len(), 1), direction, cycle);
+            switch_recorded(backend, Some(workspace.idx), idxs[col])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::Fake;
+
+    fn wmctrl(path: &str) -> Wmctrl {
+        Wmctrl {
+            path: PathBuf::from(path),
+            extra_args: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn wmctrl_failing_is_an_error() {
+        assert!(matches!(
+            wmctrl("false").switch_to(1),
+            Err(Error::WmctrlExit(1))
+        ));
+        assert!(wmctrl("true").set_viewport(0, 0).is_ok());
+    }
+
+    #[test]
+    fn repeated_name_cycles_through_prefix_matches() {
+        let fake = Fake::with(
+            ["web1", "mail", "web2", "Web3"]
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| fake::workspace(idx, idx == 1, name))
+                .collect(),
+        );
+        let mut visited = Vec::new();
+        for _ in 0..4 {
+            assert!(switch_to_name(&fake, "web").unwrap());
+            visited.push(fake.active().unwrap());
+        }
+        assert_eq!(visited, [0, 2, 3, 0]);
+    }
+
+    #[test]
+    fn cycling_advances_from_the_last_match() {
+        let fake = Fake::with(
+            ["web1", "web2", "web3", "mail"]
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| fake::workspace(idx, idx == 3, name))
+                .collect(),
+        );
+        switch_to_name(&fake, "web").unwrap();
+        switch_to_name(&fake, "web").unwrap();
+        // 在其他地方切换走之后仍然从上次匹配到的工作区继续.
+        fake.switch_to(3).unwrap();
+        switch_to_name(&fake, "web").unwrap();
+        assert_eq!(fake.active(), Some(2));
+        // 换一个名称时从头开始.
+        switch_to_name(&fake, "w").unwrap();
+        assert_eq!(fake.active(), Some(0));
+    }
+
+    #[test]
+    fn single_match_switches_there() {
+        let fake = Fake::with(
+            ["web1", "mail"]
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| fake::workspace(idx, idx == 0, name))
+                .collect(),
+        );
+        assert!(switch_to_name(&fake, "ma").unwrap());
+        assert_eq!(fake.active(), Some(1));
+        assert!(!switch_to_name(&fake, "ma").unwrap());
+        assert_eq!(fake.active(), Some(1));
+    }
+
+    fn parse(line: &str) -> Workspace {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn unusual_names() {
+        let prefix = "0  * DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053";
+        for (suffix, name) in [
+            ("  工作区 ✓", "工作区 ✓"),
+            ("  DG: my stuff", "DG: my stuff"),
+            ("  a:b VP: 1,1", "a:b VP: 1,1"),
+            ("  code  ", "code  "),
+            ("\tcode", "code"),
+            ("   code", "code"),
+            ("", ""),
+            ("  ", ""),
+        ] {
+            let ws = parse(&format!("{prefix}{suffix}"));
+            assert_eq!(ws.name, name, "{suffix:?}");
+            assert_eq!(ws.dg, Some((1920, 1080)));
+            assert_eq!(ws.available_area, Some((0, 27, 1920, 1053)));
+        }
+    }
+
+    #[test]
+    fn names_round_trip_through_display() {
+        for name in ["", "Main", "工作区 ✓", "DG: my stuff", "code  "] {
+            let ws = Workspace {
+                name: name.to_owned(),
+                ..parse("1  - DG: 1920x1080  VP: N/A  WA: N/A")
+            };
+            assert_eq!(parse(&ws.to_string()), ws);
+        }
+    }
+
+    #[test]
+    fn grid_cells() {
+        let grid = |line: &str| Grid::of(&parse(line)).unwrap();
+        let g = grid("0  * DG: 3840x2160  VP: 1920,1080  WA: 0,27 1920x1053  Main");
+        assert_eq!((g.size, g.cell), ((2, 2), (1, 1)));
+        let g = grid("0  * DG: 5760x1080  VP: 3840,0  WA: 0,0 1920x1080  Main");
+        assert_eq!((g.size, g.cell), ((3, 1), (2, 0)));
+        let g = grid("0  * DG: 3840x1080  VP: 0,0  WA: 64,0 1856x1080  Main");
+        assert_eq!((g.size, g.cell), ((2, 1), (0, 0)));
+        let g = grid("0  * DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  Main");
+        assert!(!g.is_viewports());
+        // 视口坐标超出桌面时放在最后一格.
+        let g = grid("0  * DG: 3840x1080  VP: 5760,0  WA: 0,0 1920x1080  Main");
+        assert_eq!(g.cell, (1, 0));
+    }
+
+    #[test]
+    fn grid_view_marks_the_active_cell() {
+        let viewports = [parse(
+            "0  * DG: 3840x2160  VP: 1920,0  WA: 0,27 1920x1053  Main",
+        )];
+        assert_eq!(
+            grid_view(&viewports).unwrap(),
+            "[ 0,0] [*1,0]\n[ 0,1] [ 1,1]"
+        );
+        // 索引不连续时按位置标记, 而不是按索引.
+        let workspaces = [
+            parse("0  - DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  a"),
+            parse("1  - DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  b"),
+            parse("3  * DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  c"),
+        ];
+        assert_eq!(grid_view(&workspaces).unwrap(), "[ 0 a] [ 1 b] [*3 c]");
+        let unknown = [parse("0  * DG: N/A  VP: N/A  WA: N/A  Main")];
+        assert_eq!(grid_view(&unknown), None);
+    }
+
+    fn named(names: &[&str]) -> Vec<Workspace> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| fake::workspace(idx, idx == 0, name))
+            .collect()
+    }
+
+    #[test]
+    fn name_matching() {
+        let workspaces = named(&["Web", "web", "WEB2", "mail", "Music"]);
+        let find = |name: &str| find_by_name(&workspaces, name, None).map(|ws| ws.idx);
+        // 完全匹配优先于忽略大小写.
+        assert_eq!(find("web"), Ok(1));
+        assert_eq!(find("Web"), Ok(0));
+        assert_eq!(find("MAIL"), Ok(3));
+        // 前缀匹配多个时取第一个, 之后按 last 循环.
+        assert_eq!(find("m"), Ok(3));
+        assert_eq!(find_by_name(&workspaces, "m", Some(3)).unwrap().idx, 4);
+        assert_eq!(find("we"), Ok(0));
+        assert_eq!(
+            find("video"),
+            Err(NameMatchError::NotFound("video".to_owned()))
+        );
+    }
+
+    #[test]
+    fn ambiguous_filter() {
+        let workspaces = named(&["proj-a", "mail", "proj-b"]);
+        assert_eq!(
+            find_by_filter(&workspaces, &Regex::new("^proj").unwrap()),
+            Err(NameMatchError::Ambiguous(
+                "^proj".to_owned(),
+                vec!["proj-a".to_owned(), "proj-b".to_owned()]
+            ))
+        );
+        let ws = find_by_filter(&workspaces, &Regex::new("ai").unwrap()).unwrap();
+        assert_eq!(ws.idx, 1);
+    }
+
+    #[test]
+    fn json_list_sample() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        assert_eq!(
+            serde_json::to_string(&workspaces[..2]).unwrap(),
+            r#"[{"active":true,"idx":0,"dg":[1920,1080],"vp":[0,0],"available_area":[0,27,1920,1053],"name":"Main"},{"active":false,"idx":1,"dg":[1920,1080],"vp":null,"available_area":null,"name":"Web"}]"#
+        );
+        let json: serde_json::Value = serde_json::to_value(&workspaces).unwrap();
+        let names: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|ws| ws["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Main", "Web", "", "工作区 4"]);
+    }
+
+    #[test]
+    fn grid_move_corners_and_edges() {
+        use Direction::*;
+        let size = (3, 2);
+        // 中间的格子 (1, 0) 和角落 (0, 0), (2, 1).
+        assert_eq!(grid_move((1, 0), size, Left, Cycle::None), (0, 0));
+        assert_eq!(grid_move((1, 0), size, Right, Cycle::None), (2, 0));
+        assert_eq!(grid_move((1, 0), size, Down, Cycle::None), (1, 1));
+        for cycle in [Cycle::None, Cycle::Both] {
+            let wraps = matches!(cycle, Cycle::Both);
+            let corner = |cell, direction, wrapped| {
+                let expected = if wraps { wrapped } else { cell };
+                assert_eq!(grid_move(cell, size, direction, cycle), expected);
+            };
+            corner((0, 0), Left, (2, 0));
+            corner((0, 0), Up, (0, 1));
+            corner((2, 1), Right, (0, 1));
+            corner((2, 1), Down, (2, 0));
+            corner((1, 0), Up, (1, 1));
+        }
+        assert_eq!(grid_move((0, 0), size, Right, Cycle::None), (1, 0));
+        // 单行的网格中上下移动不离开该行.
+        assert_eq!(grid_move((1, 0), (3, 1), Up, Cycle::Both), (1, 0));
+    }
+
+    #[test]
+    fn garbage_lines_are_errors() {
+        let garbage = "Cannot get current desktop properties.";
+        let output = format!("{}{garbage}\n", fake::WMCTRL_D);
+        match parse_workspaces(&output) {
+            Err(Error::Parse(e)) => {
+                assert_eq!(e.kind, ParseErrorKind::FieldMissing);
+                assert_eq!(e.line, garbage);
+            }
+            other => panic!("{other:?}"),
+        }
+        let overflow =
+            "99999999999999999999999  * DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  Main";
+        assert_eq!(
+            overflow.parse::<Workspace>().unwrap_err().kind,
+            ParseErrorKind::IncorrectFieldType
+        );
+        // 空行被跳过.
+        let with_blank_lines = format!("\n{}\n", fake::WMCTRL_D);
+        assert_eq!(parse_workspaces(&with_blank_lines).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn per_axis_cycling_at_boundaries() {
+        use Direction::*;
+        let size = (3, 2);
+        // (cycle, 右下角向右, 向下, 左上角向左, 向上)
+        for (cycle, right, down, left, up) in [
+            (Cycle::None, (2, 1), (2, 1), (0, 0), (0, 0)),
+            (Cycle::X, (0, 1), (2, 1), (2, 0), (0, 0)),
+            (Cycle::Y, (2, 1), (2, 0), (0, 0), (0, 1)),
+            (Cycle::Both, (0, 1), (2, 0), (2, 0), (0, 1)),
+        ] {
+            assert_eq!(grid_move((2, 1), size, Right, cycle), right, "{cycle:?}");
+            assert_eq!(grid_move((2, 1), size, Down, cycle), down, "{cycle:?}");
+            assert_eq!(grid_move((0, 0), size, Left, cycle), left, "{cycle:?}");
+            assert_eq!(grid_move((0, 0), size, Up, cycle), up, "{cycle:?}");
+        }
+    }
+
+    #[test]
+    fn from_str_sample() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        let names: Vec<&str> = workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        assert_eq!(names, ["Main", "Web", "", "工作区 4"]);
+        assert_eq!(active_workspace(&workspaces).unwrap().idx, 0);
+        assert_eq!(workspaces[0].vp, Some((0, 0)));
+        // wmctrl 对非当前工作区输出 N/A
+        assert_eq!(workspaces[1].vp, None);
+        assert_eq!(workspaces[1].available_area, None);
+        assert_eq!(workspaces[3].dg, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn switch_by_index_math() {
+        // (当前, delta, cycle, 切换后)
+        for (cur, delta, cycle, new) in [
+            (0, 1, true, 1),
+            (3, 1, true, 0),
+            (0, -1, true, 3),
+            (1, 6, true, 3),
+            (3, 1, false, 3),
+            (0, -1, false, 0),
+            (1, 10, false, 3),
+            (2, -2, false, 0),
+        ] {
+            let fake = Fake::new(4, cur);
+            let changed = switch_by(&fake, delta, cycle).unwrap();
+            assert_eq!(fake.active(), Some(new), "{cur} {delta} {cycle}");
+            assert_eq!(changed, cur != new);
+            // 没有移动时不调用 wmctrl
+            let calls = if changed {
+                vec![format!("switch_to {new}")]
+            } else {
+                vec![]
+            };
+            assert_eq!(fake.calls(), calls);
+        }
+        assert!(matches!(
+            switch_by(&Fake::new(0, 0), 1, true),
+            Err(Error::NoWorkspaces)
+        ));
+    }
+
+    #[test]
+    fn index_validation() {
+        assert_eq!(check_index(0, 4, false).unwrap(), 0);
+        assert_eq!(check_index(3, 4, false).unwrap(), 3);
+        assert!(matches!(
+            check_index(4, 4, false),
+            Err(Error::OutOfRange { idx: 4, num: 4 })
+        ));
+        // --clamp 截断到最后一个工作区
+        assert_eq!(check_index(4, 4, true).unwrap(), 3);
+        assert_eq!(check_index(100, 4, true).unwrap(), 3);
+        for clamp in [false, true] {
+            assert!(matches!(check_index(0, 0, clamp), Err(Error::NoWorkspaces)));
+        }
+        assert_eq!(
+            check_index(7, 4, false).unwrap_err().to_string(),
+            "Workspace idx 7 is out of range, there are 4 workspaces."
+        );
+    }
+
+    #[test]
+    fn windows_sample() {
+        let windows = parse_windows(fake::WMCTRL_L).unwrap();
+        assert_eq!(windows.len(), 6);
+        assert_eq!(
+            windows[0],
+            WindowEntry {
+                id: 0x01e00003,
+                desktop: -1,
+                host: "host".to_owned(),
+                title: "Top Bar".to_owned(),
+            }
+        );
+        let sticky: Vec<bool> = windows.iter().map(WindowEntry::is_sticky).collect();
+        assert_eq!(sticky, [true, true, false, false, false, false]);
+        assert_eq!(windows[2].desktop, 0);
+        assert_eq!(windows[2].title, "Terminal - ~/crate");
+        // 没有标题的窗口
+        assert_eq!(windows[4].host, "N/A");
+        assert_eq!(windows[4].title, "");
+        // 标题中的连续空格保留
+        assert_eq!(windows[5].title, "新标签页  - 浏览器");
+        assert!(matches!(
+            parse_windows("0x0zz 0 host x"),
+            Err(Error::WindowParse(_))
+        ));
+    }
+
+    #[test]
+    fn negative_index_resolution() {
+        // 4 个工作区: -1 是最后一个, -4 是第一个
+        for (idx, resolved) in [(0, 0), (3, 3), (-1, 3), (-2, 2), (-4, 0)] {
+            assert_eq!(resolve_index(idx, 4, false).unwrap(), resolved, "{idx}");
+        }
+        for idx in [4, -5] {
+            assert!(matches!(
+                resolve_index(idx, 4, false),
+                Err(Error::OutOfRange { idx: i, num: 4 }) if i == idx
+            ));
+        }
+        assert_eq!(resolve_index(-5, 4, true).unwrap(), 0);
+        assert_eq!(resolve_index(4, 4, true).unwrap(), 3);
+        assert!(matches!(
+            resolve_index(-1, 0, true),
+            Err(Error::NoWorkspaces)
+        ));
+    }
+
+    #[test]
+    fn filter_parsed_workspaces() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        let filter = |pattern: &str| -> Vec<usize> {
+            filter_by_name(&workspaces, &Regex::new(pattern).unwrap())
+                .iter()
+                .map(|ws| ws.idx)
+                .collect()
+        };
+        assert_eq!(filter("(?i)^w"), [1]);
+        assert_eq!(filter("a"), [0]);
+        // 空的模式匹配任何名称, 包括空名称
+        assert_eq!(filter(""), [0, 1, 2, 3]);
+        assert_eq!(filter("^$"), [2]);
+        assert_eq!(filter(r"\d$"), [3]);
+        assert!(filter("nothing").is_empty());
+        assert_eq!(
+            find_by_filter(&workspaces, &Regex::new("nothing").unwrap()),
+            Err(NameMatchError::NotFound("nothing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn count_guard() {
+        assert!(matches!(
+            check_count(0, Some(0), true),
+            Err(Error::ZeroCount)
+        ));
+        assert!(check_count(3, Some(2), false).is_ok());
+        assert!(matches!(
+            check_count(2, Some(2), false),
+            Err(Error::RemovesActive { active: 2, num: 2 })
+        ));
+        assert!(check_count(2, Some(2), true).is_ok());
+        // 没有活动工作区时不需要 --force
+        assert!(check_count(1, None, false).is_ok());
+
+        let fake = Fake::new(4, 3);
+        assert!(set_count(&fake, 3, false).is_err());
+        assert!(fake.calls().is_empty());
+        set_count(&fake, 6, false).unwrap();
+        assert_eq!(fake.workspaces.borrow().len(), 6);
+        set_count(&fake, 2, true).unwrap();
+        assert_eq!(fake.calls(), ["set_count 6", "set_count 2"]);
+    }
+
+    #[test]
+    fn viewport_layout() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D_VIEWPORTS).unwrap();
+        let grid = Grid::of(&workspaces[0]).unwrap();
+        assert_eq!(grid.size, (3, 2));
+        assert_eq!(grid.cell, (1, 1));
+        assert_eq!(grid.screen, (1920, 1080));
+
+        let fake = Fake::with(workspaces);
+        // 按行优先顺序: (1, 1) 向后一个是 (2, 1), 再向后不循环时停留
+        assert!(switch_by(&fake, 1, false).unwrap());
+        assert!(!switch_by(&fake, 1, false).unwrap());
+        // 循环时到达 (0, 0), 从那里向前两个是 (1, 1)
+        assert!(switch_by(&fake, 1, true).unwrap());
+        assert!(switch_by(&fake, -2, true).unwrap());
+        assert_eq!(
+            fake.calls(),
+            [
+                "set_viewport 3840,1080",
+                "set_viewport 0,0",
+                "set_viewport 1920,1080"
+            ]
+        );
+        // 视口模式下不切换工作区
+        assert_eq!(fake.active(), Some(0));
+    }
+
+    #[test]
+    fn offset_index_arithmetic() {
+        // (cur, delta, cycle, 结果), 共 4 个工作区
+        for (cur, delta, cycle, new) in [
+            (2, 0, true, 2),
+            (2, 0, false, 2),
+            (1, 4, true, 1),
+            (1, 9, true, 2),
+            (1, 9, false, 3),
+            (0, -1, true, 3),
+            (0, -1, false, 0),
+            (3, 1, false, 3),
+            (1, -9, true, 0),
+            (1, -9, false, 0),
+            (0, isize::MAX, true, 3),
+            (3, isize::MAX, false, 3),
+            (0, isize::MIN, true, 0),
+            (3, isize::MIN, false, 0),
+        ] {
+            assert_eq!(
+                offset_index(cur, delta, 4, cycle),
+                new,
+                "{cur} {delta} {cycle}"
+            );
+        }
+        // 当前索引超出范围时先截断
+        assert_eq!(offset_index(10, 0, 4, false), 3);
+        assert_eq!(offset_index(0, isize::MAX, 1, true), 0);
+    }
+
+    #[test]
+    fn snapshot_sample() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        let json = serde_json::to_value(Snapshot::new(workspaces.clone())).unwrap();
+        assert_eq!(json["count"], 4);
+        assert_eq!(json["current"], 0);
+        assert_eq!(
+            json["workspaces"],
+            serde_json::to_value(&workspaces).unwrap()
+        );
+        assert!(json.get("sticky_windows").is_none());
+        // 没有活动的工作区时 current 为 null
+        let inactive: Vec<Workspace> = workspaces
+            .into_iter()
+            .map(|ws| Workspace {
+                active: false,
+                ..ws
+            })
+            .collect();
+        let json = serde_json::to_string(&Snapshot::new(inactive[..1].to_vec())).unwrap();
+        assert_eq!(
+            json,
+            r#"{"count":1,"current":null,"workspaces":[{"active":false,"idx":0,"dg":[1920,1080],"vp":[0,0],"available_area":[0,27,1920,1053],"name":"Main"}]}"#
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for output in [fake::WMCTRL_D, fake::WMCTRL_D_VIEWPORTS] {
+            let workspaces = parse_workspaces(output).unwrap();
+            let json = serde_json::to_string(&workspaces).unwrap();
+            let back: Vec<Workspace> = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, workspaces);
+        }
+        // None 序列化为 null, 读取时也可以省略
+        let web = fake::workspace(1, false, "Web");
+        let json =
+            r#"{"active":false,"idx":1,"dg":null,"vp":null,"available_area":null,"name":"Web"}"#;
+        assert_eq!(serde_json::to_string(&web).unwrap(), json);
+        let ws: Workspace =
+            serde_json::from_str(r#"{"active":false,"idx":1,"name":"Web"}"#).unwrap();
+        assert_eq!(ws, web);
+        assert!(serde_json::from_str::<Workspace>(r#"{"active":false,"idx":1}"#).is_err());
+    }
+
+    #[test]
+    fn extra_wmctrl_args_are_appended_in_order() {
+        let extra: Vec<String> = ["-u", "-w", "2"].map(str::to_owned).into();
+        assert_eq!(
+            wmctrl_args(&["-s", "3"], &extra),
+            ["-s", "3", "-u", "-w", "2"]
+        );
+        assert_eq!(wmctrl_args(&["-d"], &[]), ["-d"]);
+        let wmctrl = Wmctrl {
+            extra_args: extra,
+            ..wmctrl("wmctrl")
+        };
+        let command = wmctrl.command(&["-i", "-a", "0x03a00003"]);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-i", "-a", "0x03a00003", "-u", "-w", "2"]);
+        // 会改变 wmctrl 行为的参数
+        let reserved: Vec<String> = ["-u", "-d", "-x", "-s"].map(str::to_owned).into();
+        assert_eq!(reserved_wmctrl_args(&reserved), ["-d", "-s"]);
+    }
+
+    #[test]
+    fn relative_among_matching_names() {
+        // 项目工作区 1, 3, 4 之间夹着临时工作区
+        let matches = [1, 3, 4];
+        for (cur, delta, cycle, new) in [
+            (1, 1, true, Some(3)),
+            (4, 1, true, Some(1)),
+            (4, 1, false, Some(4)),
+            (1, -1, true, Some(4)),
+            (1, -1, false, Some(1)),
+            (3, 5, true, Some(1)),
+            // 当前工作区不匹配
+            (2, 1, false, Some(3)),
+            (2, -1, false, Some(1)),
+            (0, -1, false, None),
+            (0, -1, true, Some(4)),
+            (5, 1, false, None),
+            (5, 1, true, Some(1)),
+            (2, 0, true, None),
+        ] {
+            assert_eq!(
+                offset_match(&matches, cur, delta, cycle),
+                new,
+                "{cur} {delta} {cycle}"
+            );
+        }
+
+        let names = ["proj-a", "scratch", "proj-b", "tmp", "proj-c"];
+        let fake = Fake::with(
+            names
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| fake::workspace(idx, idx == 4, name))
+                .collect(),
+        );
+        let proj = Regex::new("^proj").unwrap();
+        assert!(switch_by_matching(&fake, &proj, 1, true).unwrap());
+        assert_eq!(fake.active(), Some(0));
+        assert!(!switch_by_matching(&fake, &proj, -1, false).unwrap());
+        assert!(switch_by_matching(&fake, &proj, 1, false).unwrap());
+        assert_eq!(fake.calls(), ["switch_to 0", "switch_to 2"]);
+        assert!(matches!(
+            switch_by_matching(&fake, &Regex::new("^web").unwrap(), 1, true),
+            Err(Error::NameMatch(NameMatchError::NotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn sticky_windows() {
+        assert_eq!(
+            sticky_args(0x03a00003, true),
+            ["-i", "-r", "0x03a00003", "-b", "add,sticky"]
+        );
+        assert_eq!(
+            sticky_args(0x1e00003, false),
+            ["-i", "-r", "0x01e00003", "-b", "remove,sticky"]
+        );
+
+        let fake = Fake::new(4, 0);
+        *fake.windows.borrow_mut() = parse_windows(fake::WMCTRL_L).unwrap();
+        assert_eq!(sticky_count(&fake.windows().unwrap()), 2);
+        // 已经是要求的状态时不调用 wmctrl
+        assert!(!set_sticky(&fake, 0x01e00003, true).unwrap());
+        assert!(!set_sticky(&fake, 0x03a00003, false).unwrap());
+        assert!(set_sticky(&fake, 0x03a00003, true).unwrap());
+        assert!(set_sticky(&fake, 0x02000006, false).unwrap());
+        assert!(matches!(
+            set_sticky(&fake, 0x0badf00d, true),
+            Err(Error::NoSuchWindow(0x0badf00d))
+        ));
+        assert_eq!(
+            fake.calls(),
+            ["set_sticky 0x03a00003 true", "set_sticky 0x02000006 false"]
+        );
+    }
+
+    #[test]
+    fn quiet_suppresses_warnings_not_data() {
+        assert_eq!(
+            warning_line("wmctrl printed nothing", false).as_deref(),
+            Some("Warning: wmctrl printed nothing")
+        );
+        assert_eq!(warning_line("wmctrl printed nothing", true), None);
+        // 数据输出不受 --quiet 影响
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        let output = || {
+            (
+                listing::render_list(&workspaces, None, None, listing::ListStyle::Plain),
+                format_status("{idx}", &workspaces),
+            )
+        };
+        let loud = output();
+        set_quiet(true);
+        let quiet = output();
+        set_quiet(false);
+        assert_eq!(quiet, loud);
+        assert_eq!(quiet.0.len(), 4);
+    }
+
+    #[test]
+    fn non_contiguous_indices() {
+        // 乱序列出的 0, 1, 3, 4
+        let listed = [4, 0, 3, 1];
+        let fake_at = |active| {
+            Fake::with(
+                listed
+                    .iter()
+                    .map(|&idx| fake::workspace(idx, idx == active, ""))
+                    .collect(),
+            )
+        };
+        assert_eq!(sorted_idxs(&fake_at(0).query().unwrap()), [0, 1, 3, 4]);
+        for (active, delta, cycle, new) in [
+            (1, 1, true, 3),
+            (3, -1, true, 1),
+            (4, 1, true, 0),
+            (0, -1, true, 4),
+            (4, 1, false, 4),
+            (0, 2, false, 3),
+            (3, 5, false, 4),
+        ] {
+            let fake = fake_at(active);
+            switch_by(&fake, delta, cycle).unwrap();
+            assert_eq!(fake.active(), Some(new), "{active} {delta} {cycle}");
+        }
+    }
+
+    #[test]
+    fn windows_are_counted_per_workspace() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        let windows = parse_windows(fake::WMCTRL_L).unwrap();
+        assert_eq!(sticky_count(&windows), 2);
+        assert_eq!(
+            window_counts(&workspaces, &windows, StickyWindows::Separate),
+            [1, 2, 0, 1]
+        );
+        assert_eq!(
+            window_counts(&workspaces, &windows, StickyWindows::Everywhere),
+            [3, 4, 2, 3]
+        );
+        // 按 idx 而不是位置对应
+        let shuffled = [workspaces[3].clone(), workspaces[1].clone()];
+        assert_eq!(
+            window_counts(&shuffled, &windows, StickyWindows::Separate),
+            [1, 2]
+        );
+
+        let json = serde_json::to_value(
+            Snapshot::new(workspaces.clone()).counted(&windows, StickyWindows::Separate),
+        )
+        .unwrap();
+        assert_eq!(json["sticky_windows"], 2);
+        assert_eq!(json["workspaces"][1]["window_count"], 2);
+        assert_eq!(json["workspaces"][1]["name"], "Web");
+        let json = serde_json::to_value(
+            Snapshot::new(workspaces).counted(&windows, StickyWindows::Everywhere),
+        )
+        .unwrap();
+        assert!(json.get("sticky_windows").is_none());
+        assert_eq!(json["workspaces"][2]["window_count"], 2);
+    }
+
+    #[test]
+    fn zero_or_multiple_active_workspaces() {
+        let none = vec![
+            fake::workspace(0, false, "a"),
+            fake::workspace(1, false, "b"),
+        ];
+        assert!(matches!(pick_active(&none), Err(Error::NoActiveWorkspace)));
+        let fake = Fake::with(none);
+        assert!(matches!(
+            switch_by(&fake, 1, true),
+            Err(Error::NoActiveWorkspace)
+        ));
+        assert!(fake.calls().is_empty());
+
+        let multiple = vec![
+            fake::workspace(3, true, "d"),
+            fake::workspace(0, false, "a"),
+            fake::workspace(2, true, "c"),
+            fake::workspace(1, true, "b"),
+        ];
+        let (active, others) = pick_active(&multiple).unwrap();
+        assert_eq!((active.idx, others), (1, vec![2, 3]));
+        assert_eq!(active_workspace(&multiple).unwrap().idx, 1);
+        // 从 idx 最小的活动工作区开始切换
+        let fake = Fake::with(multiple);
+        assert!(switch_by(&fake, 1, true).unwrap());
+        assert_eq!(fake.calls(), ["switch_to 2"]);
+    }
+
+    #[test]
+    fn swap_plans_moves_from_both_snapshots() {
+        let windows = parse_windows(fake::WMCTRL_L).unwrap();
+        assert_eq!(
+            plan_swap(&windows, 0, 1),
+            [(0x03a00003, 1), (0x03c00016, 0), (0x03c0002a, 0)]
+        );
+        // 空的工作区, 粘性窗口不移动
+        assert_eq!(plan_swap(&windows, 2, 3), [(0x04200004, 2)]);
+        assert!(plan_swap(&windows, 2, 2).is_empty());
+
+        let fake = Fake::new(4, 0);
+        *fake.windows.borrow_mut() = windows;
+        assert_eq!(swap_workspaces(&fake, 1, 0).unwrap(), 3);
+        assert_eq!(
+            fake.calls(),
+            [
+                "move_window 0x03a00003 1",
+                "move_window 0x03c00016 0",
+                "move_window 0x03c0002a 0"
+            ]
+        );
+        let desktops: Vec<isize> = fake.windows.borrow().iter().map(|w| w.desktop).collect();
+        assert_eq!(desktops, [-1, -1, 1, 0, 0, 3]);
+        assert_eq!(swap_workspaces(&fake, 3, 3).unwrap(), 0);
+        assert!(swap_workspaces(&fake, 0, 4).is_err());
+    }
+
+    #[test]
+    fn windows_by_title() {
+        let windows = parse_windows(fake::WMCTRL_L).unwrap();
+        let find = |pattern: &str, first| {
+            find_window(&windows, &Regex::new(pattern).unwrap(), first).map(|w| w.id)
+        };
+        assert_eq!(find("Fire", false), Ok(0x03c00016));
+        assert_eq!(find("浏览器$", false), Ok(0x04200004));
+        assert_eq!(
+            find("(?i)^(terminal|firefox)", false),
+            Err(WindowMatchError::Ambiguous(
+                "(?i)^(terminal|firefox)".to_owned(),
+                vec!["Terminal - ~/crate".to_owned(), "Firefox".to_owned()]
+            ))
+        );
+        assert_eq!(find("(?i)^(terminal|firefox)", true), Ok(0x03a00003));
+        assert_eq!(
+            find("Thunderbird", true),
+            Err(WindowMatchError::NotFound("Thunderbird".to_owned()))
+        );
+
+        let desktop = |id| window_workspace(windows.iter().find(|w| w.id == id).unwrap());
+        assert_eq!(desktop(0x03c00016), Some(1));
+        assert_eq!(desktop(0x03a00003), Some(0));
+        assert_eq!(desktop(0x01e00003), None);
+
+        let fake = Fake::new(4, 0);
+        *fake.windows.borrow_mut() = windows.clone();
+        assert!(goto_window(&fake, &Regex::new("Fire").unwrap(), false, false).unwrap());
+        assert_eq!(fake.calls(), ["switch_to 1"]);
+        // sticky 窗口只激活
+        let fake = Fake::new(4, 0);
+        *fake.windows.borrow_mut() = windows;
+        assert!(goto_window(&fake, &Regex::new("Top Bar").unwrap(), false, false).unwrap());
+        assert_eq!(fake.calls(), ["activate_window 0x01e00003"]);
+    }
+
+    #[test]
+    fn old_and_new_indices() {
+        let query = || Ok(Fake::new(4, 1).query().unwrap());
+        assert_eq!(
+            old_and_new(query, || Ok((true, Some(3)))).unwrap(),
+            (true, 1, 3)
+        );
+        // 没有切换时两次是同一个 idx
+        assert_eq!(
+            old_and_new(query, || Ok((false, None))).unwrap(),
+            (false, 1, 1)
+        );
+        assert_eq!(
+            old_and_new(query, || Ok((false, Some(1)))).unwrap(),
+            (false, 1, 1)
+        );
+        // 查询失败时不切换
+        let result = old_and_new(
+            || Err(Error::NoWorkspaces),
+            || -> Result<_, Error> { panic!("switched") },
+        );
+        assert!(matches!(result, Err(Error::NoWorkspaces)));
+        let result = old_and_new(query, || Err(Error::NoActiveWorkspace));
+        assert!(matches!(result, Err(Error::NoActiveWorkspace)));
+    }
+
+    #[test]
+    fn raw_stdout_is_unchanged() {
+        use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+        let wmctrl = Wmctrl {
+            path: PathBuf::from("wmctrl"),
+            extra_args: Vec::new(),
+        };
+        // 不是 UTF-8, 行尾有空格, 也不能解析的输出
+        let raw =
+            b"0  * DG: 1920x1080  VP: 0,0  WA: 0,27 1920x1053  Main  \n\xff garbage\n".to_vec();
+        let output = |code| Output {
+            status: ExitStatus::from_raw(code),
+            stdout: raw.clone(),
+            stderr: Vec::new(),
+        };
+        let dumped = raw_stdout(wmctrl.command(&["-d"]), |command| {
+            assert_eq!(command.get_program(), "wmctrl");
+            assert_eq!(command.get_args().collect::<Vec<_>>(), ["-d"]);
+            Ok(output(0))
+        });
+        assert_eq!(dumped.unwrap(), raw);
+        // 退出码不为 0 时仍然输出
+        let dumped = raw_stdout(wmctrl.command(&["-d"]), |_| Ok(output(1 << 8)));
+        assert_eq!(dumped.unwrap(), raw);
+        let failed = raw_stdout(wmctrl.command(&["-d"]), |_| {
+            Err(io::ErrorKind::NotFound.into())
+        });
+        assert!(failed.is_err());
+    }
+}
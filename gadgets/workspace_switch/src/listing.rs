@@ -0,0 +1,162 @@
+//! `--list` 的输出: 终端中按列对齐并高亮活动工作区, 输出到管道时每行是以 tab 分隔的字段, 方便 `awk` 等处理.
+
+use crate::{Workspace, template::Field};
+
+/// How `--list` prints the workspaces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListStyle {
+    /// Columns padded to the same width, the active marker colored if `color`.
+    Aligned { color: bool },
+    /// One tab separated line per workspace, see [`plain_line`].
+    Plain,
+}
+
+const ACTIVE_COLOR: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+/// The fields of `ws`: idx, active, dg, vp, wa and name, followed by `window_count` if given.
+fn fields(ws: &Workspace, window_count: Option<usize>) -> Vec<String> {
+    let mut fields: Vec<String> = [
+        Field::Idx,
+        Field::Active,
+        Field::Dg,
+        Field::Vp,
+        Field::Wa,
+        Field::Name,
+    ]
+    .into_iter()
+    .map(|field| field.render(ws))
+    .collect();
+    fields.extend(window_count.map(|count| count.to_string()));
+    fields
+}
+
+/// A workspace in the [`ListStyle::Plain`] format, which is stable:
+/// `idx`, `active` (`*` or `-`), `dg`, `vp`, `wa`, `name` and, with `window_count`, the count,
+/// separated by tabs. Values are rendered like `--format-str`, tabs in the name become spaces.
+pub fn plain_line(ws: &Workspace, window_count: Option<usize>) -> String {
+    fields(ws, window_count)
+        .into_iter()
+        .map(|field| field.replace('\t', " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// The line of the sticky windows in the [`ListStyle::Plain`] format: idx `-1`, name `Sticky`,
+/// the other workspace fields empty.
+pub fn plain_sticky_line(window_count: usize) -> String {
+    format!("-1\t\t\t\t\tSticky\t{window_count}")
+}
+
+/// Lines of `--list` in `style`. `window_counts` has one entry per workspace,
+/// `sticky` appends a line for the sticky windows when they are counted separately.
+pub fn render_list(
+    workspaces: &[Workspace],
+    window_counts: Option<&[usize]>,
+    sticky: Option<usize>,
+    style: ListStyle,
+) -> Vec<String> {
+    let count = |i: usize| window_counts.and_then(|counts| counts.get(i).copied());
+    let color = match style {
+        ListStyle::Plain => {
+            let mut lines: Vec<String> = workspaces
+                .iter()
+                .enumerate()
+                .map(|(i, ws)| plain_line(ws, count(i)))
+                .collect();
+            lines.extend(sticky.map(plain_sticky_line));
+            return lines;
+        }
+        ListStyle::Aligned { color } => color,
+    };
+    let mut rows: Vec<Vec<String>> = workspaces
+        .iter()
+        .enumerate()
+        .map(|(i, ws)| {
+            let mut row = fields(ws, None);
+            row[2] = format!("DG: {}", row[2]);
+            row[3] = format!("VP: {}", row[3]);
+            row[4] = format!("WA: {}", row[4]);
+            row.extend(count(i).map(|count| format!("({count} windows)")));
+            row
+        })
+        .collect();
+    rows.extend(sticky.map(|count| {
+        ["-1", "", "", "", "", "Sticky"]
+            .map(str::to_owned)
+            .into_iter()
+            .chain([format!("({count} windows)")])
+            .collect()
+    }));
+    let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| {
+            rows.iter()
+                .filter_map(|row| row.get(c))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(c, cell)| {
+                    // 最后一列不补空格, 颜色在补齐之后加上, 不影响宽度.
+                    let padded = if c + 1 == row.len() {
+                        cell.clone()
+                    } else if c == 0 {
+                        format!("{cell:>width$}", width = widths[c])
+                    } else {
+                        format!("{cell:<width$}", width = widths[c])
+                    };
+                    if c == 1 && color && cell == "*" {
+                        format!("{ACTIVE_COLOR}{padded}{RESET}")
+                    } else {
+                        padded
+                    }
+                })
+                .collect();
+            cells.join("  ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake, parse_workspaces};
+
+    #[test]
+    fn plain_format_is_stable() {
+        let workspaces = parse_workspaces(fake::WMCTRL_D).unwrap();
+        assert_eq!(
+            render_list(&workspaces, None, None, ListStyle::Plain),
+            [
+                "0\t*\t1920x1080\t0,0\t0,27 1920x1053\tMain",
+                "1\t-\t1920x1080\tN/A\tN/A\tWeb",
+                "2\t-\t1920x1080\tN/A\tN/A\t",
+                "3\t-\t1920x1080\tN/A\tN/A\t工作区 4",
+            ]
+        );
+        assert_eq!(
+            render_list(&workspaces[..2], Some(&[1, 2]), Some(2), ListStyle::Plain),
+            [
+                "0\t*\t1920x1080\t0,0\t0,27 1920x1053\tMain\t1",
+                "1\t-\t1920x1080\tN/A\tN/A\tWeb\t2",
+                "-1\t\t\t\t\tSticky\t2",
+            ]
+        );
+        // 名字中的 tab 不会多出字段
+        let tabbed = fake::workspace(5, false, "a\tb");
+        assert_eq!(plain_line(&tabbed, None), "5\t-\tN/A\tN/A\tN/A\ta b");
+        // 管道中从不输出颜色
+        assert!(
+            render_list(&workspaces, None, None, ListStyle::Plain)
+                .iter()
+                .all(|line| !line.contains('\x1b'))
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{BackendWrapper, Error, StickyWindows, WorkspaceBackend, warn, window_counts};
+use notify_rust::{Notification, Timeout};
+
+const TIMEOUT: Timeout = Timeout::Milliseconds(1500);
+
+/// Notification body of the workspace switched to.
+pub fn workspace_body(idx: usize, name: &str, window_count: Option<usize>) -> String {
+    let mut body = if name.is_empty() {
+        format!("Workspace {idx}")
+    } else {
+        format!("Workspace {idx}: {name}")
+    };
+    if let Some(count) = window_count {
+        body.push_str(&format!(" ({count} windows)"));
+    }
+    body
+}
+
+/// Show the workspace switched to, failures (e.g. no notification daemon) are only warned.
+pub fn notify_workspace(idx: usize, name: &str, window_count: Option<usize>) {
+    let body = workspace_body(idx, name, window_count);
+    if let Err(e) = Notification::new()
+        .summary("workspace_switch")
+        .body(&body)
+        .timeout(TIMEOUT)
+        .show()
+    {
+        warn(format_args!("failed to post notification: {e}"));
+    }
+}
+
+/// Wraps a backend and posts a notification after every successful switch.
+pub struct Notifying {
+    pub inner: Box<dyn WorkspaceBackend>,
+    /// Also show the number of windows on the workspace, counting sticky windows as given.
+    pub window_count: Option<StickyWindows>,
+}
+
+impl BackendWrapper for Notifying {
+    fn inner(&self) -> &dyn WorkspaceBackend {
+        self.inner.as_ref()
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.inner.switch_to(idx)?;
+        // 名称查询失败时只显示索引, 窗口列表查询失败时不显示窗口数.
+        let workspace = self
+            .inner
+            .query()
+            .ok()
+            .and_then(|workspaces| workspaces.into_iter().find(|ws| ws.idx == idx));
+        let window_count = self
+            .window_count
+            .zip(workspace.as_ref())
+            .and_then(|(sticky, ws)| {
+                let windows = self.inner.windows().ok()?;
+                window_counts(std::slice::from_ref(ws), &windows, sticky)
+                    .first()
+                    .copied()
+            });
+        let name = workspace.map(|ws| ws.name).unwrap_or_default();
+        notify_workspace(idx, &name, window_count);
+        Ok(())
+    }
+}
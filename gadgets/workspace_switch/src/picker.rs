@@ -0,0 +1,220 @@
+//! `--interactive`: 在终端中用方向键选择工作区, 只依赖 termios 和 ANSI 转义序列.
+
+use crate::{Error, Workspace, active_workspace};
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    mem::MaybeUninit,
+};
+
+/// A key press the picker understands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Enter,
+    /// Esc, Ctrl-C or `q`.
+    Cancel,
+}
+
+/// Parse the bytes of a single read from the terminal in raw mode, None for other keys.
+/// 方向键是 `ESC [ A` 这样的序列, 单独的 ESC 是 Esc 键.
+pub fn parse_key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        b"\x1b[A" | b"\x1bOA" | b"k" => Some(Key::Up),
+        b"\x1b[B" | b"\x1bOB" | b"j" => Some(Key::Down),
+        b"\r" | b"\n" => Some(Key::Enter),
+        b"\x1b" | b"\x03" | b"q" => Some(Key::Cancel),
+        _ => None,
+    }
+}
+
+/// What the user chose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Choice {
+    Pending,
+    Selected(usize),
+    Cancelled,
+}
+
+/// Selection state of the picker, starts at `selected` (the active workspace).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Picker {
+    pub selected: usize,
+    pub len: usize,
+}
+
+impl Picker {
+    /// Move the selection or finish, the selection wraps around at both ends.
+    pub fn handle(&mut self, key: Key) -> Choice {
+        match key {
+            Key::Up => self.selected = (self.selected + self.len - 1) % self.len,
+            Key::Down => self.selected = (self.selected + 1) % self.len,
+            Key::Enter => return Choice::Selected(self.selected),
+            Key::Cancel => return Choice::Cancelled,
+        }
+        Choice::Pending
+    }
+}
+
+/// 把终端设为 raw mode, drop 时恢复.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let mut termios = MaybeUninit::uninit();
+        // SAFETY: termios 指向可写的 termios 大小的内存, 由 tcgetattr 填入.
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: tcgetattr 成功, termios 已初始化.
+        let original = unsafe { termios.assume_init() };
+        let mut raw = original;
+        // 关闭 ISIG, Ctrl-C 作为按键读取, 保证能恢复终端.
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_iflag &= !libc::ICRNL;
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: raw 是有效的 termios, 调用期间一直存在.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: original 是 tcgetattr 读出的有效 termios. 失败时无法处理, 忽略.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// 在标准错误流中绘制列表, `redraw` 时先回到列表的第一行.
+fn render(
+    out: &mut impl Write,
+    workspaces: &[Workspace],
+    picker: &Picker,
+    redraw: bool,
+) -> io::Result<()> {
+    if redraw {
+        write!(out, "\x1b[{}A", workspaces.len())?;
+    }
+    for (i, ws) in workspaces.iter().enumerate() {
+        let marker = if ws.active { '*' } else { ' ' };
+        let line = format!("{marker} {}  {}", ws.idx, ws.name);
+        if i == picker.selected {
+            // 反色显示选中的工作区.
+            write!(out, "\r\x1b[2K\x1b[7m{line}\x1b[0m\r\n")?;
+        } else {
+            write!(out, "\r\x1b[2K{line}\r\n")?;
+        }
+    }
+    out.flush()
+}
+
+/// Let the user pick one of `workspaces` with the arrow keys (or j/k) and Enter, Esc cancels.
+/// Returns the idx of the chosen workspace, None if cancelled. Stdin must be a terminal.
+pub fn pick(workspaces: &[Workspace]) -> Result<Option<usize>, Error> {
+    if workspaces.is_empty() {
+        return Err(Error::NoWorkspaces);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(Error::NotATerminal);
+    }
+    let mut picker = Picker {
+        selected: active_workspace(workspaces)
+            .ok()
+            .and_then(|active| workspaces.iter().position(|ws| ws.idx == active.idx))
+            .unwrap_or(0),
+        len: workspaces.len(),
+    };
+    let _raw = RawMode::enable().map_err(Error::Terminal)?;
+    let mut stderr = io::stderr().lock();
+    let mut stdin = io::stdin().lock();
+    // 隐藏光标.
+    write!(stderr, "\x1b[?25l").map_err(Error::Terminal)?;
+    let result = (|| {
+        render(&mut stderr, workspaces, &picker, false)?;
+        let mut buf = [0; 8];
+        loop {
+            let n = stdin.read(&mut buf)?;
+            if n == 0 {
+                return Ok(Choice::Cancelled);
+            }
+            let Some(key) = parse_key(&buf[..n]) else {
+                continue;
+            };
+            match picker.handle(key) {
+                Choice::Pending => render(&mut stderr, workspaces, &picker, true)?,
+                choice => return Ok(choice),
+            }
+        }
+    })();
+    let _ = write!(stderr, "\x1b[?25h");
+    let _ = stderr.flush();
+    match result.map_err(Error::Terminal)? {
+        Choice::Selected(i) => Ok(Some(workspaces[i].idx)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::workspace;
+
+    #[test]
+    fn keys_move_and_choose() {
+        let keys: Vec<Option<Key>> = [
+            &b"\x1b[A"[..],
+            b"j",
+            b"\r",
+            b"\x1b",
+            b"\x03",
+            b"x",
+            b"\x1b[C",
+        ]
+        .iter()
+        .map(|bytes| parse_key(bytes))
+        .collect();
+        assert_eq!(
+            keys,
+            [
+                Some(Key::Up),
+                Some(Key::Down),
+                Some(Key::Enter),
+                Some(Key::Cancel),
+                Some(Key::Cancel),
+                None,
+                None
+            ]
+        );
+        // 两端循环
+        let mut picker = Picker {
+            selected: 0,
+            len: 3,
+        };
+        assert_eq!(picker.handle(Key::Up), Choice::Pending);
+        assert_eq!(picker.selected, 2);
+        assert_eq!(picker.handle(Key::Down), Choice::Pending);
+        assert_eq!(picker.handle(Key::Enter), Choice::Selected(0));
+        assert_eq!(picker.handle(Key::Cancel), Choice::Cancelled);
+    }
+
+    #[test]
+    fn render_highlights_the_selection() {
+        let workspaces = [workspace(0, true, "Main"), workspace(1, false, "Web")];
+        let mut out = Vec::new();
+        let picker = Picker {
+            selected: 1,
+            len: 2,
+        };
+        render(&mut out, &workspaces, &picker, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[2A\r\x1b[2K* 0  Main\r\n\r\x1b[2K\x1b[7m  1  Web\x1b[0m\r\n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! `--from-stdin`: 从标准输入读取 `wmctrl -d` 格式的工作区, 代替查询窗口管理器,
+//! 例如 `wmctrl -d | wsst --from-stdin --json`. 没有窗口管理器, 只能列出和查询.
+
+use crate::{Error, WindowEntry, Workspace, WorkspaceBackend, parse_workspaces};
+use std::io::Read;
+
+/// Read the whole of `reader` and parse it as the output of `wmctrl -d`, see [`parse_workspaces`].
+pub fn read_workspaces(mut reader: impl Read) -> Result<Vec<Workspace>, Error> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input).map_err(Error::Stdin)?;
+    parse_workspaces(&input)
+}
+
+/// A backend over workspaces given up front, queries return them and everything else
+/// fails with [`Error::NoWindowManager`].
+pub struct FromStdin {
+    pub workspaces: Vec<Workspace>,
+}
+
+impl WorkspaceBackend for FromStdin {
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        Ok(self.workspaces.clone())
+    }
+
+    fn switch_to(&self, _idx: usize) -> Result<(), Error> {
+        Err(Error::NoWindowManager("switching workspaces"))
+    }
+
+    fn move_active_to(&self, _idx: usize) -> Result<(), Error> {
+        Err(Error::NoWindowManager("moving windows"))
+    }
+
+    fn set_viewport(&self, _x: isize, _y: isize) -> Result<(), Error> {
+        Err(Error::NoWindowManager("moving the viewport"))
+    }
+
+    fn rename(&self, _idx: usize, _name: &str) -> Result<(), Error> {
+        Err(Error::NoWindowManager("renaming workspaces"))
+    }
+
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        Err(Error::NoWindowManager("listing windows"))
+    }
+
+    fn pull_window(&self, _window: u32, _idx: usize) -> Result<(), Error> {
+        Err(Error::NoWindowManager("moving windows"))
+    }
+
+    fn activate_window(&self, _window: u32) -> Result<(), Error> {
+        Err(Error::NoWindowManager("activating windows"))
+    }
+
+    fn move_window(&self, _window: u32, _idx: usize) -> Result<(), Error> {
+        Err(Error::NoWindowManager("moving windows"))
+    }
+
+    fn set_count(&self, _num: usize) -> Result<(), Error> {
+        Err(Error::NoWindowManager("changing the number of workspaces"))
+    }
+
+    fn set_sticky(&self, _window: u32, _sticky: bool) -> Result<(), Error> {
+        Err(Error::NoWindowManager("sticky windows"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Snapshot, active_workspace, fake, switch_by};
+    use std::io;
+
+    #[test]
+    fn workspaces_from_a_reader() {
+        let workspaces = read_workspaces(fake::WMCTRL_D.as_bytes()).unwrap();
+        let names: Vec<&str> = workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        assert_eq!(names, ["Main", "Web", "", "工作区 4"]);
+        let backend = FromStdin { workspaces };
+        let workspaces = backend.query().unwrap();
+        assert_eq!(active_workspace(&workspaces).unwrap().idx, 0);
+        let json = serde_json::to_value(Snapshot::new(workspaces)).unwrap();
+        assert_eq!(
+            (json["count"].as_u64(), json["current"].as_u64()),
+            (Some(4), Some(0))
+        );
+        // 没有窗口管理器, 不能切换
+        assert!(matches!(
+            switch_by(&backend, 1, true),
+            Err(Error::NoWindowManager(_))
+        ));
+
+        assert!(read_workspaces(&b""[..]).unwrap().is_empty());
+        assert!(read_workspaces(&b"garbage\n"[..]).is_err());
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        assert!(matches!(read_workspaces(Broken), Err(Error::Stdin(_))));
+        // 不是 UTF-8 的输入
+        assert!(matches!(
+            read_workspaces(&b"\xff\n"[..]),
+            Err(Error::Stdin(_))
+        ));
+    }
+}
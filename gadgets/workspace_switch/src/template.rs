@@ -0,0 +1,167 @@
+//! `--format-str` 的模板: `{field}` 按工作区替换, `{{` 和 `}}` 表示花括号本身.
+
+use crate::Workspace;
+use std::str::FromStr;
+
+/// A workspace field which can be used in a [`Template`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Idx,
+    /// `*` for the active workspace, `-` otherwise.
+    Active,
+    Name,
+    Dg,
+    Vp,
+    Wa,
+}
+
+impl Field {
+    pub const NAMES: &[(&str, Field)] = &[
+        ("idx", Field::Idx),
+        ("active", Field::Active),
+        ("name", Field::Name),
+        ("dg", Field::Dg),
+        ("vp", Field::Vp),
+        ("wa", Field::Wa),
+    ];
+
+    /// Render the field of `ws` the same way as the `Display` of [`Workspace`].
+    pub fn render(self, ws: &Workspace) -> String {
+        let na = || "N/A".to_owned();
+        match self {
+            Field::Idx => ws.idx.to_string(),
+            Field::Active => if ws.active { "*" } else { "-" }.to_owned(),
+            Field::Name => ws.name.clone(),
+            Field::Dg => ws.dg.map(|(w, h)| format!("{w}x{h}")).unwrap_or_else(na),
+            Field::Vp => ws.vp.map(|(x, y)| format!("{x},{y}")).unwrap_or_else(na),
+            Field::Wa => ws
+                .available_area
+                .map(|(l, t, w, h)| format!("{l},{t} {w}x{h}"))
+                .unwrap_or_else(na),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error(
+        "Unknown placeholder {{{0}}}, expected one of {known}.",
+        known = known_placeholders()
+    )]
+    UnknownPlaceholder(String),
+    #[error("Unclosed {{ at byte {0}, use {{{{ for a literal brace.")]
+    Unclosed(usize),
+    #[error("Unmatched }} at byte {0}, use }}}} for a literal brace.")]
+    Unmatched(usize),
+}
+
+fn known_placeholders() -> String {
+    Field::NAMES
+        .iter()
+        .map(|(name, _)| format!("{{{name}}}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A parsed `--format-str`, rendered once per workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template(Vec<Segment>);
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.char_indices().peekable();
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => literal.push('}'),
+                '{' => {
+                    let rest = &s[pos + 1..];
+                    let end = rest.find('}').ok_or(TemplateError::Unclosed(pos))?;
+                    let name = &rest[..end];
+                    let field = Field::NAMES
+                        .iter()
+                        .find_map(|&(n, field)| (n == name).then_some(field))
+                        .ok_or_else(|| TemplateError::UnknownPlaceholder(name.to_owned()))?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                    // 跳过占位符名和右花括号
+                    while chars.next_if(|&(p, _)| p <= pos + 1 + end).is_some() {}
+                }
+                '}' => return Err(TemplateError::Unmatched(pos)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template(segments))
+    }
+}
+
+impl Template {
+    pub fn render(&self, ws: &Workspace) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(field) => field.render(ws),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake, parse_workspaces};
+
+    fn render_all(template: &str) -> Vec<String> {
+        let template: Template = template.parse().unwrap();
+        parse_workspaces(fake::WMCTRL_D)
+            .unwrap()
+            .iter()
+            .map(|ws| template.render(ws))
+            .collect()
+    }
+
+    #[test]
+    fn renders_parsed_workspaces() {
+        assert_eq!(
+            render_all("{active}{idx}: {name}"),
+            ["*0: Main", "-1: Web", "-2: ", "-3: 工作区 4"]
+        );
+        assert_eq!(
+            render_all("{idx} {dg} {vp} {wa}")[..2],
+            ["0 1920x1080 0,0 0,27 1920x1053", "1 1920x1080 N/A N/A"]
+        );
+        assert_eq!(render_all("{{{idx}}}")[1], "{1}");
+        assert_eq!(render_all("")[0], "");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        let parse = |s: &str| s.parse::<Template>().unwrap_err();
+        assert_eq!(
+            parse("{id}"),
+            TemplateError::UnknownPlaceholder("id".to_owned())
+        );
+        assert_eq!(parse("ab {name"), TemplateError::Unclosed(3));
+        assert_eq!(parse("a}b"), TemplateError::Unmatched(1));
+        assert_eq!(
+            parse("{}").to_string(),
+            "Unknown placeholder {}, expected one of {idx}, {active}, {name}, {dg}, {vp}, {wa}."
+        );
+    }
+}
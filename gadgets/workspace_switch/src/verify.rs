@@ -0,0 +1,198 @@
+//! 切换后确认活动工作区确实改变了: 有的窗口管理器 (例如某些 GNOME Shell) 忽略 `wmctrl -s` 却不报错.
+
+use crate::{
+    BackendWrapper, Error, WindowEntry, Workspace, WorkspaceBackend, active_workspace, warn,
+};
+use std::{thread, time::Duration};
+use tracing::debug;
+
+/// 窗口管理器处理切换请求需要时间, 未生效时再查询几次.
+const ATTEMPTS: usize = 5;
+const INTERVAL: Duration = Duration::from_millis(40);
+
+/// 最多检查 `attempts` 次, 间隔 `interval`, 直到 `check` 返回 true.
+fn poll(
+    attempts: usize,
+    interval: Duration,
+    mut check: impl FnMut() -> Result<bool, Error>,
+) -> Result<bool, Error> {
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            thread::sleep(interval);
+        }
+        if check()? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Query up to `attempts` times, `interval` apart, until workspace `idx` is active.
+pub fn verify_switched(
+    idx: usize,
+    attempts: usize,
+    interval: Duration,
+    mut query: impl FnMut() -> Result<Vec<Workspace>, Error>,
+) -> Result<(), Error> {
+    if poll(attempts, interval, || {
+        Ok(active_workspace(&query()?).is_ok_and(|ws| ws.idx == idx))
+    })? {
+        Ok(())
+    } else {
+        Err(Error::NotSwitched(idx))
+    }
+}
+
+/// `--take`: `move_window` moves the active window to workspace `idx`, then `moved` is polled
+/// until it reports the window there, and only then `switch` switches to `idx`.
+/// The switch is verified with `query` like [`verify_switched`] and retried once if the window
+/// manager dropped it. Fails with [`Error::NotMoved`] or [`Error::MovedNotSwitched`].
+pub fn take_verified(
+    idx: usize,
+    attempts: usize,
+    interval: Duration,
+    move_window: impl FnOnce() -> Result<(), Error>,
+    moved: impl FnMut() -> Result<bool, Error>,
+    mut switch: impl FnMut() -> Result<(), Error>,
+    mut query: impl FnMut() -> Result<Vec<Workspace>, Error>,
+) -> Result<(), Error> {
+    move_window()?;
+    if !poll(attempts, interval, moved)? {
+        return Err(Error::NotMoved(idx));
+    }
+    switch()?;
+    if verify_switched(idx, attempts, interval, &mut query).is_ok() {
+        return Ok(());
+    }
+    debug!("Workspace {idx} didn't become active, switch again.");
+    switch()?;
+    verify_switched(idx, attempts, interval, query).map_err(|_| Error::MovedNotSwitched(idx))
+}
+
+/// [`take_verified`] on `backend` with the default attempts and interval.
+/// The active window counts as moved once a window not on `idx` before appears there.
+pub fn take(backend: &dyn WorkspaceBackend, idx: usize) -> Result<(), Error> {
+    let before = backend.windows()?;
+    take_verified(
+        idx,
+        ATTEMPTS,
+        INTERVAL,
+        || backend.move_active_to(idx),
+        || Ok(arrived(&before, &backend.windows()?, idx)),
+        || backend.switch_to(idx),
+        || backend.query(),
+    )
+}
+
+/// Whether a window of `after` is on workspace `idx` but wasn't in `before`.
+pub fn arrived(before: &[WindowEntry], after: &[WindowEntry], idx: usize) -> bool {
+    let on_idx = |w: &WindowEntry| usize::try_from(w.desktop).is_ok_and(|d| d == idx);
+    after
+        .iter()
+        .filter(|w| on_idx(w))
+        .any(|w| !before.iter().any(|b| b.id == w.id && on_idx(b)))
+}
+
+/// Wraps a backend and checks that every switch took effect.
+/// Failures are only warned unless `strict`.
+pub struct Verifying {
+    pub inner: Box<dyn WorkspaceBackend>,
+    pub strict: bool,
+}
+
+impl BackendWrapper for Verifying {
+    fn inner(&self) -> &dyn WorkspaceBackend {
+        self.inner.as_ref()
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        self.inner.switch_to(idx)?;
+        match verify_switched(idx, ATTEMPTS, INTERVAL, || self.inner.query()) {
+            Err(e) if !self.strict => {
+                warn(e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::workspace;
+
+    /// 活动工作区为 `active` 的 3 个工作区.
+    fn workspaces(active: usize) -> Vec<Workspace> {
+        (0..3)
+            .map(|idx| workspace(idx, idx == active, ""))
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_index_is_reported() {
+        let mut queries = 0;
+        let result = verify_switched(2, 3, Duration::ZERO, || {
+            queries += 1;
+            Ok(workspaces(0))
+        });
+        assert!(matches!(result, Err(Error::NotSwitched(2))));
+        assert_eq!(queries, 3);
+        // 第二次查询时才生效
+        let mut queries = 0;
+        let result = verify_switched(2, 3, Duration::ZERO, || {
+            queries += 1;
+            Ok(workspaces(if queries < 2 { 0 } else { 2 }))
+        });
+        assert!(result.is_ok());
+        assert_eq!(queries, 2);
+        // 查询失败不再重试
+        let result = verify_switched(2, 3, Duration::ZERO, || Err(Error::NoActiveWorkspace));
+        assert!(matches!(result, Err(Error::NoActiveWorkspace)));
+    }
+
+    #[test]
+    fn take_retries_a_dropped_switch_once() {
+        use std::cell::Cell;
+        /// `switched_after` 次切换生效 (0 为从不), 窗口 `moved` 时到达.
+        fn take(moved: bool, switched_after: usize) -> (Result<(), Error>, usize) {
+            let switches = Cell::new(0);
+            let result = take_verified(
+                2,
+                3,
+                Duration::ZERO,
+                || Ok(()),
+                || Ok(moved),
+                || {
+                    switches.set(switches.get() + 1);
+                    Ok(())
+                },
+                || {
+                    let active = switched_after != 0 && switches.get() >= switched_after;
+                    Ok(workspaces(if active { 2 } else { 0 }))
+                },
+            );
+            (result, switches.get())
+        }
+        assert!(matches!(take(true, 1), (Ok(()), 1)));
+        // 窗口管理器丢掉了第一次切换
+        assert!(matches!(take(true, 2), (Ok(()), 2)));
+        assert!(matches!(
+            take(true, 0),
+            (Err(Error::MovedNotSwitched(2)), 2)
+        ));
+        // 窗口没有移动时不切换
+        assert!(matches!(take(false, 1), (Err(Error::NotMoved(2)), 0)));
+        // 移动失败
+        let result = take_verified(
+            2,
+            3,
+            Duration::ZERO,
+            || Err(Error::NoActiveWorkspace),
+            || panic!("polled"),
+            || panic!("switched"),
+            || panic!("queried"),
+        );
+        assert!(matches!(result, Err(Error::NoActiveWorkspace)));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{Error, WindowEntry, Workspace, WorkspaceBackend};
+use tracing::debug;
+use x11rb::{
+    CURRENT_TIME, NONE,
+    connection::Connection,
+    protocol::{
+        Event,
+        xproto::{
+            AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _, EventMask,
+            PropMode,
+        },
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+/// Talks to the window manager through the EWMH properties of the root window.
+pub struct X11 {
+    conn: RustConnection,
+    root: u32,
+}
+
+impl X11 {
+    pub fn connect() -> Result<X11, Error> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        Ok(X11 { conn, root })
+    }
+
+    fn atom(&self, name: &str) -> Result<u32, Error> {
+        Ok(self.conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+    }
+
+    /// 读取窗口上的属性, 属性不存在时返回 None.
+    fn property(
+        &self,
+        window: u32,
+        name: &str,
+        type_: impl Into<u32>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let reply = self
+            .conn
+            .get_property(false, window, self.atom(name)?, type_, 0, u32::MAX)?
+            .reply()?;
+        Ok((reply.type_ != NONE).then_some(reply.value))
+    }
+
+    /// 读取 32 位的属性值列表, 例如 CARDINAL 和 WINDOW.
+    fn values32(&self, window: u32, name: &str, type_: impl Into<u32>) -> Result<Vec<u32>, Error> {
+        Ok(self
+            .property(window, name, type_)?
+            .map(|value| decode_values32(&value))
+            .unwrap_or_default())
+    }
+
+    fn active_window(&self) -> Result<Option<u32>, Error> {
+        Ok(self
+            .values32(self.root, "_NET_ACTIVE_WINDOW", AtomEnum::WINDOW)?
+            .first()
+            .copied()
+            .filter(|&window| window != NONE))
+    }
+
+    /// Whether the active window has `_NET_WM_STATE_FULLSCREEN`, false if no window is active.
+    pub fn active_window_fullscreen(&self) -> Result<bool, Error> {
+        let Some(window) = self.active_window()? else {
+            return Ok(false);
+        };
+        let state = self
+            .property(window, "_NET_WM_STATE", AtomEnum::ATOM)?
+            .unwrap_or_default();
+        Ok(is_fullscreen(
+            &state,
+            self.atom("_NET_WM_STATE_FULLSCREEN")?,
+        ))
+    }
+
+    fn cardinals(&self, name: &str) -> Result<Vec<u32>, Error> {
+        self.values32(self.root, name, AtomEnum::CARDINAL)
+    }
+
+    fn window_title(&self, window: u32) -> Result<String, Error> {
+        if let Some(value) = self.property(window, "_NET_WM_NAME", self.atom("UTF8_STRING")?)? {
+            return Ok(String::from_utf8_lossy(&value).into_owned());
+        }
+        // WM_NAME 按 latin1 解码.
+        Ok(self
+            .property(window, "WM_NAME", AtomEnum::ANY)?
+            .map(|value| value.iter().map(|&b| b as char).collect())
+            .unwrap_or_default())
+    }
+
+    /// Call `on_change` with the workspaces once, then each time the current desktop,
+    /// the number of desktops or their names change, until it returns false.
+    /// Returns an error when the connection breaks, e.g. the X server exits.
+    pub fn watch(&self, mut on_change: impl FnMut(&[Workspace]) -> bool) -> Result<(), Error> {
+        self.conn.change_window_attributes(
+            self.root,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        self.conn.flush()?;
+        let atoms = [
+            self.atom("_NET_CURRENT_DESKTOP")?,
+            self.atom("_NET_NUMBER_OF_DESKTOPS")?,
+            self.atom("_NET_DESKTOP_NAMES")?,
+        ];
+        let is_watched = |event: &Event| matches!(event, Event::PropertyNotify(e) if e.window == self.root && atoms.contains(&e.atom));
+        if !on_change(&self.query()?) {
+            return Ok(());
+        }
+        loop {
+            let mut changed = is_watched(&self.conn.wait_for_event()?);
+            // 合并已经到达的事件, 切换工作区时多个属性会一起变化.
+            while let Some(event) = self.conn.poll_for_event()? {
+                changed |= is_watched(&event);
+            }
+            if changed && !on_change(&self.query()?) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 向根窗口发送 EWMH 客户端消息, 由窗口管理器处理.
+    fn send_message(&self, window: u32, name: &str, data: [u32; 5]) -> Result<(), Error> {
+        debug!("Send {name} {data:?} to window 0x{window:08x}.");
+        let event = ClientMessageEvent::new(32, window, self.atom(name)?, data);
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+            event,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// 32 位格式的属性值, 按本机字节序排列.
+fn decode_values32(value: &[u8]) -> Vec<u32> {
+    value
+        .chunks_exact(4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Whether `wm_state`, the raw value of a window's `_NET_WM_STATE` (a list of atoms),
+/// contains `fullscreen`, the atom of `_NET_WM_STATE_FULLSCREEN`.
+pub fn is_fullscreen(wm_state: &[u8], fullscreen: u32) -> bool {
+    decode_values32(wm_state).contains(&fullscreen)
+}
+
+/// `_NET_DESKTOP_NAMES` 是以 \0 结尾的 UTF-8 字符串列表.
+pub fn parse_desktop_names(value: &[u8]) -> Vec<String> {
+    let value = value.strip_suffix(&[0]).unwrap_or(value);
+    if value.is_empty() {
+        return Vec::new();
+    }
+    value
+        .split(|&b| b == 0)
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+/// Encode names as the value of `_NET_DESKTOP_NAMES`.
+pub fn encode_desktop_names(names: &[String]) -> Vec<u8> {
+    names
+        .iter()
+        .flat_map(|name| name.bytes().chain([0]))
+        .collect()
+}
+
+impl WorkspaceBackend for X11 {
+    fn query(&self) -> Result<Vec<Workspace>, Error> {
+        let num = *self
+            .cardinals("_NET_NUMBER_OF_DESKTOPS")?
+            .first()
+            .ok_or(Error::Unsupported("_NET_NUMBER_OF_DESKTOPS"))?;
+        let current = self.cardinals("_NET_CURRENT_DESKTOP")?.first().copied();
+        let names = self
+            .property(self.root, "_NET_DESKTOP_NAMES", self.atom("UTF8_STRING")?)?
+            .map(|value| parse_desktop_names(&value))
+            .unwrap_or_default();
+        let geometry = self.cardinals("_NET_DESKTOP_GEOMETRY")?;
+        let viewports = self.cardinals("_NET_DESKTOP_VIEWPORT")?;
+        let workareas = self.cardinals("_NET_WORKAREA")?;
+        let field = |values: &[u32], idx: usize, len: usize| -> Option<Vec<isize>> {
+            values
+                .get(idx * len..(idx + 1) * len)
+                .map(|v| v.iter().map(|&x| x as isize).collect())
+        };
+        Ok((0..num as usize)
+            .map(|idx| Workspace {
+                active: current == Some(idx as u32),
+                idx,
+                dg: field(&geometry, 0, 2).map(|v| (v[0], v[1])),
+                vp: field(&viewports, idx, 2).map(|v| (v[0], v[1])),
+                available_area: field(&workareas, idx, 4).map(|v| (v[0], v[1], v[2], v[3])),
+                name: names.get(idx).cloned().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    fn switch_to(&self, idx: usize) -> Result<(), Error> {
+        let data = [idx as u32, CURRENT_TIME, 0, 0, 0];
+        self.send_message(self.root, "_NET_CURRENT_DESKTOP", data)
+    }
+
+    fn move_active_to(&self, idx: usize) -> Result<(), Error> {
+        let window = self.active_window()?.ok_or(Error::NoActiveWindow)?;
+        // 2 表示请求来自 pager 之类的工具.
+        self.send_message(window, "_NET_WM_DESKTOP", [idx as u32, 2, 0, 0, 0])
+    }
+
+    fn set_viewport(&self, x: isize, y: isize) -> Result<(), Error> {
+        let data = [x as u32, y as u32, 0, 0, 0];
+        self.send_message(self.root, "_NET_DESKTOP_VIEWPORT", data)
+    }
+
+    fn rename(&self, idx: usize, name: &str) -> Result<(), Error> {
+        let net_desktop_names = self.atom("_NET_DESKTOP_NAMES")?;
+        let supported = self.values32(self.root, "_NET_SUPPORTED", AtomEnum::ATOM)?;
+        if !supported.contains(&net_desktop_names) {
+            return Err(Error::Unsupported("_NET_DESKTOP_NAMES"));
+        }
+        let utf8_string = self.atom("UTF8_STRING")?;
+        let mut names = self
+            .property(self.root, "_NET_DESKTOP_NAMES", utf8_string)?
+            .map(|value| parse_desktop_names(&value))
+            .unwrap_or_default();
+        // 没有名称的工作区用空字符串占位.
+        if names.len() <= idx {
+            names.resize(idx + 1, String::new());
+        }
+        names[idx] = name.to_owned();
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            self.root,
+            net_desktop_names,
+            utf8_string,
+            &encode_desktop_names(&names),
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn set_count(&self, num: usize) -> Result<(), Error> {
+        self.send_message(
+            self.root,
+            "_NET_NUMBER_OF_DESKTOPS",
+            [num as u32, 0, 0, 0, 0],
+        )
+    }
+
+    fn windows(&self) -> Result<Vec<WindowEntry>, Error> {
+        self.values32(self.root, "_NET_CLIENT_LIST", AtomEnum::WINDOW)?
+            .into_iter()
+            .map(|window| {
+                // 0xFFFFFFFF 表示显示在所有工作区上, 和 wmctrl 一样输出为 -1.
+                let desktop = self
+                    .values32(window, "_NET_WM_DESKTOP", AtomEnum::CARDINAL)?
+                    .first()
+                    .map_or(-1, |&desktop| desktop as i32 as isize);
+                let host = self
+                    .property(window, "WM_CLIENT_MACHINE", AtomEnum::STRING)?
+                    .map(|value| String::from_utf8_lossy(&value).into_owned())
+                    .unwrap_or_else(|| "N/A".to_owned());
+                Ok(WindowEntry {
+                    id: window,
+                    desktop,
+                    host,
+                    title: self.window_title(window)?,
+                })
+            })
+            .collect()
+    }
+
+    fn pull_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        // 2 表示请求来自 pager 之类的工具.
+        self.send_message(window, "_NET_WM_DESKTOP", [idx as u32, 2, 0, 0, 0])?;
+        self.send_message(window, "_NET_ACTIVE_WINDOW", [2, CURRENT_TIME, 0, 0, 0])
+    }
+
+    fn activate_window(&self, window: u32) -> Result<(), Error> {
+        self.send_message(window, "_NET_ACTIVE_WINDOW", [2, CURRENT_TIME, 0, 0, 0])
+    }
+
+    fn move_window(&self, window: u32, idx: usize) -> Result<(), Error> {
+        self.send_message(window, "_NET_WM_DESKTOP", [idx as u32, 2, 0, 0, 0])
+    }
+
+    fn set_sticky(&self, window: u32, sticky: bool) -> Result<(), Error> {
+        // _NET_WM_STATE 的 action: 0 移除, 1 添加; 最后的 2 表示请求来自 pager 之类的工具.
+        let sticky_atom = self.atom("_NET_WM_STATE_STICKY")?;
+        self.send_message(
+            window,
+            "_NET_WM_STATE",
+            [sticky as u32, sticky_atom, 0, 2, 0],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_names_encoding() {
+        let names: Vec<String> = ["Main", "", "工作区 3"].map(str::to_owned).into();
+        let value = encode_desktop_names(&names);
+        assert_eq!(value, b"Main\0\0\xe5\xb7\xa5\xe4\xbd\x9c\xe5\x8c\xba 3\0");
+        assert_eq!(parse_desktop_names(&value), names);
+        assert_eq!(encode_desktop_names(&[]), b"");
+        assert!(parse_desktop_names(b"").is_empty());
+        // 有的窗口管理器省略最后的 \0
+        assert_eq!(parse_desktop_names(b"a\0b"), ["a", "b"]);
+    }
+
+    #[test]
+    fn fullscreen_state_detection() {
+        const FULLSCREEN: u32 = 421;
+        let state =
+            |atoms: &[u32]| -> Vec<u8> { atoms.iter().flat_map(|a| a.to_ne_bytes()).collect() };
+        assert!(is_fullscreen(&state(&[FULLSCREEN]), FULLSCREEN));
+        // 和 _NET_WM_STATE_ABOVE 等其他状态一起
+        assert!(is_fullscreen(&state(&[419, 420, FULLSCREEN]), FULLSCREEN));
+        assert!(!is_fullscreen(&state(&[419, 420]), FULLSCREEN));
+        // 没有 _NET_WM_STATE 的窗口
+        assert!(!is_fullscreen(&[], FULLSCREEN));
+        // 不完整的最后一个值被忽略
+        let mut truncated = state(&[419]);
+        truncated.extend(&FULLSCREEN.to_ne_bytes()[..2]);
+        assert!(!is_fullscreen(&truncated, FULLSCREEN));
+    }
+}
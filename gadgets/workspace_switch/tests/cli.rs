@@ -0,0 +1,25 @@
+//! 运行 `wsst` 可执行文件, 检查输出到哪个流和退出码.
+
+use std::{env, process::Command};
+
+#[test]
+fn no_action_prints_help_to_stderr() {
+    // 空的配置目录, 默认操作是输出帮助. 没有操作时不会运行 wmctrl, 任意文件都可以.
+    let home = env::temp_dir().join(format!("wsst-cli-{}", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_wsst"))
+        .env("WMCTRL_PATH", env!("CARGO_BIN_EXE_wsst"))
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_STATE_HOME", home.join(".local/state"))
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        output.stdout.is_empty(),
+        "{:?}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Usage: wsst"), "{stderr}");
+}
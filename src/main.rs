@@ -0,0 +1,31 @@
+use clap::{Parser, Subcommand};
+use gadgets_common::logging;
+use std::io;
+
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    gadget: Gadget,
+}
+
+#[derive(Subcommand, Debug)]
+enum Gadget {
+    /// Switch input method engines, see ibus_engine_switch.
+    Ime(ibus_engine_switch::cli::Args),
+    /// Switch workspaces, see wsst.
+    Ws(Box<workspace_switch::cli::Args>),
+}
+
+fn main() {
+    let args = Args::parse();
+    match args.gadget {
+        // ibus_engine_switch 的日志由 run 设置, 切换服务器要在阻塞信号和 fork 之后才安装.
+        Gadget::Ime(args) => ibus_engine_switch::cli::run(args),
+        Gadget::Ws(args) => {
+            let filter = workspace_switch::cli::env_log_filter(args.verbose());
+            logging::init(filter, true, io::stderr, None);
+            workspace_switch::cli::exit_with(workspace_switch::cli::run(*args))
+        }
+    }
+}